@@ -134,6 +134,9 @@ pub struct Item {
     pub cursed: u32,
     #[serde(rename = "time", default, deserialize_with = "deserialize_num")]
     pub time: u32,
+    /// Fullness restored by pet food
+    #[serde(rename = "inc", default, deserialize_with = "deserialize_num")]
+    pub inc: u32,
 
 
     #[serde(rename = "summons", default)]
@@ -160,6 +163,123 @@ pub struct Item {
 
 
 
+/// Cash shop entry from Etc/Commodity
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Commodity {
+    #[serde(rename = "SN", deserialize_with = "deserialize_num")]
+    pub sn: u32,
+    #[serde(rename = "ItemId", deserialize_with = "deserialize_num")]
+    pub item_id: u32,
+    #[serde(rename = "Count", default, deserialize_with = "deserialize_num")]
+    pub count: u32,
+    #[serde(rename = "Price", default, deserialize_with = "deserialize_num")]
+    pub price: u32,
+    #[serde(rename = "Period", default, deserialize_with = "deserialize_num")]
+    pub period: u32,
+    #[serde(rename = "Priority", default, deserialize_with = "deserialize_num")]
+    pub priority: u32,
+    #[serde(rename = "Gender", default, deserialize_with = "deserialize_num")]
+    pub gender: u32,
+    #[serde(rename = "OnSale", default, deserialize_with = "deserialize_num")]
+    pub on_sale: u32,
+}
+
+impl Commodity {
+    pub fn is_on_sale(&self) -> bool {
+        self.on_sale != 0
+    }
+}
+
+/// Img lists are objects keyed by the index, this keeps the order of the indices
+fn deserialize_num_list<'de, D>(deserializer: D) -> Result<Vec<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let list = BTreeMap::<String, IntOrString<u32>>::deserialize(deserializer)?;
+    let mut list = list
+        .into_iter()
+        .map(|(ix, v)| {
+            let ix: u32 = ix.parse().map_err(D::Error::custom)?;
+            let v = match v.inner {
+                Either::Left(s) => s.parse().map_err(D::Error::custom)?,
+                Either::Right(n) => n,
+            };
+            Ok((ix, v))
+        })
+        .collect::<Result<Vec<_>, D::Error>>()?;
+    list.sort_by_key(|(ix, _)| *ix);
+    Ok(list.into_iter().map(|(_, v)| v).collect())
+}
+
+/// Choices on the character creation screen for a single gender,
+/// the numbered nodes are the option lists in the order of the screen
+#[derive(Debug, Default, Deserialize)]
+pub struct MakeCharChoices {
+    #[serde(rename = "0", default, deserialize_with = "deserialize_num_list")]
+    pub faces: Vec<u32>,
+    #[serde(rename = "1", default, deserialize_with = "deserialize_num_list")]
+    pub hairs: Vec<u32>,
+    #[serde(rename = "2", default, deserialize_with = "deserialize_num_list")]
+    pub hair_colors: Vec<u32>,
+    #[serde(rename = "3", default, deserialize_with = "deserialize_num_list")]
+    pub skins: Vec<u32>,
+    #[serde(rename = "4", default, deserialize_with = "deserialize_num_list")]
+    pub tops: Vec<u32>,
+    #[serde(rename = "5", default, deserialize_with = "deserialize_num_list")]
+    pub bottoms: Vec<u32>,
+    #[serde(rename = "6", default, deserialize_with = "deserialize_num_list")]
+    pub shoes: Vec<u32>,
+    #[serde(rename = "7", default, deserialize_with = "deserialize_num_list")]
+    pub weapons: Vec<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MakeCharInfoBasic {
+    #[serde(rename = "CharMale", default)]
+    pub male: MakeCharChoices,
+    #[serde(rename = "CharFemale", default)]
+    pub female: MakeCharChoices,
+}
+
+/// Etc/MakeCharInfo, groups without a node can't be created
+#[derive(Debug, Default, Deserialize)]
+pub struct MakeCharInfo {
+    /// Adventurer
+    #[serde(rename = "Info", default)]
+    pub basic: Option<MakeCharInfoBasic>,
+    /// Knights of Cygnus
+    #[serde(rename = "PremiumCharMale", default)]
+    pub premium_male: Option<MakeCharChoices>,
+    #[serde(rename = "PremiumCharFemale", default)]
+    pub premium_female: Option<MakeCharChoices>,
+    /// Aran
+    #[serde(rename = "OrientCharMale", default)]
+    pub orient_male: Option<MakeCharChoices>,
+    #[serde(rename = "OrientCharFemale", default)]
+    pub orient_female: Option<MakeCharChoices>,
+    #[serde(rename = "EvanCharMale", default)]
+    pub evan_male: Option<MakeCharChoices>,
+    #[serde(rename = "EvanCharFemale", default)]
+    pub evan_female: Option<MakeCharChoices>,
+}
+
+/// Etc/ForbiddenName, a list of names which must not be contained in a character name
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct ForbiddenNames(pub BTreeMap<String, String>);
+
+impl ForbiddenNames {
+    pub fn into_names(self) -> Vec<String> {
+        self.0.into_values().collect()
+    }
+}
+
+/// Loads a single img from the dump
+pub fn load_img<T: DeserializeOwned>(path: impl AsRef<Path>) -> anyhow::Result<T> {
+    let path = path.as_ref();
+    serde_json::from_reader(std::fs::File::open(path)?).with_context(|| path.display().to_string())
+}
+
 pub fn load_all<T: DeserializeOwned>(
     base_path: impl AsRef<Path>,
 ) -> anyhow::Result<BTreeMap<u32, T>> {
@@ -0,0 +1,215 @@
+use std::net::SocketAddr;
+
+use data::services::{
+    data::account::AccountId,
+    session::{
+        center::{CenterReport, CenterStatus, MigrationTicket, WorldCenter},
+        online::{OnlineError, OnlineState},
+        ShroomMigrationKey,
+    },
+};
+use tonic::transport::{Channel, Endpoint};
+
+use crate::{
+    convert::{
+        channel_ref_from_proto, channel_to_proto, key_to_proto, state_to_proto, ticket_from_proto,
+        ticket_to_proto,
+    },
+    proto::{self, center_client::CenterClient, AccountReq, LoginResult},
+};
+
+/// Client of the center, the connection is established lazily,
+/// so the processes can be started in any order
+#[derive(Debug, Clone)]
+pub struct RemoteCenter {
+    client: CenterClient<Channel>,
+}
+
+impl RemoteCenter {
+    pub fn connect_lazy(addr: SocketAddr) -> anyhow::Result<Self> {
+        let channel = Endpoint::from_shared(format!("http://{addr}"))?.connect_lazy();
+        Ok(Self {
+            client: CenterClient::new(channel),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl WorldCenter for RemoteCenter {
+    async fn login(&self, acc_id: AccountId) -> Result<(), OnlineError> {
+        let reply = self
+            .client
+            .clone()
+            .login(AccountReq { acc_id })
+            .await
+            .map_err(|err| {
+                log::error!("Unable to login account {acc_id} at the center: {err}");
+                OnlineError::Unavailable
+            })?
+            .into_inner();
+
+        match reply.result() {
+            LoginResult::LoginOk => Ok(()),
+            LoginResult::AlreadyLoggedIn => Err(OnlineError::AlreadyLoggedIn),
+            LoginResult::Closed => Err(OnlineError::Closed),
+        }
+    }
+
+    async fn logout(&self, acc_id: AccountId) -> anyhow::Result<()> {
+        self.client.clone().logout(AccountReq { acc_id }).await?;
+        Ok(())
+    }
+
+    async fn request_disconnect(&self, acc_id: AccountId) -> anyhow::Result<()> {
+        self.client
+            .clone()
+            .request_disconnect(AccountReq { acc_id })
+            .await?;
+        Ok(())
+    }
+
+    async fn push_migration(
+        &self,
+        key: ShroomMigrationKey,
+        ticket: MigrationTicket,
+    ) -> anyhow::Result<()> {
+        self.client
+            .clone()
+            .push_migration(proto::PushMigrationReq {
+                key: Some(key_to_proto(key)),
+                ticket: Some(ticket_to_proto(ticket)),
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn take_migration(
+        &self,
+        key: ShroomMigrationKey,
+        state: OnlineState,
+    ) -> anyhow::Result<MigrationTicket> {
+        let ticket = self
+            .client
+            .clone()
+            .take_migration(proto::TakeMigrationReq {
+                key: Some(key_to_proto(key)),
+                state: state_to_proto(state),
+            })
+            .await?
+            .into_inner();
+        Ok(ticket_from_proto(Some(ticket))?)
+    }
+
+    async fn report(&self, report: CenterReport) -> anyhow::Result<CenterStatus> {
+        let reply = self
+            .client
+            .clone()
+            .report(proto::ReportReq {
+                server: report.server,
+                channels: report.channels.into_iter().map(channel_to_proto).collect(),
+                accounts: report
+                    .accounts
+                    .into_iter()
+                    .filter_map(|(acc_id, state)| {
+                        state_to_proto(state).map(|state| proto::Presence {
+                            acc_id,
+                            state: Some(state),
+                        })
+                    })
+                    .collect(),
+            })
+            .await?
+            .into_inner();
+
+        let population = reply
+            .population
+            .into_iter()
+            .map(|load| Ok((channel_ref_from_proto(load.channel)?, load.users as usize)))
+            .collect::<Result<_, tonic::Status>>()?;
+        Ok(CenterStatus {
+            disconnect: reply.disconnect,
+            population,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr, SocketAddr},
+        sync::Arc,
+        time::Duration,
+    };
+
+    use data::services::session::{
+        center::{CenterReport, MigrationTicket, WorldCenter},
+        online::{OnlineError, OnlineState},
+        ShroomMigrationKey,
+    };
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+
+    use crate::{proto::center_server::CenterServer, CenterService};
+
+    use super::RemoteCenter;
+
+    /// Runs the center on a free loopback port
+    async fn spawn_center() -> (Arc<CenterService>, SocketAddr) {
+        let center = Arc::new(CenterService::new(Duration::from_secs(5)));
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(CenterServer::from_arc(center.clone()))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        (center, addr)
+    }
+
+    #[tokio::test]
+    async fn loopback() {
+        let (center, addr) = spawn_center().await;
+        let login = RemoteCenter::connect_lazy(addr).unwrap();
+        let channel = RemoteCenter::connect_lazy(addr).unwrap();
+
+        login.login(1).await.unwrap();
+        assert_eq!(channel.login(1).await, Err(OnlineError::AlreadyLoggedIn));
+
+        let key = ShroomMigrationKey::new([1; 8], IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let ticket = MigrationTicket {
+            acc_id: 1,
+            char_id: 2,
+            channel_id: 1,
+        };
+        let (taken, _) = tokio::join!(
+            channel.take_migration(key, OnlineState::Channel(0, 1)),
+            async { login.push_migration(key, ticket).await.unwrap() }
+        );
+        assert_eq!(taken.unwrap(), ticket);
+
+        let status = channel
+            .report(CenterReport {
+                server: "channel".to_string(),
+                channels: vec![(0, 1)],
+                accounts: vec![(1, OnlineState::Channel(0, 1))],
+            })
+            .await
+            .unwrap();
+        assert!(status.disconnect.is_empty());
+        assert_eq!(status.population.get(&(0, 1)), Some(&1));
+
+        login.request_disconnect(1).await.unwrap();
+        let status = channel
+            .report(CenterReport {
+                server: "channel".to_string(),
+                channels: vec![(0, 1)],
+                accounts: vec![(1, OnlineState::Channel(0, 1))],
+            })
+            .await
+            .unwrap();
+        assert_eq!(status.disconnect, vec![1]);
+
+        channel.logout(1).await.unwrap();
+        assert!(!center.online().is_online(1));
+    }
+}
@@ -0,0 +1,89 @@
+use std::net::IpAddr;
+
+use data::services::session::{center::MigrationTicket, online::OnlineState, ShroomMigrationKey};
+use proto95::login::world::{ChannelId, WorldId};
+use tonic::Status;
+
+use crate::proto::{self, online_state::State};
+
+pub fn key_to_proto(key: ShroomMigrationKey) -> proto::MigrationKey {
+    proto::MigrationKey {
+        client_key: key.client_key().to_vec(),
+        peer_addr: key.peer_addr().to_string(),
+    }
+}
+
+pub fn key_from_proto(key: Option<proto::MigrationKey>) -> Result<ShroomMigrationKey, Status> {
+    let key = key.ok_or_else(|| Status::invalid_argument("Missing migration key"))?;
+    let client_key = key
+        .client_key
+        .try_into()
+        .map_err(|_| Status::invalid_argument("Invalid client key"))?;
+    let peer_addr: IpAddr = key
+        .peer_addr
+        .parse()
+        .map_err(|_| Status::invalid_argument("Invalid peer address"))?;
+    Ok(ShroomMigrationKey::new(client_key, peer_addr))
+}
+
+pub fn ticket_to_proto(ticket: MigrationTicket) -> proto::MigrationTicket {
+    proto::MigrationTicket {
+        acc_id: ticket.acc_id,
+        char_id: ticket.char_id,
+        channel_id: ticket.channel_id as u32,
+    }
+}
+
+pub fn ticket_from_proto(
+    ticket: Option<proto::MigrationTicket>,
+) -> Result<MigrationTicket, Status> {
+    let ticket = ticket.ok_or_else(|| Status::invalid_argument("Missing migration ticket"))?;
+    Ok(MigrationTicket {
+        acc_id: ticket.acc_id,
+        char_id: ticket.char_id,
+        channel_id: channel_from_proto(ticket.channel_id)?,
+    })
+}
+
+pub fn channel_to_proto((world_id, channel_id): (WorldId, ChannelId)) -> proto::ChannelRef {
+    proto::ChannelRef {
+        world_id,
+        channel_id: channel_id as u32,
+    }
+}
+
+pub fn channel_ref_from_proto(
+    channel: Option<proto::ChannelRef>,
+) -> Result<(WorldId, ChannelId), Status> {
+    let channel = channel.ok_or_else(|| Status::invalid_argument("Missing channel"))?;
+    Ok((channel.world_id, channel_from_proto(channel.channel_id)?))
+}
+
+fn channel_from_proto(channel_id: u32) -> Result<ChannelId, Status> {
+    channel_id
+        .try_into()
+        .map_err(|_| Status::invalid_argument("Invalid channel id"))
+}
+
+/// Migrations are only tracked by the center, so they have no representation
+pub fn state_to_proto(state: OnlineState) -> Option<proto::OnlineState> {
+    let state = match state {
+        OnlineState::Login => State::Login(proto::Empty {}),
+        OnlineState::Channel(world, channel) => State::Channel(channel_to_proto((world, channel))),
+        OnlineState::CashShop => State::CashShop(proto::Empty {}),
+        OnlineState::Migrating { .. } => return None,
+    };
+    Some(proto::OnlineState { state: Some(state) })
+}
+
+pub fn state_from_proto(state: Option<proto::OnlineState>) -> Result<OnlineState, Status> {
+    match state.and_then(|state| state.state) {
+        Some(State::Login(_)) => Ok(OnlineState::Login),
+        Some(State::Channel(channel)) => {
+            let (world, channel) = channel_ref_from_proto(Some(channel))?;
+            Ok(OnlineState::Channel(world, channel))
+        }
+        Some(State::CashShop(_)) => Ok(OnlineState::CashShop),
+        None => Err(Status::invalid_argument("Missing online state")),
+    }
+}
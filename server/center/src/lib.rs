@@ -0,0 +1,10 @@
+pub mod client;
+mod convert;
+pub mod server;
+
+pub use client::RemoteCenter;
+pub use server::CenterService;
+
+pub mod proto {
+    tonic::include_proto!("center");
+}
@@ -0,0 +1,400 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use data::services::{
+    data::account::AccountId,
+    server_info::ChannelPopulation,
+    session::{
+        center::{CenterReport, CenterStatus, MigrationTicket},
+        migration::MigrationManager,
+        online::{OnlineError, OnlineLogin, OnlineRegistry, OnlineState},
+        ShroomMigrationKey,
+    },
+};
+use proto95::login::world::{ChannelId, WorldId};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    convert::{
+        channel_ref_from_proto, channel_to_proto, key_from_proto, state_from_proto,
+        ticket_from_proto, ticket_to_proto,
+    },
+    proto::{
+        self,
+        center_server::{Center, CenterServer},
+        AccountReq, LoginResult,
+    },
+};
+
+/// Channels without a report for this duration are considered down
+pub const CHANNEL_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+struct ChannelEntry {
+    server: String,
+    last_seen: Instant,
+}
+
+impl ChannelEntry {
+    fn is_alive(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_seen) < CHANNEL_TIMEOUT
+    }
+}
+
+/// Owns the migration hand-off, the world presence and the channel registry
+/// of a split deployment
+#[derive(Debug)]
+pub struct CenterService {
+    online: OnlineRegistry,
+    migration: MigrationManager<ShroomMigrationKey, MigrationTicket>,
+    migration_timeout: Duration,
+    channels: DashMap<(WorldId, ChannelId), ChannelEntry>,
+    /// Accounts of the last report per process
+    reported: DashMap<String, Vec<(AccountId, OnlineState)>>,
+}
+
+impl CenterService {
+    pub fn new(migration_timeout: Duration) -> Self {
+        Self {
+            online: OnlineRegistry::new(),
+            migration: MigrationManager::new(migration_timeout),
+            migration_timeout,
+            channels: DashMap::new(),
+            reported: DashMap::new(),
+        }
+    }
+
+    pub fn online(&self) -> &OnlineRegistry {
+        &self.online
+    }
+
+    pub fn push_migration(&self, key: ShroomMigrationKey, ticket: MigrationTicket) {
+        let now = Instant::now();
+        self.online.set_state(
+            ticket.acc_id,
+            OnlineState::Migrating {
+                key,
+                deadline: now + self.migration_timeout,
+            },
+            now,
+        );
+        if let Some(prev) = self.migration.push(key, ticket) {
+            log::warn!(
+                "Replaced pending migration of account {} for key {key:?}",
+                prev.acc_id
+            );
+        }
+    }
+
+    /// Waits for the ticket and moves the account to the given state
+    pub async fn take_migration(
+        &self,
+        key: ShroomMigrationKey,
+        state: OnlineState,
+    ) -> anyhow::Result<MigrationTicket> {
+        let ticket = self.migration.take_timeout(&key).await?;
+        self.online.set_state(ticket.acc_id, state, Instant::now());
+        Ok(ticket)
+    }
+
+    /// Refreshes the accounts and channels of the process, accounts which left the
+    /// process since the last report are logged out, unless another process took
+    /// them over meanwhile
+    pub fn report(&self, report: CenterReport, now: Instant) -> CenterStatus {
+        let prev = self
+            .reported
+            .insert(report.server.clone(), report.accounts.clone())
+            .unwrap_or_default();
+        for (acc_id, state) in prev {
+            let left = !report.accounts.iter().any(|(id, _)| *id == acc_id);
+            if left && self.online.get_state(acc_id) == Some(state) {
+                self.online.logout(acc_id);
+            }
+        }
+
+        let mut disconnect = vec![];
+        for (acc_id, state) in report.accounts {
+            // Migrations are only updated by the hand-off
+            let migrating = matches!(
+                self.online.get_state(acc_id),
+                Some(OnlineState::Migrating { .. })
+            );
+            if !migrating {
+                self.online.set_state(acc_id, state, now);
+            }
+            if self.online.keep_alive(acc_id, now).is_err() {
+                disconnect.push(acc_id);
+            }
+        }
+
+        for channel in report.channels {
+            self.channels.insert(
+                channel,
+                ChannelEntry {
+                    server: report.server.clone(),
+                    last_seen: now,
+                },
+            );
+        }
+
+        CenterStatus {
+            disconnect,
+            population: self.population(now),
+        }
+    }
+
+    /// Population of the running channels
+    pub fn population(&self, now: Instant) -> ChannelPopulation {
+        let mut population = self.online.channel_population();
+        population.retain(|channel, _| {
+            self.channels
+                .get(channel)
+                .map_or(false, |entry| entry.is_alive(now))
+        });
+        population
+    }
+
+    /// Releases stale accounts and timed out migrations, channels without a
+    /// recent report are removed from the registry
+    pub fn clean(&self, now: Instant) {
+        for (acc_id, state) in self.online.remove_stale(now) {
+            log::info!("Released stale account {acc_id}: {state:?}");
+            if let OnlineState::Migrating { key, .. } = state {
+                self.migration.remove(&key);
+            }
+        }
+        for ticket in self.migration.clean() {
+            log::info!("Dropped timed out migration of account {}", ticket.acc_id);
+        }
+        self.channels.retain(|channel, entry| {
+            let alive = entry.is_alive(now);
+            if !alive {
+                log::warn!("Channel {channel:?} of {} is down", entry.server);
+            }
+            alive
+        });
+    }
+
+    /// Serves the center until the server fails
+    pub async fn serve(self: Arc<Self>, addr: SocketAddr) -> anyhow::Result<()> {
+        tokio::spawn(clean_center(self.clone()));
+        tonic::transport::Server::builder()
+            .add_service(CenterServer::from_arc(self))
+            .serve(addr)
+            .await?;
+        Ok(())
+    }
+}
+
+async fn clean_center(center: Arc<CenterService>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(10));
+    loop {
+        interval.tick().await;
+        center.clean(Instant::now());
+    }
+}
+
+#[tonic::async_trait]
+impl Center for CenterService {
+    async fn login(
+        &self,
+        request: Request<AccountReq>,
+    ) -> Result<Response<proto::LoginReply>, Status> {
+        let acc_id = request.into_inner().acc_id;
+        let result = match self.online.login(acc_id, Instant::now()) {
+            Ok(OnlineLogin::ReplacedStale(OnlineState::Migrating { key, .. })) => {
+                self.migration.remove(&key);
+                LoginResult::LoginOk
+            }
+            Ok(_) => LoginResult::LoginOk,
+            Err(OnlineError::Closed) => LoginResult::Closed,
+            Err(_) => LoginResult::AlreadyLoggedIn,
+        };
+        Ok(Response::new(proto::LoginReply {
+            result: result as i32,
+        }))
+    }
+
+    async fn logout(&self, request: Request<AccountReq>) -> Result<Response<proto::Empty>, Status> {
+        self.online.logout(request.into_inner().acc_id);
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn request_disconnect(
+        &self,
+        request: Request<AccountReq>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        self.online.request_disconnect(request.into_inner().acc_id);
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn push_migration(
+        &self,
+        request: Request<proto::PushMigrationReq>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let req = request.into_inner();
+        let key = key_from_proto(req.key)?;
+        let ticket = ticket_from_proto(req.ticket)?;
+        CenterService::push_migration(self, key, ticket);
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn take_migration(
+        &self,
+        request: Request<proto::TakeMigrationReq>,
+    ) -> Result<Response<proto::MigrationTicket>, Status> {
+        let req = request.into_inner();
+        let key = key_from_proto(req.key)?;
+        let state = state_from_proto(req.state)?;
+        let ticket = CenterService::take_migration(self, key, state)
+            .await
+            .map_err(|err| Status::deadline_exceeded(err.to_string()))?;
+        Ok(Response::new(ticket_to_proto(ticket)))
+    }
+
+    async fn report(
+        &self,
+        request: Request<proto::ReportReq>,
+    ) -> Result<Response<proto::ReportReply>, Status> {
+        let req = request.into_inner();
+        let channels: Vec<_> = req
+            .channels
+            .into_iter()
+            .map(|channel| channel_ref_from_proto(Some(channel)))
+            .collect::<Result<_, Status>>()?;
+        let accounts: Vec<_> = req
+            .accounts
+            .into_iter()
+            .map(|presence| Ok((presence.acc_id, state_from_proto(presence.state)?)))
+            .collect::<Result<_, Status>>()?;
+
+        let status = CenterService::report(
+            self,
+            CenterReport {
+                server: req.server,
+                channels,
+                accounts,
+            },
+            Instant::now(),
+        );
+        Ok(Response::new(proto::ReportReply {
+            disconnect: status.disconnect,
+            population: status
+                .population
+                .into_iter()
+                .map(|(channel, users)| proto::ChannelLoad {
+                    channel: Some(channel_to_proto(channel)),
+                    users: users as u32,
+                })
+                .collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, Instant},
+    };
+
+    use data::services::session::{
+        center::{CenterReport, MigrationTicket},
+        online::OnlineState,
+        ShroomMigrationKey,
+    };
+
+    use super::{CenterService, CHANNEL_TIMEOUT};
+
+    fn report(server: &str, accounts: Vec<(i32, OnlineState)>) -> CenterReport {
+        CenterReport {
+            server: server.to_string(),
+            channels: vec![(0, 0)],
+            accounts,
+        }
+    }
+
+    #[test]
+    fn report_presence() {
+        let center = CenterService::new(Duration::from_secs(5));
+        let now = Instant::now();
+        center.online().login(1, now).unwrap();
+        center.online().login(2, now).unwrap();
+
+        let status = center.report(
+            report(
+                "channel",
+                vec![
+                    (1, OnlineState::Channel(0, 0)),
+                    (2, OnlineState::Channel(0, 0)),
+                ],
+            ),
+            now,
+        );
+        assert!(status.disconnect.is_empty());
+        assert_eq!(status.population.get(&(0, 0)), Some(&2));
+
+        // Disconnect requests are returned to the process of the account
+        center.online().request_disconnect(2);
+        let status = center.report(
+            report(
+                "channel",
+                vec![
+                    (1, OnlineState::Channel(0, 0)),
+                    (2, OnlineState::Channel(0, 0)),
+                ],
+            ),
+            now,
+        );
+        assert_eq!(status.disconnect, vec![2]);
+
+        // Accounts, which left the process, are logged out
+        center.report(
+            report("channel", vec![(1, OnlineState::Channel(0, 0))]),
+            now,
+        );
+        assert!(center.online().is_online(1));
+        assert!(!center.online().is_online(2));
+
+        // Channels without a report don't count
+        assert!(center.population(now + CHANNEL_TIMEOUT).is_empty());
+    }
+
+    #[tokio::test]
+    async fn migration_hand_off() {
+        let center = CenterService::new(Duration::from_secs(5));
+        let key = ShroomMigrationKey::new([1; 8], IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let ticket = MigrationTicket {
+            acc_id: 1,
+            char_id: 2,
+            channel_id: 0,
+        };
+        center.online().login(1, Instant::now()).unwrap();
+        center.report(
+            report("login", vec![(1, OnlineState::Login)]),
+            Instant::now(),
+        );
+
+        center.push_migration(key, ticket);
+        // The login process dropped the account, but It's migrating now
+        center.report(report("login", vec![]), Instant::now());
+        assert!(matches!(
+            center.online().get_state(1),
+            Some(OnlineState::Migrating { .. })
+        ));
+
+        let taken = center
+            .take_migration(key, OnlineState::Channel(0, 1))
+            .await
+            .unwrap();
+        assert_eq!(taken, ticket);
+        assert_eq!(
+            center.online().get_state(1),
+            Some(OnlineState::Channel(0, 1))
+        );
+    }
+}
@@ -4,12 +4,28 @@ pub mod shroom;
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20230601_000001_equip_upgrade_count;
+mod m20230602_000001_cash_locker;
+mod m20230603_000001_ban_details;
+mod m20230604_000001_account_created_ip;
+mod m20230605_000001_character_world;
+mod m20230606_000001_char_slot;
+mod m20230607_000001_character_deleted_at;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::<m20220101_000001_create_table::Migration>::default()]
+        vec![
+            Box::<m20220101_000001_create_table::Migration>::default(),
+            Box::new(m20230601_000001_equip_upgrade_count::Migration),
+            Box::<m20230602_000001_cash_locker::Migration>::default(),
+            Box::new(m20230603_000001_ban_details::Migration),
+            Box::new(m20230604_000001_account_created_ip::Migration),
+            Box::new(m20230605_000001_character_world::Migration),
+            Box::<m20230606_000001_char_slot::Migration>::default(),
+            Box::new(m20230607_000001_character_deleted_at::Migration),
+        ]
     }
 }
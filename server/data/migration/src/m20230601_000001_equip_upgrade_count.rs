@@ -0,0 +1,37 @@
+use sea_orm_migration::prelude::*;
+
+use crate::helper::shroom_size;
+
+#[derive(Iden)]
+enum EquipItem {
+    Table,
+    UpgradeCount,
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EquipItem::Table)
+                    .add_column(&mut shroom_size(EquipItem::UpgradeCount))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EquipItem::Table)
+                    .drop_column(EquipItem::UpgradeCount)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,81 @@
+use sea_orm_migration::prelude::*;
+
+use crate::helper::*;
+
+#[derive(Iden)]
+enum Account {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Character {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum CashItem {
+    Table,
+    Id,
+    AccId,
+    CharId,
+    CashId,
+    ItemId,
+    CommoditySn,
+    Quantity,
+    ExpiresAt,
+    GiftFrom,
+    GiftMessage,
+    CreatedAt,
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration {
+    cash_item_table: ShroomTbl,
+}
+
+impl Default for Migration {
+    fn default() -> Self {
+        // Only used to reference the existing tables
+        let acc_table = ShroomTbl::new(Account::Table, Account::Id, [], []);
+        let char_table = ShroomTbl::new(Character::Table, Character::Id, [], []);
+
+        let cash_item_table = ShroomTbl::new(
+            CashItem::Table,
+            CashItem::Id,
+            [
+                ColumnDef::new(CashItem::CashId)
+                    .big_integer()
+                    .not_null()
+                    .unique_key()
+                    .to_owned(),
+                shroom_id(CashItem::ItemId),
+                shroom_id(CashItem::CommoditySn),
+                shroom_size(CashItem::Quantity),
+                date_time(CashItem::ExpiresAt),
+                shroom_name(CashItem::GiftFrom).null().to_owned(),
+                shroom_str(CashItem::GiftMessage),
+                created_at(CashItem::CreatedAt),
+            ],
+            [
+                Ref::ownership(CashItem::AccId, &acc_table),
+                Ref::opt(CashItem::CharId, &char_table),
+            ],
+        );
+
+        Self { cash_item_table }
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        self.cash_item_table.create_table(manager).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        self.cash_item_table.drop_fk(manager).await?;
+        self.cash_item_table.drop_table(manager).await
+    }
+}
@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+use crate::helper::*;
+
+#[derive(Iden)]
+enum Ban {
+    Table,
+    ReasonCode,
+    Ip,
+    Hwid,
+    IssuedBy,
+    CreatedAt,
+    RevokedAt,
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+fn ban_columns() -> [ColumnDef; 6] {
+    [
+        shroom_int(Ban::ReasonCode),
+        shroom_str(Ban::Ip),
+        shroom_str(Ban::Hwid),
+        shroom_str(Ban::IssuedBy),
+        created_at(Ban::CreatedAt),
+        date_time(Ban::RevokedAt),
+    ]
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Sqlite only supports a single alteration per statement
+        for mut col in ban_columns() {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Ban::Table)
+                        .add_column(&mut col)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for col in [
+            Ban::ReasonCode,
+            Ban::Ip,
+            Ban::Hwid,
+            Ban::IssuedBy,
+            Ban::CreatedAt,
+            Ban::RevokedAt,
+        ] {
+            manager
+                .alter_table(Table::alter().table(Ban::Table).drop_column(col).to_owned())
+                .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+use crate::helper::shroom_int;
+
+#[derive(Iden)]
+enum Character {
+    Table,
+    WorldId,
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Existing characters belong to the first world
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Character::Table)
+                    .add_column(&mut shroom_int(Character::WorldId))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Character::Table)
+                    .drop_column(Character::WorldId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,62 @@
+use sea_orm_migration::prelude::*;
+
+use crate::helper::*;
+
+#[derive(Iden)]
+enum Account {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum CharSlot {
+    Table,
+    Id,
+    AccId,
+    WorldId,
+    Slots,
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration {
+    char_slot_table: ShroomTbl,
+}
+
+impl Default for Migration {
+    fn default() -> Self {
+        // Only used to reference the existing tables
+        let acc_table = ShroomTbl::new(Account::Table, Account::Id, [], []);
+
+        let char_slot_table = ShroomTbl::new(
+            CharSlot::Table,
+            CharSlot::Id,
+            [shroom_int(CharSlot::WorldId), shroom_int(CharSlot::Slots)],
+            [Ref::ownership(CharSlot::AccId, &acc_table)],
+        );
+
+        Self { char_slot_table }
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        self.char_slot_table.create_table(manager).await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-char_slot-acc_id-world_id")
+                    .table(CharSlot::Table)
+                    .col(CharSlot::AccId)
+                    .col(CharSlot::WorldId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        self.char_slot_table.drop_fk(manager).await?;
+        self.char_slot_table.drop_table(manager).await
+    }
+}
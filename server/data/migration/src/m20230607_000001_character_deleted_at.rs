@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+use crate::helper::date_time;
+
+#[derive(Iden)]
+enum Character {
+    Table,
+    DeletedAt,
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Soft deleted characters are purged after the grace period
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Character::Table)
+                    .add_column(&mut date_time(Character::DeletedAt))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Character::Table)
+                    .drop_column(Character::DeletedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -25,12 +25,17 @@ pub struct Model {
     pub nx_prepaid: i32,
     pub shroom_points: i32,
     pub tester: bool,
+    pub created_ip: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::ban::Entity")]
     Ban,
+    #[sea_orm(has_many = "super::cash_item::Entity")]
+    CashItem,
+    #[sea_orm(has_many = "super::char_slot::Entity")]
+    CharSlot,
     #[sea_orm(has_many = "super::character::Entity")]
     Character,
 }
@@ -41,6 +46,18 @@ impl Related<super::ban::Entity> for Entity {
     }
 }
 
+impl Related<super::cash_item::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CashItem.def()
+    }
+}
+
+impl Related<super::char_slot::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CharSlot.def()
+    }
+}
+
 impl Related<super::character::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Character.def()
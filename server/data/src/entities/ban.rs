@@ -10,6 +10,12 @@ pub struct Model {
     pub ban_reason: Option<String>,
     pub ban_time: Option<DateTime>,
     pub acc_id: i32,
+    pub reason_code: i32,
+    pub ip: Option<String>,
+    pub hwid: Option<String>,
+    pub issued_by: Option<String>,
+    pub created_at: DateTime,
+    pub revoked_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
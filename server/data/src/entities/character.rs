@@ -43,6 +43,8 @@ pub struct Model {
     pub hair: i32,
     pub spawn_point: i32,
     pub acc_id: i32,
+    pub world_id: i32,
+    pub deleted_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -55,6 +57,8 @@ pub enum Relation {
         on_delete = "NoAction"
     )]
     Account,
+    #[sea_orm(has_many = "super::cash_item::Entity")]
+    CashItem,
     #[sea_orm(has_many = "super::inventory_slot::Entity")]
     InventorySlot,
     #[sea_orm(has_many = "super::skill::Entity")]
@@ -67,6 +71,12 @@ impl Related<super::account::Entity> for Entity {
     }
 }
 
+impl Related<super::cash_item::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CashItem.def()
+    }
+}
+
 impl Related<super::inventory_slot::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::InventorySlot.def()
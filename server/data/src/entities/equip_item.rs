@@ -32,6 +32,7 @@ pub struct Model {
     pub craft: i32,
     pub speed: i32,
     pub jump: i32,
+    pub upgrade_count: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
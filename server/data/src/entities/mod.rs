@@ -4,6 +4,8 @@ pub mod prelude;
 
 pub mod account;
 pub mod ban;
+pub mod cash_item;
+pub mod char_slot;
 pub mod character;
 pub mod equip_item;
 pub mod inventory_slot;
@@ -2,6 +2,8 @@
 
 pub use super::account::Entity as Account;
 pub use super::ban::Entity as Ban;
+pub use super::cash_item::Entity as CashItem;
+pub use super::char_slot::Entity as CharSlot;
 pub use super::character::Entity as Character;
 pub use super::equip_item::Entity as EquipItem;
 pub use super::inventory_slot::Entity as InventorySlot;
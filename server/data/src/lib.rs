@@ -5,11 +5,14 @@ pub mod services;
 pub mod util;
 
 use chrono::{NaiveDateTime, Utc};
-use entities::{account, ban, character, equip_item, inventory_slot, item_stack, pet_item, skill};
+use entities::{
+    account, ban, cash_item, char_slot, character, equip_item, inventory_slot, item_stack,
+    pet_item, skill,
+};
 
 use sea_orm::{
     ActiveValue, ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbBackend, DbErr,
-    Schema,
+    EntityTrait, Schema,
 };
 pub const SQL_OPT_MEMORY: &str = "sqlite::memory:";
 pub const SQL_OPT_TEST_FILE: &str = "sqlite://test.db?mode=rwc";
@@ -21,6 +24,13 @@ pub fn created_at(db: &DatabaseConnection) -> ActiveValue<NaiveDateTime> {
     }
 }
 
+async fn create_table<E: EntityTrait>(db: &DatabaseConnection, entity: E) -> Result<(), DbErr> {
+    let mut stmt = Schema::new(DbBackend::Sqlite).create_table_from_entity(entity);
+    db.execute(db.get_database_backend().build(stmt.if_not_exists()))
+        .await?;
+    Ok(())
+}
+
 pub async fn gen_psql(opt: &str) -> Result<DatabaseConnection, DbErr> {
     let mut opt = ConnectOptions::new(opt.to_owned());
     let log_level = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
@@ -31,6 +41,8 @@ pub async fn gen_psql(opt: &str) -> Result<DatabaseConnection, DbErr> {
     Ok(db)
 }
 
+/// Connects to the sqlite database and creates the missing tables,
+/// so a database file can be shared by the processes of a split deployment
 pub async fn gen_sqlite(opt: &str) -> Result<DatabaseConnection, DbErr> {
     let mut opt = ConnectOptions::new(opt.to_owned());
     let log_level = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
@@ -39,52 +51,16 @@ pub async fn gen_sqlite(opt: &str) -> Result<DatabaseConnection, DbErr> {
     }
     let db = Database::connect(opt).await?;
 
-    let schema = Schema::new(DbBackend::Sqlite);
-
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(account::Entity)),
-    )
-    .await?;
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(character::Entity)),
-    )
-    .await?;
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(skill::Entity)),
-    )
-    .await?;
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(ban::Entity)),
-    )
-    .await?;
-
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(equip_item::Entity)),
-    )
-    .await?;
-
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(pet_item::Entity)),
-    )
-    .await?;
-
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(item_stack::Entity)),
-    )
-    .await?;
-
-    db.execute(
-        db.get_database_backend()
-            .build(&schema.create_table_from_entity(inventory_slot::Entity)),
-    )
-    .await?;
+    create_table(&db, account::Entity).await?;
+    create_table(&db, character::Entity).await?;
+    create_table(&db, skill::Entity).await?;
+    create_table(&db, ban::Entity).await?;
+    create_table(&db, equip_item::Entity).await?;
+    create_table(&db, pet_item::Entity).await?;
+    create_table(&db, item_stack::Entity).await?;
+    create_table(&db, inventory_slot::Entity).await?;
+    create_table(&db, cash_item::Entity).await?;
+    create_table(&db, char_slot::Entity).await?;
 
     Ok(db)
 }
@@ -97,4 +73,14 @@ mod tests {
     async fn sqlite_build() {
         gen_sqlite(SQL_OPT_MEMORY).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn sqlite_reopen() {
+        let path = std::env::temp_dir().join(format!("shroom-{}.db", uuid::Uuid::new_v4()));
+        let opt = format!("sqlite://{}?mode=rwc", path.display());
+        drop(gen_sqlite(&opt).await.unwrap());
+        // The tables exist already
+        drop(gen_sqlite(&opt).await.unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
 }
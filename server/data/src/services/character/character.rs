@@ -1,15 +1,29 @@
 use std::ops::{Add, Div};
 
-use proto95::shared::char::{CharStatFlags, CharStatPartial};
+use proto95::{
+    id::{job_id::JobId, MapId},
+    shared::char::{CharStatFlags, CharStatPartial},
+};
+use rand::Rng;
+use sea_orm::ActiveValue::{Set, Unchanged};
 use shroom_net::packet::CondOption;
 
-use crate::{entities::character::Model, services::helper::intentory::inv::InventorySet};
+use crate::{
+    entities::character::{ActiveModel, Model},
+    services::helper::intentory::inv::InventorySet,
+};
+
+use super::stats::{ApStat, HpMpGain, MAX_AP_STAT, MAX_HP_MP, MIN_AP_STAT, MIN_HP, MIN_MP};
 
 #[derive(Debug, Clone)]
 pub struct Character {
     pub model: Model,
     pub inventory: InventorySet,
     char_stat_flags: CharStatFlags,
+    /// Stats changed since the last save
+    dirty_flags: CharStatFlags,
+    /// Map or spawn point changed since the last save
+    pos_dirty: bool,
 }
 
 impl Character {
@@ -18,9 +32,121 @@ impl Character {
             model,
             inventory,
             char_stat_flags: CharStatFlags::empty(),
+            dirty_flags: CharStatFlags::empty(),
+            pos_dirty: false,
         }
     }
 
+    /// Marks the stat as changed for the next stat update and the next save
+    fn set_flag(&mut self, flag: CharStatFlags) {
+        self.char_stat_flags.insert(flag);
+        self.dirty_flags.insert(flag);
+    }
+
+    /// Moves the character to the spawn point of the map
+    pub fn set_pos(&mut self, map_id: MapId, spawn_point: u8) {
+        self.model.map_id = map_id.0 as i32;
+        self.model.spawn_point = spawn_point as i32;
+        self.pos_dirty = true;
+    }
+
+    /// Revives a dead character with 1 HP and MP at the spawn point of the return map
+    pub fn revive(&mut self, map_id: MapId, spawn_point: u8) {
+        self.model.hp = 1;
+        self.model.mp = 1;
+        self.set_flag(CharStatFlags::Hp);
+        self.set_flag(CharStatFlags::Mp);
+        self.set_pos(map_id, spawn_point);
+    }
+
+    /// Whether the character has unsaved changes
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty_flags.is_empty() || self.pos_dirty
+    }
+
+    /// Builds an update of the changed columns since the last save,
+    /// `None` if nothing changed
+    pub fn get_dirty_model(&self) -> Option<ActiveModel> {
+        if !self.is_dirty() {
+            return None;
+        }
+
+        let m = &self.model;
+        let dirty = |flag| self.dirty_flags.contains(flag);
+        let mut model = ActiveModel {
+            id: Unchanged(m.id),
+            ..Default::default()
+        };
+
+        if dirty(CharStatFlags::Skin) {
+            model.skin = Set(m.skin);
+        }
+        if dirty(CharStatFlags::Face) {
+            model.face = Set(m.face);
+        }
+        if dirty(CharStatFlags::Hair) {
+            model.hair = Set(m.hair);
+        }
+        if dirty(CharStatFlags::Level) {
+            model.level = Set(m.level);
+        }
+        if dirty(CharStatFlags::Job) {
+            model.job = Set(m.job);
+        }
+        if dirty(CharStatFlags::Str) {
+            model.str = Set(m.str);
+        }
+        if dirty(CharStatFlags::Dex) {
+            model.dex = Set(m.dex);
+        }
+        if dirty(CharStatFlags::Int) {
+            model.int = Set(m.int);
+        }
+        if dirty(CharStatFlags::Luk) {
+            model.luk = Set(m.luk);
+        }
+        if dirty(CharStatFlags::Hp) {
+            model.hp = Set(m.hp);
+        }
+        if dirty(CharStatFlags::MaxHp) {
+            model.max_hp = Set(m.max_hp);
+        }
+        if dirty(CharStatFlags::Mp) {
+            model.mp = Set(m.mp);
+        }
+        if dirty(CharStatFlags::MaxMp) {
+            model.max_mp = Set(m.max_mp);
+        }
+        if dirty(CharStatFlags::Ap) {
+            model.ap = Set(m.ap);
+        }
+        if dirty(CharStatFlags::Sp) {
+            model.sp = Set(m.sp);
+            model.skill_points = Set(m.skill_points.clone());
+        }
+        if dirty(CharStatFlags::Exp) {
+            model.exp = Set(m.exp);
+        }
+        if dirty(CharStatFlags::Pop) {
+            model.fame = Set(m.fame);
+        }
+        if dirty(CharStatFlags::Money) {
+            model.mesos = Set(m.mesos);
+        }
+        if self.pos_dirty {
+            model.map_id = Set(m.map_id);
+            model.spawn_point = Set(m.spawn_point);
+        }
+
+        Some(model)
+    }
+
+    /// Resets the dirty state after the character was saved
+    pub fn clear_dirty(&mut self) {
+        self.dirty_flags = CharStatFlags::empty();
+        self.pos_dirty = false;
+    }
+
     pub fn decrease_exp(&mut self, town: bool) {
         if self.model.exp <= 0 || self.model.exp >= 200 {
             return;
@@ -37,16 +163,17 @@ impl Character {
         // set exp to the max of 0 or the current exp minus the next level xp times reduction rate
         // TODO: get next level xp
         self.model.exp = 0.max(self.model.exp - (self.model.exp as f64 * reduction_rate) as i32);
+        self.set_flag(CharStatFlags::Exp);
     }
 
     pub fn update_hp(&mut self, hp: i32) {
         self.model.hp = 0.max(self.model.hp.add(hp)).min(self.model.max_hp);
-        self.char_stat_flags.insert(CharStatFlags::Hp);
+        self.set_flag(CharStatFlags::Hp);
     }
 
     pub fn update_mp(&mut self, mp: i32) {
         self.model.mp = 0.max(self.model.mp.add(mp)).min(self.model.max_mp);
-        self.char_stat_flags.insert(CharStatFlags::Mp);
+        self.set_flag(CharStatFlags::Mp);
     }
 
     pub fn update_mesos(&mut self, mesos: i32) -> bool {
@@ -54,26 +181,353 @@ impl Character {
             return false;
         }
         self.model.mesos = self.model.mesos.saturating_add(mesos);
-        self.char_stat_flags.insert(CharStatFlags::Money);
+        self.set_flag(CharStatFlags::Money);
         true
     }
 
+    pub fn job(&self) -> anyhow::Result<JobId> {
+        Ok(JobId::try_from(self.model.job as u16)?)
+    }
+
+    fn ap_stat(&self, stat: ApStat) -> i32 {
+        match stat {
+            ApStat::Str => self.model.str,
+            ApStat::Dex => self.model.dex,
+            ApStat::Int => self.model.int,
+            ApStat::Luk => self.model.luk,
+            ApStat::MaxHp => self.model.max_hp,
+            ApStat::MaxMp => self.model.max_mp,
+        }
+    }
+
+    fn ap_stat_mut(&mut self, stat: ApStat) -> &mut i32 {
+        match stat {
+            ApStat::Str => &mut self.model.str,
+            ApStat::Dex => &mut self.model.dex,
+            ApStat::Int => &mut self.model.int,
+            ApStat::Luk => &mut self.model.luk,
+            ApStat::MaxHp => &mut self.model.max_hp,
+            ApStat::MaxMp => &mut self.model.max_mp,
+        }
+    }
+
+    fn ap_stat_cap(stat: ApStat) -> i32 {
+        match stat {
+            ApStat::MaxHp | ApStat::MaxMp => MAX_HP_MP,
+            _ => MAX_AP_STAT,
+        }
+    }
+
+    /// Distributes the ability points, nothing is changed If the character
+    /// has not enough ap or a stat would exceed It's cap
+    pub fn add_ap(&mut self, mut rng: impl Rng, stats: &[(ApStat, u32)]) -> anyhow::Result<()> {
+        // The amounts are sent by the client, so the total is checked before anything
+        // is summed per stat, which also bounds the rolls for HP and MP
+        let ap = self.model.ap.max(0) as u32;
+        let mut spent = [0u32; ApStat::ALL.len()];
+        let mut total = 0u32;
+        for &(stat, n) in stats {
+            total = total
+                .checked_add(n)
+                .filter(|&total| total <= ap)
+                .ok_or_else(|| anyhow::format_err!("Not enough ap: {}", self.model.ap))?;
+            spent[stat as usize] += n;
+        }
+        if total == 0 {
+            anyhow::bail!("No ap to distribute");
+        }
+
+        for stat in ApStat::ALL {
+            let n = spent[stat as usize] as i64;
+            let cur = self.ap_stat(stat) as i64;
+            let exceeds = match stat {
+                ApStat::MaxHp | ApStat::MaxMp => n > 0 && cur >= MAX_HP_MP as i64,
+                _ => cur + n > MAX_AP_STAT as i64,
+            };
+            if exceeds {
+                anyhow::bail!("Stat {stat:?} would exceed the cap");
+            }
+        }
+
+        let gain = HpMpGain::for_job(self.job()?);
+        for stat in ApStat::ALL {
+            let n = spent[stat as usize];
+            if n == 0 {
+                continue;
+            }
+
+            let inc: i32 = match stat {
+                ApStat::MaxHp => (0..n).map(|_| rng.gen_range(gain.hp.clone())).sum(),
+                ApStat::MaxMp => (0..n).map(|_| rng.gen_range(gain.mp.clone())).sum(),
+                _ => n as i32,
+            };
+            let val = self.ap_stat_mut(stat);
+            *val = val.saturating_add(inc).min(Self::ap_stat_cap(stat));
+            self.set_flag(stat.flag());
+        }
+
+        self.model.ap -= total as i32;
+        self.set_flag(CharStatFlags::Ap);
+        Ok(())
+    }
+
+    /// Moves a single ability point from one stat to another,
+    /// HP and MP lose the maximum gain per point to avoid gaining stats by resetting.
+    /// Nothing is changed If the point can't be moved
+    pub fn reset_ap(&mut self, rng: impl Rng, from: ApStat, to: ApStat) -> anyhow::Result<()> {
+        let prev = (self.model.clone(), self.char_stat_flags, self.dirty_flags);
+        let res = self.move_ap(rng, from, to);
+        if res.is_err() {
+            (self.model, self.char_stat_flags, self.dirty_flags) = prev;
+        }
+        res
+    }
+
+    fn move_ap(&mut self, mut rng: impl Rng, from: ApStat, to: ApStat) -> anyhow::Result<()> {
+        if from == to {
+            anyhow::bail!("Can't reset ap to the same stat: {from:?}");
+        }
+        if self.ap_stat(to) >= Self::ap_stat_cap(to) {
+            anyhow::bail!("Stat {to:?} would exceed the cap");
+        }
+
+        let gain = HpMpGain::for_job(self.job()?);
+        let (dec, min) = match from {
+            ApStat::MaxHp => (*gain.hp.end(), MIN_HP),
+            ApStat::MaxMp => (*gain.mp.end(), MIN_MP),
+            _ => (1, MIN_AP_STAT),
+        };
+        let cur = self.ap_stat(from);
+        if cur - dec < min {
+            anyhow::bail!("Stat {from:?} is too low to be reset");
+        }
+
+        *self.ap_stat_mut(from) = cur - dec;
+        self.set_flag(from.flag());
+        match from {
+            ApStat::MaxHp if self.model.hp > self.model.max_hp => self.update_hp(0),
+            ApStat::MaxMp if self.model.mp > self.model.max_mp => self.update_mp(0),
+            _ => (),
+        }
+
+        self.model.ap += 1;
+        self.add_ap(&mut rng, &[(to, 1)])
+    }
+
+    fn take_flag(&mut self, flag: CharStatFlags) -> bool {
+        let set = self.char_stat_flags.contains(flag);
+        self.char_stat_flags.remove(flag);
+        set
+    }
+
     pub fn get_char_partial(&mut self) -> CharStatPartial {
         let mut stats = CharStatPartial::default();
 
-        if self.char_stat_flags.contains(CharStatFlags::Hp) {
+        if self.take_flag(CharStatFlags::Str) {
+            stats.str = CondOption(Some(self.model.str as u16));
+        }
+        if self.take_flag(CharStatFlags::Dex) {
+            stats.dex = CondOption(Some(self.model.dex as u16));
+        }
+        if self.take_flag(CharStatFlags::Int) {
+            stats.int = CondOption(Some(self.model.int as u16));
+        }
+        if self.take_flag(CharStatFlags::Luk) {
+            stats.luk = CondOption(Some(self.model.luk as u16));
+        }
+        if self.take_flag(CharStatFlags::Hp) {
             stats.hp = CondOption(Some(self.model.hp as u32));
-            self.char_stat_flags.remove(CharStatFlags::Hp);
         }
-        if self.char_stat_flags.contains(CharStatFlags::Mp) {
+        if self.take_flag(CharStatFlags::MaxHp) {
+            stats.max_hp = CondOption(Some(self.model.max_hp as u32));
+        }
+        if self.take_flag(CharStatFlags::Mp) {
             stats.mp = CondOption(Some(self.model.mp as u32));
-            self.char_stat_flags.remove(CharStatFlags::Mp);
         }
-        if self.char_stat_flags.contains(CharStatFlags::Money) {
+        if self.take_flag(CharStatFlags::MaxMp) {
+            stats.max_mp = CondOption(Some(self.model.max_mp as u32));
+        }
+        if self.take_flag(CharStatFlags::Ap) {
+            stats.ap = CondOption(Some(self.model.ap as u16));
+        }
+        if self.take_flag(CharStatFlags::Exp) {
+            stats.exp = CondOption(Some(self.model.exp as u32));
+        }
+        if self.take_flag(CharStatFlags::Money) {
             stats.money = CondOption(Some(self.model.mesos as u32));
-            self.char_stat_flags.remove(CharStatFlags::Money);
         }
 
         stats
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proto95::{id::MapId, shared::char::CharStatFlags};
+    use sea_orm::ActiveValue::{NotSet, Set, Unchanged};
+
+    use crate::{
+        entities::{character::Model, sea_orm_active_enums::GenderTy},
+        services::{
+            character::stats::{ApStat, MAX_AP_STAT},
+            helper::intentory::inv::InventorySet,
+        },
+    };
+
+    use super::Character;
+
+    fn get_char(ap: i32) -> Character {
+        let model = Model {
+            id: 1,
+            name: "Test".to_string(),
+            created_at: chrono::Utc::now().naive_utc(),
+            last_login_at: None,
+            gender: GenderTy::Male,
+            skill_points: vec![0; 10],
+            play_time: 0,
+            level: 10,
+            exp: 0,
+            gacha_exp: 0,
+            str: 4,
+            dex: 4,
+            luk: 4,
+            int: 4,
+            hp: 50,
+            max_hp: 50,
+            mp: 5,
+            max_mp: 5,
+            mesos: 0,
+            map_id: 0,
+            buddy_capacity: 20,
+            fame: 0,
+            ap,
+            sp: 0,
+            job: 100,
+            equip_slots: 24,
+            use_slots: 24,
+            setup_slots: 24,
+            etc_slots: 24,
+            cash_slots: 24,
+            storage_slots: 24,
+            face: 0,
+            skin: 0,
+            hair: 0,
+            spawn_point: 0,
+            acc_id: 1,
+            world_id: 0,
+            deleted_at: None,
+        };
+        Character::new(model, InventorySet::with_default_slots())
+    }
+
+    #[test]
+    fn add_ap() {
+        let mut char = get_char(5);
+        char.add_ap(rand::thread_rng(), &[(ApStat::Str, 3), (ApStat::MaxHp, 1)])
+            .unwrap();
+        assert_eq!(char.model.str, 7);
+        assert_eq!(char.model.ap, 1);
+        // Warriors gain 20-24 HP per ap
+        assert!((70..=74).contains(&char.model.max_hp));
+
+        let partial = char.get_char_partial();
+        assert_eq!(partial.str.0, Some(7));
+        assert_eq!(partial.ap.0, Some(1));
+        assert!(partial.dex.0.is_none());
+        assert!(partial.hp.0.is_none());
+    }
+
+    #[test]
+    fn add_ap_exceeds() {
+        let mut char = get_char(2);
+        assert!(char
+            .add_ap(rand::thread_rng(), &[(ApStat::Str, 2), (ApStat::Dex, 1)])
+            .is_err());
+        assert_eq!(char.model.ap, 2);
+        assert_eq!(char.model.str, 4);
+
+        char.model.luk = MAX_AP_STAT;
+        assert!(char
+            .add_ap(rand::thread_rng(), &[(ApStat::Luk, 1)])
+            .is_err());
+        assert!(char.add_ap(rand::thread_rng(), &[]).is_err());
+    }
+
+    #[test]
+    fn add_ap_overflow() {
+        let mut char = get_char(5);
+        // The sum wraps around to 1 without checked arithmetic
+        assert!(char
+            .add_ap(
+                rand::thread_rng(),
+                &[(ApStat::Str, u32::MAX), (ApStat::Dex, 2)]
+            )
+            .is_err());
+        assert!(char
+            .add_ap(rand::thread_rng(), &[(ApStat::MaxHp, u32::MAX)])
+            .is_err());
+        assert_eq!(char.model.ap, 5);
+        assert_eq!(char.model.str, 4);
+        assert_eq!(char.model.max_hp, 50);
+    }
+
+    #[test]
+    fn reset_ap() {
+        let mut char = get_char(0);
+        assert!(char
+            .reset_ap(rand::thread_rng(), ApStat::Str, ApStat::Dex)
+            .is_err());
+
+        char.model.str = 10;
+        char.reset_ap(rand::thread_rng(), ApStat::Str, ApStat::Dex)
+            .unwrap();
+        assert_eq!(char.model.str, 9);
+        assert_eq!(char.model.dex, 5);
+        assert_eq!(char.model.ap, 0);
+        assert!(!char.char_stat_flags.contains(CharStatFlags::Int));
+
+        // A failed reset changes nothing
+        char.model.luk = MAX_AP_STAT;
+        assert!(char
+            .reset_ap(rand::thread_rng(), ApStat::Str, ApStat::Luk)
+            .is_err());
+        assert_eq!(char.model.str, 9);
+        assert_eq!(char.model.ap, 0);
+    }
+
+    #[test]
+    fn dirty_model() {
+        let mut char = get_char(1);
+        assert!(char.get_dirty_model().is_none());
+
+        char.add_ap(rand::thread_rng(), &[(ApStat::Str, 1)])
+            .unwrap();
+        char.set_pos(MapId(100000000), 2);
+        // Sending the stat update must not reset the dirty state
+        char.get_char_partial();
+
+        let model = char.get_dirty_model().unwrap();
+        assert_eq!(model.id, Unchanged(1));
+        assert_eq!(model.str, Set(5));
+        assert_eq!(model.ap, Set(0));
+        assert_eq!(model.map_id, Set(100000000));
+        assert_eq!(model.spawn_point, Set(2));
+        assert_eq!(model.dex, NotSet);
+        assert_eq!(model.mesos, NotSet);
+
+        char.clear_dirty();
+        assert!(char.get_dirty_model().is_none());
+    }
+
+    #[test]
+    fn decrease_exp() {
+        let mut char = get_char(0);
+        char.model.exp = 100;
+        char.decrease_exp(true);
+        assert_eq!(char.model.exp, 99);
+
+        let model = char.get_dirty_model().unwrap();
+        assert_eq!(model.exp, Set(99));
+        assert_eq!(char.get_char_partial().exp.0, Some(99));
+    }
+}
@@ -1,3 +1,4 @@
 mod character;
+pub mod stats;
 
-pub use self::character::*;
\ No newline at end of file
+pub use self::character::*;
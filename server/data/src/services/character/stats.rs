@@ -0,0 +1,83 @@
+use std::ops::RangeInclusive;
+
+use proto95::{
+    id::job_id::{JobClass, JobId},
+    shared::char::CharStatFlags,
+};
+
+pub const MAX_AP_STAT: i32 = 999;
+pub const MIN_AP_STAT: i32 = 4;
+pub const MAX_HP_MP: i32 = 30_000;
+pub const MIN_HP: i32 = 50;
+pub const MIN_MP: i32 = 5;
+
+/// Stats which can be raised with ability points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApStat {
+    Str,
+    Dex,
+    Int,
+    Luk,
+    MaxHp,
+    MaxMp,
+}
+
+impl ApStat {
+    pub const ALL: [ApStat; 6] = [
+        ApStat::Str,
+        ApStat::Dex,
+        ApStat::Int,
+        ApStat::Luk,
+        ApStat::MaxHp,
+        ApStat::MaxMp,
+    ];
+
+    pub fn flag(&self) -> CharStatFlags {
+        match self {
+            ApStat::Str => CharStatFlags::Str,
+            ApStat::Dex => CharStatFlags::Dex,
+            ApStat::Int => CharStatFlags::Int,
+            ApStat::Luk => CharStatFlags::Luk,
+            ApStat::MaxHp => CharStatFlags::MaxHp,
+            ApStat::MaxMp => CharStatFlags::MaxMp,
+        }
+    }
+}
+
+impl TryFrom<CharStatFlags> for ApStat {
+    type Error = anyhow::Error;
+
+    fn try_from(flag: CharStatFlags) -> Result<Self, Self::Error> {
+        ApStat::ALL
+            .into_iter()
+            .find(|stat| stat.flag().bits() == flag.bits())
+            .ok_or_else(|| anyhow::format_err!("Invalid ap stat: {flag:?}"))
+    }
+}
+
+/// HP and MP gained per ability point
+#[derive(Debug, Clone)]
+pub struct HpMpGain {
+    pub hp: RangeInclusive<i32>,
+    pub mp: RangeInclusive<i32>,
+}
+
+impl HpMpGain {
+    pub fn for_job(job: JobId) -> Self {
+        let (hp, mp) = match job.job_class() {
+            JobClass::Warrior | JobClass::DawnWarrior | JobClass::Aran => (20..=24, 2..=4),
+            JobClass::Magician | JobClass::BlazeWizard | JobClass::Evan | JobClass::BattleMage => {
+                (6..=10, 18..=20)
+            }
+            JobClass::Bowman
+            | JobClass::WindArcher
+            | JobClass::WildHunter
+            | JobClass::Thief
+            | JobClass::NightWalker => (16..=20, 10..=12),
+            JobClass::Pirate | JobClass::ThunderBreaker | JobClass::Mechanic => (18..=22, 14..=16),
+            _ => (8..=12, 6..=8),
+        };
+
+        Self { hp, mp }
+    }
+}
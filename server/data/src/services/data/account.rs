@@ -1,18 +1,24 @@
 use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use constant_time_eq::constant_time_eq;
+use dashmap::DashMap;
 use rand::{thread_rng, RngCore};
 use sea_orm::{ActiveModelTrait, DbErr, TryIntoModel};
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, Set};
 use thiserror::Error;
 
 use crate::created_at;
 use crate::entities::account::{ActiveModel, Column, Entity, Model};
-use crate::entities::ban;
 use crate::entities::sea_orm_active_enums::GenderTy;
 
+use super::ban::{Ban, BanService};
+
 pub type AccountId = i32;
-pub type HardwareInfo = u32;
+/// Hex encoded machine id of the client
+pub type HardwareId = str;
 
 #[derive(Debug)]
 #[repr(u8)]
@@ -34,23 +40,70 @@ pub enum AccountServiceError {
     PasswordWrongSize,
     #[error("Password is only supposed to contain ASCII characters")]
     PasswordWrongChar,
-    #[error("Password size is wrong")]
+    #[error("Username size is wrong")]
     UsernameWrongSize,
-    #[error("Password is only supposed to contain ASCII characters")]
+    #[error("Username is only supposed to contain ASCII letters and digits")]
     UsernameWrongChar,
+    #[error("Too many accounts were created from this IP")]
+    IpAccountLimit,
     #[error("Account is banned")]
-    AccountIsBanned,
+    AccountIsBanned(Box<Ban>),
+    #[error("IP or hardware id is banned")]
+    HostIsBanned(Box<Ban>),
+    #[error("Too many invalid PIC attempts")]
+    PicLocked,
     #[error("database")]
     Disconnect(#[from] DbErr),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
 }
 
 //MAybe use passwords crate
 
 pub type AccResult<T> = std::result::Result<T, AccountServiceError>;
 
+/// Invalid PIC attempts, before the PIC gets locked
+pub const MAX_PIC_ATTEMPTS: u32 = 5;
+pub const PIC_LOCK_DURATION: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Default)]
+struct PicAttempts {
+    failed: u32,
+    locked_until: Option<Instant>,
+}
+
+pub const USERNAME_LEN: RangeInclusive<usize> = 4..=12;
+pub const PASSWORD_LEN: RangeInclusive<usize> = 4..=12;
+
+pub fn check_username(username: &str) -> AccResult<()> {
+    if !USERNAME_LEN.contains(&username.len()) {
+        return Err(AccountServiceError::UsernameWrongSize);
+    }
+    if !username.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(AccountServiceError::UsernameWrongChar);
+    }
+    Ok(())
+}
+
+pub fn check_password(password: &str) -> AccResult<()> {
+    if !PASSWORD_LEN.contains(&password.len()) {
+        return Err(AccountServiceError::PasswordWrongSize);
+    }
+    if !password.chars().all(|c| c.is_ascii_graphic()) {
+        return Err(AccountServiceError::PasswordWrongChar);
+    }
+    Ok(())
+}
+
+pub fn is_valid_pic(pic: &str) -> bool {
+    (6..=16).contains(&pic.len()) && pic.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
 #[derive(Debug, Clone)]
 pub struct AccountService {
     db: DatabaseConnection,
+    ban: BanService,
+    pic_attempts: Arc<DashMap<AccountId, PicAttempts>>,
 }
 
 type PasswordSalt = [u8; 16];
@@ -72,13 +125,23 @@ fn hash_password(pw: &str) -> String {
 
 impl AccountService {
     pub fn new(db: DatabaseConnection) -> Self {
-        Self { db }
+        Self {
+            ban: BanService::new(db.clone()),
+            db,
+            pic_attempts: Arc::default(),
+        }
     }
 
     pub async fn get(&self, id: AccountId) -> anyhow::Result<Option<Model>> {
         Ok(Entity::find_by_id(id).one(&self.db).await?)
     }
 
+    pub async fn must_get(&self, id: AccountId) -> anyhow::Result<Model> {
+        self.get(id)
+            .await?
+            .ok_or_else(|| anyhow::format_err!("No account for id: {id}"))
+    }
+
     pub async fn create(
         &self,
         username: impl ToString,
@@ -87,11 +150,61 @@ impl AccountService {
         accepted_tos: bool,
         gender: Option<GenderTy>,
     ) -> anyhow::Result<AccountId> {
-        //TODO check username + password
+        Ok(self
+            .insert(username, password, region, accepted_tos, gender, None)
+            .await?)
+    }
+
+    /// Registers a new account on the first login, the TOS must still be accepted.
+    /// Fails if `max_per_ip` accounts were already registered from the IP
+    pub async fn register(
+        &self,
+        username: &str,
+        password: &str,
+        ip: IpAddr,
+        max_per_ip: usize,
+    ) -> AccResult<Model> {
+        let registered = Entity::find()
+            .filter(Column::CreatedIp.eq(ip.to_string()))
+            .count(&self.db)
+            .await?;
+        if registered >= max_per_ip as u64 {
+            return Err(AccountServiceError::IpAccountLimit);
+        }
+
+        let id = self
+            .insert(username, password, Region::Europe, false, None, Some(ip))
+            .await?;
+        Ok(self
+            .get(id)
+            .await?
+            .ok_or_else(|| anyhow::format_err!("Registered account not found"))?)
+    }
+
+    async fn insert(
+        &self,
+        username: impl ToString,
+        password: &str,
+        region: Region,
+        accepted_tos: bool,
+        gender: Option<GenderTy>,
+        ip: Option<IpAddr>,
+    ) -> AccResult<AccountId> {
+        let username = username.to_string();
+        check_username(&username)?;
+        check_password(password)?;
+        let exists = Entity::find()
+            .filter(Column::Username.eq(username.as_str()))
+            .count(&self.db)
+            .await?;
+        if exists > 0 {
+            return Err(AccountServiceError::UsernameAlreadyExists);
+        }
+
         let hash = hash_password(password);
 
         let acc = ActiveModel {
-            username: Set(username.to_string()),
+            username: Set(username),
             password_hash: Set(hash),
             accepted_tos: Set(accepted_tos),
             created_at: created_at(&self.db),
@@ -104,6 +217,7 @@ impl AccountService {
             nx_prepaid: Set(0),
             gender: Set(gender),
             tester: Set(false),
+            created_ip: Set(ip.map(|ip| ip.to_string())),
             ..Default::default()
         };
 
@@ -112,27 +226,24 @@ impl AccountService {
     }
 
     pub async fn try_login(&self, username: &str, password: &str) -> AccResult<Model> {
-        let res = Entity::find()
+        let acc = Entity::find()
             .filter(Column::Username.eq(username))
-            //TODO find latest ban
-            .find_also_related(ban::Entity)
             .one(&self.db)
             .await?;
 
-        let Some((acc, last_ban)) = res else {
+        let Some(acc) = acc else {
             return Err(AccountServiceError::UsernameNotFound)
         };
 
-        if let Some(_last_ban) = last_ban {
-            return Err(AccountServiceError::AccountIsBanned);
-        }
-
-        let verfiy_password = self.verify_password(password, &acc.password_hash).unwrap();
+        let verfiy_password = self.verify_password(password, &acc.password_hash)?;
         if !verfiy_password {
             return Err(AccountServiceError::PasswordMismatch);
         }
 
-        //TODO add some locking logic
+        // Only reveal the ban after the password was verified
+        if let Some(ban) = self.ban.get_active_ban(acc.id).await? {
+            return Err(AccountServiceError::AccountIsBanned(Box::new(ban)));
+        }
 
         Ok(acc)
     }
@@ -155,6 +266,18 @@ impl AccountService {
         .await
     }
 
+    /// Sets the PIC for an account, which has no PIC yet
+    pub async fn register_pic(&self, acc: Model, pic: String) -> anyhow::Result<Model> {
+        if acc.pic.is_some() {
+            anyhow::bail!("Pic already set");
+        }
+        if !is_valid_pic(&pic) {
+            anyhow::bail!("Invalid pic");
+        }
+
+        self.set_pic(acc, pic).await
+    }
+
     pub async fn set_pic(&self, acc: Model, pic: String) -> anyhow::Result<Model> {
         self.update(acc, |acc| {
             acc.pic = Set(Some(pic));
@@ -200,23 +323,68 @@ impl AccountService {
         Ok(constant_time_eq(acc_pic.as_bytes(), pic.as_bytes()))
     }
 
-    pub fn check_hardware_info(
+    /// Checks the PIC and counts the invalid attempts,
+    /// after too many invalid attempts the PIC is locked for a while
+    pub fn verify_pic(&self, acc: &Model, pic: &str) -> AccResult<bool> {
+        self.verify_pic_at(acc, pic, Instant::now())
+    }
+
+    fn verify_pic_at(&self, acc: &Model, pic: &str, now: Instant) -> AccResult<bool> {
+        let mut attempts = self.pic_attempts.entry(acc.id).or_default();
+        match attempts.locked_until {
+            Some(locked_until) if locked_until > now => return Err(AccountServiceError::PicLocked),
+            Some(_) => *attempts = PicAttempts::default(),
+            None => {}
+        }
+
+        if self.check_pic(acc, pic)? {
+            *attempts = PicAttempts::default();
+            return Ok(true);
+        }
+
+        attempts.failed += 1;
+        if attempts.failed >= MAX_PIC_ATTEMPTS {
+            attempts.failed = 0;
+            attempts.locked_until = Some(now + PIC_LOCK_DURATION);
+        }
+        Ok(false)
+    }
+
+    /// Checks whether the host of the client is banned
+    pub async fn check_hardware_info(
         &self,
         _acc: &Model,
-        _hw_info: &HardwareInfo,
-        _ip: IpAddr,
-    ) -> anyhow::Result<()> {
+        hwid: &HardwareId,
+        ip: IpAddr,
+    ) -> AccResult<()> {
+        if let Some(ban) = self.ban.get_active_host_ban(ip, Some(hwid)).await? {
+            return Err(AccountServiceError::HostIsBanned(Box::new(ban)));
+        }
+
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::Instant,
+    };
+
+    use chrono::Duration;
+    use proto95::login::BanReason;
     use sea_orm::DatabaseConnection;
 
-    use crate::{entities::sea_orm_active_enums::GenderTy, services::data::account::Region};
+    use crate::{
+        entities::sea_orm_active_enums::GenderTy,
+        services::data::{
+            account::Region,
+            ban::{BanParams, BanService},
+        },
+    };
 
-    use super::AccountService;
+    use super::{AccountService, AccountServiceError, MAX_PIC_ATTEMPTS, PIC_LOCK_DURATION};
 
     pub(crate) async fn get_test_db() -> anyhow::Result<DatabaseConnection> {
         let db = crate::gen_sqlite(crate::SQL_OPT_MEMORY).await?;
@@ -249,4 +417,113 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn register() -> anyhow::Result<()> {
+        let svc = get_test_svc().await?;
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        assert!(matches!(
+            svc.register("abc", "abc123", ip, 2).await,
+            Err(AccountServiceError::UsernameWrongSize)
+        ));
+        assert!(matches!(
+            svc.register("test_1", "abc123", ip, 2).await,
+            Err(AccountServiceError::UsernameWrongChar)
+        ));
+        assert!(matches!(
+            svc.register("test1", "abc 123", ip, 2).await,
+            Err(AccountServiceError::PasswordWrongChar)
+        ));
+
+        let acc = svc.register("test1", "abc123", ip, 2).await?;
+        assert!(!acc.accepted_tos);
+        assert_eq!(acc.created_ip.as_deref(), Some("127.0.0.1"));
+        assert!(matches!(
+            svc.register("test1", "abc123", ip, 2).await,
+            Err(AccountServiceError::UsernameAlreadyExists)
+        ));
+
+        svc.register("test2", "abc123", ip, 2).await?;
+        assert!(matches!(
+            svc.register("test3", "abc123", ip, 2).await,
+            Err(AccountServiceError::IpAccountLimit)
+        ));
+        svc.try_login("test2", "abc123").await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn banned_login() -> anyhow::Result<()> {
+        const USERNAME: &str = "test1";
+        const PW: &str = "abc123";
+
+        let svc = get_test_svc().await?;
+        let acc_id = svc.create(USERNAME, PW, Region::Europe, true, None).await?;
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let ban_svc = BanService::new(svc.db.clone());
+        ban_svc
+            .ban(
+                acc_id,
+                BanParams {
+                    ip: Some(ip),
+                    ..BanParams::temporary(BanReason::Scamming, Duration::days(1))
+                },
+            )
+            .await?;
+
+        // Wrong passwords must not reveal the ban
+        assert!(matches!(
+            svc.try_login(USERNAME, "wrong").await,
+            Err(AccountServiceError::PasswordMismatch)
+        ));
+        let login = svc.try_login(USERNAME, PW).await;
+        let Err(AccountServiceError::AccountIsBanned(ban)) = login else {
+            panic!("Account must be banned");
+        };
+        assert_eq!(ban.reason, BanReason::Scamming);
+        assert!(!ban.is_permanent());
+
+        ban_svc.unban(acc_id).await?;
+        let acc = svc.try_login(USERNAME, PW).await?;
+        svc.check_hardware_info(&acc, "abcd", ip).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pic_lockout() -> anyhow::Result<()> {
+        let svc = get_test_svc().await?;
+        let acc_id = svc
+            .create("test1", "abc123", Region::Europe, true, None)
+            .await?;
+        let acc = svc.get(acc_id).await?.unwrap();
+
+        assert!(svc
+            .register_pic(acc.clone(), "123".to_string())
+            .await
+            .is_err());
+        let acc = svc.register_pic(acc, "abc123".to_string()).await?;
+        assert!(svc
+            .register_pic(acc.clone(), "xyz789".to_string())
+            .await
+            .is_err());
+
+        let now = Instant::now();
+        assert!(svc.verify_pic_at(&acc, "abc123", now)?);
+        for _ in 0..MAX_PIC_ATTEMPTS {
+            assert!(!svc.verify_pic_at(&acc, "wrong1", now)?);
+        }
+
+        // Even the right PIC is rejected while locked
+        assert!(matches!(
+            svc.verify_pic_at(&acc, "abc123", now),
+            Err(AccountServiceError::PicLocked)
+        ));
+        assert!(svc.verify_pic_at(&acc, "abc123", now + PIC_LOCK_DURATION)?);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,333 @@
+use std::net::IpAddr;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use proto95::login::BanReason;
+use sea_orm::{
+    sea_query::Expr, ActiveValue::NotSet, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect, Set,
+};
+
+use crate::entities::{ban, character};
+
+use super::account::AccountId;
+
+pub type BanId = i32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ban {
+    pub id: BanId,
+    pub acc_id: AccountId,
+    pub reason: BanReason,
+    pub message: Option<String>,
+    /// Bans without an expiry are permanent
+    pub expires_at: Option<NaiveDateTime>,
+    pub ip: Option<IpAddr>,
+    pub hwid: Option<String>,
+    pub issued_by: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+impl From<ban::Model> for Ban {
+    fn from(value: ban::Model) -> Self {
+        Self {
+            id: value.id,
+            acc_id: value.acc_id,
+            reason: BanReason::try_from(value.reason_code as u8).unwrap_or_default(),
+            message: value.ban_reason,
+            expires_at: value.ban_time,
+            ip: value.ip.and_then(|ip| ip.parse().ok()),
+            hwid: value.hwid,
+            issued_by: value.issued_by,
+            created_at: value.created_at,
+            revoked_at: value.revoked_at,
+        }
+    }
+}
+
+impl Ban {
+    pub fn is_permanent(&self) -> bool {
+        self.expires_at.is_none()
+    }
+
+    pub fn is_active(&self, now: NaiveDateTime) -> bool {
+        self.revoked_at.is_none() && self.expires_at.map_or(true, |t_exp| t_exp > now)
+    }
+}
+
+/// Parameters for a new ban, IP and hardware id are optional
+/// and block every account logging in from that host
+#[derive(Debug, Clone, Default)]
+pub struct BanParams {
+    pub reason: BanReason,
+    pub message: Option<String>,
+    /// Duration of the ban, `None` bans permanently
+    pub duration: Option<Duration>,
+    pub ip: Option<IpAddr>,
+    pub hwid: Option<String>,
+    pub issued_by: Option<String>,
+}
+
+impl BanParams {
+    pub fn permanent(reason: BanReason) -> Self {
+        Self {
+            reason,
+            ..Default::default()
+        }
+    }
+
+    pub fn temporary(reason: BanReason, duration: Duration) -> Self {
+        Self {
+            reason,
+            duration: Some(duration),
+            ..Default::default()
+        }
+    }
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+fn active_cond(now: NaiveDateTime) -> Condition {
+    Condition::all().add(ban::Column::RevokedAt.is_null()).add(
+        Condition::any()
+            .add(ban::Column::BanTime.is_null())
+            .add(ban::Column::BanTime.gt(now)),
+    )
+}
+
+/// Selects the ban, which lasts the longest
+fn longest_ban(bans: Vec<ban::Model>) -> Option<Ban> {
+    bans.into_iter()
+        .map(Ban::from)
+        .max_by_key(|ban| (ban.is_permanent(), ban.expires_at))
+}
+
+#[derive(Debug, Clone)]
+pub struct BanService {
+    db: DatabaseConnection,
+}
+
+impl BanService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    pub async fn ban(&self, acc_id: AccountId, params: BanParams) -> anyhow::Result<Ban> {
+        let created_at = now();
+        let ban = ban::ActiveModel {
+            id: NotSet,
+            ban_reason: Set(params.message),
+            ban_time: Set(params.duration.map(|dur| created_at + dur)),
+            acc_id: Set(acc_id),
+            reason_code: Set(u8::from(params.reason) as i32),
+            ip: Set(params.ip.map(|ip| ip.to_string())),
+            hwid: Set(params.hwid),
+            issued_by: Set(params.issued_by),
+            created_at: Set(created_at),
+            revoked_at: Set(None),
+        };
+
+        let id = ban::Entity::insert(ban)
+            .exec(&self.db)
+            .await?
+            .last_insert_id;
+        let ban = ban::Entity::find_by_id(id)
+            .one(&self.db)
+            .await?
+            .ok_or_else(|| anyhow::format_err!("Inserted ban not found"))?;
+        Ok(ban.into())
+    }
+
+    /// Bans the account, which owns the character with the given name
+    pub async fn ban_character(&self, name: &str, params: BanParams) -> anyhow::Result<Ban> {
+        let acc_id = self.get_account_by_char_name(name).await?;
+        self.ban(acc_id, params).await
+    }
+
+    /// Revokes all active bans of the account, returns the number of revoked bans
+    pub async fn unban(&self, acc_id: AccountId) -> anyhow::Result<u64> {
+        let now = now();
+        let res = ban::Entity::update_many()
+            .col_expr(ban::Column::RevokedAt, Expr::value(now))
+            .filter(ban::Column::AccId.eq(acc_id))
+            .filter(active_cond(now))
+            .exec(&self.db)
+            .await?;
+
+        Ok(res.rows_affected)
+    }
+
+    pub async fn unban_character(&self, name: &str) -> anyhow::Result<u64> {
+        let acc_id = self.get_account_by_char_name(name).await?;
+        self.unban(acc_id).await
+    }
+
+    /// Revokes a single ban, returns false if the ban was not active
+    pub async fn revoke(&self, ban_id: BanId) -> anyhow::Result<bool> {
+        let now = now();
+        let res = ban::Entity::update_many()
+            .col_expr(ban::Column::RevokedAt, Expr::value(now))
+            .filter(ban::Column::Id.eq(ban_id))
+            .filter(active_cond(now))
+            .exec(&self.db)
+            .await?;
+
+        Ok(res.rows_affected > 0)
+    }
+
+    pub async fn get_active_ban(&self, acc_id: AccountId) -> anyhow::Result<Option<Ban>> {
+        let bans = ban::Entity::find()
+            .filter(ban::Column::AccId.eq(acc_id))
+            .filter(active_cond(now()))
+            .all(&self.db)
+            .await?;
+
+        Ok(longest_ban(bans))
+    }
+
+    /// Finds an active ban, which blocks the IP or the hardware id
+    pub async fn get_active_host_ban(
+        &self,
+        ip: IpAddr,
+        hwid: Option<&str>,
+    ) -> anyhow::Result<Option<Ban>> {
+        let mut host = Condition::any().add(ban::Column::Ip.eq(ip.to_string()));
+        if let Some(hwid) = hwid {
+            host = host.add(ban::Column::Hwid.eq(hwid));
+        }
+
+        let bans = ban::Entity::find()
+            .filter(host)
+            .filter(active_cond(now()))
+            .all(&self.db)
+            .await?;
+
+        Ok(longest_ban(bans))
+    }
+
+    /// All bans of the account including expired and revoked ones, newest first
+    pub async fn get_history(&self, acc_id: AccountId) -> anyhow::Result<Vec<Ban>> {
+        Ok(ban::Entity::find()
+            .filter(ban::Column::AccId.eq(acc_id))
+            .order_by_desc(ban::Column::CreatedAt)
+            .order_by_desc(ban::Column::Id)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(Ban::from)
+            .collect())
+    }
+
+    async fn get_account_by_char_name(&self, name: &str) -> anyhow::Result<AccountId> {
+        let acc_id: Option<AccountId> = character::Entity::find()
+            .select_only()
+            .column(character::Column::AccId)
+            .filter(character::Column::Name.eq(name))
+            .into_tuple()
+            .one(&self.db)
+            .await?;
+
+        acc_id.ok_or_else(|| anyhow::format_err!("No character with name: {name}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use chrono::Duration;
+    use proto95::login::BanReason;
+
+    use crate::services::data::{account::Region, AccountService};
+
+    use super::{BanParams, BanService};
+
+    async fn get_test_svc() -> anyhow::Result<(BanService, AccountService)> {
+        let db = crate::gen_sqlite(crate::SQL_OPT_MEMORY).await?;
+        Ok((BanService::new(db.clone()), AccountService::new(db)))
+    }
+
+    #[tokio::test]
+    async fn ban_expiry_and_unban() -> anyhow::Result<()> {
+        let (svc, acc_svc) = get_test_svc().await?;
+        let acc_id = acc_svc
+            .create("test1", "abc123", Region::Europe, true, None)
+            .await?;
+        assert!(svc.get_active_ban(acc_id).await?.is_none());
+
+        // Already expired bans are only part of the history
+        svc.ban(
+            acc_id,
+            BanParams::temporary(BanReason::Botting, Duration::seconds(-1)),
+        )
+        .await?;
+        assert!(svc.get_active_ban(acc_id).await?.is_none());
+
+        let temp = svc
+            .ban(
+                acc_id,
+                BanParams::temporary(BanReason::Cursing, Duration::days(3)),
+            )
+            .await?;
+        assert_eq!(svc.get_active_ban(acc_id).await?, Some(temp.clone()));
+
+        // A permanent ban outlasts every temporary ban
+        let perm = svc
+            .ban(acc_id, BanParams::permanent(BanReason::Hacking))
+            .await?;
+        let active = svc.get_active_ban(acc_id).await?.unwrap();
+        assert_eq!(active.id, perm.id);
+        assert!(active.is_permanent());
+
+        assert!(svc.revoke(perm.id).await?);
+        assert!(!svc.revoke(perm.id).await?);
+        assert_eq!(svc.get_active_ban(acc_id).await?.unwrap().id, temp.id);
+
+        assert_eq!(svc.unban(acc_id).await?, 1);
+        assert!(svc.get_active_ban(acc_id).await?.is_none());
+
+        let history = svc.get_history(acc_id).await?;
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].id, perm.id);
+        assert_eq!(history[2].reason, BanReason::Botting);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn host_ban() -> anyhow::Result<()> {
+        let (svc, acc_svc) = get_test_svc().await?;
+        let acc_id = acc_svc
+            .create("test1", "abc123", Region::Europe, true, None)
+            .await?;
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let other_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        svc.ban(
+            acc_id,
+            BanParams {
+                ip: Some(ip),
+                hwid: Some("abcd".to_string()),
+                ..BanParams::permanent(BanReason::Hacking)
+            },
+        )
+        .await?;
+
+        assert!(svc.get_active_host_ban(ip, None).await?.is_some());
+        assert!(svc
+            .get_active_host_ban(other_ip, Some("abcd"))
+            .await?
+            .is_some());
+        assert!(svc
+            .get_active_host_ban(other_ip, Some("efgh"))
+            .await?
+            .is_none());
+
+        svc.unban(acc_id).await?;
+        assert!(svc.get_active_host_ban(ip, Some("abcd")).await?.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,610 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use game_data::wz2::Commodity;
+use proto95::{
+    game::cash_shop::{CashId, CashType},
+    id::ItemId,
+    login::world::WorldId,
+};
+use rand::{thread_rng, Rng};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, Set, TransactionTrait,
+};
+use thiserror::Error;
+
+use crate::{
+    created_at,
+    entities::{account, cash_item, character},
+    services::helper::intentory::inv::InventorySet,
+};
+
+use super::{
+    account::AccountId,
+    character::{inc_char_slots, CharSlots, CharacterID},
+    item::save_inventory,
+};
+
+#[derive(Debug, Error)]
+pub enum CashShopError {
+    #[error("Commodity is not on sale")]
+    NotOnSale,
+    #[error("Not enough cash")]
+    NotEnoughCash,
+    #[error("No receiver with this name was found")]
+    ReceiverNotFound,
+    #[error("Gifts to your own account are not allowed")]
+    GiftSameAccount,
+    #[error("No locker item for the cash id: {0}")]
+    ItemNotFound(CashId),
+    #[error("No account for the id: {0}")]
+    AccountNotFound(AccountId),
+    #[error("The character slot limit is reached")]
+    SlotLimitReached,
+    #[error("Inventory is full")]
+    InventoryFull,
+    #[error("Invalid item: {0:?}")]
+    InvalidItem(ItemId),
+    #[error("Database: {0}")]
+    Db(#[from] DbErr),
+}
+
+pub type CashShopResult<T> = std::result::Result<T, CashShopError>;
+
+/// Item which is moved from a character inventory back into the locker
+#[derive(Debug, Clone)]
+pub struct LockerItem {
+    pub cash_id: CashId,
+    pub item_id: i32,
+    pub quantity: i32,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CashShopService {
+    db: DatabaseConnection,
+}
+
+fn gen_cash_id() -> CashId {
+    thread_rng().gen_range(1..i64::MAX as CashId)
+}
+
+fn expires_at(commodity: &Commodity) -> Option<NaiveDateTime> {
+    (commodity.period > 0).then(|| Utc::now().naive_utc() + Duration::days(commodity.period as i64))
+}
+
+impl CashShopService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    pub async fn get_locker(&self, acc_id: AccountId) -> anyhow::Result<Vec<cash_item::Model>> {
+        Ok(cash_item::Entity::find()
+            .filter(cash_item::Column::AccId.eq(acc_id))
+            .all(&self.db)
+            .await?)
+    }
+
+    /// Withdraws the price of the commodity from the balance of the account
+    async fn withdraw<C: ConnectionTrait>(
+        db: &C,
+        payer: AccountId,
+        cash_type: CashType,
+        commodity: &Commodity,
+    ) -> CashShopResult<account::Model> {
+        if !commodity.is_on_sale() {
+            return Err(CashShopError::NotOnSale);
+        }
+
+        let price = i32::try_from(commodity.price).map_err(|_| CashShopError::NotEnoughCash)?;
+        let balance = match cash_type {
+            CashType::NxCredit => account::Column::NxCredit,
+            CashType::ShroomPoints => account::Column::ShroomPoints,
+            CashType::NxPrepaid => account::Column::NxPrepaid,
+        };
+
+        // The balance is checked by the update itself,
+        // so concurrent purchases can't spend the same cash twice
+        let res = account::Entity::update_many()
+            .col_expr(balance, Expr::col(balance).sub(price))
+            .filter(account::Column::Id.eq(payer))
+            .filter(balance.gte(price))
+            .exec(db)
+            .await?;
+
+        let acc = account::Entity::find_by_id(payer)
+            .one(db)
+            .await?
+            .ok_or(CashShopError::AccountNotFound(payer))?;
+        if res.rows_affected == 0 {
+            return Err(CashShopError::NotEnoughCash);
+        }
+        Ok(acc)
+    }
+
+    /// Deletes the item from the locker, racing takes of the same item fail
+    async fn take_locker_item<C: ConnectionTrait>(
+        db: &C,
+        acc_id: AccountId,
+        cash_id: CashId,
+    ) -> CashShopResult<cash_item::Model> {
+        let item = cash_item::Entity::find()
+            .filter(cash_item::Column::AccId.eq(acc_id))
+            .filter(cash_item::Column::CashId.eq(cash_id as i64))
+            .one(db)
+            .await?
+            .ok_or(CashShopError::ItemNotFound(cash_id))?;
+
+        let res = cash_item::Entity::delete_by_id(item.id).exec(db).await?;
+        if res.rows_affected == 0 {
+            return Err(CashShopError::ItemNotFound(cash_id));
+        }
+        Ok(item)
+    }
+
+    async fn insert_locker_item<C: ConnectionTrait>(
+        db: &C,
+        acc_id: AccountId,
+        char_id: CharacterID,
+        item: LockerItem,
+    ) -> CashShopResult<cash_item::Model> {
+        Ok(cash_item::ActiveModel {
+            cash_id: Set(item.cash_id as i64),
+            item_id: Set(item.item_id),
+            // The commodity is unknown once the item left the locker
+            commodity_sn: Set(0),
+            quantity: Set(item.quantity),
+            expires_at: Set(item.expires_at),
+            gift_from: Set(None),
+            gift_message: Set(None),
+            created_at: created_at(db),
+            acc_id: Set(acc_id),
+            char_id: Set(Some(char_id)),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?)
+    }
+
+    /// Withdraws the price of the commodity and puts the item into the locker of the account
+    async fn purchase(
+        &self,
+        payer: AccountId,
+        cash_type: CashType,
+        commodity: &Commodity,
+        receiver: (AccountId, Option<CharacterID>),
+        gift: Option<(String, String)>,
+    ) -> CashShopResult<(account::Model, cash_item::Model)> {
+        let txn = self.db.begin().await?;
+        let acc = Self::withdraw(&txn, payer, cash_type, commodity).await?;
+
+        let (gift_from, gift_message) = gift.unzip();
+        let (acc_id, char_id) = receiver;
+        let item = cash_item::ActiveModel {
+            cash_id: Set(gen_cash_id() as i64),
+            item_id: Set(commodity.item_id as i32),
+            commodity_sn: Set(commodity.sn as i32),
+            quantity: Set(commodity.count.max(1) as i32),
+            expires_at: Set(expires_at(commodity)),
+            gift_from: Set(gift_from),
+            gift_message: Set(gift_message),
+            created_at: created_at(&self.db),
+            acc_id: Set(acc_id),
+            char_id: Set(char_id),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        txn.commit().await?;
+        Ok((acc, item))
+    }
+
+    pub async fn buy(
+        &self,
+        acc_id: AccountId,
+        char_id: CharacterID,
+        cash_type: CashType,
+        commodity: &Commodity,
+    ) -> CashShopResult<(account::Model, cash_item::Model)> {
+        self.purchase(acc_id, cash_type, commodity, (acc_id, Some(char_id)), None)
+            .await
+    }
+
+    /// Buys an additional character slot for the world, the slot is applied directly
+    /// instead of being put into the locker
+    pub async fn buy_char_slot(
+        &self,
+        acc_id: AccountId,
+        world_id: WorldId,
+        cash_type: CashType,
+        commodity: &Commodity,
+    ) -> CashShopResult<(account::Model, CharSlots)> {
+        if commodity.item_id != ItemId::CHAR_SLOT_EXPANSION.0 {
+            return Err(CashShopError::NotOnSale);
+        }
+
+        let txn = self.db.begin().await?;
+        let acc = Self::withdraw(&txn, acc_id, cash_type, commodity).await?;
+        let slots = inc_char_slots(&txn, &acc, world_id, commodity.count.max(1))
+            .await?
+            .ok_or(CashShopError::SlotLimitReached)?;
+
+        txn.commit().await?;
+        Ok((acc, slots))
+    }
+
+    /// Gifts can only be paid with NX credit
+    pub async fn gift(
+        &self,
+        acc_id: AccountId,
+        sender_name: &str,
+        commodity: &Commodity,
+        receiver_name: &str,
+        msg: String,
+    ) -> CashShopResult<(account::Model, cash_item::Model)> {
+        let receiver = character::Entity::find()
+            .filter(character::Column::Name.eq(receiver_name))
+            .one(&self.db)
+            .await?
+            .ok_or(CashShopError::ReceiverNotFound)?;
+
+        if receiver.acc_id == acc_id {
+            return Err(CashShopError::GiftSameAccount);
+        }
+
+        self.purchase(
+            acc_id,
+            CashType::NxCredit,
+            commodity,
+            (receiver.acc_id, Some(receiver.id)),
+            Some((sender_name.to_string(), msg)),
+        )
+        .await
+    }
+
+    /// Takes the item from the locker and adds It to the inventory with `add`,
+    /// the locker and the inventory are saved in a single transaction.
+    /// The inventory is left unchanged, If anything fails
+    pub async fn move_to_inventory<T>(
+        &self,
+        acc_id: AccountId,
+        char_id: CharacterID,
+        cash_id: CashId,
+        invs: &mut InventorySet,
+        add: impl FnOnce(&mut InventorySet, &cash_item::Model) -> CashShopResult<T>,
+    ) -> CashShopResult<(cash_item::Model, T)> {
+        let prev = invs.clone();
+        let res = async {
+            let txn = self.db.begin().await?;
+            let item = Self::take_locker_item(&txn, acc_id, cash_id).await?;
+            let added = add(invs, &item)?;
+            save_inventory(&txn, invs, char_id).await?;
+            txn.commit().await?;
+            Ok((item, added))
+        }
+        .await;
+
+        if res.is_err() {
+            *invs = prev;
+        }
+        res
+    }
+
+    /// Removes the item from the inventory with `remove` and puts It into the locker,
+    /// the inventory and the locker are saved in a single transaction.
+    /// The inventory is left unchanged, If anything fails
+    pub async fn move_to_locker(
+        &self,
+        acc_id: AccountId,
+        char_id: CharacterID,
+        cash_id: CashId,
+        invs: &mut InventorySet,
+        remove: impl FnOnce(&mut InventorySet) -> Option<LockerItem>,
+    ) -> CashShopResult<cash_item::Model> {
+        let prev = invs.clone();
+        let res = async {
+            let item = remove(invs).ok_or(CashShopError::ItemNotFound(cash_id))?;
+            let txn = self.db.begin().await?;
+            save_inventory(&txn, invs, char_id).await?;
+            let item = Self::insert_locker_item(&txn, acc_id, char_id, item).await?;
+            txn.commit().await?;
+            Ok(item)
+        }
+        .await;
+
+        if res.is_err() {
+            *invs = prev;
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game_data::wz2::Commodity;
+    use proto95::{game::cash_shop::CashType, id::ItemId};
+
+    use sea_orm::{DatabaseConnection, EntityTrait, Set};
+
+    use crate::{
+        created_at,
+        entities::{cash_item, character, sea_orm_active_enums::GenderTy},
+        services::{
+            data::{
+                account::{AccountId, AccountService, Region},
+                character::{CharSlots, CharacterID, CharacterService, MAX_CHAR_SLOTS},
+            },
+            helper::intentory::inv::{InventoryExt, InventorySet},
+            model::item::StackItem,
+        },
+    };
+
+    use super::{CashShopError, CashShopService, LockerItem};
+
+    /// Character without items, the starter set would require the meta data
+    async fn create_char(
+        db: &DatabaseConnection,
+        acc_id: AccountId,
+    ) -> anyhow::Result<CharacterID> {
+        let char = character::ActiveModel {
+            acc_id: Set(acc_id),
+            world_id: Set(0),
+            created_at: created_at(db),
+            gender: Set(GenderTy::Female),
+            name: Set("Locker".to_string()),
+            map_id: Set(100000000),
+            job: Set(0),
+            level: Set(1),
+            str: Set(4),
+            dex: Set(4),
+            int: Set(4),
+            luk: Set(4),
+            hp: Set(50),
+            max_hp: Set(50),
+            mp: Set(5),
+            max_mp: Set(5),
+            equip_slots: Set(24),
+            use_slots: Set(24),
+            setup_slots: Set(24),
+            etc_slots: Set(24),
+            cash_slots: Set(24),
+            storage_slots: Set(16),
+            buddy_capacity: Set(20),
+            skin: Set(0),
+            face: Set(20000),
+            hair: Set(30000),
+            exp: Set(0),
+            gacha_exp: Set(0),
+            mesos: Set(0),
+            fame: Set(0),
+            ap: Set(0),
+            sp: Set(0),
+            spawn_point: Set(0),
+            skill_points: Set(vec![0; 20]),
+            play_time: Set(0),
+            ..Default::default()
+        };
+        Ok(character::Entity::insert(char)
+            .exec(db)
+            .await?
+            .last_insert_id)
+    }
+
+    fn get_commodity(price: u32) -> Commodity {
+        get_item_commodity(5000000, price)
+    }
+
+    fn get_item_commodity(item_id: u32, price: u32) -> Commodity {
+        Commodity {
+            sn: 10000000,
+            item_id,
+            count: 1,
+            price,
+            period: 90,
+            priority: 0,
+            gender: 2,
+            on_sale: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn buy() -> anyhow::Result<()> {
+        let db = crate::gen_sqlite(crate::SQL_OPT_MEMORY).await?;
+        let acc_svc = AccountService::new(db.clone());
+        let svc = CashShopService::new(db);
+
+        let acc_id = acc_svc
+            .create("cash", "abc123", Region::Europe, true, None)
+            .await?;
+        let commodity = get_commodity(1_000);
+
+        let receiver = (acc_id, None);
+
+        let res = svc
+            .purchase(acc_id, CashType::NxCredit, &commodity, receiver, None)
+            .await;
+        assert!(matches!(res, Err(CashShopError::NotEnoughCash)));
+
+        let acc = acc_svc.get(acc_id).await?.unwrap();
+        acc_svc
+            .update(acc, |acc| acc.nx_credit = sea_orm::Set(1_500))
+            .await?;
+
+        let (acc, item) = svc
+            .purchase(acc_id, CashType::NxCredit, &commodity, receiver, None)
+            .await?;
+        assert_eq!(acc.nx_credit, 500);
+        assert!(item.expires_at.is_some());
+        assert_eq!(svc.get_locker(acc_id).await?.len(), 1);
+
+        // The remaining balance is not enough for a second item
+        let res = svc
+            .purchase(acc_id, CashType::NxCredit, &commodity, receiver, None)
+            .await;
+        assert!(matches!(res, Err(CashShopError::NotEnoughCash)));
+        assert_eq!(acc_svc.get(acc_id).await?.unwrap().nx_credit, 500);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn move_item() -> anyhow::Result<()> {
+        let db = crate::gen_sqlite(crate::SQL_OPT_MEMORY).await?;
+        let acc_svc = AccountService::new(db.clone());
+        let svc = CashShopService::new(db.clone());
+
+        let acc_id = acc_svc
+            .create("move", "abc123", Region::Europe, true, None)
+            .await?;
+        let char_id = create_char(&db, acc_id).await?;
+        let acc = acc_svc.get(acc_id).await?.unwrap();
+        acc_svc
+            .update(acc, |acc| acc.nx_credit = sea_orm::Set(1_000))
+            .await?;
+        let (_, item) = svc
+            .buy(acc_id, char_id, CashType::NxCredit, &get_commodity(1_000))
+            .await?;
+        let cash_id = item.cash_id as u64;
+
+        let add = |invs: &mut InventorySet, item: &cash_item::Model| {
+            let mut stack = StackItem::from_item_id(ItemId(item.item_id as u32), 1);
+            stack.cash_id = Some(item.cash_id as u64);
+            invs.cash
+                .get_inner_mut()
+                .try_add_slot(stack.into())
+                .map_err(|_| CashShopError::InventoryFull)
+        };
+        let mut invs = InventorySet::with_default_slots();
+        let (_, slot) = svc
+            .move_to_inventory(acc_id, char_id, cash_id, &mut invs, add)
+            .await?;
+        assert!(svc.get_locker(acc_id).await?.is_empty());
+        assert_eq!(
+            invs.cash.get(slot).unwrap().item.cash_id,
+            Some(item.cash_id as u64)
+        );
+
+        // The item is gone from the locker, so a second move fails
+        let res = svc
+            .move_to_inventory(acc_id, char_id, cash_id, &mut invs, add)
+            .await;
+        assert!(matches!(res, Err(CashShopError::ItemNotFound(_))));
+        assert_eq!(invs.cash.iter().count(), 1);
+
+        let remove = |invs: &mut InventorySet| {
+            invs.cash.remove(slot).map(|stack| LockerItem {
+                cash_id,
+                item_id: stack.item_id.0 as i32,
+                quantity: stack.quantity as i32,
+                expires_at: stack.item.expiration,
+            })
+        };
+        let item = svc
+            .move_to_locker(acc_id, char_id, cash_id, &mut invs, remove)
+            .await?;
+        assert_eq!(item.cash_id as u64, cash_id);
+        assert_eq!(item.char_id, Some(char_id));
+        assert_eq!(svc.get_locker(acc_id).await?.len(), 1);
+        assert_eq!(invs.cash.iter().count(), 0);
+
+        let res = svc
+            .move_to_locker(acc_id, char_id, cash_id, &mut invs, remove)
+            .await;
+        assert!(matches!(res, Err(CashShopError::ItemNotFound(_))));
+        assert_eq!(svc.get_locker(acc_id).await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_move_keeps_item() -> anyhow::Result<()> {
+        let db = crate::gen_sqlite(crate::SQL_OPT_MEMORY).await?;
+        let acc_svc = AccountService::new(db.clone());
+        let svc = CashShopService::new(db);
+
+        let acc_id = acc_svc
+            .create("locker", "abc123", Region::Europe, true, None)
+            .await?;
+        let acc = acc_svc.get(acc_id).await?.unwrap();
+        acc_svc
+            .update(acc, |acc| acc.nx_credit = sea_orm::Set(1_000))
+            .await?;
+        let (_, item) = svc
+            .purchase(
+                acc_id,
+                CashType::NxCredit,
+                &get_commodity(1_000),
+                (acc_id, None),
+                None,
+            )
+            .await?;
+
+        let mut invs = InventorySet::with_default_slots();
+        let res = svc
+            .move_to_inventory(acc_id, 1, item.cash_id as u64, &mut invs, |invs, item| {
+                let stack = StackItem::from_item_id(ItemId(item.item_id as u32), 1);
+                invs.cash.get_inner_mut().try_add(stack.into()).unwrap();
+                Err::<(), _>(CashShopError::InventoryFull)
+            })
+            .await;
+        assert!(matches!(res, Err(CashShopError::InventoryFull)));
+
+        // Neither the locker nor the inventory changed
+        assert_eq!(svc.get_locker(acc_id).await?.len(), 1);
+        assert_eq!(invs.cash.iter().count(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn buy_char_slot() -> anyhow::Result<()> {
+        let db = crate::gen_sqlite(crate::SQL_OPT_MEMORY).await?;
+        let acc_svc = AccountService::new(db.clone());
+        let char_svc = CharacterService::new(db.clone(), acc_svc.clone());
+        let svc = CashShopService::new(db);
+
+        let acc_id = acc_svc
+            .create("slots", "abc123", Region::Europe, true, None)
+            .await?;
+        let acc = acc_svc.get(acc_id).await?.unwrap();
+        acc_svc
+            .update(acc, |acc| acc.nx_credit = sea_orm::Set(100_000))
+            .await?;
+
+        let res = svc
+            .buy_char_slot(acc_id, 0, CashType::NxCredit, &get_commodity(1_000))
+            .await;
+        assert!(matches!(res, Err(CashShopError::NotOnSale)));
+
+        let commodity = get_item_commodity(ItemId::CHAR_SLOT_EXPANSION.0, 6_900);
+        let (acc, slots) = svc
+            .buy_char_slot(acc_id, 0, CashType::NxCredit, &commodity)
+            .await?;
+        assert_eq!(acc.nx_credit, 93_100);
+        assert_eq!(
+            slots,
+            CharSlots {
+                slots: 4,
+                bought: 1
+            }
+        );
+
+        // Slots are tracked per world
+        assert_eq!(char_svc.get_char_slots(&acc, 0).await?.slots, 4);
+        assert_eq!(char_svc.get_char_slots(&acc, 1).await?.slots, 3);
+
+        char_svc
+            .add_char_slots(&acc, 0, MAX_CHAR_SLOTS - slots.slots)
+            .await?;
+        let res = svc
+            .buy_char_slot(acc_id, 0, CashType::NxCredit, &commodity)
+            .await;
+        assert!(matches!(res, Err(CashShopError::SlotLimitReached)));
+
+        // The failed purchase must not withdraw the price
+        let acc = acc_svc.get(acc_id).await?.unwrap();
+        assert_eq!(acc.nx_credit, 93_100);
+
+        Ok(())
+    }
+}
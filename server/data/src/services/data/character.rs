@@ -1,20 +1,33 @@
+use chrono::Utc;
 use proto95::{
-    id::{job_id::JobGroup, FaceId, HairId, ItemId, MapId, Skin},
-    login::char::{DeleteCharResult, SelectCharResultCode},
+    id::{job_id::JobGroup, FaceId, HairId, ItemId, Skin},
+    login::{
+        char::{DeleteCharResult, SelectCharResultCode},
+        world::WorldId,
+    },
     shared::Gender,
 };
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QuerySelect, Set, ActiveModelTrait};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::NotSet, ColumnTrait, ConnectionTrait,
+    DatabaseConnection, DbErr, EntityTrait, PaginatorTrait, QueryFilter, QuerySelect, Set,
+    TransactionTrait,
+};
 
 use crate::{
     created_at,
     entities::{
-        account,
+        account, cash_item, char_slot,
         character::{ActiveModel, Column, Entity, Model, self},
         skill,
     },
 };
 
-use super::{account::AccountService, item::ItemService};
+use crate::services::meta::char_creation::{CharCreateError, CharCreateResult, CharCreationRules};
+
+use super::{
+    account::{AccountService, AccountServiceError},
+    item::{clear_inventory, ItemService},
+};
 
 #[derive(Debug, Clone)]
 pub struct ItemStarterSet {
@@ -26,19 +39,6 @@ pub struct ItemStarterSet {
 }
 
 impl ItemStarterSet {
-    pub fn validate(&self, job: JobGroup) -> anyhow::Result<()> {
-        //TODO: update to v95
-        let _bottom = check_contains(job.get_starter_bottoms(), self.bottom, "Bottom ID")?;
-        let _shoes = check_contains(job.get_starter_shoes(), self.shoes, "Shoes ID")?;
-        let _top = check_contains(job.get_starter_tops(), self.top, "Top ID")?;
-        let _weapon = check_contains(job.get_starter_weapons(), self.weapon, "Weapon ID")?;
-        if self.guide != job.get_guide_item() {
-            anyhow::bail!("Invalid starter guide");
-        }
-
-        Ok(())
-    }
-
     pub fn default_starter_set(job: JobGroup) -> Self {
         Self {
             shoes: ItemId::LEATHER_SANDALS,
@@ -59,49 +59,157 @@ pub struct CharacterCreateDTO {
     pub face: FaceId,
     pub skin: Skin,
     pub hair: HairId,
+    /// Added to the hair id, the last digit of the hair is the colour
+    pub hair_color: u32,
     pub starter_set: ItemStarterSet,
     pub gender: Gender,
+    pub world_id: WorldId,
 }
 
 impl CharacterCreateDTO {
     pub fn get_starter_set(&self) -> ItemStarterSet {
         self.starter_set.clone()
     }
-    pub fn validate(&self) -> anyhow::Result<()> {
-        Ok(())
-        /*  de-uglify and test this
-        let job = self.job_group;
-        let _face = check_contains(job.get_starter_face(), self.face, "Face ID")?;
-        let _hair = check_contains(job.get_starter_hair(), self.hair, "Hair")?;
-        self.starter_set.validate(job)?;
 
-        Ok(())*/
+    /// Validates the name format and the appearance, not whether the name is in use
+    pub fn validate(&self, rules: &CharCreationRules) -> CharCreateResult<()> {
+        rules.check_name(&self.name)?;
+        rules
+            .get_choices(self.job_group, self.gender)?
+            .validate(self)
+    }
+
+    pub fn get_hair(&self) -> HairId {
+        HairId(self.hair.0 + self.hair_color)
     }
 }
 
-fn is_valid_char_name(name: &str) -> bool {
-    //TODO error messages
-    if !(3..13).contains(&name.len()) {
-        return false;
+/// Upper limit of character slots per world, including the bought slots
+pub const MAX_CHAR_SLOTS: u32 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharSlots {
+    /// Total slots in the world
+    pub slots: u32,
+    /// Slots which were bought on top of the account base slots
+    pub bought: u32,
+}
+
+impl CharSlots {
+    fn new(base: i32, slots: Option<i32>) -> Self {
+        let base = base.max(0) as u32;
+        let slots = slots.map_or(base, |slots| slots.max(0) as u32);
+        Self {
+            slots,
+            bought: slots.saturating_sub(base),
+        }
     }
 
-    if !name.chars().all(|c| c.is_ascii_alphanumeric()) {
-        return false;
+    pub fn has_free_slot(&self, char_count: usize) -> bool {
+        char_count < self.slots as usize
     }
+}
 
-    true
+async fn find_char_slot<C: ConnectionTrait>(
+    db: &C,
+    acc_id: i32,
+    world_id: WorldId,
+) -> Result<Option<char_slot::Model>, DbErr> {
+    char_slot::Entity::find()
+        .filter(char_slot::Column::AccId.eq(acc_id))
+        .filter(char_slot::Column::WorldId.eq(world_id as i32))
+        .one(db)
+        .await
 }
 
-pub fn check_contains<T: PartialEq + std::fmt::Debug>(
-    mut iter: impl Iterator<Item = T>,
-    check_id: T,
-    name: &str,
-) -> anyhow::Result<T> {
-    if !iter.any(|id| id == check_id) {
-        anyhow::bail!("Invalid {name} ({check_id:?}) for char creation ")
+/// Slots of the account in the world, worlds without bought slots use the account base slots
+pub(crate) async fn get_char_slots<C: ConnectionTrait>(
+    db: &C,
+    acc: &account::Model,
+    world_id: WorldId,
+) -> Result<CharSlots, DbErr> {
+    let slot = find_char_slot(db, acc.id, world_id).await?;
+    Ok(CharSlots::new(
+        acc.character_slots,
+        slot.map(|slot| slot.slots),
+    ))
+}
+
+/// Adds slots to the world, returns `None` if the slots would exceed `MAX_CHAR_SLOTS`
+pub(crate) async fn inc_char_slots<C: ConnectionTrait>(
+    db: &C,
+    acc: &account::Model,
+    world_id: WorldId,
+    n: u32,
+) -> Result<Option<CharSlots>, DbErr> {
+    let slot = find_char_slot(db, acc.id, world_id).await?;
+    let slots = CharSlots::new(acc.character_slots, slot.as_ref().map(|slot| slot.slots));
+    let new_slots = slots.slots + n;
+    if new_slots > MAX_CHAR_SLOTS {
+        return Ok(None);
     }
 
-    Ok(check_id)
+    match slot {
+        Some(slot) => {
+            let mut slot: char_slot::ActiveModel = slot.into();
+            slot.slots = Set(new_slots as i32);
+            slot.update(db).await?;
+        }
+        None => {
+            char_slot::ActiveModel {
+                acc_id: Set(acc.id),
+                world_id: Set(world_id as i32),
+                slots: Set(new_slots as i32),
+                ..Default::default()
+            }
+            .insert(db)
+            .await?;
+        }
+    }
+
+    Ok(Some(CharSlots::new(
+        acc.character_slots,
+        Some(new_slots as i32),
+    )))
+}
+
+/// Writes the changed columns of the character
+pub(crate) async fn update_char<C: ConnectionTrait>(
+    db: &C,
+    char: ActiveModel,
+) -> Result<(), DbErr> {
+    Entity::update(char).exec(db).await?;
+    Ok(())
+}
+
+/// Replaces the learned skills of the character
+pub(crate) async fn save_skills<'a, C: ConnectionTrait>(
+    db: &C,
+    char_id: CharacterID,
+    skills: impl Iterator<Item = &'a skill::Model>,
+) -> Result<(), DbErr> {
+    skill::Entity::delete_many()
+        .filter(skill::Column::CharId.eq(char_id))
+        .exec(db)
+        .await?;
+
+    let skills = skills
+        .map(|skill| skill::ActiveModel {
+            id: NotSet,
+            skill_id: Set(skill.skill_id),
+            skill_level: Set(skill.skill_level),
+            master_level: Set(skill.master_level),
+            expires_at: Set(skill.expires_at),
+            cooldown: Set(skill.cooldown),
+            char_id: Set(char_id),
+        })
+        .collect::<Vec<_>>();
+    if skills.is_empty() {
+        return Ok(());
+    }
+    skill::Entity::insert_many(skills).exec(db).await?;
+
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
@@ -111,17 +219,12 @@ pub struct CharacterService {
 }
 
 impl CharacterService {
-    pub fn new(db: DatabaseConnection) -> Self {
-        Self {
-            db: db.clone(),
-            account: AccountService::new(db),
-        }
+    pub fn new(db: DatabaseConnection, account: AccountService) -> Self {
+        Self { db, account }
     }
 
-    pub async fn check_name(&self, name: &str) -> anyhow::Result<bool> {
-        if !is_valid_char_name(name) {
-            return Ok(false);
-        }
+    pub async fn check_name(&self, name: &str, rules: &CharCreationRules) -> CharCreateResult<()> {
+        rules.check_name(name)?;
 
         let other_id = Entity::find()
             .select_only()
@@ -130,20 +233,74 @@ impl CharacterService {
             .one(&self.db)
             .await?;
 
-        Ok(other_id.is_none())
+        if other_id.is_some() {
+            return Err(CharCreateError::NameInUse);
+        }
+
+        Ok(())
     }
 
-    pub async fn get_characters_for_account(&self, acc_id: i32) -> anyhow::Result<Vec<Model>> {
+    /// Characters of the account in the given world
+    pub async fn get_characters_for_account(
+        &self,
+        acc_id: i32,
+        world_id: WorldId,
+    ) -> anyhow::Result<Vec<Model>> {
         Ok(Entity::find()
             .filter(Column::AccId.eq(acc_id))
+            .filter(Column::WorldId.eq(world_id as i32))
+            .filter(Column::DeletedAt.is_null())
             .all(&self.db)
             .await?)
     }
 
+    pub async fn get_char_slots(
+        &self,
+        acc: &account::Model,
+        world_id: WorldId,
+    ) -> anyhow::Result<CharSlots> {
+        Ok(get_char_slots(&self.db, acc, world_id).await?)
+    }
+
+    /// Adds character slots to the world without payment, used for GM commands
+    pub async fn add_char_slots(
+        &self,
+        acc: &account::Model,
+        world_id: WorldId,
+        n: u32,
+    ) -> anyhow::Result<CharSlots> {
+        inc_char_slots(&self.db, acc, world_id, n)
+            .await?
+            .ok_or_else(|| anyhow::format_err!("Character slots can't exceed {MAX_CHAR_SLOTS}"))
+    }
+
+    /// Checks whether the account can create another character in the world
+    pub async fn has_free_char_slot(
+        &self,
+        acc: &account::Model,
+        world_id: WorldId,
+    ) -> anyhow::Result<bool> {
+        let slots = self.get_char_slots(acc, world_id).await?;
+        let char_count = Entity::find()
+            .filter(Column::AccId.eq(acc.id))
+            .filter(Column::WorldId.eq(world_id as i32))
+            .filter(Column::DeletedAt.is_null())
+            .count(&self.db)
+            .await?;
+        Ok(slots.has_free_slot(char_count as usize))
+    }
+
     pub async fn get(&self, char_id: CharacterID) -> anyhow::Result<Option<Model>> {
         Ok(Entity::find_by_id(char_id).one(&self.db).await?)
     }
 
+    pub async fn get_by_name(&self, name: &str) -> anyhow::Result<Option<Model>> {
+        Ok(Entity::find()
+            .filter(Column::Name.eq(name))
+            .one(&self.db)
+            .await?)
+    }
+
     pub async fn must_get(&self, char_id: CharacterID) -> anyhow::Result<Model> {
         self.get(char_id)
             .await?
@@ -154,20 +311,24 @@ impl CharacterService {
         &self,
         acc_id: i32,
         create: CharacterCreateDTO,
+        rules: &CharCreationRules,
         item_svc: &ItemService,
-    ) -> anyhow::Result<CharacterID> {
-        create.validate()?;
+    ) -> CharCreateResult<CharacterID> {
+        create.validate(rules)?;
+        self.check_name(&create.name, rules).await?;
 
-        if !self.check_name(&create.name).await? {
-            anyhow::bail!("Name is not valid");
+        let acc = self.account.must_get(acc_id).await?;
+        if !self.has_free_char_slot(&acc, create.world_id).await? {
+            return Err(CharCreateError::NoFreeSlot);
         }
 
-        let job = create.job_group;
-        let map_id = MapId::AMHERST.0 as i32; //job.get_start_map().0 as i32;
-        let job = job.get_noob_job_id() as u32;
+        let map_id = rules.get_start_map(create.job_group)?.0 as i32;
+        let job = create.job_group.get_noob_job_id() as u32;
+        let hair = create.get_hair();
 
         let char = ActiveModel {
             acc_id: Set(acc_id),
+            world_id: Set(create.world_id as i32),
             created_at: created_at(&self.db),
             gender: Set((create.gender).into()),
             name: Set(create.name),
@@ -191,7 +352,7 @@ impl CharacterService {
             buddy_capacity: Set(20),
             skin: Set(create.skin as u8 as i32),
             face: Set(create.face.0 as i32),
-            hair: Set(create.hair.0 as i32),
+            hair: Set(hair.0 as i32),
             exp: Set(0),
             gacha_exp: Set(0),
             mesos: Set(50_000),
@@ -212,50 +373,116 @@ impl CharacterService {
         Ok(char_id)
     }
 
+    /// Deletes the character, with a grace period the character is only marked as deleted
+    /// and purged by `purge_deleted_characters` once the period passed
     pub async fn delete_character(
         &self,
         acc: &account::Model,
+        world_id: WorldId,
         char_id: CharacterID,
-        pic: &str,
+        pic: Option<&str>,
+        grace: Option<std::time::Duration>,
     ) -> anyhow::Result<DeleteCharResult> {
-        if !self.account.check_pic(acc, pic)? {
-            return Ok(DeleteCharResult::InvalidPic);
+        if let Some(pic) = pic {
+            match self.account.verify_pic(acc, pic) {
+                Ok(true) => {}
+                Ok(false) | Err(AccountServiceError::PicLocked) => {
+                    return Ok(DeleteCharResult::InvalidPic)
+                }
+                Err(err) => return Err(err.into()),
+            }
         }
 
-        let char = self.must_get(char_id).await?;
-        if char.acc_id != acc.id {
+        let Some(char) = self.get(char_id).await? else {
+            return Ok(DeleteCharResult::UnknownErr);
+        };
+        if char.acc_id != acc.id || char.world_id != world_id as i32 || char.deleted_at.is_some() {
             return Ok(DeleteCharResult::UnknownErr);
         }
 
-        /* Check:
-        - world transfer
-        - family
-        - guild
+        /* Guilds, families, marriages, parcels and world transfers are not persisted yet,
+        once they are the following must be rejected here:
+        - guild master => ErrGuildMaster
+        - engaged or married => ErrPendingWedding
+        - family member => ErrHasFamily
+        - pending world transfer => ErrPendingWorldTransfer
+        - pending parcels
         */
 
+        if grace.is_some() {
+            let mut char: ActiveModel = char.into();
+            char.deleted_at = Set(Some(Utc::now().naive_utc()));
+            char.update(&self.db).await?;
+        } else {
+            self.purge_character(char_id).await?;
+        }
+
         Ok(DeleteCharResult::Success)
     }
 
+    /// Removes the character with the inventory and the skills,
+    /// cash items stay in the locker of the account
+    async fn purge_character(&self, char_id: CharacterID) -> anyhow::Result<()> {
+        let txn = self.db.begin().await?;
+        clear_inventory(&txn, char_id).await?;
+        skill::Entity::delete_many()
+            .filter(skill::Column::CharId.eq(char_id))
+            .exec(&txn)
+            .await?;
+        cash_item::Entity::update_many()
+            .col_expr(cash_item::Column::CharId, Expr::value(Option::<i32>::None))
+            .filter(cash_item::Column::CharId.eq(char_id))
+            .exec(&txn)
+            .await?;
+        Entity::delete_by_id(char_id).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Purges soft deleted characters after the grace period, returns the number of
+    /// purged characters
+    pub async fn purge_deleted_characters(
+        &self,
+        grace: std::time::Duration,
+    ) -> anyhow::Result<usize> {
+        let deleted_before = Utc::now().naive_utc() - chrono::Duration::from_std(grace)?;
+        let char_ids: Vec<CharacterID> = Entity::find()
+            .select_only()
+            .column(Column::Id)
+            .filter(Column::DeletedAt.lte(deleted_before))
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+
+        for &char_id in char_ids.iter() {
+            self.purge_character(char_id).await?;
+        }
+        Ok(char_ids.len())
+    }
+
     pub async fn select_char_with_pic(
         &self,
         acc: &account::Model,
+        world_id: WorldId,
         char_id: CharacterID,
         pic: &str,
     ) -> anyhow::Result<SelectCharResultCode> {
-        if !self.account.check_pic(acc, pic)? {
+        if !self.account.verify_pic(acc, pic)? {
             return Ok(SelectCharResultCode::InvalidPic);
         }
 
-        self.select_char(acc, char_id).await
+        self.select_char(acc, world_id, char_id).await
     }
 
+    /// Checks that the character belongs to the account and the selected world
     pub async fn select_char(
         &self,
         acc: &account::Model,
+        world_id: WorldId,
         char_id: CharacterID,
     ) -> anyhow::Result<SelectCharResultCode> {
         let char = self.must_get(char_id).await?;
-        if char.acc_id != acc.id {
+        if char.acc_id != acc.id || char.world_id != world_id as i32 || char.deleted_at.is_some() {
             return Ok(SelectCharResultCode::UnknownErr);
         }
         Ok(SelectCharResultCode::Success)
@@ -268,6 +495,22 @@ impl CharacterService {
             .await?)
     }
 
+    /// Adds mesos to a character, which is not loaded by a session,
+    /// the mesos can't drop below zero or overflow
+    pub async fn add_mesos(&self, char_id: CharacterID, amount: i32) -> anyhow::Result<i32> {
+        let char = self.must_get(char_id).await?;
+        let mesos = char
+            .mesos
+            .checked_add(amount)
+            .filter(|mesos| *mesos >= 0)
+            .ok_or_else(|| anyhow::format_err!("Invalid mesos for {char_id}: {amount}"))?;
+
+        let mut char: ActiveModel = char.into();
+        char.mesos = Set(mesos);
+        char.update(&self.db).await?;
+        Ok(mesos)
+    }
+
     pub async fn save_char(&self, char: character::ActiveModel) -> anyhow::Result<()> {
         char.save(&self.db).await?;
         Ok(())
@@ -1,5 +1,5 @@
 use crate::{
-    entities::{equip_item, inventory_slot, item_stack},
+    entities::{equip_item, inventory_slot, item_stack, pet_item},
     services::{
         helper::intentory::{
             inv::{
@@ -8,21 +8,57 @@ use crate::{
             },
             Inventory,
         },
-        meta::meta_service::MetaService,
+        meta::meta_service::MetaHandle,
         model::item::{EquipItem, EquipStat, StackItem},
     },
 };
 use anyhow::anyhow;
 use itertools::Itertools;
 use num_enum::TryFromPrimitive;
-use proto95::{id::ItemId, shared::inventory::CharEquipSlot};
+use proto95::{
+    id::ItemId,
+    shared::inventory::{self as proto_inv, CharEquipSlot},
+};
 use sea_orm::{
-    ActiveValue::NotSet, ColumnTrait, DatabaseConnection, DeriveColumn, EntityTrait, EnumIter,
-    QueryFilter, QuerySelect, Set,
+    ActiveValue::NotSet, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, DeriveColumn,
+    EntityTrait, EnumIter, QueryFilter, QuerySelect, Set,
 };
 
 use super::character::{CharacterID, ItemStarterSet};
 
+/// Deletes the inventory slots of the character and the items in those slots
+pub(crate) async fn clear_inventory<C: ConnectionTrait>(
+    db: &C,
+    char_id: CharacterID,
+) -> Result<(), DbErr> {
+    let slots = inventory_slot::Entity::find()
+        .filter(inventory_slot::Column::CharId.eq(char_id))
+        .all(db)
+        .await?;
+    inventory_slot::Entity::delete_many()
+        .filter(inventory_slot::Column::CharId.eq(char_id))
+        .exec(db)
+        .await?;
+
+    let equips = slots.iter().filter_map(|slot| slot.equip_item_id);
+    equip_item::Entity::delete_many()
+        .filter(equip_item::Column::Id.is_in(equips))
+        .exec(db)
+        .await?;
+    let stacks = slots.iter().filter_map(|slot| slot.stack_item_id);
+    item_stack::Entity::delete_many()
+        .filter(item_stack::Column::Id.is_in(stacks))
+        .exec(db)
+        .await?;
+    let pets = slots.iter().filter_map(|slot| slot.pet_item_id);
+    pet_item::Entity::delete_many()
+        .filter(pet_item::Column::Id.is_in(pets))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct CharacterEquippedItemIds {
     pub equipped: Vec<(CharEquipSlot, ItemId)>,
@@ -45,7 +81,7 @@ pub struct Inventories {
 #[derive(Debug, Clone)]
 pub struct ItemService {
     db: DatabaseConnection,
-    meta: &'static MetaService,
+    meta: &'static MetaHandle,
 }
 
 fn map_equip_to_active_model(item: &EquipItem) -> equip_item::ActiveModel {
@@ -67,6 +103,7 @@ fn map_equip_to_active_model(item: &EquipItem) -> equip_item::ActiveModel {
         owner_tag: Set(item.owner.clone().unwrap_or(String::new())),
         level: Set(0),
         upgrade_slots: Set(item.slots as i32),
+        upgrade_count: Set(item.upgrades as i32),
         str: Set(stats[EquipStat::Str] as i32),
         dex: Set(stats[EquipStat::Dex] as i32),
         luk: Set(stats[EquipStat::Luk] as i32),
@@ -98,8 +135,138 @@ fn map_stack_to_active_model(item: &StackItem) -> item_stack::ActiveModel {
     }
 }
 
+async fn save_eq_inventory_type<C: ConnectionTrait, const CAP: usize>(
+    db: &C,
+    inv_type: InventoryType,
+    char_id: CharacterID,
+    inv: &mut Inventory<CAP, EquipItemSlot>,
+) -> Result<(), DbErr> {
+    if inv.is_empty() {
+        return Ok(());
+    }
+
+    // Update items
+    for item_slot in inv.items_mut() {
+        let item = &mut item_slot.item;
+        if item.db_id.is_none() {
+            let res = equip_item::Entity::insert(map_equip_to_active_model(item))
+                .exec(db)
+                .await?;
+            item.db_id = Some(res.last_insert_id);
+        } else if item.last_update > 0 {
+            equip_item::Entity::update(map_equip_to_active_model(item))
+                .exec(db)
+                .await?;
+            item.last_update = 0;
+        }
+    }
+
+    let slots = inv
+        .items_with_slot()
+        .map(|(slot, item)| inventory_slot::ActiveModel {
+            id: NotSet,
+            equip_item_id: Set(Some(item.item.db_id.unwrap())),
+            char_id: Set(char_id),
+            slot: Set(slot as u8 as i32),
+            inv_type: Set(inv_type as i32),
+            stack_item_id: Set(None),
+            pet_item_id: Set(None),
+        })
+        .collect_vec();
+
+    inventory_slot::Entity::insert_many(slots).exec(db).await?;
+
+    Ok(())
+}
+
+async fn save_stack_inventory_type<C: ConnectionTrait>(
+    db: &C,
+    inv_type: InventoryType,
+    char_id: CharacterID,
+    inv: &mut StackInventory,
+) -> Result<(), DbErr> {
+    if inv.len() == 0 {
+        return Ok(());
+    }
+
+    // Update items
+    for item_slot in inv.items_mut() {
+        let item = item_slot.item.as_mut();
+        if item.db_id.is_none() {
+            let res = item_stack::Entity::insert(map_stack_to_active_model(item))
+                .exec(db)
+                .await?;
+            item.db_id = Some(res.last_insert_id);
+        } else if item.last_update > 0 {
+            item_stack::Entity::update(map_stack_to_active_model(item))
+                .exec(db)
+                .await?;
+            item.last_update = 0;
+        }
+    }
+
+    let slots = inv
+        .iter()
+        .map(|(slot, item)| inventory_slot::ActiveModel {
+            id: NotSet,
+            equip_item_id: Set(None),
+            char_id: Set(char_id),
+            slot: Set(slot as i32),
+            inv_type: Set(inv_type as i32),
+            stack_item_id: Set(Some(item.item.db_id.unwrap())),
+            pet_item_id: Set(None),
+        })
+        .collect_vec();
+
+    inventory_slot::Entity::insert_many(slots).exec(db).await?;
+
+    Ok(())
+}
+
+/// Replaces the inventory slots of the character,
+/// new items are created and changed items are updated
+pub(crate) async fn save_inventory<C: ConnectionTrait>(
+    db: &C,
+    invs: &mut InventorySet,
+    char_id: CharacterID,
+) -> Result<(), DbErr> {
+    inventory_slot::Entity::delete_many()
+        .filter(inventory_slot::Column::CharId.eq(char_id))
+        .exec(db)
+        .await?;
+
+    save_eq_inventory_type(
+        db,
+        InventoryType::Equipped,
+        char_id,
+        invs.equipped.get_inner_mut(),
+    )
+    .await?;
+    save_eq_inventory_type(
+        db,
+        InventoryType::MaskedEquipped,
+        char_id,
+        invs.masked_equipped.get_inner_mut(),
+    )
+    .await?;
+    save_eq_inventory_type(
+        db,
+        InventoryType::Equip,
+        char_id,
+        invs.equip.get_inner_mut(),
+    )
+    .await?;
+
+    save_stack_inventory_type(db, InventoryType::Use, char_id, &mut invs.use_).await?;
+    save_stack_inventory_type(db, InventoryType::Misc, char_id, &mut invs.misc).await?;
+    save_stack_inventory_type(db, InventoryType::Etc, char_id, &mut invs.etc).await?;
+    save_stack_inventory_type(db, InventoryType::Cash, char_id, &mut invs.cash).await?;
+
+    Ok(())
+}
+
 impl ItemService {
-    pub fn new(db: DatabaseConnection, meta: &'static MetaService) -> Self {
+    pub fn new(db: DatabaseConnection, meta: &'static MetaHandle) -> Self {
         Self { db, meta }
     }
 
@@ -117,6 +284,67 @@ impl ItemService {
         Ok(item)
     }
 
+    /// Adds a new item to the first free slot of the matching inventory,
+    /// returns the inventory type of the client and the slot index
+    pub fn add_item(
+        &self,
+        invs: &mut InventorySet,
+        item_id: ItemId,
+        quantity: u16,
+    ) -> anyhow::Result<(proto_inv::InventoryType, usize)> {
+        // Pets require a pet entry, which is only created for cash shop items
+        if item_id.is_pet() {
+            anyhow::bail!("Pets can not be added: {item_id:?}");
+        }
+
+        let (inv_type, slot) = if item_id.is_equip() {
+            if quantity != 1 {
+                anyhow::bail!("Equips can not be stacked: {quantity}");
+            }
+            let item = self.get_eq_item_from_id(item_id)?;
+            let slot = invs.equip.get_inner_mut().try_add_slot(item.into()).ok();
+            (proto_inv::InventoryType::Equip, slot)
+        } else {
+            let item_meta = self
+                .meta
+                .get_item_data(item_id)
+                .ok_or_else(|| anyhow!("Invalid item: {item_id:?}"))?;
+            if quantity == 0 || (item_meta.slot_max > 0 && quantity as u32 > item_meta.slot_max) {
+                anyhow::bail!("Invalid quantity for {item_id:?}: {quantity}");
+            }
+
+            let (inv_type, ty) = match item_id.0 / 1_000_000 {
+                2 => (proto_inv::InventoryType::Consume, InventoryType::Use),
+                3 => (proto_inv::InventoryType::Install, InventoryType::Misc),
+                4 => (proto_inv::InventoryType::Etc, InventoryType::Etc),
+                5 => (proto_inv::InventoryType::Cash, InventoryType::Cash),
+                _ => anyhow::bail!("Invalid stack item: {item_id:?}"),
+            };
+            let item = self.get_stack_item_from_id(item_id, quantity)?;
+            let slot = invs
+                .get_stack_inventory_mut(ty)?
+                .get_inner_mut()
+                .try_add_slot(item.into())
+                .ok();
+            (inv_type, slot)
+        };
+
+        let slot = slot.ok_or_else(|| anyhow!("Inventory is full: {inv_type:?}"))?;
+        Ok((inv_type, slot))
+    }
+
+    /// Adds the item to the inventory of a character, which is not loaded by a session
+    pub async fn give_item(
+        &self,
+        char_id: CharacterID,
+        item_id: ItemId,
+        quantity: u16,
+    ) -> anyhow::Result<()> {
+        let mut invs = self.load_inventory_for_character(char_id).await?;
+        self.add_item(&mut invs, item_id, quantity)?;
+        self.save_inventory(&mut invs, char_id).await
+    }
+
     pub async fn create_equip(&self, item: &EquipItem) -> anyhow::Result<DbItemId> {
         if item.db_id.is_some() {
             anyhow::bail!("DB id already set");
@@ -197,140 +425,23 @@ impl ItemService {
         inv.etc
             .set(0, self.get_stack_item_from_id(starter_set.guide, 1)?.into());
 
-        self.save_inventory(inv, char_id).await?;
+        self.save_inventory(&mut inv, char_id).await?;
 
         Ok(())
     }
 
     pub async fn clear_inventory(&self, char_id: i32) -> anyhow::Result<()> {
-        inventory_slot::Entity::delete_many()
-            .filter(inventory_slot::Column::CharId.eq(char_id))
-            .exec(&self.db)
-            .await?;
-
-        Ok(())
-    }
-
-    async fn save_eq_inventory_type<'a, const CAP: usize>(
-        &self,
-        inv_type: InventoryType,
-        char_id: i32,
-        inv: &mut Inventory<CAP, EquipItemSlot>,
-    ) -> anyhow::Result<()> {
-        if inv.is_empty() {
-            return Ok(());
-        }
-
-        // Update items
-        for item_slot in inv.items_mut() {
-            let item = &mut item_slot.item;
-            if item.db_id.is_none() {
-                let id = self.create_equip(item).await?;
-                item.db_id = Some(id);
-            } else if item.last_update > 0 {
-                self.update_equip(item).await?;
-                item.last_update = 0;
-            }
-        }
-
-        let slots = inv
-            .items_with_slot()
-            .map(|(slot, item)| inventory_slot::ActiveModel {
-                id: NotSet,
-                equip_item_id: Set(Some(item.item.db_id.unwrap())),
-                char_id: Set(char_id),
-                slot: Set(slot as u8 as i32),
-                inv_type: Set(inv_type as i32),
-                stack_item_id: Set(None),
-                pet_item_id: Set(None),
-            });
-
-        let slots = slots.collect_vec();
-
-        inventory_slot::Entity::insert_many(slots)
-            .exec(&self.db)
-            .await?;
-
-        Ok(())
-    }
-
-    async fn save_stack_inventory_type<'a>(
-        &self,
-        inv_type: InventoryType,
-        char_id: i32,
-        inv: &mut StackInventory,
-    ) -> anyhow::Result<()> {
-        if inv.len() == 0 {
-            return Ok(());
-        }
-
-        // Update items
-        for item_slot in inv.items_mut() {
-            let item = item_slot.item.as_mut();
-            if item.db_id.is_none() {
-                let id = self.create_stack(item).await?;
-                item.db_id = Some(id);
-            } else if item.last_update > 0 {
-                self.update_stack(item).await?;
-                item.last_update = 0;
-            }
-        }
-
-        let slots = inv.iter().map(|(slot, item)| inventory_slot::ActiveModel {
-            id: NotSet,
-            equip_item_id: Set(None),
-            char_id: Set(char_id),
-            slot: Set(slot as i32),
-            inv_type: Set(inv_type as i32),
-            stack_item_id: Set(Some(item.item.db_id.unwrap())),
-            pet_item_id: Set(None),
-        });
-
-        inventory_slot::Entity::insert_many(slots)
-            .exec(&self.db)
-            .await?;
-
-        Ok(())
+        Ok(clear_inventory(&self.db, char_id).await?)
     }
 
+    /// Replaces the inventory slots of the character,
+    /// new items are created and changed items are updated
     pub async fn save_inventory(
         &self,
-        mut invs: InventorySet,
+        invs: &mut InventorySet,
         char_id: CharacterID,
     ) -> anyhow::Result<()> {
-        inventory_slot::Entity::delete_many()
-            .filter(inventory_slot::Column::CharId.eq(char_id))
-            .exec(&self.db)
-            .await?;
-
-        self.save_eq_inventory_type(
-            InventoryType::Equipped,
-            char_id,
-            invs.equipped.get_inner_mut(),
-        )
-        .await?;
-
-        log::info!("Saved acc");
-
-        self.save_eq_inventory_type(
-            InventoryType::MaskedEquipped,
-            char_id,
-            invs.masked_equipped.get_inner_mut(),
-        )
-        .await?;
-
-        self.save_eq_inventory_type(InventoryType::Equip, char_id, invs.equip.get_inner_mut())
-            .await?;
-
-        self.save_stack_inventory_type(InventoryType::Use, char_id, &mut invs.use_)
-            .await?;
-        self.save_stack_inventory_type(InventoryType::Misc, char_id, &mut invs.misc)
-            .await?;
-        self.save_stack_inventory_type(InventoryType::Etc, char_id, &mut invs.etc)
-            .await?;
-        self.save_stack_inventory_type(InventoryType::Cash, char_id, &mut invs.cash)
-            .await?;
-        Ok(())
+        Ok(save_inventory(&self.db, invs, char_id).await?)
     }
 
     pub async fn load_inventory_for_character(&self, char_id: i32) -> anyhow::Result<InventorySet> {
@@ -454,13 +565,13 @@ mod tests {
                 AccountService, CharacterService,
             },
             helper::intentory::inv::InventoryExt,
-            meta::meta_service::MetaService,
+            meta::{char_creation::CharCreationRules, meta_service::MetaHandle},
         },
     };
 
     use super::ItemService;
 
-    fn get_mock_meta() -> &'static MetaService {
+    fn get_mock_meta() -> &'static MetaHandle {
         todo!()
     }
 
@@ -472,7 +583,7 @@ mod tests {
             .create("test", "hunter3", Region::Europe, true, None)
             .await?;
 
-        let char = CharacterService::new(db.clone());
+        let char = CharacterService::new(db.clone(), acc);
         let item_svc = ItemService::new(db.clone(), get_mock_meta());
         let job = JobGroup::Legend;
         let char_id = char
@@ -484,6 +595,7 @@ mod tests {
                     face: FaceId::FEARFUL_STARE_F,
                     skin: Skin::White,
                     hair: HairId::BLACK_TOBEN,
+                    hair_color: 0,
                     starter_set: ItemStarterSet {
                         bottom: job.get_starter_bottoms().next().unwrap(),
                         shoes: job.get_starter_shoes().next().unwrap(),
@@ -492,7 +604,9 @@ mod tests {
                         guide: job.get_guide_item(),
                     },
                     gender: Gender::Male,
+                    world_id: 0,
                 },
+                &CharCreationRules::default(),
                 &item_svc,
             )
             .await?;
@@ -520,7 +634,7 @@ mod tests {
         stack_1.item.quantity += 5;
         stack_1.item.last_update += 1;
 
-        svc.save_inventory(inv, char_id).await.unwrap();
+        svc.save_inventory(&mut inv, char_id).await.unwrap();
         let inv = svc.load_inventory_for_character(char_id).await.unwrap();
         assert_eq!(inv.equipped.len(), 3);
         assert_eq!(inv.etc.get(0).unwrap().quantity, 1 + 5);
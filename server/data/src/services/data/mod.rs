@@ -1,27 +1,40 @@
 pub mod account;
+pub mod ban;
+pub mod cash_shop;
 pub mod character;
 pub mod item;
+pub mod pet;
 
 pub use account::AccountService;
+pub use ban::BanService;
+pub use cash_shop::CashShopService;
 pub use character::CharacterService;
 pub use item::ItemService;
+pub use pet::PetService;
 use sea_orm::DatabaseConnection;
 
-use super::meta::meta_service::MetaService;
+use super::meta::meta_service::MetaHandle;
 
 #[derive(Debug)]
 pub struct DataServices {
     pub account: AccountService,
+    pub ban: BanService,
+    pub cash_shop: CashShopService,
     pub char: CharacterService,
     pub item: ItemService,
+    pub pet: PetService,
 }
 
 impl DataServices {
-    pub fn new(db: DatabaseConnection, meta: &'static MetaService) -> Self {
+    pub fn new(db: DatabaseConnection, meta: &'static MetaHandle) -> Self {
+        let account = AccountService::new(db.clone());
         DataServices {
-            account: AccountService::new(db.clone()),
-            char: CharacterService::new(db.clone()),
-            item: ItemService::new(db, meta),
+            char: CharacterService::new(db.clone(), account.clone()),
+            account,
+            ban: BanService::new(db.clone()),
+            cash_shop: CashShopService::new(db.clone()),
+            item: ItemService::new(db.clone(), meta),
+            pet: PetService::new(db),
         }
     }
 }
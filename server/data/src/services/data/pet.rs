@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use proto95::{id::ItemId, shared::char::CashID};
+use sea_orm::{
+    ActiveValue::NotSet, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Set,
+};
+
+use crate::{
+    entities::pet_item,
+    services::{
+        helper::intentory::inv::{InventoryExt, StackInventory},
+        model::pet::Pet,
+    },
+};
+
+use super::item::DbItemId;
+
+#[derive(Debug, Clone)]
+pub struct PetService {
+    db: DatabaseConnection,
+}
+
+fn map_pet_to_active_model(pet: &Pet) -> pet_item::ActiveModel {
+    let id = pet.db_id.map(Set).unwrap_or(NotSet);
+
+    pet_item::ActiveModel {
+        id,
+        expires_at: Set(pet.expiration),
+        cash_id: Set(Some(pet.cash_id as i64)),
+        item_id: Set(pet.item_id.0 as i32),
+        flags: Set(pet.flags.bits() as i32),
+        name: Set(pet.name.clone()),
+        level: Set(pet.level as i32),
+        tameness: Set(pet.tameness as i32),
+        fullness: Set(pet.fullness as i32),
+        skill: Set(pet.skill as i32),
+        remaining_life: Set(pet.remaining_life as i32),
+        summoned: Set(pet.summoned),
+    }
+}
+
+/// Creates new pets and updates the changed ones
+pub(crate) async fn save_pets<'a, C: ConnectionTrait>(
+    db: &C,
+    pets: impl Iterator<Item = &'a mut Pet>,
+) -> Result<(), DbErr> {
+    for pet in pets {
+        if pet.db_id.is_none() {
+            let res = pet_item::Entity::insert(map_pet_to_active_model(pet))
+                .exec(db)
+                .await?;
+            pet.db_id = Some(res.last_insert_id);
+        } else if pet.last_update > 0 {
+            pet_item::Entity::update(map_pet_to_active_model(pet))
+                .exec(db)
+                .await?;
+        }
+        pet.last_update = 0;
+    }
+
+    Ok(())
+}
+
+impl PetService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    pub async fn create_pet(&self, pet: &Pet) -> anyhow::Result<DbItemId> {
+        if pet.db_id.is_some() {
+            anyhow::bail!("DB id already set");
+        }
+        let res = pet_item::Entity::insert(map_pet_to_active_model(pet))
+            .exec(&self.db)
+            .await?;
+
+        Ok(res.last_insert_id)
+    }
+
+    pub async fn update_pet(&self, pet: &Pet) -> anyhow::Result<()> {
+        if pet.db_id.is_none() {
+            anyhow::bail!("DB id not set");
+        }
+        pet_item::Entity::update(map_pet_to_active_model(pet))
+            .exec(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Creates new pets and updates the changed ones
+    pub async fn save_pets<'a>(
+        &self,
+        pets: impl Iterator<Item = &'a mut Pet>,
+    ) -> anyhow::Result<()> {
+        Ok(save_pets(&self.db, pets).await?)
+    }
+
+    /// Loads the pet for a pet item, which was moved into an inventory
+    pub async fn get_or_create_pet(
+        &self,
+        item_id: ItemId,
+        cash_id: CashID,
+        expiration: Option<NaiveDateTime>,
+    ) -> anyhow::Result<Pet> {
+        let pet = pet_item::Entity::find()
+            .filter(pet_item::Column::CashId.eq(cash_id as i64))
+            .one(&self.db)
+            .await?;
+        if let Some(pet) = pet {
+            return Ok(pet.into());
+        }
+
+        let mut pet = Pet::from_item_id(item_id, cash_id, expiration);
+        pet.db_id = Some(self.create_pet(&pet).await?);
+        Ok(pet)
+    }
+
+    /// Loads the pets for the pet items in the cash inventory,
+    /// pets without stored data are created with the default stats
+    pub async fn load_pets(
+        &self,
+        cash_inv: &StackInventory,
+    ) -> anyhow::Result<BTreeMap<CashID, Pet>> {
+        let pet_items = cash_inv
+            .iter()
+            .filter(|(_, item)| item.item_id.is_pet())
+            .filter_map(|(_, item)| item.item.cash_id.map(|cash_id| (cash_id, item)))
+            .collect::<Vec<_>>();
+
+        let mut pets: BTreeMap<CashID, Pet> = pet_item::Entity::find()
+            .filter(
+                pet_item::Column::CashId
+                    .is_in(pet_items.iter().map(|(cash_id, _)| *cash_id as i64)),
+            )
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|pet| {
+                let pet = Pet::from(pet);
+                (pet.cash_id, pet)
+            })
+            .collect();
+
+        for (cash_id, item) in pet_items {
+            if !pets.contains_key(&cash_id) {
+                let mut pet = Pet::from_item_id(item.item_id, cash_id, item.item.expiration);
+                pet.db_id = Some(self.create_pet(&pet).await?);
+                pets.insert(cash_id, pet);
+            }
+        }
+
+        Ok(pets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proto95::id::ItemId;
+
+    use crate::services::{
+        helper::intentory::inv::{InventoryExt, InventorySet},
+        model::item::StackItem,
+    };
+
+    use super::PetService;
+
+    #[tokio::test]
+    async fn load_save_pets() -> anyhow::Result<()> {
+        let db = crate::gen_sqlite(crate::SQL_OPT_MEMORY).await?;
+        let svc = PetService::new(db);
+
+        let mut inv = InventorySet::with_default_slots();
+        let mut pet_item = StackItem::from_item_id(ItemId(5000000), 1);
+        pet_item.cash_id = Some(10);
+        inv.cash.set(0, pet_item.into());
+        inv.cash
+            .set(1, StackItem::from_item_id(ItemId(5040000), 1).into());
+
+        let mut pets = svc.load_pets(&inv.cash).await?;
+        assert_eq!(pets.len(), 1);
+
+        let pet = pets.get_mut(&10).unwrap();
+        assert!(pet.db_id.is_some());
+        pet.add_tameness(5);
+        pet.set_summoned(true);
+        svc.save_pets(pets.values_mut()).await?;
+
+        let pets = svc.load_pets(&inv.cash).await?;
+        let pet = pets.get(&10).unwrap();
+        assert_eq!((pet.tameness, pet.level), (5, 3));
+        assert!(pet.summoned);
+
+        Ok(())
+    }
+}
@@ -1,31 +1,154 @@
-use std::{ops::Deref, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use dashmap::DashMap;
+use dashmap::{mapref::entry::Entry, DashMap};
+use game_data::map;
 use proto95::{
     game::{
         chat::UserChatMsgResp,
         drop::DropId,
-        mob::{MobLeaveType, MobMoveReq},
-        user::UserMoveReq,
+        mob::{MobId, MobLeaveType, MobMoveReq},
+        pet::{PetActionCommandResp, PetIx, PetLeaveReason},
+        user::{remote::UserItemUpgradeEffectResp, UserMoveReq},
         ObjectId,
     },
     id::MapId,
-    shared::{char::AvatarData, FootholdId, Range2, Vec2},
+    login::world::{ChannelId, WorldId},
+    shared::{char::AvatarData, movement::MovePath, FootholdId, Range2, Vec2},
 };
-use ractor::{Actor, ActorProcessingErr, ActorRef, RpcReplyPort};
-use shroom_net::{net::service::{server_sess::SharedSessionHandle}, PacketBuffer};
+use ractor::{rpc::CallResult, Actor, ActorProcessingErr, ActorRef, RpcReplyPort};
+use shroom_net::{net::service::server_sess::SharedSessionHandle, PacketBuffer};
+use tokio::task::JoinHandle;
 
 use super::{
-    character::Character,
     data::character::CharacterID,
-    helper::pool::{drop::DropLeaveParam, reactor::Reactor, user::User, Drop, Mob, Npc, Pool},
+    helper::pool::{
+        drop::{DropLeaveParam, DropTypeValue},
+        reactor::Reactor,
+        user::{FieldPet, User},
+        Drop, Mob, Npc, Pool,
+    },
     meta::{
         fh_tree::FhTree,
-        meta_service::{FieldMeta, MetaService},
+        meta_service::{FieldMeta, MetaHandle, MetaService, MobMeta},
     },
+    metrics,
     session::ShroomSessionSet,
 };
 
+/// Interval of the field tick, which respawns mobs and expires drops
+const FIELD_TICK: Duration = Duration::from_secs(1);
+
+/// Drops vanish after this duration, which matches the expiration shown by the client
+const DROP_EXPIRATION: Duration = Duration::from_secs(60);
+
+/// Owned drops are free for all after this duration
+const DROP_OWNER_DURATION: Duration = Duration::from_secs(30);
+
+/// Minimum delay until a killed mob respawns
+const MOB_RESPAWN_DELAY: Duration = Duration::from_secs(10);
+
+/// Attempts to join a field, which is shutting down while the user joins
+const JOIN_ATTEMPTS: usize = 3;
+
+/// Empty fields are unloaded after this duration by default
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Id of a single join, so a late leave doesn't remove the user after a rejoin
+pub type JoinId = u64;
+
+pub type InstanceId = u32;
+
+/// Identifies a field instance, every channel of a world has a public instance of each field
+/// and any number of private instances for party quests, boss rooms or tutorials
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FieldKey {
+    pub world_id: WorldId,
+    pub channel_id: ChannelId,
+    pub field_id: MapId,
+    /// Only private instances have an id
+    pub instance: Option<InstanceId>,
+}
+
+impl FieldKey {
+    pub fn public(world_id: WorldId, channel_id: ChannelId, field_id: MapId) -> Self {
+        Self {
+            world_id,
+            channel_id,
+            field_id,
+            instance: None,
+        }
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.instance.is_some()
+    }
+}
+
+/// Character, which picks up a drop within the range of its last known position
+#[derive(Debug, Clone, Copy)]
+pub struct DropPicker {
+    pub char_id: CharacterID,
+    pub pos: Vec2,
+    pub range: i32,
+}
+
+/// Pending move of a character into another field instance,
+/// which is applied by the session of the character
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldWarp {
+    pub key: FieldKey,
+    pub spawn_point: u8,
+}
+
+#[derive(Debug)]
+enum SpawnState {
+    Alive(ObjectId),
+    Respawn(Instant),
+}
+
+/// Mob spawn point of the field, the mob respawns after It was killed
+#[derive(Debug)]
+struct MobSpawn {
+    meta: MobMeta,
+    tmpl_id: MobId,
+    pos: Vec2,
+    fh: FootholdId,
+    delay: Duration,
+    state: SpawnState,
+}
+
+impl MobSpawn {
+    fn mob(&self) -> Mob {
+        Mob {
+            meta: self.meta,
+            tmpl_id: self.tmpl_id,
+            pos: self.pos,
+            fh: self.fh,
+            origin_fh: Some(self.fh),
+            hp: self.meta.max_hp,
+            perc: 100,
+        }
+    }
+}
+
+/// Number of objects in the pools of a field
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FieldStats {
+    pub users: usize,
+    pub mobs: usize,
+    pub npcs: usize,
+    pub drops: usize,
+    pub reactors: usize,
+}
+
+/// State of a single field instance, which is owned by a `FieldActor`
 #[derive(Debug)]
 pub struct FieldData {
     _meta: &'static MetaService,
@@ -37,25 +160,10 @@ pub struct FieldData {
     reactor_pool: Pool<Reactor>,
     user_pool: Pool<User>,
     sessions: ShroomSessionSet,
-}
-
-pub struct FieldJoinHandle {
-    field_data: Arc<FieldData>,
-    char_id: CharacterID,
-}
-
-impl Deref for FieldJoinHandle {
-    type Target = FieldData;
-
-    fn deref(&self) -> &Self::Target {
-        &self.field_data
-    }
-}
-
-impl std::ops::Drop for FieldJoinHandle {
-    fn drop(&mut self) {
-        self.field_data.leave_field(self.char_id)
-    }
+    mob_spawns: Vec<MobSpawn>,
+    drop_expirations: BTreeMap<DropId, Instant>,
+    joins: BTreeMap<CharacterID, JoinId>,
+    next_join: JoinId,
 }
 
 impl FieldData {
@@ -80,29 +188,32 @@ impl FieldData {
                 enabled: true,
             });
 
-        let mobs = field_meta
+        let reactors = field_meta.reactor.values().map(|r| Reactor {
+            pos: Vec2::from((r.x as i16, r.y as i16)),
+            tmpl_id: r.id.parse().unwrap(),
+            state: 0,
+        });
+
+        let mut mob_pool = Pool::new(meta);
+        let mob_spawns = field_meta
             .life
             .values()
             .filter(|life| life._type == "m" && life.hide != Some(1))
             .map(|mob| {
                 let tmpl_id = mob.id.parse().unwrap();
-                let meta = meta.get_mob_data(tmpl_id).unwrap();
-                Mob {
-                    meta,
+                let mut spawn = MobSpawn {
+                    meta: meta.get_mob_data(tmpl_id).unwrap(),
                     tmpl_id,
                     pos: Vec2::from((mob.x as i16, mob.y as i16)),
                     fh: mob.fh as FootholdId,
-                    origin_fh: Some(mob.fh as FootholdId),
-                    hp: meta.max_hp,
-                    perc: 100,
-                }
-            });
-
-        let reactors = field_meta.reactor.values().map(|r| Reactor {
-            pos: Vec2::from((r.x as i16, r.y as i16)),
-            tmpl_id: r.id.parse().unwrap(),
-            state: 0,
-        });
+                    delay: Duration::from_secs(mob.mob_time.unwrap_or(0).max(0) as u64)
+                        .max(MOB_RESPAWN_DELAY),
+                    state: SpawnState::Respawn(Instant::now()),
+                };
+                spawn.state = SpawnState::Alive(mob_pool.insert(spawn.mob()));
+                spawn
+            })
+            .collect();
 
         Self {
             _meta: meta,
@@ -110,19 +221,42 @@ impl FieldData {
             field_fh: fh_meta,
             drop_pool: Pool::new(meta),
             sessions: ShroomSessionSet::new(),
-            mob_pool: Pool::from_elems(meta, mobs),
+            mob_pool,
             npc_pool: Pool::from_elems(meta, npcs),
             reactor_pool: Pool::from_elems(meta, reactors),
             user_pool: Pool::new(meta),
+            mob_spawns,
+            drop_expirations: BTreeMap::new(),
+            joins: BTreeMap::new(),
+            next_join: 0,
         }
     }
 
-    pub async fn enter_field(
-        &self,
+    /// Users, which joined the field, fake users don't count
+    pub fn has_users(&self) -> bool {
+        !self.joins.is_empty()
+    }
+
+    pub fn users(&self) -> Vec<CharacterID> {
+        self.joins.keys().copied().collect()
+    }
+
+    pub fn stats(&self) -> FieldStats {
+        FieldStats {
+            users: self.user_pool.len(),
+            mobs: self.mob_pool.len(),
+            npcs: self.npc_pool.len(),
+            drops: self.drop_pool.len(),
+            reactors: self.reactor_pool.len(),
+        }
+    }
+
+    pub fn enter_field(
+        &mut self,
         char_id: CharacterID,
         mut session: SharedSessionHandle,
         avatar_data: AvatarData,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<JoinId> {
         self.sessions.add(char_id, session.clone());
         self.user_pool.add(
             User {
@@ -130,6 +264,7 @@ impl FieldData {
                 pos: Vec2::from((0, 0)),
                 fh: 1,
                 avatar_data,
+                pets: Default::default(),
             },
             &self.sessions,
         )?;
@@ -142,47 +277,57 @@ impl FieldData {
 
         session.try_send_pkt_buf(&buf)?;
 
-        Ok(())
+        self.next_join += 1;
+        self.joins.insert(char_id, self.next_join);
+        Ok(self.next_join)
     }
 
-    pub fn leave_field(&self, id: CharacterID) {
+    /// Removes the user, unless the user joined the field again meanwhile
+    pub fn leave_field(&mut self, id: CharacterID, join_id: JoinId) -> anyhow::Result<()> {
+        if self.joins.get(&id) != Some(&join_id) {
+            return Ok(());
+        }
+        self.joins.remove(&id);
         self.sessions.remove(id);
-        self.user_pool
-            .remove(id as u32, (), &self.sessions)
-            .expect("Must remove user");
+        self.user_pool.remove(id as u32, (), &self.sessions)?;
+        Ok(())
     }
 
-    pub fn add_user(&self, user: User) -> anyhow::Result<()> {
+    pub fn add_user(&mut self, user: User) -> anyhow::Result<()> {
         self.user_pool.add(user, &self.sessions)?;
         Ok(())
     }
 
-    pub fn remove_user(&self, id: CharacterID) -> anyhow::Result<()> {
+    pub fn remove_user(&mut self, id: CharacterID) -> anyhow::Result<()> {
         self.user_pool.remove(id as u32, (), &self.sessions)?;
         Ok(())
     }
 
-    pub fn add_npc(&self, npc: Npc) -> anyhow::Result<()> {
+    pub fn add_npc(&mut self, npc: Npc) -> anyhow::Result<()> {
         self.npc_pool.add(npc, &self.sessions)?;
         Ok(())
     }
 
-    pub fn remove_npc(&self, id: u32, param: ()) -> anyhow::Result<()> {
+    pub fn remove_npc(&mut self, id: u32, param: ()) -> anyhow::Result<()> {
         self.npc_pool.remove(id, param, &self.sessions)?;
         Ok(())
     }
 
-    pub async fn add_mob(&self, drop: Mob) -> anyhow::Result<()> {
-        self.mob_pool.add(drop, &self.sessions)?;
+    pub fn add_mob(&mut self, mob: Mob) -> anyhow::Result<()> {
+        self.mob_pool.add(mob, &self.sessions)?;
         Ok(())
     }
 
-    pub fn remove_mob(&self, id: u32, param: MobLeaveType) -> anyhow::Result<()> {
+    pub fn remove_mob(&mut self, id: u32, param: MobLeaveType) -> anyhow::Result<()> {
         self.mob_pool.remove(id, param, &self.sessions)?;
         Ok(())
     }
 
-    pub fn update_user_pos(&self, movement: UserMoveReq, id: CharacterID) -> anyhow::Result<()> {
+    pub fn update_user_pos(
+        &mut self,
+        movement: UserMoveReq,
+        id: CharacterID,
+    ) -> anyhow::Result<()> {
         let last_pos_fh = movement.move_path.get_last_pos_fh();
 
         if let Some((pos, fh)) = last_pos_fh {
@@ -198,8 +343,43 @@ impl FieldData {
         Ok(())
     }
 
+    pub fn add_pet(
+        &mut self,
+        id: CharacterID,
+        pet_ix: PetIx,
+        pet: FieldPet,
+        show_effect: bool,
+    ) -> anyhow::Result<()> {
+        self.user_pool
+            .pet_enter(id, pet_ix, pet, show_effect, &self.sessions)
+    }
+
+    pub fn remove_pet(
+        &mut self,
+        id: CharacterID,
+        pet_ix: PetIx,
+        reason: PetLeaveReason,
+    ) -> anyhow::Result<()> {
+        self.user_pool.pet_leave(id, pet_ix, reason, &self.sessions)
+    }
+
+    pub fn update_pet_pos(
+        &mut self,
+        id: CharacterID,
+        pet_ix: PetIx,
+        move_path: MovePath,
+    ) -> anyhow::Result<()> {
+        self.user_pool
+            .pet_move(id, pet_ix, move_path, &self.sessions)
+    }
+
+    pub fn add_pet_action(&self, action: PetActionCommandResp) -> anyhow::Result<()> {
+        metrics::broadcast_pkt(&self.sessions, action, -1)?;
+        Ok(())
+    }
+
     pub fn update_mob_pos(
-        &self,
+        &mut self,
         movement: MobMoveReq,
         controller: CharacterID,
     ) -> anyhow::Result<()> {
@@ -220,14 +400,52 @@ impl FieldData {
         Ok(())
     }
 
-    pub fn add_drop(&self, drop: Drop) -> anyhow::Result<()> {
-        self.drop_pool.add(drop, &self.sessions)?;
+    pub fn add_drop(&mut self, drop: Drop) -> anyhow::Result<()> {
+        let id = self.drop_pool.add(drop, &self.sessions)?;
+        self.drop_expirations
+            .insert(id, Instant::now() + DROP_EXPIRATION);
         Ok(())
     }
 
-    pub fn remove_drop(&self, id: DropId, param: DropLeaveParam) -> anyhow::Result<()> {
-        self.drop_pool.remove(id, param, &self.sessions)?;
-        Ok(())
+    pub fn remove_drop(&mut self, id: DropId, param: DropLeaveParam) -> anyhow::Result<Drop> {
+        self.drop_expirations.remove(&id);
+        self.drop_pool.remove(id, param, &self.sessions)
+    }
+
+    /// Checks that the drop is in range of the picker and not owned by another character
+    pub fn check_pick_up(
+        &self,
+        id: DropId,
+        picker: &DropPicker,
+        now: Instant,
+    ) -> anyhow::Result<&Drop> {
+        let drop = self
+            .drop_pool
+            .get(id)
+            .ok_or_else(|| anyhow::format_err!("No drop: {id}"))?;
+        if !drop.in_range(picker.pos, picker.range) {
+            anyhow::bail!("Drop {id} is out of range");
+        }
+
+        // The expiration is set, when the drop is added
+        let free_for_all = self
+            .drop_expirations
+            .get(&id)
+            .is_some_and(|expiration| *expiration + DROP_OWNER_DURATION <= now + DROP_EXPIRATION);
+        if !drop.can_pick_up(picker.char_id, free_for_all) {
+            anyhow::bail!("Drop {id} is owned by another character");
+        }
+        Ok(drop)
+    }
+
+    pub fn pick_up_drop(
+        &mut self,
+        id: DropId,
+        picker: &DropPicker,
+        param: DropLeaveParam,
+    ) -> anyhow::Result<Drop> {
+        self.check_pick_up(id, picker, Instant::now())?;
+        self.remove_drop(id, param)
     }
 
     pub fn assign_mob_controller(&self, session: SharedSessionHandle) -> anyhow::Result<()> {
@@ -236,23 +454,17 @@ impl FieldData {
     }
 
     pub fn add_chat(&self, chat: UserChatMsgResp) -> anyhow::Result<()> {
-        self.sessions.broadcast_pkt(chat, -1)?;
+        metrics::broadcast_pkt(&self.sessions, chat, -1)?;
         Ok(())
     }
 
-    // TODO: handle various drop items
-    pub fn handle_pickup(&self, item: DropId, char: &mut Character) -> anyhow::Result<()> {
-        match self.drop_pool.is_money(item) {
-            Some(m) => {
-                char.update_mesos(m.try_into().unwrap());
-            }
-            None => {}
-        };
+    pub fn add_item_upgrade_effect(&self, effect: UserItemUpgradeEffectResp) -> anyhow::Result<()> {
+        metrics::broadcast_pkt(&self.sessions, effect, -1)?;
         Ok(())
     }
 
-    pub async fn attack_mob(
-        &self,
+    pub fn attack_mob(
+        &mut self,
         id: ObjectId,
         dmg: u32,
         attacker: CharacterID,
@@ -268,13 +480,48 @@ impl FieldData {
             let mob = self
                 .mob_pool
                 .remove(id, MobLeaveType::Etc(()), &self.sessions)?;
+            if let Some(spawn) = self
+                .mob_spawns
+                .iter_mut()
+                .find(|spawn| matches!(spawn.state, SpawnState::Alive(mob_id) if mob_id == id))
+            {
+                spawn.state = SpawnState::Respawn(Instant::now() + spawn.delay);
+            }
 
             let fh = self
                 .field_fh
                 .get_foothold_below((mob.pos.x as f32, mob.pos.y as f32 - 20.).into());
 
-            self.drop_pool
-                .add_mob_drops(mob.tmpl_id, mob.pos, fh, attacker, &self.sessions)?;
+            let drops =
+                self.drop_pool
+                    .add_mob_drops(mob.tmpl_id, mob.pos, fh, attacker, &self.sessions)?;
+            let expiration = Instant::now() + DROP_EXPIRATION;
+            self.drop_expirations
+                .extend(drops.into_iter().map(|id| (id, expiration)));
+        }
+
+        Ok(())
+    }
+
+    /// Respawns the killed mobs and removes the expired drops
+    pub fn update(&mut self, now: Instant) -> anyhow::Result<()> {
+        for spawn in self.mob_spawns.iter_mut() {
+            if let SpawnState::Respawn(at) = spawn.state {
+                if at <= now {
+                    let id = self.mob_pool.add(spawn.mob(), &self.sessions)?;
+                    spawn.state = SpawnState::Alive(id);
+                }
+            }
+        }
+
+        let expired: Vec<_> = self
+            .drop_expirations
+            .iter()
+            .filter(|(_, expiration)| **expiration <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            self.remove_drop(id, DropLeaveParam::TimeOut)?;
         }
 
         Ok(())
@@ -283,92 +530,751 @@ impl FieldData {
     pub fn get_meta(&self) -> FieldMeta {
         self.field_meta
     }
+
+    /// Spawn point closest to the position
+    pub fn nearest_spawn_point(&self, pos: Vec2) -> u8 {
+        nearest_spawn_point(&self.field_meta.portal, pos)
+    }
+}
+
+/// Portal type of spawn points
+const SPAWN_POINT_TYPE: i64 = 0;
+
+/// Finds the spawn point closest to the position,
+/// falls back to the first portal If the field has no spawn points
+pub fn nearest_spawn_point(portals: &BTreeMap<i64, map::Portal>, pos: Vec2) -> u8 {
+    let dist = |p: &map::Portal| (p.x - pos.x as i64).pow(2) + (p.y - pos.y as i64).pow(2);
+    portals
+        .iter()
+        .filter(|(_, p)| p.pt == SPAWN_POINT_TYPE)
+        .min_by_key(|(_, p)| dist(p))
+        .or_else(|| portals.first_key_value())
+        .map(|(id, _)| *id as u8)
+        .unwrap_or_default()
 }
 
+/// Messages of a field, replies are only used when the sender needs the result
 pub enum FieldMessage {
-    UserEnter(User, RpcReplyPort<FieldJoinHandle>),
-    UserLeave(CharacterID),
+    UserEnter {
+        char_id: CharacterID,
+        session: SharedSessionHandle,
+        avatar_data: AvatarData,
+        reply: RpcReplyPort<anyhow::Result<JoinId>>,
+    },
+    UserLeave(CharacterID, JoinId),
+    AddUser(User),
+    UserMove(CharacterID, UserMoveReq),
+    PetEnter {
+        char_id: CharacterID,
+        pet_ix: PetIx,
+        pet: FieldPet,
+        show_effect: bool,
+    },
+    PetLeave(CharacterID, PetIx, PetLeaveReason),
+    PetMove(CharacterID, PetIx, MovePath),
+    PetAction(PetActionCommandResp),
+    AddMob(Mob),
+    MobMove(CharacterID, MobMoveReq),
+    AssignMobController(SharedSessionHandle),
+    AttackMob {
+        attacker: CharacterID,
+        session: SharedSessionHandle,
+        mob_id: ObjectId,
+        dmg: u32,
+    },
+    AddDrop(Drop),
+    PeekDrop(
+        DropId,
+        DropPicker,
+        RpcReplyPort<anyhow::Result<(DropTypeValue, usize)>>,
+    ),
+    PickUp(
+        DropId,
+        DropPicker,
+        DropLeaveParam,
+        RpcReplyPort<anyhow::Result<Drop>>,
+    ),
+    Chat(UserChatMsgResp),
+    ItemUpgradeEffect(UserItemUpgradeEffectResp),
+    Tick,
+    /// Stops the field once the remaining users left, replies the remaining users
+    Close(RpcReplyPort<Vec<CharacterID>>),
+    Stats(RpcReplyPort<FieldStats>),
+    Users(RpcReplyPort<Vec<CharacterID>>),
+}
+
+type FieldRegistry = Arc<DashMap<FieldKey, ActorRef<FieldActor>>>;
+
+/// Tracks how long a field has been empty
+#[derive(Debug)]
+struct IdleTimer {
+    timeout: Duration,
+    empty_since: Option<Instant>,
+}
+
+impl IdleTimer {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            empty_since: None,
+        }
+    }
+
+    /// Returns true once the field has been empty for the whole timeout
+    fn is_idle(&mut self, has_users: bool, now: Instant) -> bool {
+        if has_users {
+            self.empty_since = None;
+            return false;
+        }
+        let empty_since = *self.empty_since.get_or_insert(now);
+        now.saturating_duration_since(empty_since) >= self.timeout
+    }
+}
+
+pub struct FieldArgs {
+    data: FieldData,
+    key: FieldKey,
+    registry: FieldRegistry,
+    idle_timeout: Duration,
+}
+
+pub struct FieldState {
+    data: FieldData,
+    key: FieldKey,
+    registry: FieldRegistry,
+    ticker: JoinHandle<()>,
+    idle: IdleTimer,
+    closing: bool,
 }
 
+/// Owns the pools of a single field instance,
+/// It stops once the field has been empty for the idle timeout
 pub struct FieldActor;
 
+impl FieldActor {
+    fn handle_msg(message: FieldMessage, state: &mut FieldState) -> anyhow::Result<()> {
+        let field = &mut state.data;
+        match message {
+            FieldMessage::UserEnter {
+                char_id,
+                session,
+                avatar_data,
+                reply,
+            } => {
+                let res = field.enter_field(char_id, session, avatar_data);
+                let join_id = res.as_ref().ok().copied();
+                // The user already left, If the reply is not received
+                if let (Err(_), Some(join_id)) = (reply.send(res), join_id) {
+                    field.leave_field(char_id, join_id)?;
+                }
+            }
+            FieldMessage::UserLeave(char_id, join_id) => field.leave_field(char_id, join_id)?,
+            FieldMessage::AddUser(user) => field.add_user(user)?,
+            FieldMessage::UserMove(char_id, req) => field.update_user_pos(req, char_id)?,
+            FieldMessage::PetEnter {
+                char_id,
+                pet_ix,
+                pet,
+                show_effect,
+            } => field.add_pet(char_id, pet_ix, pet, show_effect)?,
+            FieldMessage::PetLeave(char_id, pet_ix, reason) => {
+                field.remove_pet(char_id, pet_ix, reason)?
+            }
+            FieldMessage::PetMove(char_id, pet_ix, move_path) => {
+                field.update_pet_pos(char_id, pet_ix, move_path)?
+            }
+            FieldMessage::PetAction(action) => field.add_pet_action(action)?,
+            FieldMessage::AddMob(mob) => field.add_mob(mob)?,
+            FieldMessage::MobMove(controller, req) => field.update_mob_pos(req, controller)?,
+            FieldMessage::AssignMobController(session) => field.assign_mob_controller(session)?,
+            FieldMessage::AttackMob {
+                attacker,
+                mut session,
+                mob_id,
+                dmg,
+            } => field.attack_mob(mob_id, dmg, attacker, &mut session)?,
+            FieldMessage::AddDrop(drop) => field.add_drop(drop)?,
+            FieldMessage::PeekDrop(drop_id, picker, reply) => {
+                let res = field
+                    .check_pick_up(drop_id, &picker, Instant::now())
+                    .map(|drop| (drop.value, drop.quantity));
+                let _ = reply.send(res);
+            }
+            FieldMessage::PickUp(drop_id, picker, param, reply) => {
+                // A drop is only picked up once, the remaining requests fail
+                let _ = reply.send(field.pick_up_drop(drop_id, &picker, param));
+            }
+            FieldMessage::Chat(chat) => field.add_chat(chat)?,
+            FieldMessage::ItemUpgradeEffect(effect) => field.add_item_upgrade_effect(effect)?,
+            FieldMessage::Tick => field.update(Instant::now())?,
+            FieldMessage::Close(reply) => {
+                state.closing = true;
+                let _ = reply.send(field.users());
+            }
+            FieldMessage::Stats(reply) => {
+                let _ = reply.send(field.stats());
+            }
+            FieldMessage::Users(reply) => {
+                let _ = reply.send(field.users());
+            }
+        }
+        Ok(())
+    }
+}
+
 #[async_trait::async_trait]
 impl Actor for FieldActor {
     type Msg = FieldMessage;
-    type State = FieldData;
-    type Arguments = FieldData;
+    type State = FieldState;
+    type Arguments = FieldArgs;
 
     async fn pre_start(
         &self,
-        _myself: ActorRef<Self>,
-        field_data: Self::Arguments,
+        myself: ActorRef<Self>,
+        args: Self::Arguments,
     ) -> Result<Self::State, ActorProcessingErr> {
-        Ok(field_data)
+        // The first tick is delayed, so the first user is able to join
+        let ticker = tokio::spawn(async move {
+            let start = tokio::time::Instant::now() + FIELD_TICK;
+            let mut interval = tokio::time::interval_at(start, FIELD_TICK);
+            loop {
+                interval.tick().await;
+                if myself.send_message(FieldMessage::Tick).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(FieldState {
+            data: args.data,
+            key: args.key,
+            registry: args.registry,
+            ticker,
+            idle: IdleTimer::new(args.idle_timeout),
+            closing: false,
+        })
     }
 
     // This is our main message handler
     async fn handle(
         &self,
-        _myself: ActorRef<Self>,
+        myself: ActorRef<Self>,
         message: Self::Msg,
         state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
-        match message {
-            FieldMessage::UserEnter(user, _reply) => {
-                state.add_user(user).unwrap();
-            }
-            FieldMessage::UserLeave(id) => state.remove_user(id)?,
+        // Errors of a single message must not stop the field
+        if let Err(err) = Self::handle_msg(message, state) {
+            log::warn!("Field {:?} failed to handle message: {err:?}", state.key);
         }
+
+        // Joining users get a new instance, once this one stopped
+        let has_users = state.data.has_users();
+        if state.idle.is_idle(has_users, Instant::now()) || (state.closing && !has_users) {
+            myself.stop(None);
+        }
+        Ok(())
+    }
+
+    async fn post_stop(
+        &self,
+        myself: ActorRef<Self>,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        state.ticker.abort();
+        state
+            .registry
+            .remove_if(&state.key, |_, field| field.get_id() == myself.get_id());
+        log::info!("Stopped field {:?}", state.key);
         Ok(())
     }
 }
 
-#[derive(Debug)]
+/// Sends the message to the field and waits for the reply
+async fn call_field<T: Send + 'static>(
+    field: &ActorRef<FieldActor>,
+    msg: impl FnOnce(RpcReplyPort<T>) -> FieldMessage,
+) -> anyhow::Result<T> {
+    match field
+        .call(msg, None)
+        .await
+        .map_err(|err| anyhow::format_err!("Field is unavailable: {err}"))?
+    {
+        CallResult::Success(res) => Ok(res),
+        _ => anyhow::bail!("Field did not reply"),
+    }
+}
+
+/// Handle of a joined user, the user leaves the field when the handle is dropped
+pub struct FieldJoinHandle {
+    field: ActorRef<FieldActor>,
+    key: FieldKey,
+    field_meta: FieldMeta,
+    char_id: CharacterID,
+    join_id: JoinId,
+}
+
+impl std::ops::Drop for FieldJoinHandle {
+    fn drop(&mut self) {
+        // The field might have stopped already
+        let _ = self
+            .field
+            .send_message(FieldMessage::UserLeave(self.char_id, self.join_id));
+    }
+}
+
+impl FieldJoinHandle {
+    fn send(&self, msg: FieldMessage) -> anyhow::Result<()> {
+        self.field
+            .send_message(msg)
+            .map_err(|err| anyhow::format_err!("Field is unavailable: {err}"))
+    }
+
+    pub fn add_user(&self, user: User) -> anyhow::Result<()> {
+        self.send(FieldMessage::AddUser(user))
+    }
+
+    pub fn add_mob(&self, mob: Mob) -> anyhow::Result<()> {
+        self.send(FieldMessage::AddMob(mob))
+    }
+
+    pub fn update_user_pos(&self, movement: UserMoveReq, id: CharacterID) -> anyhow::Result<()> {
+        self.send(FieldMessage::UserMove(id, movement))
+    }
+
+    pub fn add_pet(
+        &self,
+        id: CharacterID,
+        pet_ix: PetIx,
+        pet: FieldPet,
+        show_effect: bool,
+    ) -> anyhow::Result<()> {
+        self.send(FieldMessage::PetEnter {
+            char_id: id,
+            pet_ix,
+            pet,
+            show_effect,
+        })
+    }
+
+    pub fn remove_pet(
+        &self,
+        id: CharacterID,
+        pet_ix: PetIx,
+        reason: PetLeaveReason,
+    ) -> anyhow::Result<()> {
+        self.send(FieldMessage::PetLeave(id, pet_ix, reason))
+    }
+
+    pub fn update_pet_pos(
+        &self,
+        id: CharacterID,
+        pet_ix: PetIx,
+        move_path: MovePath,
+    ) -> anyhow::Result<()> {
+        self.send(FieldMessage::PetMove(id, pet_ix, move_path))
+    }
+
+    pub fn add_pet_action(&self, action: PetActionCommandResp) -> anyhow::Result<()> {
+        self.send(FieldMessage::PetAction(action))
+    }
+
+    pub fn update_mob_pos(
+        &self,
+        movement: MobMoveReq,
+        controller: CharacterID,
+    ) -> anyhow::Result<()> {
+        self.send(FieldMessage::MobMove(controller, movement))
+    }
+
+    pub fn add_drop(&self, drop: Drop) -> anyhow::Result<()> {
+        self.send(FieldMessage::AddDrop(drop))
+    }
+
+    pub fn assign_mob_controller(&self, session: SharedSessionHandle) -> anyhow::Result<()> {
+        self.send(FieldMessage::AssignMobController(session))
+    }
+
+    pub fn add_chat(&self, chat: UserChatMsgResp) -> anyhow::Result<()> {
+        self.send(FieldMessage::Chat(chat))
+    }
+
+    pub fn add_item_upgrade_effect(&self, effect: UserItemUpgradeEffectResp) -> anyhow::Result<()> {
+        self.send(FieldMessage::ItemUpgradeEffect(effect))
+    }
+
+    /// Value and quantity of the drop, If the picker is allowed to pick It up
+    pub async fn peek_drop(
+        &self,
+        id: DropId,
+        picker: DropPicker,
+    ) -> anyhow::Result<(DropTypeValue, usize)> {
+        call_field(&self.field, |reply| {
+            FieldMessage::PeekDrop(id, picker, reply)
+        })
+        .await?
+    }
+
+    /// Removes the drop from the field, the picker has to credit It
+    pub async fn pick_up(
+        &self,
+        id: DropId,
+        picker: DropPicker,
+        param: DropLeaveParam,
+    ) -> anyhow::Result<Drop> {
+        call_field(&self.field, |reply| {
+            FieldMessage::PickUp(id, picker, param, reply)
+        })
+        .await?
+    }
+
+    pub fn attack_mob(
+        &self,
+        id: ObjectId,
+        dmg: u32,
+        attacker: CharacterID,
+        session: &SharedSessionHandle,
+    ) -> anyhow::Result<()> {
+        self.send(FieldMessage::AttackMob {
+            attacker,
+            session: session.clone(),
+            mob_id: id,
+            dmg,
+        })
+    }
+
+    pub fn get_meta(&self) -> FieldMeta {
+        self.field_meta
+    }
+
+    pub fn key(&self) -> FieldKey {
+        self.key
+    }
+
+    /// Spawn point closest to the position
+    pub fn nearest_spawn_point(&self, pos: Vec2) -> u8 {
+        nearest_spawn_point(&self.field_meta.portal, pos)
+    }
+}
+
 pub struct FieldService {
-    fields: DashMap<MapId, Arc<FieldData>>,
-    meta: &'static MetaService,
+    /// Each channel has a separate instance of every field
+    fields: FieldRegistry,
+    warps: DashMap<CharacterID, FieldWarp>,
+    next_instance: AtomicU32,
+    idle_timeout: Duration,
+    meta: &'static MetaHandle,
+}
+
+impl std::fmt::Debug for FieldService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FieldService")
+            .field("fields", &self.fields.len())
+            .field("idle_timeout", &self.idle_timeout)
+            .finish()
+    }
 }
 
 impl FieldService {
-    pub fn new(meta: &'static MetaService) -> Self {
+    pub fn new(meta: &'static MetaHandle) -> Self {
         Self {
-            fields: DashMap::new(),
+            fields: Arc::default(),
+            warps: DashMap::new(),
+            next_instance: AtomicU32::new(1),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
             meta,
         }
     }
 
-    fn create_field(&self, field_id: MapId) -> anyhow::Result<Arc<FieldData>> {
-        let field_meta = self
-            .meta
+    /// Empty fields are unloaded after the timeout, zero unloads them right away
+    pub fn set_idle_timeout(&mut self, timeout: Duration) {
+        self.idle_timeout = timeout;
+    }
+
+    fn get_field_meta(&self, field_id: MapId) -> anyhow::Result<FieldMeta> {
+        self.meta
             .get_field_data(field_id)
-            .ok_or_else(|| anyhow::format_err!("Invalid field id: {field_id:?}"))?;
+            .ok_or_else(|| anyhow::format_err!("Invalid field id: {field_id:?}"))
+    }
+
+    async fn spawn_field(&self, key: FieldKey) -> anyhow::Result<ActorRef<FieldActor>> {
+        // The field keeps this meta data, even If the meta data is reloaded
+        let meta = self.meta.get();
+        let field_meta = meta
+            .get_field_data(key.field_id)
+            .ok_or_else(|| anyhow::format_err!("Invalid field id: {:?}", key.field_id))?;
+        let field_fh = meta
+            .get_field_fh_data(key.field_id)
+            .ok_or_else(|| anyhow::format_err!("No footholds for field: {:?}", key.field_id))?;
+
+        let args = FieldArgs {
+            data: FieldData::new(meta, field_meta, field_fh),
+            key,
+            registry: self.fields.clone(),
+            idle_timeout: self.idle_timeout,
+        };
+        let (field, _) = FieldActor::spawn(None, FieldActor, args)
+            .await
+            .map_err(|err| anyhow::format_err!("Unable to spawn field {key:?}: {err}"))?;
+        Ok(field)
+    }
+
+    /// Public fields are spawned on demand, private instances must be created
+    async fn get_field(&self, key: FieldKey) -> anyhow::Result<ActorRef<FieldActor>> {
+        if let Some(field) = self.fields.get(&key) {
+            return Ok(field.clone());
+        }
+        if key.is_private() {
+            anyhow::bail!("Field instance {key:?} does not exist");
+        }
 
-        let field_fh = self.meta.get_field_fh_data(field_id).unwrap();
+        let field = self.spawn_field(key).await?;
+        // Another user might have spawned the field meanwhile
+        match self.fields.entry(key) {
+            Entry::Occupied(entry) => {
+                field.stop(None);
+                Ok(entry.get().clone())
+            }
+            Entry::Vacant(entry) => Ok(entry.insert(field).clone()),
+        }
+    }
 
-        Ok(Arc::new(FieldData::new(self.meta, field_meta, field_fh)))
+    /// Creates a private instance of the field, which is unloaded like a public field
+    /// once It has been empty for the idle timeout
+    pub async fn create_instance(
+        &self,
+        world_id: WorldId,
+        channel_id: ChannelId,
+        field_id: MapId,
+    ) -> anyhow::Result<FieldKey> {
+        let key = FieldKey {
+            world_id,
+            channel_id,
+            field_id,
+            instance: Some(self.next_instance.fetch_add(1, Ordering::Relaxed)),
+        };
+        let field = self.spawn_field(key).await?;
+        self.fields.insert(key, field);
+        Ok(key)
     }
 
-    pub fn get_field(&self, field_id: MapId) -> anyhow::Result<Arc<FieldData>> {
-        Ok(self
+    /// Closes the private instance, the remaining users are warped to the return field
+    pub async fn destroy_instance(&self, key: FieldKey) -> anyhow::Result<()> {
+        if !key.is_private() {
+            anyhow::bail!("Public field {key:?} can't be destroyed");
+        }
+        let (_, field) = self
             .fields
-            .entry(field_id)
-            .or_try_insert_with(|| self.create_field(field_id))?
-            .clone())
+            .remove(&key)
+            .ok_or_else(|| anyhow::format_err!("Field instance {key:?} does not exist"))?;
+        let users = call_field(&field, FieldMessage::Close).await?;
+
+        let return_field = self
+            .get_field_meta(key.field_id)?
+            .info
+            .return_map
+            .map(|id| MapId(id as u32))
+            .filter(|id| self.meta.get_field_data(*id).is_some())
+            .unwrap_or(key.field_id);
+        let return_key = FieldKey::public(key.world_id, key.channel_id, return_field);
+        self.warp_group(users, return_key, 0);
+        Ok(())
+    }
+
+    /// Warps the characters into the field, the session of each character
+    /// applies the warp with the next packet of the character
+    pub fn warp_group(
+        &self,
+        chars: impl IntoIterator<Item = CharacterID>,
+        key: FieldKey,
+        spawn_point: u8,
+    ) {
+        for char_id in chars {
+            self.warps.insert(char_id, FieldWarp { key, spawn_point });
+        }
+    }
+
+    pub fn take_warp(&self, char_id: CharacterID) -> Option<FieldWarp> {
+        self.warps.remove(&char_id).map(|(_, warp)| warp)
+    }
+
+    fn loaded_fields(&self) -> Vec<(FieldKey, ActorRef<FieldActor>)> {
+        self.fields
+            .iter()
+            .map(|field| (*field.key(), field.value().clone()))
+            .collect()
+    }
+
+    /// Pool sizes of the loaded fields, fields which stop meanwhile are skipped
+    pub async fn stats(&self) -> Vec<(FieldKey, FieldStats)> {
+        let fields = self.loaded_fields();
+        let mut stats = Vec::with_capacity(fields.len());
+        for (key, field) in fields {
+            if let Ok(field_stats) = call_field(&field, FieldMessage::Stats).await {
+                stats.push((key, field_stats));
+            }
+        }
+        stats
     }
 
+    /// Field of every user, which joined a loaded field
+    pub async fn user_fields(&self) -> BTreeMap<CharacterID, FieldKey> {
+        let mut users = BTreeMap::new();
+        for (key, field) in self.loaded_fields() {
+            if let Ok(ids) = call_field(&field, FieldMessage::Users).await {
+                users.extend(ids.into_iter().map(|id| (id, key)));
+            }
+        }
+        users
+    }
+
+    /// Joins the public instance of the field
     pub async fn join_field(
         &self,
         char_id: CharacterID,
         avatar_data: AvatarData,
         session: SharedSessionHandle,
+        world_id: WorldId,
+        channel_id: ChannelId,
         field_id: MapId,
     ) -> anyhow::Result<FieldJoinHandle> {
-        let field = self.get_field(field_id)?;
-        field.enter_field(char_id, session, avatar_data).await?;
-
-        Ok(FieldJoinHandle {
-            field_data: field.clone(),
+        self.join_instance(
             char_id,
-        })
+            avatar_data,
+            session,
+            FieldKey::public(world_id, channel_id, field_id),
+        )
+        .await
+    }
+
+    pub async fn join_instance(
+        &self,
+        char_id: CharacterID,
+        avatar_data: AvatarData,
+        session: SharedSessionHandle,
+        key: FieldKey,
+    ) -> anyhow::Result<FieldJoinHandle> {
+        let field_meta = self.get_field_meta(key.field_id)?;
+        for _ in 0..JOIN_ATTEMPTS {
+            let field = self.get_field(key).await?;
+            let join = call_field(&field, |reply| FieldMessage::UserEnter {
+                char_id,
+                session: session.clone(),
+                avatar_data: avatar_data.clone(),
+                reply,
+            })
+            .await;
+
+            // The field stopped before the user joined, so a new instance is spawned
+            let Ok(join_id) = join else {
+                self.fields
+                    .remove_if(&key, |_, f| f.get_id() == field.get_id());
+                continue;
+            };
+
+            return Ok(FieldJoinHandle {
+                field,
+                key,
+                field_meta,
+                char_id,
+                join_id: join_id?,
+            });
+        }
+
+        anyhow::bail!("Unable to join field {key:?}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        time::{Duration, Instant},
+    };
+
+    use game_data::map::Portal;
+    use proto95::{game::drop::DropOwner, id::ItemId, shared::Vec2};
+
+    use crate::services::helper::pool::drop::{Drop, DropTypeValue};
+
+    use super::{nearest_spawn_point, IdleTimer};
+
+    fn portal(pn: &str, pt: i64, x: i64, y: i64) -> Portal {
+        Portal {
+            pn: pn.to_string(),
+            tm: 999999999,
+            x,
+            horizontal_impact: None,
+            only_once: None,
+            hide_tooltip: None,
+            tn: String::new(),
+            y,
+            script: None,
+            delay: None,
+            pt,
+        }
+    }
+
+    #[test]
+    fn nearest_spawn() {
+        let portals = BTreeMap::from([
+            (0, portal("sp", 0, -500, 100)),
+            (1, portal("sp", 0, 300, 100)),
+            // Regular portals are no spawn points
+            (2, portal("east00", 2, 1000, 100)),
+        ]);
+
+        assert_eq!(nearest_spawn_point(&portals, Vec2::from((-400, 80))), 0);
+        assert_eq!(nearest_spawn_point(&portals, Vec2::from((200, 100))), 1);
+        assert_eq!(nearest_spawn_point(&portals, Vec2::from((1000, 100))), 1);
+
+        let portals = BTreeMap::from([(3, portal("east00", 2, 1000, 100))]);
+        assert_eq!(nearest_spawn_point(&portals, Vec2::default()), 3);
+        assert_eq!(nearest_spawn_point(&BTreeMap::new(), Vec2::default()), 0);
+    }
+
+    #[test]
+    fn idle_timer() {
+        let timeout = Duration::from_secs(60);
+        let mut idle = IdleTimer::new(timeout);
+        let now = Instant::now();
+
+        assert!(!idle.is_idle(true, now));
+        assert!(!idle.is_idle(false, now));
+        assert!(!idle.is_idle(false, now + timeout / 2));
+        assert!(idle.is_idle(false, now + timeout));
+
+        // A joining user resets the timer
+        assert!(!idle.is_idle(true, now + timeout));
+        assert!(!idle.is_idle(false, now + timeout * 2 - Duration::from_secs(1)));
+        assert!(idle.is_idle(false, now + timeout * 3));
+
+        // Without a timeout empty fields are unloaded right away
+        assert!(IdleTimer::new(Duration::ZERO).is_idle(false, now));
+    }
+
+    #[test]
+    fn drop_pick_up() {
+        let drop = Drop {
+            owner: DropOwner::User(1),
+            pos: Vec2::from((100, 50)),
+            start_pos: Vec2::from((100, 50)),
+            value: DropTypeValue::Item(ItemId(2000000)),
+            quantity: 1,
+        };
+
+        assert!(drop.in_range(Vec2::from((100, 50)), 0));
+        assert!(drop.in_range(Vec2::from((-50, 50)), 150));
+        assert!(!drop.in_range(Vec2::from((-51, 50)), 150));
+        assert!(!drop.in_range(Vec2::from((200, 150)), 100));
+
+        assert!(drop.can_pick_up(1, false));
+        assert!(!drop.can_pick_up(2, false));
+        assert!(drop.can_pick_up(2, true));
+
+        let drop = Drop {
+            owner: DropOwner::None,
+            ..drop
+        };
+        assert!(drop.can_pick_up(2, false));
     }
 }
@@ -0,0 +1,79 @@
+use dashmap::DashMap;
+use proto95::id::ItemId;
+
+use super::data::character::CharacterID;
+
+/// Mesos or an item, which is given to a character by an admin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grant {
+    Mesos(i32),
+    Item { id: ItemId, quantity: u16 },
+}
+
+/// Where a grant was applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrantTarget {
+    /// The grant is applied by the connection of the online character
+    Live,
+    /// The grant was written to the database
+    Stored,
+}
+
+/// Grants for online characters, the connection of the character takes them
+/// after handling a packet and before the session is saved on disconnect
+#[derive(Debug, Default)]
+pub struct GrantService {
+    pending: DashMap<CharacterID, Vec<Grant>>,
+}
+
+impl GrantService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, char_id: CharacterID, grant: Grant) {
+        self.pending.entry(char_id).or_default().push(grant);
+    }
+
+    /// Takes the pending grants in the order they were pushed
+    pub fn take(&self, char_id: CharacterID) -> Vec<Grant> {
+        self.pending
+            .remove(&char_id)
+            .map(|(_, grants)| grants)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proto95::id::ItemId;
+
+    use super::{Grant, GrantService};
+
+    #[test]
+    fn take_grants() {
+        let svc = GrantService::new();
+        svc.push(1, Grant::Mesos(100));
+        svc.push(
+            1,
+            Grant::Item {
+                id: ItemId(2000000),
+                quantity: 5,
+            },
+        );
+        svc.push(2, Grant::Mesos(-50));
+
+        assert_eq!(
+            svc.take(1),
+            vec![
+                Grant::Mesos(100),
+                Grant::Item {
+                    id: ItemId(2000000),
+                    quantity: 5
+                }
+            ]
+        );
+        assert!(svc.take(1).is_empty());
+        assert_eq!(svc.take(2), vec![Grant::Mesos(-50)]);
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use num_enum::TryFromPrimitive;
 use proto95::{id::ItemId, shared::inventory::CharEquipSlot};
 use crate::services::model::item::{EquipItem, StackItem};
@@ -242,6 +244,79 @@ impl<const CAP: usize> StackInventory<CAP> {
     pub fn items_mut(&mut self) -> impl Iterator<Item = &mut StackItemSlot> {
         self.0.items_mut()
     }
+
+    fn can_merge(a: &StackItemSlot, b: &StackItemSlot) -> bool {
+        a.item_id == b.item_id
+            && !a.item_id.is_rechargable()
+            && a.item.cash_id.is_none()
+            && b.item.cash_id.is_none()
+            && a.item.expiration == b.item.expiration
+    }
+
+    fn set_quantity(&mut self, slot: usize, quantity: usize) {
+        let item = self.get_mut(slot).expect("Merge slot");
+        item.quantity = quantity;
+        item.item.quantity = quantity as u16;
+        item.item.last_update += 1;
+    }
+
+    /// Merges partial stacks of the same item into the lower slots
+    /// returns the changed slots with their new quantity,
+    /// slots with a quantity of 0 were cleared
+    pub fn merge_stacks(&mut self, slot_max: impl Fn(ItemId) -> usize) -> Vec<(usize, usize)> {
+        let slots = self.iter().map(|(slot, _)| slot).collect::<Vec<_>>();
+        let mut changes = BTreeMap::new();
+
+        for (i, &slot) in slots.iter().enumerate() {
+            for &other in slots[i + 1..].iter() {
+                let (Some(item), Some(other_item)) = (self.get(slot), self.get(other)) else {
+                    continue;
+                };
+                if !Self::can_merge(item, other_item) {
+                    continue;
+                }
+
+                let space = slot_max(item.item_id).saturating_sub(item.quantity);
+                if space == 0 {
+                    break;
+                }
+
+                let n = space.min(other_item.quantity);
+                let (quantity, other_quantity) = (item.quantity + n, other_item.quantity - n);
+                self.set_quantity(slot, quantity);
+                changes.insert(slot, quantity);
+                if other_quantity == 0 {
+                    self.remove(other);
+                } else {
+                    self.set_quantity(other, other_quantity);
+                }
+                changes.insert(other, other_quantity);
+            }
+        }
+
+        changes.into_iter().collect()
+    }
+
+    /// Takes the given quantity from the slot, the slot is cleared once It's empty
+    /// returns the remaining quantity
+    pub fn take_items(&mut self, slot: usize, quantity: usize) -> anyhow::Result<usize> {
+        let item = self
+            .get_mut(slot)
+            .ok_or_else(|| anyhow::format_err!("No item in slot: {slot}"))?;
+        if item.quantity < quantity {
+            anyhow::bail!("Not enough items in slot: {slot}");
+        }
+
+        item.quantity -= quantity;
+        item.item.quantity = item.quantity as u16;
+        item.item.last_update += 1;
+        let remaining = item.quantity;
+        if remaining == 0 {
+            self.remove(slot);
+        }
+
+        Ok(remaining)
+    }
 }
 
 #[derive(Debug, Clone, Copy, TryFromPrimitive)]
@@ -269,6 +344,37 @@ impl InventoryType {
     }
 }
 
+/// Inventory type, slot, db id, last update and quantity of an item
+type ItemLayout = (u8, usize, Option<i32>, u32, usize);
+
+/// Items with their slots and their changes, comparing two layouts tells
+/// whether items were added, moved, removed or changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InventoryLayout(Vec<ItemLayout>);
+
+fn equip_layout<const CAP: usize>(
+    ty: InventoryType,
+    inv: &Inventory<CAP, EquipItemSlot>,
+) -> impl Iterator<Item = ItemLayout> + '_ {
+    inv.items_with_slot()
+        .map(move |(slot, item)| (ty as u8, slot, item.item.db_id, item.item.last_update, 1))
+}
+
+fn stack_layout<const CAP: usize>(
+    ty: InventoryType,
+    inv: &StackInventory<CAP>,
+) -> impl Iterator<Item = ItemLayout> + '_ {
+    inv.iter().map(move |(slot, item)| {
+        (
+            ty as u8,
+            slot,
+            item.item.db_id,
+            item.item.last_update,
+            item.quantity,
+        )
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct InventorySet {
     pub equipped: EquippedInventory,
@@ -336,6 +442,22 @@ impl InventorySet {
         })
     }
 
+    pub fn layout(&self) -> InventoryLayout {
+        InventoryLayout(
+            equip_layout(InventoryType::Equipped, self.equipped.get_inner())
+                .chain(equip_layout(
+                    InventoryType::MaskedEquipped,
+                    self.masked_equipped.get_inner(),
+                ))
+                .chain(equip_layout(InventoryType::Equip, self.equip.get_inner()))
+                .chain(stack_layout(InventoryType::Use, &self.use_))
+                .chain(stack_layout(InventoryType::Misc, &self.misc))
+                .chain(stack_layout(InventoryType::Etc, &self.etc))
+                .chain(stack_layout(InventoryType::Cash, &self.cash))
+                .collect(),
+        )
+    }
+
     pub fn slots(&self, ty: InventoryType) -> usize {
         if ty.is_stack() {
             self.get_stack_inventory(ty).unwrap().slots()
@@ -344,3 +466,56 @@ impl InventorySet {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proto95::id::ItemId;
+
+    use crate::services::model::item::StackItem;
+
+    use super::{InventoryExt, InventorySet, StackInventory};
+
+    #[test]
+    fn layout() {
+        let mut invs = InventorySet::with_default_slots();
+        let layout = invs.layout();
+        assert_eq!(layout, invs.clone().layout());
+
+        let mut potion = StackItem::from_item_id(ItemId(2000000), 10);
+        potion.db_id = Some(1);
+        invs.use_.set(0, potion.into());
+        let saved = invs.layout();
+        assert_ne!(saved, layout);
+
+        // Changed quantities and moved items change the layout
+        invs.use_.take_items(0, 1).unwrap();
+        assert_ne!(invs.layout(), saved);
+        invs.use_
+            .items_mut()
+            .for_each(|item| item.item.last_update = 0);
+        let saved = invs.layout();
+        invs.use_.swap(0, 1);
+        assert_ne!(invs.layout(), saved);
+    }
+
+    #[test]
+    fn merge_stacks() {
+        let potion = ItemId(2000000);
+        let elixir = ItemId(2000004);
+        let mut inv = StackInventory::<8>::new(8);
+        inv.set(0, StackItem::from_item_id(potion, 80).into());
+        inv.set(2, StackItem::from_item_id(elixir, 10).into());
+        inv.set(3, StackItem::from_item_id(potion, 30).into());
+        inv.set(5, StackItem::from_item_id(potion, 50).into());
+        inv.set(6, StackItem::from_item_id(elixir, 5).into());
+
+        let changes = inv.merge_stacks(|_| 100);
+        assert_eq!(changes, [(0, 100), (2, 15), (3, 60), (5, 0), (6, 0)]);
+        assert_eq!(inv.len(), 3);
+        assert_eq!(inv.get(0).unwrap().quantity, 100);
+        assert_eq!(inv.get(3).unwrap().item.quantity, 60);
+        assert!(inv.get(5).is_none());
+
+        assert!(inv.merge_stacks(|_| 100).is_empty());
+    }
+}
@@ -100,7 +100,7 @@ impl<const CAP: usize, Item: InventoryItem> SortedItemVec<CAP, Item> {
 
         let insert_ix = self.find_insert_index_by_id(&item.id());
         self.0.insert(insert_ix, item);
-        self.0.len() - 1
+        insert_ix
     }
 
     pub fn try_add(&mut self, item: Item) -> Result<usize, Item> {
@@ -110,7 +110,7 @@ impl<const CAP: usize, Item: InventoryItem> SortedItemVec<CAP, Item> {
 
         let insert_ix = self.find_insert_index_by_id(&item.id());
         self.0.insert(insert_ix, item);
-        Ok(self.0.len() - 1)
+        Ok(insert_ix)
     }
 
     pub fn remove(&mut self, ix: usize) -> Item {
@@ -258,6 +258,15 @@ where
         Ok(ix)
     }
 
+    /// Like `try_add`, but returns the slot the item was put into
+    pub fn try_add_slot(&mut self, item: Item) -> Result<usize, Item> {
+        let Some(free_slot) = self.find_free_slot() else {
+            return Err(item);
+        };
+        self.try_add(item)?;
+        Ok(free_slot)
+    }
+
     pub fn remove(&mut self, slot: usize) -> Result<Option<Item>, InventoryError> {
         self.check_slot(slot)?;
         let item = self.slot_mapping[slot]
@@ -323,6 +332,7 @@ where
         }
 
         let ix = self.items.add(item);
+        self.update_add(ix);
         self.slot_mapping[slot] = Some(ix as u8);
         Ok(())
     }
@@ -333,6 +343,7 @@ where
         }
 
         let ix = self.items.add(item);
+        self.update_add(ix);
         self.slot_mapping[slot] = Some(ix as u8);
     }
 
@@ -347,47 +358,49 @@ where
     pub fn find_first_shift_slot(&self, after_slot: usize) -> Option<usize> {
         self.slot_mapping
             .iter()
+            .take(self.slots)
             .skip(after_slot)
             .position(|slot| slot.is_some())
-    }
-
-    pub fn shift_slots(&mut self) {
-        // Stores the last known position where an item was
-        let mut last_shift_ix = 0;
-        for gap in 0..CAP {
-            //Check if this  is a gap
-            if self.slot_mapping[gap].is_none() {
-                let after_slot = last_shift_ix.max(gap + 1);
-                //Find shift item
-                let Some(shift_ix) = self.find_first_shift_slot(after_slot) else {
-                    // No more items available
-                    return;
-                };
-
-                //Indices are checked must work
-                self.slot_mapping.swap(gap, shift_ix);
-                last_shift_ix = shift_ix;
+            .map(|pos| pos + after_slot)
+    }
+
+    /// Moves all items into the lowest free slots while keeping their order
+    /// returns the swapped slots in the order they were applied
+    pub fn shift_slots(&mut self) -> Vec<(usize, usize)> {
+        let mut swaps = Vec::new();
+        let mut free_slot = 0;
+        while let Some(slot) = self.find_first_shift_slot(free_slot) {
+            if slot != free_slot {
+                self.slot_mapping.swap(free_slot, slot);
+                swaps.push((slot, free_slot));
             }
+            free_slot += 1;
         }
+
+        swaps
     }
 
-    pub fn sort(&mut self) {
+    /// Sorts the items by their id
+    /// returns the swapped slots in the order they were applied
+    pub fn sort(&mut self) -> Vec<(usize, usize)> {
         // We know the underlying item array is already sorted
         // so sorting the inventory is as simple
-        // as sorting the mappings by the index
+        // as moving every item to the slot of It's index
+        let mut swaps = Vec::new();
         for slot in 0..self.slots {
-            // If the index is set to a slot then we update it's slot
-            let Some(ix) = self.slot_mapping[slot] else {
-                continue
-            };
-
-            let ix = ix as usize;
+            // Follow the cycle until the slot contains the right item
+            while let Some(ix) = self.slot_mapping[slot] {
+                let ix = ix as usize;
+                if ix == slot {
+                    break;
+                }
 
-            if ix != slot {
-                self.slot_mapping.swap(slot, ix)
-                //Log swap here
+                self.slot_mapping.swap(slot, ix);
+                swaps.push((slot, ix));
             }
         }
+
+        swaps
     }
 
     pub fn items(&self) -> impl Iterator<Item = &Item> + '_ {
@@ -499,6 +512,69 @@ mod tests {
         assert!(inv.try_add(2).is_err());
     }
 
+    #[test]
+    fn inventory_slot_mapping() {
+        let mut inv = Inventory::<8, u32>::new(4);
+        inv.set_slot(3, 5);
+        inv.set_slot(1, 1);
+        inv.set_slot(0, 3);
+
+        assert_eq!(inv.get(0).unwrap(), Some(&3));
+        assert_eq!(inv.get(1).unwrap(), Some(&1));
+        assert_eq!(inv.get(2).unwrap(), None);
+        assert_eq!(inv.get(3).unwrap(), Some(&5));
+    }
+
+    #[test]
+    fn inventory_add_slot() {
+        let mut inv = Inventory::<8, u32>::new(4);
+        inv.set_slot(0, 5);
+        inv.set_slot(2, 7);
+
+        // The item index is sorted by id, the slot is the first free one
+        assert_eq!(inv.try_add_slot(1), Ok(1));
+        assert_eq!(inv.get(1).unwrap(), Some(&1));
+        assert_eq!(inv.try_add(3), Ok(1));
+        assert_eq!(inv.get(3).unwrap(), Some(&3));
+        assert_eq!(inv.try_add_slot(9), Err(9));
+    }
+
+    #[test]
+    fn inventory_shift_slots() {
+        let mut inv = Inventory::<8, u32>::new(6);
+        inv.set_slot(1, 5);
+        inv.set_slot(3, 1);
+        inv.set_slot(5, 3);
+
+        let swaps = inv.shift_slots();
+        assert_eq!(swaps, [(1, 0), (3, 1), (5, 2)]);
+        itertools::assert_equal(inv.items_with_slot(), [(0, &5), (1, &1), (2, &3)]);
+
+        // Already shifted
+        assert!(inv.shift_slots().is_empty());
+    }
+
+    #[test]
+    fn inventory_sort() {
+        let mut inv = Inventory::<8, u32>::new(6);
+        inv.set_slot(0, 7);
+        inv.set_slot(2, 5);
+        inv.set_slot(4, 1);
+        inv.set_slot(5, 3);
+
+        let swaps = inv.sort();
+        itertools::assert_equal(inv.items_with_slot(), [(0, &1), (1, &3), (2, &5), (3, &7)]);
+
+        // Replaying the swaps on the original layout must give the same result
+        let mut slots = [Some(7), None, Some(5), None, Some(1), Some(3)];
+        for (a, b) in swaps {
+            slots.swap(a, b);
+        }
+        assert_eq!(slots, [Some(1), Some(3), Some(5), Some(7), None, None]);
+
+        assert!(inv.sort().is_empty());
+    }
+
     #[test]
     fn test_insert() {
         const SLOTS: usize = 4;
@@ -30,7 +30,7 @@ pub struct Drop {
     pub quantity: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum DropTypeValue {
     Mesos(u32),
     Item(ItemId),
@@ -43,11 +43,27 @@ pub enum DropLeaveParam {
     UserPickup(u32),
     MobPickup(u32),
     Explode,
-    PetPickup(u32),
+    PetPickup(u32, u8),
     PassConvex,
     PetSkill,
 }
 
+impl Drop {
+    /// Owned drops can only be picked up by their owner, until they are free for all
+    pub fn can_pick_up(&self, char_id: CharacterID, free_for_all: bool) -> bool {
+        match self.owner {
+            DropOwner::User(owner) => free_for_all || owner == char_id as u32,
+            _ => true,
+        }
+    }
+
+    pub fn in_range(&self, pos: Vec2, range: i32) -> bool {
+        let dx = self.pos.x as i32 - pos.x as i32;
+        let dy = self.pos.y as i32 - pos.y as i32;
+        dx * dx + dy * dy <= range * range
+    }
+}
+
 impl PoolItem for Drop {
     type Id = ObjectId;
 
@@ -88,6 +104,10 @@ impl PoolItem for Drop {
     }
 
     fn get_leave_pkt(&self, id: Self::Id, param: Self::LeaveParam) -> Self::LeavePacket {
+        let pet_ix = match param {
+            DropLeaveParam::PetPickup(_, ix) => Some(ix as u32),
+            _ => None,
+        };
         let (leave_type, pickup_id) = match param {
             DropLeaveParam::Explode => (DropLeaveType::Explode, None),
             DropLeaveParam::PassConvex => (DropLeaveType::PassConvex, None),
@@ -96,24 +116,21 @@ impl PoolItem for Drop {
             DropLeaveParam::TimeOut => (DropLeaveType::TimeOut, None),
             DropLeaveParam::UserPickup(id) => (DropLeaveType::UserPickup, Some(id)),
             DropLeaveParam::MobPickup(id) => (DropLeaveType::MobPickup, Some(id)),
-            DropLeaveParam::PetPickup(id) => (DropLeaveType::PetPickup, Some(id)),
+            DropLeaveParam::PetPickup(id, _) => (DropLeaveType::PetPickup, Some(id)),
         };
 
         DropLeaveFieldResp {
             leave_type,
             id,
             pickup_id: pickup_id.into(),
+            pet_ix: pet_ix.into(),
         }
     }
 }
 
 impl Pool<Drop> {
     pub fn is_money(&self, item: DropId) -> Option<u32> {
-        let pool = match self.items.read() {
-            Ok(map) => map,
-            Err(_) => return None,
-        };
-        match pool.get(&item) {
+        match self.items.get(&item) {
             Some(i) => match i.value {
                 DropTypeValue::Item(_) => None,
                 DropTypeValue::Mesos(m) => Some(m),
@@ -122,16 +139,17 @@ impl Pool<Drop> {
         }
     }
 
+    /// Drops the loot of the killed mob, returns the ids of the drops
     pub fn add_mob_drops(
-        &self,
+        &mut self,
         killed_mob: MobId,
         pos: Vec2,
         fh: Option<&Foothold>,
         killer: CharacterID,
         sessions: &ShroomSessionSet,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<Vec<DropId>> {
         let Some(drops) = self.meta.get_drops_for_mob(killed_mob)  else {
-            return Ok(())
+            return Ok(vec![])
         };
 
         let money = drops.get_money_drop(&mut rand::thread_rng());
@@ -145,8 +163,9 @@ impl Pool<Drop> {
             coord! {x: c.x as i16, y: c.y as i16}
         }
 
+        let mut ids = Vec::with_capacity(n);
         if money > 0 {
-            self.add(
+            let id = self.add(
                 Drop {
                     owner: DropOwner::User(killer as u32),
                     pos: spread
@@ -159,10 +178,11 @@ impl Pool<Drop> {
                 },
                 sessions,
             )?;
+            ids.push(id);
         }
 
         for (item, quantity) in items {
-            self.add(
+            let id = self.add(
                 Drop {
                     owner: DropOwner::User(killer as u32),
                     pos: spread
@@ -175,8 +195,9 @@ impl Pool<Drop> {
                 },
                 sessions,
             )?;
+            ids.push(id);
         }
 
-        Ok(())
+        Ok(ids)
     }
 }
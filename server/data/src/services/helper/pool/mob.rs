@@ -16,7 +16,7 @@ use shroom_net::{
 };
 
 use crate::services::{
-    data::character::CharacterID, meta::meta_service::MobMeta, session::ShroomSessionSet,
+    data::character::CharacterID, meta::meta_service::MobMeta, metrics, session::ShroomSessionSet,
 };
 
 use super::{next_id, Pool, PoolItem};
@@ -93,7 +93,7 @@ impl PoolItem for Mob {
 impl Pool<Mob> {
     pub fn assign_controller(&self, mut session: SharedSessionHandle) -> anyhow::Result<()> {
         //TODO move out loop
-        for (id, mob) in self.items.read().expect("Mob assign controller").iter() {
+        for (id, mob) in self.items.iter() {
             let empty_stats = PartialMobTemporaryStat {
                 hdr: (),
                 data: MobTemporaryStatPartial {
@@ -117,27 +117,29 @@ impl Pool<Mob> {
             .encode_packet(&mut pw)?;
 
             //TODO
-            session.tx.try_send(pw.into_packet().as_ref()).unwrap();
+            let pkt = pw.into_packet();
+            metrics::count_packet_out(pkt.as_ref());
+            session.tx.try_send(pkt.as_ref()).unwrap();
         }
         Ok(())
     }
 
     pub fn attack_mob(
-        &self,
+        &mut self,
         attacker: CharacterID,
         id: ObjectId,
         dmg: u32,
         buf: &mut PacketBuffer,
         sessions: &ShroomSessionSet,
     ) -> anyhow::Result<bool> {
-        // TODO: Locking the whole pool to update a single mob is not right
-        let mut mobs = self.items.write().expect("Mob attack");
-        let mob = mobs
+        let mob = self
+            .items
             .get_mut(&id)
             .ok_or(anyhow::format_err!("Invalid mob"))?;
         mob.damage(dmg);
 
-        sessions.broadcast_pkt(
+        metrics::broadcast_pkt(
+            sessions,
             MobDamagedResp {
                 id,
                 ty: 0,
@@ -148,10 +150,13 @@ impl Pool<Mob> {
             attacker,
         )?;
 
-        buf.write_packet(MobHPIndicatorResp {
-            id,
-            hp_perc: mob.perc,
-        })?;
+        metrics::write_pkt(
+            buf,
+            MobHPIndicatorResp {
+                id,
+                hp_perc: mob.perc,
+            },
+        )?;
 
         Ok(mob.is_dead())
     }
@@ -175,7 +180,7 @@ impl Pool<Mob> {
             move_path: req.move_path.path,
         };
 
-        sessions.broadcast_pkt(pkt, controller)?;
+        metrics::broadcast_pkt(sessions, pkt, controller)?;
         Ok(())
     }
 }
@@ -9,16 +9,13 @@ pub use drop::Drop;
 pub use mob::Mob;
 pub use npc::Npc;
 
-use std::{
-    collections::BTreeMap,
-    sync::{atomic::AtomicU32, RwLock},
-};
+use std::{collections::BTreeMap, sync::atomic::AtomicU32};
 
 use proto95::game::ObjectId;
 use shroom_net::{packet::EncodePacket, HasOpcode, PacketBuffer};
 use std::fmt::Debug;
 
-use crate::services::{meta::meta_service::MetaService, session::ShroomSessionSet};
+use crate::services::{meta::meta_service::MetaService, metrics, session::ShroomSessionSet};
 
 pub fn next_id() -> ObjectId {
     static ID: AtomicU32 = AtomicU32::new(0);
@@ -44,7 +41,7 @@ pub struct Pool<T>
 where
     T: PoolItem<Id = ObjectId>,
 {
-    items: RwLock<BTreeMap<T::Id, T>>,
+    items: BTreeMap<T::Id, T>,
     meta: &'static MetaService,
 }
 
@@ -54,54 +51,70 @@ where
 {
     pub fn new(meta: &'static MetaService) -> Self {
         Self {
-            items: RwLock::new(BTreeMap::new()),
+            items: BTreeMap::new(),
             meta,
         }
     }
     pub fn from_elems(meta: &'static MetaService, elems: impl Iterator<Item = T>) -> Self {
-        let pool = Pool::new(meta);
-        {
-            let mut items = pool.items.try_write().unwrap();
-            items.extend(elems.map(|item| (T::get_id(&item), item)));
+        let mut pool = Pool::new(meta);
+        for item in elems {
+            pool.insert(item);
         }
         pool
     }
 
-    pub fn update(&self, id: ObjectId, update: impl Fn(&mut T)) {
-        let mut items = self.items.write().expect("Pool update");
-        if let Some(item) = items.get_mut(&id) {
+    /// Inserts the item without notifying the sessions
+    pub fn insert(&mut self, item: T) -> T::Id {
+        let id = T::get_id(&item);
+        self.items.insert(id, item);
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn get(&self, id: ObjectId) -> Option<&T> {
+        self.items.get(&id)
+    }
+
+    pub fn update(&mut self, id: ObjectId, update: impl FnOnce(&mut T)) {
+        if let Some(item) = self.items.get_mut(&id) {
             update(item);
         }
     }
 
-    pub fn add(&self, item: T, sessions: &ShroomSessionSet) -> anyhow::Result<u32> {
+    pub fn add(&mut self, item: T, sessions: &ShroomSessionSet) -> anyhow::Result<u32> {
         let id = T::get_id(&item);
         let pkt = item.get_enter_pkt(id);
-        self.items.write().expect("Pool insert").insert(id, item);
+        self.items.insert(id, item);
 
-        sessions.broadcast_pkt(pkt, -1)?;
+        metrics::broadcast_pkt(sessions, pkt, -1)?;
         Ok(id)
     }
 
     pub fn remove(
-        &self,
+        &mut self,
         id: T::Id,
         param: T::LeaveParam,
         sessions: &ShroomSessionSet,
     ) -> anyhow::Result<T> {
-        //TODO migrate to actors
-        let Some(item) = self.items.try_write().unwrap().remove(&id) else {
+        let Some(item) = self.items.remove(&id) else {
             anyhow::bail!("Item does not exist");
         };
 
         let pkt = item.get_leave_pkt(id, param);
-        sessions.broadcast_pkt(pkt, -1)?;
+        metrics::broadcast_pkt(sessions, pkt, -1)?;
         Ok(item)
     }
 
     pub fn on_enter(&self, packet_buf: &mut PacketBuffer) -> anyhow::Result<()> {
-        for (id, pkt) in self.items.read().expect("Pool on enter").iter() {
-            packet_buf.write_packet(pkt.get_enter_pkt(*id))?;
+        for (id, pkt) in self.items.iter() {
+            metrics::write_pkt(packet_buf, pkt.get_enter_pkt(*id))?;
         }
 
         Ok(())
@@ -1,29 +1,61 @@
-use shroom_net::packet::proto::list::ShroomIndexListZ;
 use proto95::{
-    game::user::{
-        remote::{
-            GuildMarkData, TamingMobData, UserEnterFieldResp, UserLeaveFieldResp, UserMoveResp,
-            UserRemoteInitData,
+    game::{
+        pet::{
+            PetActivateResult, PetActivatedResp, PetEnterData, PetIx, PetLeaveReason, PetMoveResp,
+        },
+        user::{
+            remote::{
+                GuildMarkData, PetInitInfo, TamingMobData, UserEnterFieldResp, UserLeaveFieldResp,
+                UserMoveResp, UserRemoteInitData,
+            },
+            UserMoveReq,
         },
-        UserMoveReq,
     },
     id::{job_id::JobId, ItemId},
     shared::{
-        char::{AvatarData, CharacterId, RemoteCharSecondaryStatPartial},
-        Vec2,
+        char::{AvatarData, CashID, CharacterId, RemoteCharSecondaryStatPartial},
+        movement::MovePath,
+        FootholdId, Vec2,
     },
 };
 
-use crate::services::{data::character::CharacterID, session::ShroomSessionSet};
+use crate::services::{
+    data::character::CharacterID, metrics, model::pet::MAX_ACTIVE_PETS, session::ShroomSessionSet,
+};
 
 use super::{Pool, PoolItem};
 
+/// Active pet, which follows the user around the field
+#[derive(Debug, Clone)]
+pub struct FieldPet {
+    pub tmpl_id: ItemId,
+    pub name: String,
+    pub cash_id: CashID,
+    pub pos: Vec2,
+    pub move_action: u8,
+    pub fh: FootholdId,
+}
+
+impl FieldPet {
+    pub fn get_init_info(&self) -> PetInitInfo {
+        PetInitInfo {
+            tmpl_id: self.tmpl_id.0,
+            name: self.name.clone(),
+            pet_locker_sn: self.cash_id,
+            pos_prev: self.pos,
+            move_action: self.move_action,
+            fh: self.fh,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct User {
     pub char_id: CharacterId,
     pub pos: Vec2,
     pub fh: u16,
     pub avatar_data: AvatarData,
+    pub pets: [Option<FieldPet>; MAX_ACTIVE_PETS],
 }
 
 impl PoolItem for User {
@@ -67,7 +99,12 @@ impl PoolItem for User {
                 pos: self.pos,
                 fh: self.fh,
                 show_admin_effects: false,
-                pet_infos: ShroomIndexListZ::default(),
+                pet_infos: self
+                    .pets
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(ix, pet)| Some((ix as u8 + 1, pet.as_ref()?.get_init_info())))
+                    .collect(),
                 taming_mob: TamingMobData::default(),
                 mini_room: None.into(),
                 ad_board: None.into(),
@@ -104,7 +141,76 @@ impl Pool<User> {
             move_path: req.move_path,
         };
 
-        sessions.broadcast_pkt(pkt, id)?;
+        metrics::broadcast_pkt(sessions, pkt, id)?;
+        Ok(())
+    }
+
+    pub fn pet_enter(
+        &mut self,
+        id: CharacterID,
+        pet_ix: PetIx,
+        pet: FieldPet,
+        show_effect: bool,
+        sessions: &ShroomSessionSet,
+    ) -> anyhow::Result<()> {
+        let pkt = PetActivatedResp {
+            char_id: id as u32,
+            pet_ix,
+            result: PetActivateResult::Activated(PetEnterData {
+                show_effect,
+                pet: pet.get_init_info(),
+            }),
+        };
+        self.update(id as u32, |usr| {
+            usr.pets[pet_ix as usize] = Some(pet.clone());
+        });
+
+        metrics::broadcast_pkt(sessions, pkt, -1)?;
+        Ok(())
+    }
+
+    pub fn pet_leave(
+        &mut self,
+        id: CharacterID,
+        pet_ix: PetIx,
+        reason: PetLeaveReason,
+        sessions: &ShroomSessionSet,
+    ) -> anyhow::Result<()> {
+        let pkt = PetActivatedResp {
+            char_id: id as u32,
+            pet_ix,
+            result: PetActivateResult::Deactivated(reason),
+        };
+        self.update(id as u32, |usr| {
+            usr.pets[pet_ix as usize] = None;
+        });
+
+        metrics::broadcast_pkt(sessions, pkt, -1)?;
+        Ok(())
+    }
+
+    pub fn pet_move(
+        &mut self,
+        id: CharacterID,
+        pet_ix: PetIx,
+        move_path: MovePath,
+        sessions: &ShroomSessionSet,
+    ) -> anyhow::Result<()> {
+        if let Some((pos, fh)) = move_path.get_last_pos_fh() {
+            self.update(id as u32, |usr| {
+                if let Some(pet) = usr.pets[pet_ix as usize].as_mut() {
+                    pet.pos = pos;
+                    pet.fh = fh.unwrap_or(pet.fh);
+                }
+            });
+        }
+
+        let pkt = PetMoveResp {
+            char_id: id as u32,
+            pet_ix,
+            move_path,
+        };
+        metrics::broadcast_pkt(sessions, pkt, id)?;
         Ok(())
     }
 }
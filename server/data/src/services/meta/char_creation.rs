@@ -0,0 +1,390 @@
+use game_data::wz2;
+use proto95::{
+    id::{job_id::JobGroup, ItemId, MapId},
+    shared::Gender,
+};
+use sea_orm::DbErr;
+use thiserror::Error;
+
+use crate::services::data::character::CharacterCreateDTO;
+
+pub const CHAR_NAME_LEN: std::ops::RangeInclusive<usize> = 3..=12;
+
+#[derive(Debug, Error)]
+pub enum CharCreateError {
+    #[error("Name must be 3 to 12 alphanumeric characters")]
+    InvalidName,
+    #[error("Name contains a forbidden word")]
+    ForbiddenName,
+    #[error("Name is already in use")]
+    NameInUse,
+    #[error("Job group {0:?} can not be created")]
+    JobNotAvailable(JobGroup),
+    #[error("Invalid {0} ({1}) for the job group")]
+    InvalidChoice(&'static str, u32),
+    #[error("No free character slot")]
+    NoFreeSlot,
+    #[error("database")]
+    Disconnect(#[from] DbErr),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+pub type CharCreateResult<T> = std::result::Result<T, CharCreateError>;
+
+/// Allowed options of the creation screen for a single gender of a job group
+#[derive(Debug, Clone, Default)]
+pub struct CreationChoices {
+    faces: Vec<u32>,
+    hairs: Vec<u32>,
+    hair_colors: Vec<u32>,
+    skins: Vec<u32>,
+    tops: Vec<u32>,
+    bottoms: Vec<u32>,
+    shoes: Vec<u32>,
+    weapons: Vec<u32>,
+}
+
+fn item_ids(items: impl Iterator<Item = ItemId>) -> Vec<u32> {
+    items.map(|id| id.0).collect()
+}
+
+fn check_choice(choices: &[u32], v: u32, name: &'static str) -> CharCreateResult<()> {
+    if !choices.contains(&v) {
+        return Err(CharCreateError::InvalidChoice(name, v));
+    }
+    Ok(())
+}
+
+impl CreationChoices {
+    /// Choices from the hard-coded starter lists of the job group
+    fn from_job_defaults(job: JobGroup) -> Self {
+        Self {
+            faces: job.get_starter_face().map(|id| id.0).collect(),
+            hairs: job.get_starter_hair().map(|id| id.0).collect(),
+            hair_colors: (0..8).collect(),
+            skins: vec![0, 1, 2, 3, 4, 5, 9, 10],
+            tops: item_ids(job.get_starter_tops()),
+            bottoms: item_ids(job.get_starter_bottoms()),
+            shoes: item_ids(job.get_starter_shoes()),
+            weapons: item_ids(job.get_starter_weapons()),
+        }
+    }
+
+    pub fn validate(&self, create: &CharacterCreateDTO) -> CharCreateResult<()> {
+        let set = &create.starter_set;
+        check_choice(&self.faces, create.face.0, "face")?;
+        check_choice(&self.hairs, create.hair.0, "hair")?;
+        check_choice(&self.hair_colors, create.hair_color, "hair color")?;
+        check_choice(&self.skins, create.skin as u8 as u32, "skin")?;
+        check_choice(&self.tops, set.top.0, "top")?;
+        check_choice(&self.bottoms, set.bottom.0, "bottom")?;
+        check_choice(&self.shoes, set.shoes.0, "shoes")?;
+        check_choice(&self.weapons, set.weapon.0, "weapon")?;
+        if set.guide != create.job_group.get_guide_item() {
+            return Err(CharCreateError::InvalidChoice("guide", set.guide.0));
+        }
+        Ok(())
+    }
+}
+
+impl From<&wz2::MakeCharChoices> for CreationChoices {
+    fn from(choices: &wz2::MakeCharChoices) -> Self {
+        Self {
+            faces: choices.faces.clone(),
+            hairs: choices.hairs.clone(),
+            hair_colors: choices.hair_colors.clone(),
+            skins: choices.skins.clone(),
+            tops: choices.tops.clone(),
+            bottoms: choices.bottoms.clone(),
+            shoes: choices.shoes.clone(),
+            weapons: choices.weapons.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct JobRules {
+    job: JobGroup,
+    start_map: MapId,
+    male: CreationChoices,
+    female: CreationChoices,
+}
+
+impl JobRules {
+    fn new(job: JobGroup, male: CreationChoices, female: CreationChoices) -> Self {
+        Self {
+            job,
+            start_map: job.get_start_map(),
+            male,
+            female,
+        }
+    }
+}
+
+/// Rules for the character creation per job group
+#[derive(Debug, Clone)]
+pub struct CharCreationRules {
+    jobs: Vec<JobRules>,
+    /// Lowercase words which must not be contained in a name
+    forbidden_names: Vec<String>,
+}
+
+impl Default for CharCreationRules {
+    fn default() -> Self {
+        Self::from_job_defaults()
+    }
+}
+
+impl CharCreationRules {
+    pub const CREATABLE_JOBS: [JobGroup; 4] = [
+        JobGroup::Adventurer,
+        JobGroup::KnightsOfCygnus,
+        JobGroup::Legend,
+        JobGroup::Evan,
+    ];
+
+    /// Rules from the hard-coded starter lists, used when there's no MakeCharInfo data
+    pub fn from_job_defaults() -> Self {
+        Self {
+            jobs: Self::CREATABLE_JOBS
+                .into_iter()
+                .map(|job| {
+                    let choices = CreationChoices::from_job_defaults(job);
+                    JobRules::new(job, choices.clone(), choices)
+                })
+                .collect(),
+            forbidden_names: Vec::new(),
+        }
+    }
+
+    pub fn from_make_char_info(info: &wz2::MakeCharInfo) -> Self {
+        let mut jobs = Vec::new();
+        if let Some(basic) = info.basic.as_ref() {
+            jobs.push(JobRules::new(
+                JobGroup::Adventurer,
+                (&basic.male).into(),
+                (&basic.female).into(),
+            ));
+        }
+
+        let groups = [
+            (
+                JobGroup::KnightsOfCygnus,
+                &info.premium_male,
+                &info.premium_female,
+            ),
+            (JobGroup::Legend, &info.orient_male, &info.orient_female),
+            (JobGroup::Evan, &info.evan_male, &info.evan_female),
+        ];
+        for (job, male, female) in groups {
+            if let (Some(male), Some(female)) = (male, female) {
+                jobs.push(JobRules::new(job, male.into(), female.into()));
+            }
+        }
+
+        Self {
+            jobs,
+            forbidden_names: Vec::new(),
+        }
+    }
+
+    pub fn with_forbidden_names(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.forbidden_names = names
+            .into_iter()
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+        self
+    }
+
+    /// Replaces start maps which are not available with the fallback map
+    pub fn with_start_map_fallback(
+        mut self,
+        is_available: impl Fn(MapId) -> bool,
+        fallback: MapId,
+    ) -> Self {
+        for rules in self.jobs.iter_mut() {
+            if !is_available(rules.start_map) {
+                log::warn!(
+                    "Start map {:?} of {:?} is not available, using {fallback:?}",
+                    rules.start_map,
+                    rules.job
+                );
+                rules.start_map = fallback;
+            }
+        }
+        self
+    }
+
+    fn get_job(&self, job: JobGroup) -> CharCreateResult<&JobRules> {
+        self.jobs
+            .iter()
+            .find(|rules| rules.job == job)
+            .ok_or(CharCreateError::JobNotAvailable(job))
+    }
+
+    pub fn get_choices(&self, job: JobGroup, gender: Gender) -> CharCreateResult<&CreationChoices> {
+        let rules = self.get_job(job)?;
+        Ok(match gender {
+            Gender::Male => &rules.male,
+            Gender::Female => &rules.female,
+        })
+    }
+
+    pub fn get_start_map(&self, job: JobGroup) -> CharCreateResult<MapId> {
+        Ok(self.get_job(job)?.start_map)
+    }
+
+    /// Checks the name format and the forbidden names, not whether the name is in use
+    pub fn check_name(&self, name: &str) -> CharCreateResult<()> {
+        if !CHAR_NAME_LEN.contains(&name.len()) || !name.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(CharCreateError::InvalidName);
+        }
+
+        let name = name.to_lowercase();
+        if self
+            .forbidden_names
+            .iter()
+            .any(|forbidden| name.contains(forbidden.as_str()))
+        {
+            return Err(CharCreateError::ForbiddenName);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game_data::wz2;
+    use proto95::{
+        id::{job_id::JobGroup, FaceId, HairId, ItemId, MapId, Skin},
+        shared::Gender,
+    };
+
+    use crate::services::data::character::{CharacterCreateDTO, ItemStarterSet};
+
+    use super::{CharCreateError, CharCreationRules};
+
+    fn get_dto(job: JobGroup) -> CharacterCreateDTO {
+        CharacterCreateDTO {
+            name: "Aran".to_string(),
+            job_group: job,
+            face: FaceId::LEISURE_LOOK_M,
+            skin: Skin::Normal,
+            hair: HairId::BLACK_TOBEN,
+            hair_color: 0,
+            starter_set: ItemStarterSet::default_starter_set(job),
+            gender: Gender::Male,
+            world_id: 0,
+        }
+    }
+
+    #[test]
+    fn names() {
+        let rules = CharCreationRules::default()
+            .with_forbidden_names(["GM".to_string(), " admin ".to_string()]);
+
+        assert!(rules.check_name("Aran").is_ok());
+        assert!(matches!(
+            rules.check_name("ab"),
+            Err(CharCreateError::InvalidName)
+        ));
+        assert!(matches!(
+            rules.check_name("Aran_1"),
+            Err(CharCreateError::InvalidName)
+        ));
+        assert!(matches!(
+            rules.check_name("TheAdmin"),
+            Err(CharCreateError::ForbiddenName)
+        ));
+        assert!(matches!(
+            rules.check_name("xGMx"),
+            Err(CharCreateError::ForbiddenName)
+        ));
+    }
+
+    #[test]
+    fn job_defaults() {
+        let rules = CharCreationRules::default();
+        for job in CharCreationRules::CREATABLE_JOBS {
+            let dto = get_dto(job);
+            let choices = rules.get_choices(job, dto.gender).unwrap();
+            assert!(choices.validate(&dto).is_ok());
+        }
+
+        assert!(matches!(
+            rules.get_choices(JobGroup::Resistance, Gender::Male),
+            Err(CharCreateError::JobNotAvailable(JobGroup::Resistance))
+        ));
+
+        let choices = rules
+            .get_choices(JobGroup::Adventurer, Gender::Male)
+            .unwrap();
+        let mut dto = get_dto(JobGroup::Adventurer);
+        dto.hair_color = 9;
+        assert!(matches!(
+            choices.validate(&dto),
+            Err(CharCreateError::InvalidChoice("hair color", 9))
+        ));
+
+        let mut dto = get_dto(JobGroup::Adventurer);
+        dto.starter_set.guide = JobGroup::Legend.get_guide_item();
+        assert!(choices.validate(&dto).is_err());
+
+        let rules =
+            rules.with_start_map_fallback(|map| map == MapId::MUSHROOM_TOWN, MapId::AMHERST);
+        assert_eq!(
+            rules.get_start_map(JobGroup::Adventurer).unwrap(),
+            MapId::MUSHROOM_TOWN
+        );
+        assert_eq!(
+            rules.get_start_map(JobGroup::Legend).unwrap(),
+            MapId::AMHERST
+        );
+    }
+
+    #[test]
+    fn make_char_info() {
+        let info: wz2::MakeCharInfo = serde_json::from_str(
+            r#"{
+                "Info": {
+                    "CharMale": {
+                        "0": { "0": 20000, "1": "20001" },
+                        "1": { "0": 30030, "1": 30020 },
+                        "2": { "0": 0, "1": 7 },
+                        "3": { "0": 0 },
+                        "4": { "0": 1040002 },
+                        "5": { "0": 1060002 },
+                        "6": { "0": 1072001 },
+                        "7": { "0": 1302000 }
+                    },
+                    "CharFemale": {}
+                }
+            }"#,
+        )
+        .unwrap();
+        let rules = CharCreationRules::from_make_char_info(&info);
+
+        let mut dto = get_dto(JobGroup::Adventurer);
+        dto.face = FaceId::PERPLEXED_STARE;
+        dto.hair = HairId::BLACK_BUZZ;
+        dto.hair_color = 7;
+        dto.starter_set.top = ItemId::WHITE_UNDERSHIRT;
+        dto.starter_set.bottom = ItemId::BLUE_JEAN_SHORTS;
+        dto.starter_set.shoes = ItemId::RED_RUBBER_BOOTS;
+        dto.starter_set.weapon = ItemId::SWORD;
+        let male = rules
+            .get_choices(JobGroup::Adventurer, Gender::Male)
+            .unwrap();
+        assert!(male.validate(&dto).is_ok());
+
+        let female = rules
+            .get_choices(JobGroup::Adventurer, Gender::Female)
+            .unwrap();
+        assert!(female.validate(&dto).is_err());
+        assert!(rules.get_choices(JobGroup::Legend, Gender::Male).is_err());
+    }
+}
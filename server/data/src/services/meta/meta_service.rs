@@ -1,7 +1,9 @@
 use std::{
     collections::BTreeMap,
     fs::File,
+    ops::Deref,
     path::{Path, PathBuf},
+    sync::RwLock,
 };
 
 use game_data::{map, wz2};
@@ -13,7 +15,7 @@ use rand::Rng;
 
 use crate::services::model::item::{EquipStat, EquipStats};
 
-use super::fh_tree::FhTree;
+use super::{char_creation::CharCreationRules, fh_tree::FhTree};
 
 #[derive(Debug)]
 pub struct DropEntry {
@@ -80,12 +82,16 @@ pub struct MetaData {
     pub mobs: BTreeMap<u32, wz2::Mob>,
     pub items: BTreeMap<u32, wz2::Item>,
     pub equips: BTreeMap<u32, wz2::Item>,
+    pub commodities: BTreeMap<u32, wz2::Commodity>,
+    pub make_char_info: Option<wz2::MakeCharInfo>,
+    pub forbidden_names: Vec<String>,
 }
 
 pub type FieldMeta = &'static map::Map;
 pub type MobMeta = &'static wz2::Mob;
 pub type ItemMeta = &'static wz2::Item;
 pub type DropsMeta = &'static DropPool;
+pub type CommodityMeta = &'static wz2::Commodity;
 
 impl MetaData {
     fn load_from_file<T: serde::de::DeserializeOwned>(file: impl AsRef<Path>) -> anyhow::Result<T> {
@@ -93,6 +99,37 @@ impl MetaData {
         Ok(bincode::deserialize_from(file)?)
     }
 
+    /// Commodities are keyed by their serial number rather than the index
+    fn load_commodities(dir: PathBuf) -> anyhow::Result<BTreeMap<u32, wz2::Commodity>> {
+        if !dir.exists() {
+            log::warn!("No commodity data found in {dir:?}, cash shop will be empty");
+            return Ok(BTreeMap::new());
+        }
+
+        Ok(wz2::load_all::<wz2::Commodity>(dir)?
+            .into_values()
+            .map(|commodity| (commodity.sn, commodity))
+            .collect())
+    }
+
+    fn load_make_char_info(file: PathBuf) -> anyhow::Result<Option<wz2::MakeCharInfo>> {
+        if !file.exists() {
+            log::warn!("No MakeCharInfo found in {file:?}, using the default starter sets");
+            return Ok(None);
+        }
+
+        Ok(Some(wz2::load_img(file)?))
+    }
+
+    fn load_forbidden_names(file: PathBuf) -> anyhow::Result<Vec<String>> {
+        if !file.exists() {
+            log::warn!("No forbidden names found in {file:?}");
+            return Ok(Vec::new());
+        }
+
+        Ok(wz2::load_img::<wz2::ForbiddenNames>(file)?.into_names())
+    }
+
     pub fn load_from_dir(dir: PathBuf) -> anyhow::Result<Self> {
         let maps0: BTreeMap<i64, map::Map> = Self::load_from_file(dir.join("maps0.rbin"))?;
         Ok(Self {
@@ -104,6 +141,9 @@ impl MetaData {
             mobs: wz2::load_all(dir.join("wz/Mob"))?,
             items: wz2::load_all(dir.join("wz/Item"))?,
             equips: wz2::load_all(dir.join("wz/Equip"))?,
+            commodities: Self::load_commodities(dir.join("wz/Etc/Commodity"))?,
+            make_char_info: Self::load_make_char_info(dir.join("wz/Etc/MakeCharInfo.json"))?,
+            forbidden_names: Self::load_forbidden_names(dir.join("wz/Etc/ForbiddenName.json"))?,
         })
     }
 }
@@ -112,6 +152,7 @@ impl MetaData {
 pub struct MetaService {
     meta_data: MetaData,
     hard_coded_drop_pool: DropPool,
+    char_creation_rules: CharCreationRules,
 }
 
 impl MetaService {
@@ -132,9 +173,23 @@ impl MetaService {
             money: 1_000,
             money_variance: 970,
         };
+        let char_creation_rules = meta_data
+            .make_char_info
+            .as_ref()
+            .map_or_else(
+                CharCreationRules::from_job_defaults,
+                CharCreationRules::from_make_char_info,
+            )
+            .with_forbidden_names(meta_data.forbidden_names.iter().cloned())
+            // Only the maps of the first map group are loaded
+            .with_start_map_fallback(
+                |map| meta_data.maps0.contains_key(&(map.0 as i64)),
+                MapId::AMHERST,
+            );
         Self {
             meta_data,
             hard_coded_drop_pool,
+            char_creation_rules,
         }
     }
 
@@ -164,7 +219,51 @@ impl MetaService {
         self.meta_data.equips.get(&id.0)
     }
 
+    pub fn get_commodity(&self, sn: u32) -> Option<&wz2::Commodity> {
+        self.meta_data.commodities.get(&sn)
+    }
+
+    pub fn get_commodities(&self) -> impl Iterator<Item = &wz2::Commodity> {
+        self.meta_data.commodities.values()
+    }
+
+    pub fn get_char_creation_rules(&self) -> &CharCreationRules {
+        &self.char_creation_rules
+    }
+
     pub fn get_drops_for_mob(&self, _id: MobId) -> Option<&DropPool> {
         Some(&self.hard_coded_drop_pool)
     }
 }
+
+/// Meta data, which can be reloaded at runtime. Replaced data is leaked like the
+/// initial data, because loaded fields and mobs keep references into It
+#[derive(Debug)]
+pub struct MetaHandle {
+    current: RwLock<&'static MetaService>,
+}
+
+impl MetaHandle {
+    pub fn new(meta: MetaService) -> Self {
+        Self {
+            current: RwLock::new(Box::leak(Box::new(meta))),
+        }
+    }
+
+    pub fn get(&self) -> &'static MetaService {
+        *self.current.read().expect("Meta lock")
+    }
+
+    /// Replaces the meta data, loaded fields keep the previous data until they are unloaded
+    pub fn replace(&self, meta: MetaService) {
+        *self.current.write().expect("Meta lock") = Box::leak(Box::new(meta));
+    }
+}
+
+impl Deref for MetaHandle {
+    type Target = MetaService;
+
+    fn deref(&self) -> &Self::Target {
+        self.get()
+    }
+}
@@ -1,2 +1,3 @@
+pub mod char_creation;
 pub mod fh_tree;
-pub mod meta_service;
\ No newline at end of file
+pub mod meta_service;
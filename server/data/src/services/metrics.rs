@@ -0,0 +1,286 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+use proto95::{recv_opcodes::RecvOpcodes, send_opcodes::SendOpcodes};
+use sea_orm::metric;
+use shroom_net::{packet::EncodePacket, HasOpcode, PacketBuffer};
+
+use super::{data::character::CharacterID, session::ShroomSessionSet, Services};
+
+// The collectors are registered in the default registry on their first use
+
+static SESSIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "shroom_sessions",
+        "Connected sessions per channel",
+        &["world", "channel"]
+    )
+    .expect("Sessions metric")
+});
+
+static PACKETS_IN: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "shroom_packets_in_total",
+        "Received packets by opcode",
+        &["opcode"]
+    )
+    .expect("Packets in metric")
+});
+
+static PACKETS_OUT: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "shroom_packets_out_total",
+        "Sent packets by opcode, broadcasts are counted once",
+        &["opcode"]
+    )
+    .expect("Packets out metric")
+});
+
+static UNHANDLED_PACKETS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "shroom_unhandled_packets_total",
+        "Received packets without a handler by opcode",
+        &["opcode"]
+    )
+    .expect("Unhandled packets metric")
+});
+
+static FIELDS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("shroom_fields", "Loaded field instances").expect("Fields metric")
+});
+
+static FIELD_POOL_SIZE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "shroom_field_pool_size",
+        "Objects in the pools of a field",
+        &["world", "channel", "field", "instance", "pool"]
+    )
+    .expect("Field pool size metric")
+});
+
+static PENDING_MIGRATIONS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "shroom_pending_migrations",
+        "Sessions waiting for the client to migrate"
+    )
+    .expect("Pending migrations metric")
+});
+
+static DB_QUERY_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "shroom_db_query_seconds",
+        "Latency of the database queries by statement",
+        &["statement"]
+    )
+    .expect("Database query metric")
+});
+
+static SESSION_SAVE_FAILURES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "shroom_session_save_failures_total",
+        "Sessions which could not be saved"
+    )
+    .expect("Session save failures metric")
+});
+
+static LOGIN_THROTTLE: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "shroom_login_throttle_total",
+        "Login attempts, failures, throttled attempts and lockouts of the login throttle",
+        &["event"]
+    )
+    .expect("Login throttle metric")
+});
+
+static LOGIN_THROTTLE_TRACKED: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "shroom_login_throttle_tracked",
+        "IPs and usernames with recent login failures",
+        &["key"]
+    )
+    .expect("Login throttle tracked metric")
+});
+
+/// Unknown opcodes share one label, so clients can't create a series per opcode
+const UNKNOWN_OPCODE: &str = "unknown";
+
+fn recv_opcode_label(op: u16) -> String {
+    RecvOpcodes::try_from(op).map_or_else(|_| UNKNOWN_OPCODE.to_string(), |op| format!("{op:?}"))
+}
+
+fn send_opcode_label(op: u16) -> String {
+    SendOpcodes::try_from(op).map_or_else(|_| UNKNOWN_OPCODE.to_string(), |op| format!("{op:?}"))
+}
+
+fn count_opcode_out(op: u16) {
+    PACKETS_OUT
+        .with_label_values(&[&send_opcode_label(op)])
+        .inc();
+}
+
+/// Counts the received packet by the opcode in the first two bytes
+pub fn count_packet_in(data: &[u8]) {
+    let label = match data {
+        [lo, hi, ..] => recv_opcode_label(u16::from_le_bytes([*lo, *hi])),
+        _ => "invalid".to_string(),
+    };
+    PACKETS_IN.with_label_values(&[&label]).inc();
+}
+
+/// Counts the encoded packet by the opcode in the first two bytes
+pub fn count_packet_out(data: &[u8]) {
+    match data {
+        [lo, hi, ..] => count_opcode_out(u16::from_le_bytes([*lo, *hi])),
+        _ => PACKETS_OUT.with_label_values(&["invalid"]).inc(),
+    }
+}
+
+/// Counts the packet, which is returned from a handler and sent by the session
+pub fn count_resp<P: HasOpcode>(pkt: P) -> P {
+    count_opcode_out(P::OPCODE.into());
+    pkt
+}
+
+/// Counts packets of the same type, which were encoded once and sent together
+pub fn count_packets_out<P: HasOpcode>(n: usize) {
+    PACKETS_OUT
+        .with_label_values(&[&send_opcode_label(P::OPCODE.into())])
+        .inc_by(n as u64);
+}
+
+/// Writes the packet into the buffer of a session and counts it
+pub fn write_pkt<P: EncodePacket + HasOpcode>(
+    buf: &mut PacketBuffer,
+    pkt: P,
+) -> anyhow::Result<()> {
+    count_opcode_out(P::OPCODE.into());
+    buf.write_packet(pkt)?;
+    Ok(())
+}
+
+pub fn count_unhandled_packet(op: RecvOpcodes) {
+    UNHANDLED_PACKETS
+        .with_label_values(&[&format!("{op:?}")])
+        .inc();
+}
+
+pub fn count_save_failure() {
+    SESSION_SAVE_FAILURES.inc();
+}
+
+/// Broadcasts the packet and counts It once, regardless of the number of receivers
+pub fn broadcast_pkt<P: EncodePacket + HasOpcode>(
+    sessions: &ShroomSessionSet,
+    pkt: P,
+    src: CharacterID,
+) -> anyhow::Result<()> {
+    count_opcode_out(P::OPCODE.into());
+    sessions.broadcast_pkt(pkt, src)?;
+    Ok(())
+}
+
+/// Snapshot of the login throttle counters, the throttle lives in the login server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoginThrottleStats {
+    pub attempts: u64,
+    pub failures: u64,
+    pub throttled: u64,
+    pub lockouts: u64,
+    pub tracked_ips: usize,
+    pub tracked_users: usize,
+}
+
+/// Exports a snapshot of the login throttle, the counters of the throttle only grow
+pub fn set_login_throttle(stats: LoginThrottleStats) {
+    for (event, n) in [
+        ("attempt", stats.attempts),
+        ("failure", stats.failures),
+        ("throttled", stats.throttled),
+        ("lockout", stats.lockouts),
+    ] {
+        let counter = LOGIN_THROTTLE.with_label_values(&[event]);
+        counter.inc_by(n.saturating_sub(counter.get()));
+    }
+    for (key, n) in [("ip", stats.tracked_ips), ("user", stats.tracked_users)] {
+        LOGIN_THROTTLE_TRACKED
+            .with_label_values(&[key])
+            .set(n as i64);
+    }
+}
+
+/// Observes the latency of a database query, used as metric callback of the connection
+pub fn observe_query(info: &metric::Info<'_>) {
+    let statement = info
+        .statement
+        .sql
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_uppercase();
+    DB_QUERY_SECONDS
+        .with_label_values(&[&statement])
+        .observe(info.elapsed.as_secs_f64());
+}
+
+/// Refreshes the gauges from the state of the services and
+/// encodes all metrics in the text format
+pub async fn render(services: &Services) -> anyhow::Result<String> {
+    // Only the sessions of this process are counted
+    SESSIONS.reset();
+    for ((world, channel), n) in services.session_manager.online().channel_population() {
+        SESSIONS
+            .with_label_values(&[&world.to_string(), &channel.to_string()])
+            .set(n as i64);
+    }
+    PENDING_MIGRATIONS.set(services.session_manager.pending_migrations() as i64);
+
+    // Unloaded fields must not be exported anymore
+    let stats = services.field.stats().await;
+    FIELDS.set(stats.len() as i64);
+    FIELD_POOL_SIZE.reset();
+    for (key, stats) in stats {
+        let world = key.world_id.to_string();
+        let channel = key.channel_id.to_string();
+        let field = key.field_id.0.to_string();
+        let instance = key.instance.map(|id| id.to_string()).unwrap_or_default();
+        for (pool, n) in [
+            ("user", stats.users),
+            ("mob", stats.mobs),
+            ("npc", stats.npcs),
+            ("drop", stats.drops),
+            ("reactor", stats.reactors),
+        ] {
+            FIELD_POOL_SIZE
+                .with_label_values(&[&world, &channel, &field, &instance, pool])
+                .set(n as i64);
+        }
+    }
+
+    let mut buf = String::new();
+    TextEncoder::new().encode_utf8(&prometheus::gather(), &mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use proto95::{recv_opcodes::RecvOpcodes, send_opcodes::SendOpcodes};
+
+    use super::{recv_opcode_label, send_opcode_label, UNKNOWN_OPCODE};
+
+    #[test]
+    fn opcode_labels() {
+        assert_eq!(
+            recv_opcode_label(RecvOpcodes::AliveAck.into()),
+            format!("{:?}", RecvOpcodes::AliveAck)
+        );
+        assert_eq!(
+            send_opcode_label(SendOpcodes::AliveReq.into()),
+            format!("{:?}", SendOpcodes::AliveReq)
+        );
+        assert_eq!(send_opcode_label(u16::MAX), UNKNOWN_OPCODE);
+        assert_eq!(recv_opcode_label(u16::MAX), UNKNOWN_OPCODE);
+    }
+}
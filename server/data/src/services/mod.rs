@@ -1,8 +1,10 @@
 pub mod character;
 pub mod data;
 pub mod field;
+pub mod grant;
 pub mod helper;
 pub mod meta;
+pub mod metrics;
 pub mod model;
 pub mod server_info;
 pub mod session;
@@ -11,7 +13,8 @@ use std::{sync::Arc, time::Duration};
 
 use proto95::{
     id::{job_id::JobGroup, FaceId, HairId, Skin},
-    shared::Gender,
+    login::world::{ChannelId, WorldId},
+    shared::{inventory::InventoryType, Gender},
 };
 use sea_orm::{DatabaseConnection, DbErr};
 use server_info::{ServerInfo, ServerService};
@@ -19,14 +22,16 @@ use server_info::{ServerInfo, ServerService};
 use crate::entities::sea_orm_active_enums::GenderTy;
 
 use self::{
+    character::Character,
     data::{
         account::{AccountId, Region},
         character::{CharacterCreateDTO, CharacterID, ItemStarterSet},
         DataServices,
     },
     field::FieldService,
-    meta::meta_service::MetaService,
-    session::{session_data::ShroomSessionBackend, GameSessionManager},
+    grant::{Grant, GrantService, GrantTarget},
+    meta::meta_service::MetaHandle,
+    session::{center::WorldCenter, session_data::ShroomSessionBackend, GameSessionManager},
 };
 
 pub type SharedServices = Arc<Services>;
@@ -37,49 +42,148 @@ pub struct Services {
     pub server_info: ServerService,
     pub session_manager: GameSessionManager<ShroomSessionBackend>,
     pub field: FieldService,
-    pub meta: &'static MetaService,
+    pub grants: GrantService,
+    pub meta: &'static MetaHandle,
 }
 
 impl Services {
     pub fn new(
-        db: DatabaseConnection,
+        mut db: DatabaseConnection,
         servers: impl IntoIterator<Item = ServerInfo>,
-        meta: &'static MetaService,
+        meta: &'static MetaHandle,
     ) -> Self {
-        let data = Arc::new(DataServices::new(db, meta));
+        db.set_metric_callback(metrics::observe_query);
+        let data = Arc::new(DataServices::new(db.clone(), meta));
 
-        let session_backend = ShroomSessionBackend { data: data.clone() };
+        let session_backend = ShroomSessionBackend {
+            data: data.clone(),
+            db,
+        };
 
         Self {
             data,
-            session_manager: GameSessionManager::new(session_backend, Duration::from_secs(30)),
+            session_manager: GameSessionManager::new(
+                session_backend,
+                Duration::from_secs(30),
+                Duration::from_secs(5 * 60),
+            ),
             server_info: ServerService::new(servers),
             field: FieldService::new(meta),
+            grants: GrantService::new(),
             meta,
         }
     }
 
     pub async fn seeded_in_memory(
         servers: impl IntoIterator<Item = ServerInfo>,
-        meta: &'static MetaService,
+        meta: &'static MetaHandle,
     ) -> Result<Self, DbErr> {
         let db = crate::gen_sqlite(crate::SQL_OPT_MEMORY).await?;
         Ok(Self::new(db, servers, meta))
     }
 
+    /// Sqlite database file, which can be shared by the processes of a split deployment
+    pub async fn seeded_in_sqlite(
+        opt: &str,
+        servers: impl IntoIterator<Item = ServerInfo>,
+        meta: &'static MetaHandle,
+    ) -> Result<Self, DbErr> {
+        let db = crate::gen_sqlite(opt).await?;
+        Ok(Self::new(db, servers, meta))
+    }
+
     pub async fn seeded_in_db(
         servers: impl IntoIterator<Item = ServerInfo>,
-        meta: &'static MetaService,
+        meta: &'static MetaHandle,
     ) -> Result<Self, DbErr> {
         let opt = std::env::var("DATABASE_URL").expect("DATABASE_URL not set");
         let db = crate::gen_psql(&opt).await?;
         Ok(Self::new(db, servers, meta))
     }
 
+    /// Hands the migrations of this process over to the center
+    pub fn with_center(
+        mut self,
+        center: Arc<dyn WorldCenter>,
+        server: String,
+        channels: Vec<(WorldId, ChannelId)>,
+    ) -> Self {
+        self.session_manager.set_center(center, server, channels);
+        self
+    }
+
+    /// Empty fields are unloaded after the timeout
+    pub fn with_field_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.field.set_idle_timeout(timeout);
+        self
+    }
+
     pub fn as_shared(self) -> SharedServices {
         Arc::new(self)
     }
 
+    /// Gives the grant to the character, the connection of an online character
+    /// applies It, otherwise It's written to the database. A session, which is loaded
+    /// without a connected client, would overwrite the database, so It's rejected
+    pub async fn give(&self, char_id: CharacterID, grant: Grant) -> anyhow::Result<GrantTarget> {
+        if self.session_manager.is_client(char_id) {
+            self.grants.push(char_id, grant);
+            return Ok(GrantTarget::Live);
+        }
+        // No session can be loaded, until the grant is written
+        let Ok(_reservation) = self.session_manager.reserve_owner(char_id) else {
+            // The client might have connected after the check
+            if self.session_manager.is_client(char_id) {
+                self.grants.push(char_id, grant);
+                return Ok(GrantTarget::Live);
+            }
+            anyhow::bail!("Session of character {char_id} is busy, try again later");
+        };
+
+        match grant {
+            Grant::Mesos(amount) => {
+                self.data.char.add_mesos(char_id, amount).await?;
+            }
+            Grant::Item { id, quantity } => {
+                self.data.item.give_item(char_id, id, quantity).await?;
+            }
+        }
+        Ok(GrantTarget::Stored)
+    }
+
+    /// Applies the grant to a loaded character, returns the inventory slot of an added item.
+    /// Added equips require a db id, so the inventory must be saved afterwards
+    pub fn apply_grant(
+        &self,
+        char: &mut Character,
+        grant: Grant,
+    ) -> anyhow::Result<Option<(InventoryType, usize)>> {
+        match grant {
+            Grant::Mesos(amount) => {
+                if !char.update_mesos(amount) {
+                    anyhow::bail!("Not enough mesos to take: {amount}");
+                }
+                Ok(None)
+            }
+            Grant::Item { id, quantity } => Ok(Some(self.data.item.add_item(
+                &mut char.inventory,
+                id,
+                quantity,
+            )?)),
+        }
+    }
+
+    /// Applies the pending grants of the character without notifying the client,
+    /// used before the session is saved
+    pub fn apply_pending_grants(&self, char: &mut Character) {
+        let char_id = char.model.id;
+        for grant in self.grants.take(char_id) {
+            if let Err(err) = self.apply_grant(char, grant) {
+                log::warn!("Unable to apply {grant:?} to {char_id}: {err:?}");
+            }
+        }
+    }
+
     pub async fn seed_acc_char(&self) -> anyhow::Result<(AccountId, CharacterID)> {
         let acc_id = self
             .data
@@ -93,7 +197,7 @@ impl Services {
             )
             .await?;
 
-        let job = JobGroup::Legend;
+        let job = JobGroup::Adventurer;
         let _char_id = self
             .data
             .char
@@ -105,6 +209,7 @@ impl Services {
                     face: FaceId::LEISURE_LOOK_M,
                     skin: Skin::Normal,
                     hair: HairId::BLACK_TOBEN,
+                    hair_color: 0,
                     starter_set: ItemStarterSet {
                         bottom: job.get_starter_bottoms().next().unwrap(),
                         shoes: job.get_starter_shoes().next().unwrap(),
@@ -113,12 +218,14 @@ impl Services {
                         guide: job.get_guide_item(),
                     },
                     gender: Gender::Male,
+                    world_id: 0,
                 },
+                self.meta.get_char_creation_rules(),
                 &self.data.item,
             )
             .await?;
 
-        let job = JobGroup::Legend;
+        let job = JobGroup::Adventurer;
         let char_id = self
             .data
             .char
@@ -130,6 +237,7 @@ impl Services {
                     face: FaceId::LEISURE_LOOK_M,
                     skin: Skin::Normal,
                     hair: HairId::BLACK_TOBEN,
+                    hair_color: 0,
                     starter_set: ItemStarterSet {
                         bottom: job.get_starter_bottoms().next().unwrap(),
                         shoes: job.get_starter_shoes().next().unwrap(),
@@ -138,7 +246,9 @@ impl Services {
                         guide: job.get_guide_item(),
                     },
                     gender: Gender::Male,
+                    world_id: 0,
                 },
+                self.meta.get_char_creation_rules(),
                 &self.data.item,
             )
             .await?;
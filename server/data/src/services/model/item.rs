@@ -125,7 +125,7 @@ impl From<equip_item::Model> for EquipItem {
                 exp: value.item_exp as u32,
             }),
             slots: value.upgrade_slots as u8,
-            upgrades: 0,
+            upgrades: value.upgrade_count as u8,
             stats,
         }
     }
@@ -189,8 +189,157 @@ impl EquipItem {
 
         true
     }
+
+    /// Slots the item can have in total, base slots + hammers
+    fn total_slots(&self, meta: ItemMeta) -> u8 {
+        (meta.slot_max as u8).saturating_add(self.hammers_used)
+    }
+
+    /// Enhancements are only possible, after all upgrade slots were used
+    pub fn can_use_enhancement(&self) -> bool {
+        self.info.cash_id.is_none() && self.slots == 0
+    }
+
+    pub fn can_use_scroll(&self, scroll: &ScrollInfo, meta: ItemMeta) -> bool {
+        if scroll.item_id.is_enhancement_scroll() {
+            return self.can_use_enhancement() && scroll.item_id.can_scroll_equip(self.item_id);
+        }
+
+        if scroll.item_id.is_clean_slate_scroll() {
+            return self.slots + self.upgrades < self.total_slots(meta);
+        }
+
+        self.slots > 0 && scroll.item_id.can_scroll_equip(self.item_id)
+    }
+
+    /// Applies the scroll, a failed scroll uses up an upgrade slot unless a white scroll is used
+    /// and a cursed scroll might destroy the item
+    pub fn apply_scroll(
+        &mut self,
+        mut rng: impl rand::Rng,
+        scroll: &ScrollInfo,
+        white_scroll: bool,
+    ) -> ScrollResult {
+        if scroll.item_id.is_enhancement_scroll() {
+            return self.apply_enhancement(rng, scroll);
+        }
+
+        let chance = scroll.success as f64 / 100.;
+        let success = if scroll.item_id.is_clean_slate_scroll() {
+            let success = rng.gen_bool(chance);
+            if success {
+                self.slots += 1;
+            }
+            success
+        } else {
+            let success = if scroll.item_id.is_chaos_scroll() {
+                self.apply_chaos_scroll(&mut rng, chance, ScrollInfo::CHAOS_RANGE)
+            } else {
+                let success = rng.gen_bool(chance);
+                if success {
+                    for (stat, val) in self.stats.iter_mut() {
+                        *val = val.saturating_add(scroll.stats[stat]);
+                    }
+                }
+                success
+            };
+
+            if success {
+                self.slots -= 1;
+                self.upgrades += 1;
+            } else if !white_scroll {
+                self.slots -= 1;
+            }
+            success
+        };
+
+        self.info.last_update += 1;
+        if success {
+            ScrollResult::Success
+        } else if scroll.cursed > 0 && rng.gen_bool(scroll.cursed as f64 / 100.) {
+            ScrollResult::Destroyed
+        } else {
+            ScrollResult::Fail
+        }
+    }
+
+    /// Enhancements raise every stat of the equip without using a slot,
+    /// a failed enhancement always destroys the equip
+    fn apply_enhancement(&mut self, mut rng: impl rand::Rng, scroll: &ScrollInfo) -> ScrollResult {
+        self.info.last_update += 1;
+        if !rng.gen_bool(scroll.success as f64 / 100.) {
+            return ScrollResult::Destroyed;
+        }
+
+        for val in self.stats.values_mut().filter(|val| **val > 0) {
+            *val = val.saturating_add(rng.gen_range(ScrollInfo::ENHANCEMENT_RANGE));
+        }
+        ScrollResult::Success
+    }
+
+    /// Cash equips can't be hammered
+    pub fn can_use_hammer(&self) -> bool {
+        self.info.cash_id.is_none()
+            && self.info.item_id.can_use_hammer()
+            && self.hammers_used < MAX_HAMMERS
+    }
+
+    /// Adds an upgrade slot with the hammer
+    pub fn apply_hammer(&mut self) -> bool {
+        if !self.can_use_hammer() {
+            return false;
+        }
+
+        self.hammers_used += 1;
+        self.slots += 1;
+        self.info.last_update += 1;
+        true
+    }
+}
+
+/// Outcome of applying a scroll to an equip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollResult {
+    Success,
+    Fail,
+    Destroyed,
+}
+
+impl ScrollResult {
+    pub fn is_success(&self) -> bool {
+        matches!(self, Self::Success)
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        matches!(self, Self::Destroyed)
+    }
+}
+
+/// Scroll data, success and cursed are chances in percent
+#[derive(Debug, Clone)]
+pub struct ScrollInfo {
+    pub item_id: ItemId,
+    pub success: u32,
+    pub cursed: u32,
+    pub stats: EquipStats,
+}
+
+impl ScrollInfo {
+    pub const CHAOS_RANGE: RangeInclusive<i16> = -5..=5;
+    pub const ENHANCEMENT_RANGE: RangeInclusive<u16> = 1..=3;
+
+    pub fn from_meta(item_id: ItemId, meta: ItemMeta) -> Self {
+        Self {
+            item_id,
+            success: meta.success.min(100),
+            cursed: meta.cursed.min(100),
+            stats: get_equip_stats(meta),
+        }
+    }
 }
 
+pub const MAX_HAMMERS: u8 = 2;
+
 #[derive(Debug, Clone)]
 pub struct StackItem {
     pub info: ItemInfo,
@@ -322,3 +471,103 @@ fn map_item(item: &EquipItem) -> Item {
         unknown1: 0,
     })
 }*/
+
+#[cfg(test)]
+mod tests {
+    use enum_map::EnumMap;
+    use proto95::id::ItemId;
+
+    use super::{EquipItem, EquipStat, ItemInfo, ScrollInfo, ScrollResult};
+
+    fn get_equip() -> EquipItem {
+        EquipItem {
+            info: ItemInfo::from_id(ItemId(1302000)),
+            stats: EnumMap::default(),
+            upgrades: 0,
+            slots: 7,
+            hammers_used: 0,
+            level_info: None,
+        }
+    }
+
+    fn get_scroll(success: u32, cursed: u32) -> ScrollInfo {
+        let mut stats = EnumMap::default();
+        stats[EquipStat::WeaponAtk] = 5;
+        ScrollInfo {
+            item_id: ItemId(2043001),
+            success,
+            cursed,
+            stats,
+        }
+    }
+
+    #[test]
+    fn scroll_success() {
+        let mut eq = get_equip();
+        let res = eq.apply_scroll(rand::thread_rng(), &get_scroll(100, 0), false);
+        assert_eq!(res, ScrollResult::Success);
+        assert_eq!(eq.stats[EquipStat::WeaponAtk], 5);
+        assert_eq!(eq.slots, 6);
+        assert_eq!(eq.upgrades, 1);
+    }
+
+    #[test]
+    fn scroll_fail() {
+        let mut eq = get_equip();
+        let res = eq.apply_scroll(rand::thread_rng(), &get_scroll(0, 0), true);
+        assert_eq!(res, ScrollResult::Fail);
+        assert_eq!(eq.slots, 7);
+
+        let res = eq.apply_scroll(rand::thread_rng(), &get_scroll(0, 0), false);
+        assert_eq!(res, ScrollResult::Fail);
+        assert_eq!(eq.slots, 6);
+        assert_eq!(eq.upgrades, 0);
+
+        let res = eq.apply_scroll(rand::thread_rng(), &get_scroll(0, 100), false);
+        assert_eq!(res, ScrollResult::Destroyed);
+    }
+
+    #[test]
+    fn enhancement() {
+        let mut scroll = get_scroll(100, 0);
+        scroll.item_id = ItemId::EQUIP_ENHANCEMENT_SCROLL;
+        assert!(scroll.item_id.can_scroll_equip(ItemId(1302000)));
+
+        // All slots must be used up
+        let mut eq = get_equip();
+        assert!(!eq.can_use_enhancement());
+        eq.slots = 0;
+        eq.stats[EquipStat::WeaponAtk] = 17;
+        assert!(eq.can_use_enhancement());
+
+        let res = eq.apply_scroll(rand::thread_rng(), &scroll, false);
+        assert_eq!(res, ScrollResult::Success);
+        assert!(ScrollInfo::ENHANCEMENT_RANGE.contains(&(eq.stats[EquipStat::WeaponAtk] - 17)));
+        assert_eq!(eq.stats[EquipStat::Str], 0);
+        assert_eq!(eq.slots, 0);
+        assert_eq!(eq.upgrades, 0);
+
+        scroll.success = 0;
+        let res = eq.apply_scroll(rand::thread_rng(), &scroll, true);
+        assert_eq!(res, ScrollResult::Destroyed);
+    }
+
+    #[test]
+    fn hammer() {
+        let mut eq = get_equip();
+        assert!(eq.apply_hammer());
+        assert!(eq.apply_hammer());
+        assert!(!eq.apply_hammer());
+        assert_eq!(eq.slots, 9);
+        assert_eq!(eq.hammers_used, 2);
+
+        let mut eq = get_equip();
+        eq.info.cash_id = Some(1);
+        assert!(!eq.apply_hammer());
+
+        let mut eq = get_equip();
+        eq.info.item_id = ItemId::HORNTAIL_NECKLACE;
+        assert!(!eq.apply_hammer());
+        assert_eq!(eq.slots, 7);
+    }
+}
@@ -1 +1,2 @@
-pub mod item;
\ No newline at end of file
+pub mod item;
+pub mod pet;
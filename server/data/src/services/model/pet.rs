@@ -0,0 +1,290 @@
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+use proto95::{
+    id::ItemId,
+    shared::{
+        char::CashID,
+        item::{self as proto_item},
+    },
+};
+use rand::Rng;
+
+use crate::{entities::pet_item, proto_mapper::db_to_shroom_time};
+
+pub const MAX_ACTIVE_PETS: usize = 3;
+pub const MAX_PET_LEVEL: u8 = 30;
+pub const MAX_TAMENESS: u16 = 30_000;
+pub const MAX_FULLNESS: u8 = 100;
+/// Name used for pets, which were never named by the owner
+pub const DEFAULT_PET_NAME: &str = "Pet";
+/// Longest pet name in bytes, which fits into the name of the pet item
+pub const MAX_PET_NAME_LEN: usize = 12;
+/// An active pet loses one point of fullness per interval
+pub const HUNGER_INTERVAL: Duration = Duration::from_secs(60);
+/// Chance in percent for a pet command to succeed
+pub const COMMAND_SUCCESS_RATE: u32 = 60;
+
+/// Closeness required to reach the levels 2 to 30
+const CLOSENESS_TABLE: [u16; MAX_PET_LEVEL as usize - 1] = [
+    1, 3, 6, 14, 31, 60, 108, 181, 287, 434, 632, 891, 1224, 1642, 2161, 2793, 3557, 4467, 5542,
+    6801, 8263, 9950, 11882, 14084, 16578, 19391, 22547, 26074, 30000,
+];
+
+pub fn level_for_tameness(tameness: u16) -> u8 {
+    1 + CLOSENESS_TABLE
+        .iter()
+        .take_while(|&&req| req <= tameness)
+        .count() as u8
+}
+
+#[derive(Debug, Clone)]
+pub struct Pet {
+    pub db_id: Option<i32>,
+    pub item_id: ItemId,
+    pub cash_id: CashID,
+    pub name: String,
+    pub level: u8,
+    pub tameness: u16,
+    pub fullness: u8,
+    pub skill: u16,
+    pub remaining_life: u32,
+    pub expiration: Option<NaiveDateTime>,
+    pub flags: proto_item::ItemPetFlags,
+    pub summoned: bool,
+    pub last_update: u32,
+}
+
+impl From<pet_item::Model> for Pet {
+    fn from(value: pet_item::Model) -> Self {
+        Self {
+            db_id: Some(value.id),
+            item_id: ItemId(value.item_id as u32),
+            cash_id: value.cash_id.unwrap_or_default() as CashID,
+            name: value.name,
+            level: value.level as u8,
+            tameness: value.tameness as u16,
+            fullness: value.fullness as u8,
+            skill: value.skill as u16,
+            remaining_life: value.remaining_life as u32,
+            expiration: value.expires_at,
+            flags: proto_item::ItemPetFlags::from_bits_truncate(value.flags as u16),
+            summoned: value.summoned,
+            last_update: 0,
+        }
+    }
+}
+
+impl Pet {
+    pub fn from_item_id(
+        item_id: ItemId,
+        cash_id: CashID,
+        expiration: Option<NaiveDateTime>,
+    ) -> Self {
+        Self {
+            db_id: None,
+            item_id,
+            cash_id,
+            name: DEFAULT_PET_NAME.to_string(),
+            level: 1,
+            tameness: 0,
+            fullness: MAX_FULLNESS,
+            skill: 0,
+            remaining_life: 0,
+            expiration,
+            flags: proto_item::ItemPetFlags::empty(),
+            summoned: false,
+            last_update: 0,
+        }
+    }
+
+    pub fn is_expired(&self, now: NaiveDateTime) -> bool {
+        match self.expiration {
+            Some(t_exp) => t_exp <= now,
+            _ => false,
+        }
+    }
+
+    pub fn is_starving(&self) -> bool {
+        self.fullness == 0
+    }
+
+    pub fn set_summoned(&mut self, summoned: bool) {
+        self.summoned = summoned;
+        self.last_update += 1;
+    }
+
+    /// Changes the closeness and returns true, if the level changed
+    pub fn add_tameness(&mut self, delta: i32) -> bool {
+        let tameness = (self.tameness as i32 + delta).clamp(0, MAX_TAMENESS as i32) as u16;
+        let level = level_for_tameness(tameness);
+        let level_changed = level != self.level;
+
+        self.tameness = tameness;
+        self.level = level;
+        self.last_update += 1;
+        level_changed
+    }
+
+    /// Feeding a hungry pet raises the closeness, overfeeding lowers it
+    pub fn feed(&mut self, inc: u8) -> bool {
+        if self.fullness >= MAX_FULLNESS {
+            self.add_tameness(-1);
+            return false;
+        }
+
+        self.fullness = self.fullness.saturating_add(inc).min(MAX_FULLNESS);
+        self.add_tameness(1);
+        true
+    }
+
+    /// Pets only follow commands, when they are not starving
+    pub fn command(&mut self, mut rng: impl Rng) -> bool {
+        if self.is_starving() || rng.gen_range(0..100) >= COMMAND_SUCCESS_RATE {
+            return false;
+        }
+
+        self.add_tameness(1);
+        true
+    }
+
+    /// Applies the elapsed hunger intervals, a starving pet loses closeness
+    pub fn hunger(&mut self, intervals: u32) {
+        if intervals == 0 {
+            return;
+        }
+
+        let fullness = self.fullness as u32;
+        self.fullness = fullness.saturating_sub(intervals) as u8;
+        if self.is_starving() {
+            self.add_tameness(-1);
+        }
+        self.last_update += 1;
+    }
+}
+
+/// Tracks the hunger intervals of an active pet
+#[derive(Debug, Clone)]
+pub struct HungerTimer {
+    last: Instant,
+}
+
+impl HungerTimer {
+    pub fn new(now: Instant) -> Self {
+        Self { last: now }
+    }
+
+    /// Returns the number of elapsed intervals, the remainder is carried over
+    pub fn tick(&mut self, now: Instant) -> u32 {
+        let elapsed = now.saturating_duration_since(self.last);
+        let intervals = (elapsed.as_millis() / HUNGER_INTERVAL.as_millis()) as u32;
+        self.last += HUNGER_INTERVAL * intervals;
+        intervals
+    }
+}
+
+/// Cuts the name at a char boundary, so It fits into the name of the pet item
+fn truncate_name(name: &str) -> &str {
+    let mut end = name.len().min(MAX_PET_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+impl From<&Pet> for proto_item::ItemPetData {
+    fn from(value: &Pet) -> Self {
+        let expiration = value.expiration.map(db_to_shroom_time);
+        proto_item::ItemPetData {
+            info: proto_item::ItemInfo {
+                item_id: value.item_id,
+                cash_id: Some(value.cash_id).into(),
+                expiration: expiration.into(),
+            },
+            name: truncate_name(&value.name)
+                .try_into()
+                .or_else(|_| DEFAULT_PET_NAME.try_into())
+                .expect("Default pet name"),
+            level: value.level,
+            tameness: value.tameness,
+            fullness: value.fullness,
+            expiration: expiration.into(),
+            attribute1: 0,
+            skill: value.skill,
+            remain_life: value.remaining_life,
+            attribute2: value.flags.bits(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use proto95::id::ItemId;
+
+    use super::{
+        level_for_tameness, truncate_name, HungerTimer, Pet, HUNGER_INTERVAL, MAX_FULLNESS,
+    };
+
+    fn get_pet() -> Pet {
+        Pet::from_item_id(ItemId(5000000), 1, None)
+    }
+
+    #[test]
+    fn closeness_levels() {
+        assert_eq!(level_for_tameness(0), 1);
+        assert_eq!(level_for_tameness(1), 2);
+        assert_eq!(level_for_tameness(5), 3);
+        assert_eq!(level_for_tameness(30_000), 30);
+
+        let mut pet = get_pet();
+        assert!(pet.add_tameness(3));
+        assert_eq!(pet.level, 3);
+        assert!(!pet.add_tameness(1));
+        assert!(pet.add_tameness(-10));
+        assert_eq!((pet.tameness, pet.level), (0, 1));
+    }
+
+    #[test]
+    fn long_names() {
+        assert_eq!(truncate_name("Kitty"), "Kitty");
+        assert_eq!(truncate_name("ABCDEFGHIJKLMNOP"), "ABCDEFGHIJKL");
+        // Multi byte chars are not split
+        assert_eq!(truncate_name("ääääääää"), "ääääää");
+        assert_eq!(truncate_name("aääääääää"), "aäääää");
+
+        let mut pet = get_pet();
+        pet.name = "A".repeat(64);
+        let _ = proto95::shared::item::ItemPetData::from(&pet);
+    }
+
+    #[test]
+    fn feed_and_hunger() {
+        let mut pet = get_pet();
+        assert!(!pet.feed(30));
+        assert_eq!(pet.tameness, 0);
+
+        pet.hunger(40);
+        assert_eq!(pet.fullness, MAX_FULLNESS - 40);
+        assert!(pet.feed(30));
+        assert_eq!((pet.fullness, pet.tameness), (MAX_FULLNESS - 10, 1));
+
+        pet.hunger(200);
+        assert!(pet.is_starving());
+        assert_eq!(pet.tameness, 0);
+        assert!(!pet.command(rand::thread_rng()));
+    }
+
+    #[test]
+    fn hunger_timer() {
+        let now = Instant::now();
+        let mut timer = HungerTimer::new(now);
+        assert_eq!(timer.tick(now + HUNGER_INTERVAL / 2), 0);
+        assert_eq!(
+            timer.tick(now + HUNGER_INTERVAL * 2 + HUNGER_INTERVAL / 2),
+            2
+        );
+        assert_eq!(timer.tick(now + HUNGER_INTERVAL * 3), 1);
+    }
+}
@@ -1,9 +1,51 @@
 use anyhow::anyhow;
-use std::net::{IpAddr, SocketAddr};
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+};
 
-use shroom_net::packet::proto::ShroomList16;
+use shroom_net::{packet::proto::ShroomList16, PacketBuffer};
 
-use proto95::login::world::{ChannelId, ChannelItem, WorldId, WorldInfoResp, WorldItem};
+use proto95::login::world::{
+    ChannelId, ChannelItem, PopulateLevel, WorldCheckUserLimitResp, WorldId, WorldInfoResp,
+    WorldItem,
+};
+
+use super::metrics;
+
+/// Number of users per world and channel
+pub type ChannelPopulation = BTreeMap<(WorldId, ChannelId), usize>;
+
+/// Population thresholds of a world
+#[derive(Debug, Clone)]
+pub struct UserLimit {
+    /// Users from which the world is reported as busy
+    pub busy_users: usize,
+    /// Users from which the world is full and no further users are accepted
+    pub max_users: usize,
+}
+
+impl Default for UserLimit {
+    fn default() -> Self {
+        Self {
+            busy_users: 800,
+            max_users: 1000,
+        }
+    }
+}
+
+impl UserLimit {
+    pub fn populate_level(&self, users: usize) -> PopulateLevel {
+        if users >= self.max_users {
+            PopulateLevel::Full
+        } else if users >= self.busy_users {
+            PopulateLevel::Busy
+        } else {
+            PopulateLevel::Normal
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct ChannelInfo {
@@ -15,9 +57,17 @@ pub struct ChannelInfo {
 #[derive(Debug, Clone)]
 pub struct ServerInfo {
     pub ip: IpAddr,
+    /// Port of the login server, which is shared by all worlds
     pub port: u16,
     pub channels: Vec<ChannelInfo>,
+    pub cash_shop_port: u16,
     pub name: String,
+    pub user_limit: UserLimit,
+    pub event_desc: String,
+    /// Rates in percent
+    pub event_exp: u16,
+    pub event_drop_rate: u16,
+    pub block_char_creation: bool,
 }
 
 impl ChannelInfo {
@@ -36,17 +86,30 @@ impl ChannelInfo {
 }
 
 impl ServerInfo {
-    pub fn new(ip: IpAddr, port: u16, name: String, channels: usize) -> Self {
+    /// Creates a world, the channels listen on consecutive ports starting at `channel_port`
+    pub fn new(ip: IpAddr, port: u16, channel_port: u16, name: String, channels: usize) -> Self {
         Self {
             ip,
             port,
             channels: (0..channels)
-                .map(|id| ChannelInfo::new(ip, port + 1 + id as u16, &name, id as ChannelId))
+                .map(|id| ChannelInfo::new(ip, channel_port + id as u16, &name, id as ChannelId))
                 .collect(),
+            // The cash shop listens right after the last channel
+            cash_shop_port: channel_port + channels as u16,
             name,
+            user_limit: UserLimit::default(),
+            event_desc: String::new(),
+            event_exp: 100,
+            event_drop_rate: 100,
+            block_char_creation: false,
         }
     }
 
+    /// First port after the ports of this world
+    pub fn next_free_port(&self) -> u16 {
+        self.cash_shop_port + 1
+    }
+
     pub fn get_channel_addr(&self, ch: ChannelId) -> anyhow::Result<SocketAddr> {
         self.channels
             .get(ch as usize)
@@ -58,11 +121,32 @@ impl ServerInfo {
         SocketAddr::new(self.ip, self.port)
     }
 
-    pub fn get_world_info(&self, world_id: WorldId) -> WorldItem {
-        //TODO add some caching mechanismn so world item is not re-encoded each time
-        // maybe a custom impl of encode for WorldItem
-        // should look into making something like CachedPacket<Buf, WorldItem>
+    pub fn get_cash_shop_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, self.cash_shop_port)
+    }
+
+    /// Total users in all channels of this world
+    pub fn get_user_count(&self, world_id: WorldId, population: &ChannelPopulation) -> usize {
+        (0..self.channels.len())
+            .filter_map(|ch| population.get(&(world_id, ch as ChannelId)))
+            .sum()
+    }
+
+    pub fn check_user_limit(
+        &self,
+        world_id: WorldId,
+        population: &ChannelPopulation,
+    ) -> WorldCheckUserLimitResp {
+        let level = self
+            .user_limit
+            .populate_level(self.get_user_count(world_id, population));
+        WorldCheckUserLimitResp {
+            over_user_limit: level == PopulateLevel::Full,
+            populate_level: level,
+        }
+    }
 
+    pub fn get_world_info(&self, world_id: WorldId, population: &ChannelPopulation) -> WorldItem {
         let channels = self
             .channels
             .iter()
@@ -72,35 +156,61 @@ impl ServerInfo {
                 id: id as u8,
                 adult_channel: false,
                 world_id: world_id as u8,
-                user_number: 0,
+                user_number: population
+                    .get(&(world_id, id as ChannelId))
+                    .copied()
+                    .unwrap_or(0) as u32,
             })
             .collect();
 
         WorldItem {
             name: self.name.clone(),
             state: 1,
-            event_desc: "Event!!".to_string(),
-            event_exp: 100,
-            event_drop_rate: 100,
-            block_char_creation: false,
+            event_desc: self.event_desc.clone(),
+            event_exp: self.event_exp,
+            event_drop_rate: self.event_drop_rate,
+            block_char_creation: self.block_char_creation,
             channels,
             balloons: ShroomList16::default(),
         }
     }
 }
 
+/// Encoded world list for the population it was encoded with
+struct WorldInfoCache {
+    population: ChannelPopulation,
+    packets: Arc<PacketBuffer>,
+}
+
+impl std::fmt::Debug for WorldInfoCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorldInfoCache")
+            .field("population", &self.population)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ServerService {
     servers: Vec<ServerInfo>,
+    world_info_cache: Mutex<Option<WorldInfoCache>>,
 }
 
 impl ServerService {
     pub fn new(servers: impl IntoIterator<Item = ServerInfo>) -> Self {
         Self {
             servers: servers.into_iter().collect(),
+            world_info_cache: Mutex::default(),
         }
     }
 
+    pub fn worlds(&self) -> impl Iterator<Item = (WorldId, &ServerInfo)> {
+        self.servers
+            .iter()
+            .enumerate()
+            .map(|(id, server)| (id as WorldId, server))
+    }
+
     pub fn get_server(&self, world: WorldId) -> anyhow::Result<&ServerInfo> {
         self.servers
             .get(world as usize)
@@ -111,14 +221,124 @@ impl ServerService {
         self.get_server(world)?.get_channel_addr(ch)
     }
 
-    pub fn get_world_info_packets(&self) -> Vec<WorldInfoResp> {
+    pub fn get_cash_shop_addr(&self, world: WorldId) -> anyhow::Result<SocketAddr> {
+        Ok(self.get_server(world)?.get_cash_shop_addr())
+    }
+
+    pub fn get_world_info_packets(&self, population: &ChannelPopulation) -> Vec<WorldInfoResp> {
         self.servers
             .iter()
             .enumerate()
             .map(|(id, server)| {
-                WorldInfoResp::world(id as u8, server.get_world_info(id as WorldId))
+                WorldInfoResp::world(id as u8, server.get_world_info(id as WorldId, population))
             })
             .chain(std::iter::once(WorldInfoResp::end()))
             .collect()
     }
+
+    /// Encoded world list, the list is only re-encoded when the population changed
+    pub fn get_encoded_world_info(
+        &self,
+        population: ChannelPopulation,
+    ) -> anyhow::Result<Arc<PacketBuffer>> {
+        // The list is sent after every call, an entry per world and the end marker
+        metrics::count_packets_out::<WorldInfoResp>(self.servers.len() + 1);
+
+        let mut cache = self
+            .world_info_cache
+            .lock()
+            .map_err(|_| anyhow!("World info cache poisoned"))?;
+        if let Some(cache) = cache.as_ref().filter(|c| c.population == population) {
+            return Ok(cache.packets.clone());
+        }
+
+        let mut buf = PacketBuffer::new();
+        for pkt in self.get_world_info_packets(&population) {
+            buf.write_packet(pkt)?;
+        }
+        let packets = Arc::new(buf);
+        *cache = Some(WorldInfoCache {
+            population,
+            packets: packets.clone(),
+        });
+        Ok(packets)
+    }
+
+    pub fn check_user_limit(
+        &self,
+        world: WorldId,
+        population: &ChannelPopulation,
+    ) -> anyhow::Result<WorldCheckUserLimitResp> {
+        Ok(self.get_server(world)?.check_user_limit(world, population))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        sync::Arc,
+    };
+
+    use proto95::login::world::PopulateLevel;
+
+    use super::{ChannelPopulation, ServerInfo, ServerService, UserLimit};
+
+    fn get_svc() -> ServerService {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let mut server = ServerInfo::new(ip, 8484, 8485, "test".to_string(), 2);
+        server.user_limit = UserLimit {
+            busy_users: 2,
+            max_users: 3,
+        };
+        let other = ServerInfo::new(ip, 8484, server.next_free_port(), "other".to_string(), 1);
+        assert_eq!(other.channels[0].port, 8488);
+        ServerService::new([server, other])
+    }
+
+    #[test]
+    fn user_limit() -> anyhow::Result<()> {
+        let svc = get_svc();
+        let mut population = ChannelPopulation::new();
+        assert_eq!(
+            svc.check_user_limit(0, &population)?.populate_level,
+            PopulateLevel::Normal
+        );
+
+        population.insert((0, 0), 1);
+        population.insert((0, 1), 1);
+        assert_eq!(
+            svc.check_user_limit(0, &population)?.populate_level,
+            PopulateLevel::Busy
+        );
+
+        // Channels of other worlds are not counted
+        population.insert((1, 0), 10);
+        population.insert((0, 5), 10);
+        assert!(!svc.check_user_limit(0, &population)?.over_user_limit);
+
+        population.insert((0, 1), 2);
+        let resp = svc.check_user_limit(0, &population)?;
+        assert!(resp.over_user_limit);
+        assert_eq!(resp.populate_level, PopulateLevel::Full);
+        assert!(svc.check_user_limit(2, &population).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn world_info_cache() -> anyhow::Result<()> {
+        let svc = get_svc();
+        let mut population = ChannelPopulation::new();
+
+        let a = svc.get_encoded_world_info(population.clone())?;
+        let b = svc.get_encoded_world_info(population.clone())?;
+        assert!(Arc::ptr_eq(&a, &b));
+
+        population.insert((0, 1), 1);
+        let c = svc.get_encoded_world_info(population.clone())?;
+        assert!(!Arc::ptr_eq(&a, &c));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,69 @@
+use proto95::login::world::{ChannelId, WorldId};
+
+use crate::services::{
+    data::{account::AccountId, character::CharacterID},
+    server_info::ChannelPopulation,
+};
+
+use super::{
+    online::{OnlineError, OnlineState},
+    ShroomMigrationKey,
+};
+
+/// Character, which migrates to another process,
+/// the session is saved before the ticket is handed over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MigrationTicket {
+    pub acc_id: AccountId,
+    pub char_id: CharacterID,
+    /// Last game channel, used to return from the cash shop
+    pub channel_id: ChannelId,
+}
+
+/// Accounts and channels of a process, which are reported to the center
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CenterReport {
+    /// Unique name of the process
+    pub server: String,
+    pub channels: Vec<(WorldId, ChannelId)>,
+    /// Accounts connected to the process, migrating accounts are tracked by the center
+    pub accounts: Vec<(AccountId, OnlineState)>,
+}
+
+/// World wide state, which the center returns for a report
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CenterStatus {
+    /// Accounts of the process, which were asked to disconnect by another process
+    pub disconnect: Vec<AccountId>,
+    /// Population of all running channels
+    pub population: ChannelPopulation,
+}
+
+/// Center of a split deployment, It owns the migration hand-off, the world presence
+/// and the channel registry for the login and channel processes
+#[async_trait::async_trait]
+pub trait WorldCenter: std::fmt::Debug + Send + Sync {
+    /// Registers the account as online in the whole world
+    async fn login(&self, acc_id: AccountId) -> Result<(), OnlineError>;
+
+    async fn logout(&self, acc_id: AccountId) -> anyhow::Result<()>;
+
+    /// Asks the process of the account to disconnect It
+    async fn request_disconnect(&self, acc_id: AccountId) -> anyhow::Result<()>;
+
+    async fn push_migration(
+        &self,
+        key: ShroomMigrationKey,
+        ticket: MigrationTicket,
+    ) -> anyhow::Result<()>;
+
+    /// Waits for the pushed ticket and moves the account to the given state
+    async fn take_migration(
+        &self,
+        key: ShroomMigrationKey,
+        state: OnlineState,
+    ) -> anyhow::Result<MigrationTicket>;
+
+    /// Refreshes the accounts and channels of the process
+    async fn report(&self, report: CenterReport) -> anyhow::Result<CenterStatus>;
+}
@@ -1,7 +1,9 @@
 use std::hash::Hash;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use dashmap::DashMap;
+use tokio::sync::Notify;
 
 #[derive(Debug, Clone)]
 struct MigrationContext<V> {
@@ -22,6 +24,10 @@ impl<V> MigrationContext<V> {
     }
 }
 
+/// Hands the data over from one server to another, the receiving server
+/// waits for the data of It's key and is woken up once the data is pushed.
+/// Timed out entries stay until they are removed by `clean`, so their owner
+/// can release them
 #[derive(Debug)]
 pub struct MigrationManager<K, V>
 where
@@ -29,6 +35,8 @@ where
 {
     timeout: Duration,
     pending: DashMap<K, MigrationContext<V>>,
+    /// Wake-ups for the servers waiting in `take_timeout`
+    waiters: DashMap<K, Arc<Notify>>,
 }
 
 impl<K, V> Clone for MigrationManager<K, V>
@@ -40,18 +48,20 @@ where
         Self {
             timeout: self.timeout,
             pending: self.pending.clone(),
+            waiters: self.waiters.clone(),
         }
     }
 }
 
 impl<K, V> MigrationManager<K, V>
 where
-    K: Eq + Hash,
+    K: Eq + Hash + Clone,
 {
     pub fn new(timeout: Duration) -> Self {
         Self {
             timeout,
             pending: DashMap::default(),
+            waiters: DashMap::default(),
         }
     }
 
@@ -59,49 +69,92 @@ where
         self.pending.len()
     }
 
+    /// Waits until the data for the key is pushed, fails after the timeout.
+    /// A timed out entry is not taken, It's left for `clean`
     pub async fn take_timeout(&self, key: &K) -> anyhow::Result<V> {
-        let start = Instant::now();
-        //TODO this should be handled better
-        while start.elapsed() < self.timeout {
-            if let Some((_, ctx)) = self.pending.remove(key) {
-                if !ctx.is_timeout() {
-                    return Ok(ctx.data);
-                } else {
-                    // If timeout is reached the session handle will be dropped
-                    break;
-                }
-            };
-
-            tokio::time::sleep(Duration::from_millis(100)).await;
-        }
+        // The waiter must be registered before checking the data, either the
+        // data is found or the push sees the waiter and stores a wake-up
+        let notify = self
+            .waiters
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(Notify::new()))
+            .clone();
+
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        let res = loop {
+            if let Some(data) = self.take(key) {
+                break Ok(data);
+            }
+
+            if tokio::time::timeout_at(deadline, notify.notified())
+                .await
+                .is_err()
+            {
+                break Err(anyhow::format_err!("Timeout reached for migration"));
+            }
+        };
 
-        anyhow::bail!("Timeout reached for migration");
+        self.waiters
+            .remove_if(key, |_, waiter| Arc::ptr_eq(waiter, &notify));
+        res
     }
 
+    /// Takes the data, If It's not timed out
     pub fn take(&self, key: &K) -> Option<V> {
-        let ctx = self.pending.remove(key);
+        self.pending
+            .remove_if(key, |_, ctx| !ctx.is_timeout())
+            .map(|(_, ctx)| ctx.data)
+    }
+
+    /// Removes the data regardless of the timeout
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.pending.remove(key).map(|(_, ctx)| ctx.data)
+    }
+
+    /// Pushes the data and wakes up the server waiting for the key,
+    /// an existing entry for the key is replaced and returned, so the caller can release It
+    #[must_use]
+    pub fn push(&self, key: K, data: V) -> Option<V> {
+        let prev = self
+            .pending
+            .insert(key.clone(), MigrationContext::new(data, self.timeout));
 
-        match ctx {
-            Some((_, v)) if !v.is_timeout() => Some(v.data),
-            _ => None,
+        if let Some(waiter) = self.waiters.get(&key) {
+            waiter.notify_one();
         }
+
+        prev.map(|ctx| ctx.data)
     }
 
-    pub fn push(&self, key: K, data: V) {
-        //TODO: what to do if there's already a migration data entry for that key
-        self.pending
-            .insert(key, MigrationContext::new(data, self.timeout));
+    /// Removes all pending migrations regardless of the timeout
+    pub fn drain(&self) -> Vec<V> {
+        let keys: Vec<K> = self.pending.iter().map(|ctx| ctx.key().clone()).collect();
+        keys.iter().filter_map(|key| self.remove(key)).collect()
     }
 
-    pub fn clean(&self) {
-        //TODO figure out how to call clean, capping insert and executing It every x inserts would be nice
-        self.pending.retain(|_, v| !v.is_timeout())
+    /// Removes all timed out entries and returns them
+    pub fn clean(&self) -> Vec<V> {
+        let keys: Vec<K> = self
+            .pending
+            .iter()
+            .filter(|ctx| ctx.value().is_timeout())
+            .map(|ctx| ctx.key().clone())
+            .collect();
+
+        keys.iter()
+            .filter_map(|key| self.pending.remove_if(key, |_, ctx| ctx.is_timeout()))
+            .map(|(_, ctx)| ctx.data)
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{thread::sleep, time::Duration};
+    use std::{
+        sync::Arc,
+        thread::sleep,
+        time::{Duration, Instant},
+    };
 
     use crate::services::session::migration::MigrationManager;
 
@@ -116,23 +169,104 @@ mod tests {
 
         // Test insert/remove
         assert_eq!(svc.take(&key_1), None);
-        svc.push(key_1, 10);
+        assert_eq!(svc.push(key_1, 10), None);
         assert_eq!(svc.take(&key_1), Some(10));
         assert_eq!(svc.take(&key_1), None);
         assert_eq!(svc.take(&key_2), None);
 
         //Test timeout
-        svc.push(key_1, 10);
+        assert_eq!(svc.push(key_1, 10), None);
         assert_eq!(svc.pending(), 1);
         sleep(TIMEOUT * 2);
         assert_eq!(svc.take(&key_1), None);
 
-        // Test clean
-        svc.push(key_1, 10);
+        // Test clean, the timed out entry is replaced
+        assert_eq!(svc.push(key_1, 11), Some(10));
         assert_eq!(svc.pending(), 1);
         sleep(TIMEOUT * 2);
         assert_eq!(svc.pending(), 1);
-        svc.clean();
+        assert_eq!(svc.clean(), vec![11]);
+        assert_eq!(svc.pending(), 0);
+
+        // Test drain
+        assert_eq!(svc.push(key_1, 10), None);
+        assert_eq!(svc.push(key_2, 20), None);
+        sleep(TIMEOUT * 2);
+        let mut drained = svc.drain();
+        drained.sort();
+        assert_eq!(drained, vec![10, 20]);
+        assert_eq!(svc.pending(), 0);
+    }
+
+    #[tokio::test]
+    async fn take_wakes_up_on_push() {
+        const TIMEOUT: Duration = Duration::from_secs(5);
+        let svc = Arc::new(MigrationManager::<u32, u32>::new(TIMEOUT));
+
+        let start = Instant::now();
+        let taker = tokio::spawn({
+            let svc = svc.clone();
+            async move { svc.take_timeout(&1).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(svc.push(1, 10), None);
+
+        assert_eq!(taker.await.unwrap().unwrap(), 10);
+        // Woken up by the push instead of waiting for the timeout
+        assert!(start.elapsed() < TIMEOUT);
+        assert_eq!(svc.pending(), 0);
+        assert!(svc.waiters.is_empty());
+    }
+
+    #[tokio::test]
+    async fn take_after_push() {
+        let svc = MigrationManager::<u32, u32>::new(Duration::from_secs(5));
+        assert_eq!(svc.push(1, 10), None);
+        assert_eq!(svc.take_timeout(&1).await.unwrap(), 10);
+        assert!(svc.waiters.is_empty());
+    }
+
+    #[tokio::test]
+    async fn take_timeout() {
+        const TIMEOUT: Duration = Duration::from_millis(100);
+        let svc = MigrationManager::<u32, u32>::new(TIMEOUT);
+
+        // Nothing is pushed
+        assert!(svc.take_timeout(&1).await.is_err());
+        assert!(svc.waiters.is_empty());
+
+        // Timed out entries are left for clean
+        assert_eq!(svc.push(1, 10), None);
+        tokio::time::sleep(TIMEOUT * 2).await;
+        assert!(svc.take_timeout(&1).await.is_err());
+        assert_eq!(svc.clean(), vec![10]);
+    }
+
+    #[tokio::test]
+    async fn concurrent_push_take() {
+        const N: u32 = 64;
+        let svc = Arc::new(MigrationManager::<u32, u32>::new(Duration::from_secs(5)));
+
+        let takers: Vec<_> = (0..N)
+            .map(|key| {
+                let svc = svc.clone();
+                tokio::spawn(async move { svc.take_timeout(&key).await })
+            })
+            .collect();
+        let pushers: Vec<_> = (0..N)
+            .map(|key| {
+                let svc = svc.clone();
+                tokio::spawn(async move { svc.push(key, key * 10) })
+            })
+            .collect();
+
+        for pusher in pushers {
+            assert_eq!(pusher.await.unwrap(), None);
+        }
+        for (key, taker) in takers.into_iter().enumerate() {
+            assert_eq!(taker.await.unwrap().unwrap(), key as u32 * 10);
+        }
         assert_eq!(svc.pending(), 0);
+        assert!(svc.waiters.is_empty());
     }
 }
@@ -1,18 +1,35 @@
+pub mod center;
 pub mod migration;
+pub mod online;
 pub mod session_data;
 pub mod session_manager;
 use std::net::IpAddr;
-use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 
-use shroom_net::net::service::session_set::SessionSet;
+use anyhow::anyhow;
+use dashmap::DashMap;
+use proto95::game::{BroadcastMessageResp, ServerMessage};
+use proto95::login::world::{ChannelId, WorldId};
+use proto95::shared::PingResp;
+use shroom_net::net::service::{server_sess::SharedSessionHandle, session_set::SessionSet};
 
 use self::{
+    center::{CenterReport, MigrationTicket, WorldCenter},
     migration::MigrationManager,
-    session_manager::{SessionBackend, OwnedSession, SessionManager},
+    online::{OnlineError, OnlineLogin, OnlineRegistry, OnlineState},
+    session_manager::{
+        OwnedSession, OwnerReservation, SessionBackend, SessionManager, SessionResult,
+    },
 };
 
-use super::data::character::CharacterID;
+use super::{
+    data::{account::AccountId, character::CharacterID},
+    metrics,
+    server_info::ChannelPopulation,
+};
 
 pub type ShroomSessionSet = SessionSet<CharacterID>;
 
@@ -32,55 +49,827 @@ impl ShroomMigrationKey {
             peer_addr,
         }
     }
+
+    pub fn client_key(&self) -> ClientKey {
+        self.client_key
+    }
+
+    pub fn peer_addr(&self) -> IpAddr {
+        self.peer_addr
+    }
+}
+
+/// Session data, which belongs to an account
+pub trait AccountSessionData {
+    fn account_id(&self) -> AccountId;
+    /// Ticket to load the session again in another process
+    fn migration_ticket(&self) -> MigrationTicket;
+}
+
+/// Kicked clients are pinged again in this interval during the shutdown
+const SHUTDOWN_KICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sessions saved during the shutdown
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    pub saved: usize,
+    pub failed: usize,
+}
+
+/// Sessions saved by a save-all, claimed sessions are saved on their next autosave
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SaveSummary {
+    pub saved: usize,
+    pub failed: usize,
+    pub requested: usize,
+}
+
+/// Character of a connected game or cash shop client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnlineChar {
+    pub acc_id: AccountId,
+    pub char_id: CharacterID,
+    pub name: String,
+}
+
+/// Connection of a process to the center of a split deployment
+#[derive(Debug)]
+struct CenterLink {
+    center: Arc<dyn WorldCenter>,
+    server: String,
+    channels: Vec<(WorldId, ChannelId)>,
+    /// World wide population of the last report
+    population: Mutex<ChannelPopulation>,
 }
 
 #[derive(Debug)]
 pub struct GameSessionManager<Backend: SessionBackend> {
     session_man: SessionManager<uuid::Uuid, Backend>,
     migration: MigrationManager<ShroomMigrationKey, OwnedSession<uuid::Uuid, Backend::SessionData>>,
+    migration_timeout: Duration,
+    online: OnlineRegistry,
+    /// Connected game and cash shop clients, used for server wide notices
+    clients: ShroomSessionSet,
+    client_chars: DashMap<CharacterID, OnlineChar>,
+    saved_sessions: AtomicUsize,
+    failed_sessions: AtomicUsize,
+    /// Migrations are handed over by the center, If the processes are split
+    center: Option<CenterLink>,
 }
 
 impl<Backend> GameSessionManager<Backend>
 where
-    Backend: SessionBackend + Send + 'static,
+    Backend: SessionBackend<SessionLoadParam = MigrationTicket> + Send + 'static,
+    Backend::SessionData: AccountSessionData,
 {
-    pub fn new(backend: Backend, migration_timeout: Duration) -> Self {
+    pub fn new(backend: Backend, migration_timeout: Duration, autosave_interval: Duration) -> Self {
         GameSessionManager {
-            session_man: SessionManager::new(backend),
+            session_man: SessionManager::new(backend, autosave_interval),
             migration: MigrationManager::new(migration_timeout),
+            migration_timeout,
+            online: OnlineRegistry::new(),
+            clients: ShroomSessionSet::new(),
+            client_chars: DashMap::new(),
+            saved_sessions: AtomicUsize::new(0),
+            failed_sessions: AtomicUsize::new(0),
+            center: None,
         }
     }
 
-    pub async fn close_session(&self, session: OwnedSession<uuid::Uuid, Backend::SessionData>) -> anyhow::Result<()> {
-        self.session_man.close_session(session).await
+    /// Hands migrations over to the center instead of keeping them in this process,
+    /// the world presence is shared with the center as well
+    pub fn set_center(
+        &mut self,
+        center: Arc<dyn WorldCenter>,
+        server: String,
+        channels: Vec<(WorldId, ChannelId)>,
+    ) {
+        self.center = Some(CenterLink {
+            center,
+            server,
+            channels,
+            population: Mutex::default(),
+        });
     }
 
-    pub async fn create_migration_session(
+    pub fn online(&self) -> &OnlineRegistry {
+        &self.online
+    }
+
+    /// Registers the account as online at the login server,
+    /// the session of a timed out migration is saved before the account is released
+    pub async fn login(&self, acc_id: AccountId) -> Result<(), OnlineError> {
+        if let Some(link) = &self.center {
+            link.center.login(acc_id).await?;
+        }
+
+        if let OnlineLogin::ReplacedStale(state) = self.online.login(acc_id, Instant::now())? {
+            log::info!("Replaced stale online state for account {acc_id}: {state:?}");
+            self.release_migration(state).await;
+        }
+
+        Ok(())
+    }
+
+    /// Releases all stale accounts of timed out migrations and crashed sessions,
+    /// timed out migrations without a stale account are saved as well
+    pub async fn release_stale(&self) {
+        for (acc_id, state) in self.online.remove_stale(Instant::now()) {
+            log::info!("Released stale account {acc_id}: {state:?}");
+            self.release_migration(state).await;
+        }
+
+        for session in self.migration.clean() {
+            log::info!(
+                "Released timed out migration of account {}",
+                session.account_id()
+            );
+            self.save_released(session, "timed out migration").await;
+        }
+    }
+
+    async fn release_migration(&self, state: OnlineState) {
+        let OnlineState::Migrating { key, .. } = state else {
+            return;
+        };
+
+        if let Some(session) = self.migration.remove(&key) {
+            self.save_released(session, "timed out migration").await;
+        }
+    }
+
+    /// Saves a session, which was released without the account logging out
+    async fn save_released(
+        &self,
+        session: OwnedSession<uuid::Uuid, Backend::SessionData>,
+        reason: &str,
+    ) {
+        let res = self.session_man.close_session(session).await;
+        if let Err(err) = self.count_save(res) {
+            log::error!("Unable to save {reason} session: {err:?}");
+        }
+    }
+
+    /// Pushes the session for the migration, a pending session with the same key
+    /// is replaced and saved
+    async fn push_migration(
         &self,
         migration_key: ShroomMigrationKey,
-        param: Backend::SessionLoadParam,
+        session: OwnedSession<uuid::Uuid, Backend::SessionData>,
+    ) {
+        self.set_migrating(session.account_id(), migration_key);
+        if let Some(prev) = self.migration.push(migration_key, session) {
+            log::warn!("Replaced pending migration for key {migration_key:?}");
+            self.save_released(prev, "replaced migration").await;
+        }
+    }
+
+    fn set_migrating(&self, acc_id: AccountId, key: ShroomMigrationKey) {
+        let now = Instant::now();
+        self.online.set_state(
+            acc_id,
+            OnlineState::Migrating {
+                key,
+                deadline: now + self.migration_timeout,
+            },
+            now,
+        );
+    }
+
+    /// Saves the session and releases the account
+    pub async fn close_session(
+        &self,
+        session: OwnedSession<uuid::Uuid, Backend::SessionData>,
+    ) -> anyhow::Result<()> {
+        let acc_id = session.account_id();
+        let res = self.session_man.close_session(session).await;
+        self.logout(acc_id).await;
+        self.count_save(res)
+    }
+
+    /// Saves the session of a migration to another process,
+    /// the center keeps the account online meanwhile
+    async fn save_migrating(
+        &self,
+        session: OwnedSession<uuid::Uuid, Backend::SessionData>,
+    ) -> anyhow::Result<()> {
+        let acc_id = session.account_id();
+        let res = self.session_man.close_session(session).await;
+        self.online.logout(acc_id);
+        self.count_save(res)
+    }
+
+    pub async fn logout(&self, acc_id: AccountId) {
+        self.online.logout(acc_id);
+        if let Some(link) = &self.center {
+            if let Err(err) = link.center.logout(acc_id).await {
+                log::error!("Unable to logout account {acc_id} at the center: {err:?}");
+            }
+        }
+    }
+
+    /// Requests the session of the account to disconnect, even If It's in another process
+    pub async fn request_disconnect(&self, acc_id: AccountId) {
+        self.online.request_disconnect(acc_id);
+        if let Some(link) = &self.center {
+            if let Err(err) = link.center.request_disconnect(acc_id).await {
+                log::error!("Unable to request the disconnect of account {acc_id}: {err:?}");
+            }
+        }
+    }
+
+    /// Number of accounts per channel, the center knows the population of the whole world
+    pub fn channel_population(&self) -> anyhow::Result<ChannelPopulation> {
+        match &self.center {
+            Some(link) => Ok(link
+                .population
+                .lock()
+                .map_err(|_| anyhow!("Population poisoned"))?
+                .clone()),
+            None => Ok(self.online.channel_population()),
+        }
+    }
+
+    /// Reports the accounts and channels of this process to the center,
+    /// applies the disconnect requests of other processes and caches the world population
+    pub async fn report_to_center(&self) -> anyhow::Result<()> {
+        let Some(link) = &self.center else {
+            return Ok(());
+        };
+
+        let status = link
+            .center
+            .report(CenterReport {
+                server: link.server.clone(),
+                channels: link.channels.clone(),
+                accounts: self.online.accounts(),
+            })
+            .await?;
+        for acc_id in status.disconnect {
+            self.online.request_disconnect(acc_id);
+        }
+        *link
+            .population
+            .lock()
+            .map_err(|_| anyhow!("Population poisoned"))? = status.population;
+        Ok(())
+    }
+
+    /// Sessions waiting for the client to migrate into this process
+    pub fn pending_migrations(&self) -> usize {
+        self.migration.pending()
+    }
+
+    fn count_save(&self, res: SessionResult<()>) -> anyhow::Result<()> {
+        let counter = match res {
+            Ok(_) => &self.saved_sessions,
+            Err(_) => {
+                metrics::count_save_failure();
+                &self.failed_sessions
+            }
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(res?)
+    }
+
+    /// Registers the connected client for server wide notices
+    pub fn add_client(&self, char: OnlineChar, handle: SharedSessionHandle) {
+        self.clients.add(char.char_id, handle);
+        self.client_chars.insert(char.char_id, char);
+    }
+
+    pub fn remove_client(&self, char_id: CharacterID) {
+        self.clients.remove(char_id);
+        self.client_chars.remove(&char_id);
+    }
+
+    /// Characters of the connected clients in this process
+    pub fn online_chars(&self) -> Vec<OnlineChar> {
+        self.client_chars
+            .iter()
+            .map(|char| char.value().clone())
+            .collect()
+    }
+
+    pub fn is_client(&self, char_id: CharacterID) -> bool {
+        self.client_chars.contains_key(&char_id)
+    }
+
+    /// Whether a session of the owner is loaded, even If no client is connected
+    pub fn is_loaded(&self, owner: &Backend::SessionOwner) -> bool {
+        self.session_man.is_loaded(owner)
+    }
+
+    /// Keeps the owner from loading a session, while data outside of a session is written
+    pub fn reserve_owner(
+        &self,
+        owner: Backend::SessionOwner,
+    ) -> SessionResult<OwnerReservation<'_, Backend::SessionOwner, uuid::Uuid>> {
+        self.session_man.reserve_owner(owner, uuid::Uuid::new_v4())
+    }
+
+    /// Sends a notice to all connected game and cash shop clients
+    pub fn broadcast_notice(&self, msg: String) -> anyhow::Result<()> {
+        metrics::broadcast_pkt(
+            &self.clients,
+            BroadcastMessageResp::ServerMessage(ServerMessage { flag: true, msg }),
+            -1,
+        )
+    }
+
+    /// Pings all clients, their keep-alive fails once the registry is closed,
+    /// so the connections save their session and disconnect right away
+    fn kick_clients(&self) {
+        if let Err(err) = metrics::broadcast_pkt(&self.clients, PingResp, -1) {
+            log::error!("Unable to kick clients: {err:?}");
+        }
+    }
+
+    /// Disconnects all clients and saves every session, pending migrations are
+    /// saved right away, connected clients are kicked and save their session before
+    /// they disconnect. Sessions, which are still claimed after the timeout, count as failed
+    pub async fn shutdown(&self, timeout: Duration) -> ShutdownSummary {
+        let saved = self.saved_sessions.load(Ordering::Relaxed);
+        let failed = self.failed_sessions.load(Ordering::Relaxed);
+        self.online.close();
+
+        let deadline = Instant::now() + timeout;
+        let mut next_kick = Instant::now();
+        loop {
+            // Clients, which finish their migration meanwhile, are kicked as well
+            if Instant::now() >= next_kick {
+                self.kick_clients();
+                next_kick += SHUTDOWN_KICK_INTERVAL;
+            }
+
+            // Clients, which disconnect while migrating, end up here as well
+            for session in self.migration.drain() {
+                if let Err(err) = self.close_session(session).await {
+                    log::error!("Unable to save migration session: {err:?}");
+                }
+            }
+
+            if self.session_man.claimed() == 0 || Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        // Sessions of handlers, which ended without closing their session
+        for key in self.session_man.keys() {
+            match self.session_man.try_claim_session(&key) {
+                Ok(session) => {
+                    if let Err(err) = self.close_session(session).await {
+                        log::error!("Unable to save session {key}: {err:?}");
+                    }
+                }
+                Err(_) => {
+                    log::error!("Session {key} is still claimed after the timeout");
+                    self.failed_sessions.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        ShutdownSummary {
+            saved: self.saved_sessions.load(Ordering::Relaxed) - saved,
+            failed: self.failed_sessions.load(Ordering::Relaxed) - failed,
+        }
+    }
+
+    /// Saves the unclaimed sessions right away, claimed sessions of connected clients
+    /// and pending migrations are saved on their next autosave
+    pub async fn save_all(&self) -> SaveSummary {
+        self.session_man.request_save();
+
+        let mut summary = SaveSummary::default();
+        for key in self.session_man.keys() {
+            let Ok(mut session) = self.session_man.try_claim_session(&key) else {
+                summary.requested += 1;
+                continue;
+            };
+
+            match self.session_man.save_session(&mut session).await {
+                Ok(_) => summary.saved += 1,
+                Err(err) => {
+                    log::error!("Unable to save session {key}: {err:?}");
+                    metrics::count_save_failure();
+                    summary.failed += 1;
+                }
+            }
+        }
+        summary
+    }
+
+    /// Saves the claimed session right away, used by connections which are kicked,
+    /// so the character is stored even If the session can't be released
+    pub async fn save_session(
+        &self,
+        session: &mut OwnedSession<uuid::Uuid, Backend::SessionData>,
     ) -> anyhow::Result<()> {
+        let res = self.session_man.save_session(session).await;
+        if res.is_err() {
+            metrics::count_save_failure();
+        }
+        res
+    }
+
+    /// Saves the session if the autosave interval passed since the last save
+    pub async fn autosave(
+        &self,
+        session: &mut OwnedSession<uuid::Uuid, Backend::SessionData>,
+    ) -> anyhow::Result<bool> {
+        self.session_man.autosave(session).await
+    }
+
+    pub async fn create_migration_session(
+        &self,
+        migration_key: ShroomMigrationKey,
+        param: MigrationTicket,
+    ) -> SessionResult<()> {
+        if let Some(link) = &self.center {
+            // The target process loads the session from the ticket
+            link.center.push_migration(migration_key, param).await?;
+            return Ok(());
+        }
+
         let session = self
             .session_man
             .create_claim_session(uuid::Uuid::new_v4(), param)
             .await?;
-        self.migration.push(migration_key, session);
+        self.push_migration(migration_key, session).await;
         Ok(())
     }
 
-    pub fn migrate_session(
+    pub async fn migrate_session(
         &self,
         migration_key: ShroomMigrationKey,
         session: OwnedSession<uuid::Uuid, Backend::SessionData>,
     ) -> anyhow::Result<()> {
-        self.migration.push(migration_key, session);
+        if let Some(link) = &self.center {
+            // The target process loads the saved session, a failed save must not be handed over
+            let ticket = session.migration_ticket();
+            self.save_migrating(session).await?;
+            link.center.push_migration(migration_key, ticket).await?;
+            return Ok(());
+        }
+
+        self.push_migration(migration_key, session).await;
         Ok(())
     }
 
+    /// Claims the migrated session as soon as It's pushed and moves the account
+    /// to the given state
     pub async fn claim_migration_session(
         &self,
         migration_key: ShroomMigrationKey,
+        state: OnlineState,
     ) -> anyhow::Result<OwnedSession<uuid::Uuid, Backend::SessionData>> {
-        self.migration.take_timeout(&migration_key).await
+        if let Some(link) = &self.center {
+            let ticket = link.center.take_migration(migration_key, state).await?;
+            let session = match self
+                .session_man
+                .create_claim_session(uuid::Uuid::new_v4(), ticket)
+                .await
+            {
+                Ok(session) => session,
+                Err(err) => {
+                    self.logout(ticket.acc_id).await;
+                    return Err(err.into());
+                }
+            };
+            self.online.set_state(ticket.acc_id, state, Instant::now());
+            return Ok(session);
+        }
+
+        let session = self.migration.take_timeout(&migration_key).await?;
+        self.online
+            .set_state(session.account_id(), state, Instant::now());
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        net::{IpAddr, Ipv4Addr},
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use crate::services::{
+        data::account::AccountId,
+        meta::meta_service::{MetaHandle, MetaService},
+        Services,
+    };
+
+    use super::{
+        center::{CenterReport, CenterStatus, MigrationTicket, WorldCenter},
+        migration::MigrationManager,
+        online::{OnlineError, OnlineRegistry, OnlineState},
+        session_data::ChannelBoundActivity,
+        session_manager::{SessionBackend, SessionResult},
+        AccountSessionData, GameSessionManager, ShroomMigrationKey,
+    };
+
+    #[derive(Debug)]
+    struct MockSession {
+        acc_id: AccountId,
+        map_id: u32,
+    }
+
+    impl AccountSessionData for MockSession {
+        fn account_id(&self) -> AccountId {
+            self.acc_id
+        }
+
+        fn migration_ticket(&self) -> MigrationTicket {
+            MigrationTicket {
+                acc_id: self.acc_id,
+                char_id: self.acc_id,
+                channel_id: 0,
+            }
+        }
+    }
+
+    /// Saved maps per account, shared like a database
+    #[derive(Debug, Default, Clone)]
+    struct MockBackend(Arc<Mutex<BTreeMap<AccountId, u32>>>);
+
+    #[async_trait::async_trait]
+    impl SessionBackend for MockBackend {
+        type SessionData = MockSession;
+        type SessionLoadParam = MigrationTicket;
+        type SessionOwner = AccountId;
+
+        fn get_owner(param: &Self::SessionLoadParam) -> Self::SessionOwner {
+            param.acc_id
+        }
+
+        async fn load(&self, ticket: MigrationTicket) -> SessionResult<MockSession> {
+            let map_id = self
+                .0
+                .lock()
+                .unwrap()
+                .get(&ticket.acc_id)
+                .copied()
+                .unwrap_or_default();
+            Ok(MockSession {
+                acc_id: ticket.acc_id,
+                map_id,
+            })
+        }
+
+        async fn save(&self, session: &mut MockSession) -> anyhow::Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(session.acc_id, session.map_id);
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    struct MockCenter {
+        online: OnlineRegistry,
+        migration: MigrationManager<ShroomMigrationKey, MigrationTicket>,
+    }
+
+    #[async_trait::async_trait]
+    impl WorldCenter for MockCenter {
+        async fn login(&self, acc_id: AccountId) -> Result<(), OnlineError> {
+            self.online.login(acc_id, Instant::now())?;
+            Ok(())
+        }
+
+        async fn logout(&self, acc_id: AccountId) -> anyhow::Result<()> {
+            self.online.logout(acc_id);
+            Ok(())
+        }
+
+        async fn request_disconnect(&self, acc_id: AccountId) -> anyhow::Result<()> {
+            self.online.request_disconnect(acc_id);
+            Ok(())
+        }
+
+        async fn push_migration(
+            &self,
+            key: ShroomMigrationKey,
+            ticket: MigrationTicket,
+        ) -> anyhow::Result<()> {
+            let now = Instant::now();
+            let deadline = now + Duration::from_secs(5);
+            self.online
+                .set_state(ticket.acc_id, OnlineState::Migrating { key, deadline }, now);
+            assert!(self.migration.push(key, ticket).is_none());
+            Ok(())
+        }
+
+        async fn take_migration(
+            &self,
+            key: ShroomMigrationKey,
+            state: OnlineState,
+        ) -> anyhow::Result<MigrationTicket> {
+            let ticket = self.migration.take_timeout(&key).await?;
+            self.online.set_state(ticket.acc_id, state, Instant::now());
+            Ok(ticket)
+        }
+
+        async fn report(&self, report: CenterReport) -> anyhow::Result<CenterStatus> {
+            let now = Instant::now();
+            let disconnect = report
+                .accounts
+                .into_iter()
+                .filter(|(acc_id, _)| self.online.keep_alive(*acc_id, now).is_err())
+                .map(|(acc_id, _)| acc_id)
+                .collect();
+            Ok(CenterStatus {
+                disconnect,
+                population: self.online.channel_population(),
+            })
+        }
+    }
+
+    fn get_mgr(backend: MockBackend) -> GameSessionManager<MockBackend> {
+        GameSessionManager::new(backend, Duration::from_secs(5), Duration::from_secs(60))
+    }
+
+    fn get_key() -> ShroomMigrationKey {
+        ShroomMigrationKey::new([1; 8], IpAddr::V4(Ipv4Addr::LOCALHOST))
+    }
+
+    fn get_ticket(acc_id: AccountId) -> MigrationTicket {
+        MigrationTicket {
+            acc_id,
+            char_id: acc_id,
+            channel_id: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn channel_change() {
+        let mgr = get_mgr(MockBackend::default());
+        let key = get_key();
+        let online = mgr.online();
+
+        // Login migrates the client into the first channel
+        mgr.login(1).await.unwrap();
+        mgr.create_migration_session(key, get_ticket(1))
+            .await
+            .unwrap();
+        let mut session = mgr
+            .claim_migration_session(key, OnlineState::Channel(0, 0))
+            .await
+            .unwrap();
+        assert_eq!(online.channel_count(0, 0), 1);
+        session.map_id = 100000000;
+
+        // The second channel waits for the client, before the first channel pushed the session
+        let (session, _) = tokio::join!(
+            mgr.claim_migration_session(key, OnlineState::Channel(0, 1)),
+            async { mgr.migrate_session(key, session).await.unwrap() }
+        );
+        let session = session.unwrap();
+        assert_eq!(session.map_id, 100000000);
+        assert_eq!(online.channel_count(0, 0), 0);
+        assert_eq!(online.channel_count(0, 1), 1);
+        assert_eq!(online.get_state(1), Some(OnlineState::Channel(0, 1)));
+
+        mgr.close_session(session).await.unwrap();
+        assert!(!online.is_online(1));
+    }
+
+    #[tokio::test]
+    async fn split_channel_change() {
+        let center = Arc::new(MockCenter {
+            online: OnlineRegistry::new(),
+            migration: MigrationManager::new(Duration::from_secs(5)),
+        });
+        let db = MockBackend::default();
+        let mut login = get_mgr(db.clone());
+        login.set_center(center.clone(), "login".to_string(), vec![]);
+        let mut ch1 = get_mgr(db.clone());
+        ch1.set_center(center.clone(), "channel-1".to_string(), vec![(0, 0)]);
+        let mut ch2 = get_mgr(db);
+        ch2.set_center(center.clone(), "channel-2".to_string(), vec![(0, 1)]);
+        let key = get_key();
+
+        // The presence is shared by all processes
+        login.login(1).await.unwrap();
+        assert_eq!(ch1.login(1).await, Err(OnlineError::AlreadyLoggedIn));
+
+        login
+            .create_migration_session(key, get_ticket(1))
+            .await
+            .unwrap();
+        let mut session = ch1
+            .claim_migration_session(key, OnlineState::Channel(0, 0))
+            .await
+            .unwrap();
+        session.map_id = 100000000;
+        ch1.report_to_center().await.unwrap();
+        assert_eq!(login.channel_population().unwrap().len(), 0);
+        login.report_to_center().await.unwrap();
+        assert_eq!(login.channel_population().unwrap().get(&(0, 0)), Some(&1));
+
+        // The session is saved by the first channel and loaded by the second one
+        let (session, _) = tokio::join!(
+            ch2.claim_migration_session(key, OnlineState::Channel(0, 1)),
+            async { ch1.migrate_session(key, session).await.unwrap() }
+        );
+        let session = session.unwrap();
+        assert_eq!(session.map_id, 100000000);
+        assert!(!ch1.online().is_online(1));
+        assert!(ch2.online().is_online(1));
+        assert_eq!(center.online.get_state(1), Some(OnlineState::Channel(0, 1)));
+
+        // A disconnect requested by the login reaches the second channel
+        login.request_disconnect(1).await;
+        ch2.report_to_center().await.unwrap();
+        assert_eq!(
+            ch2.online().keep_alive(1, Instant::now()),
+            Err(OnlineError::DisconnectRequested)
+        );
+
+        ch2.close_session(session).await.unwrap();
+        assert!(!center.online.is_online(1));
+    }
+
+    /// Two channel processes with the real session backend on a shared database
+    #[tokio::test]
+    async fn channel_change_with_activity() -> anyhow::Result<()> {
+        // The meta data isn't part of the repository
+        const META_DIR: &str = "../../game_data/rbin";
+        if !std::path::Path::new(META_DIR).is_dir() {
+            eprintln!("Skipped, no meta data in {META_DIR}");
+            return Ok(());
+        }
+        let meta = MetaService::load_from_dir(META_DIR)?;
+        let meta: &'static MetaHandle = Box::leak(Box::new(MetaHandle::new(meta)));
+        let path = std::env::temp_dir().join(format!("shroom-{}.db", uuid::Uuid::new_v4()));
+        let opt = format!("sqlite://{}?mode=rwc", path.display());
+        let center = Arc::new(MockCenter {
+            online: OnlineRegistry::new(),
+            migration: MigrationManager::new(Duration::from_secs(5)),
+        });
+        let ch1 = Services::seeded_in_sqlite(&opt, [], meta)
+            .await?
+            .with_center(center.clone(), "channel-1".to_string(), vec![(0, 0)]);
+        let ch2 = Services::seeded_in_sqlite(&opt, [], meta)
+            .await?
+            .with_center(center.clone(), "channel-2".to_string(), vec![(0, 1)]);
+        let (acc_id, char_id) = ch1.seed_acc_char().await?;
+        let key = get_key();
+
+        ch1.session_manager.login(acc_id).await?;
+        ch1.session_manager
+            .create_migration_session(
+                key,
+                MigrationTicket {
+                    acc_id,
+                    char_id,
+                    channel_id: 0,
+                },
+            )
+            .await?;
+        let mut session = ch1
+            .session_manager
+            .claim_migration_session(key, OnlineState::Channel(0, 0))
+            .await?;
+
+        // A running trade binds the character to the first channel
+        session.start_activity(ChannelBoundActivity::Trade)?;
+        assert!(session.check_migration().is_err());
+        assert!(session.start_activity(ChannelBoundActivity::Shop).is_err());
+        session.end_activity(ChannelBoundActivity::Trade);
+        session.check_migration()?;
+
+        let mesos = session.char.model.mesos;
+        assert!(session.char.update_mesos(1_000));
+
+        // The first channel saves the session, the second one loads It from the database
+        let (session, _) = tokio::join!(
+            ch2.session_manager
+                .claim_migration_session(key, OnlineState::Channel(0, 1)),
+            async {
+                ch1.session_manager
+                    .migrate_session(key, session)
+                    .await
+                    .unwrap()
+            }
+        );
+        let session = session?;
+        assert_eq!(session.char.model.mesos, mesos + 1_000);
+        assert_eq!(session.activity, None);
+        assert!(!ch1.session_manager.online().is_online(acc_id));
+        assert_eq!(
+            center.online.get_state(acc_id),
+            Some(OnlineState::Channel(0, 1))
+        );
+
+        ch2.session_manager.close_session(session).await?;
+        assert!(!center.online.is_online(acc_id));
+        std::fs::remove_file(path)?;
+        Ok(())
     }
 }
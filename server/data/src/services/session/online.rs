@@ -0,0 +1,302 @@
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use proto95::login::world::{ChannelId, WorldId};
+use thiserror::Error;
+
+use crate::services::data::account::AccountId;
+
+use super::ShroomMigrationKey;
+
+/// Accounts which didn't send a keep-alive for this duration are considered stale,
+/// this releases accounts of crashed handlers
+pub const ONLINE_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum OnlineError {
+    #[error("Account is already logged in")]
+    AlreadyLoggedIn,
+    #[error("Disconnect was requested")]
+    DisconnectRequested,
+    #[error("Server is shutting down")]
+    Closed,
+    #[error("Center is unavailable")]
+    Unavailable,
+}
+
+/// Where an online account currently is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnlineState {
+    Login,
+    /// The session is pending in the migration manager until the deadline
+    Migrating {
+        key: ShroomMigrationKey,
+        deadline: Instant,
+    },
+    Channel(WorldId, ChannelId),
+    CashShop,
+}
+
+#[derive(Debug, Clone)]
+struct OnlineEntry {
+    state: OnlineState,
+    last_seen: Instant,
+    disconnect: bool,
+}
+
+impl OnlineEntry {
+    fn new(state: OnlineState, now: Instant) -> Self {
+        Self {
+            state,
+            last_seen: now,
+            disconnect: false,
+        }
+    }
+
+    fn is_stale(&self, now: Instant) -> bool {
+        match self.state {
+            OnlineState::Migrating { deadline, .. } => deadline <= now,
+            _ => now.saturating_duration_since(self.last_seen) >= ONLINE_TIMEOUT,
+        }
+    }
+}
+
+/// Result of a successful login
+#[derive(Debug, PartialEq, Eq)]
+pub enum OnlineLogin {
+    Fresh,
+    /// A stale entry was replaced, a timed out migration must be cleaned up
+    ReplacedStale(OnlineState),
+}
+
+/// Registry of all online accounts across the login server and all channels,
+/// an account can only hold a single entry at a time
+#[derive(Debug, Default)]
+pub struct OnlineRegistry {
+    accounts: DashMap<AccountId, OnlineEntry>,
+    /// Set for the shutdown, every session has to disconnect
+    closed: AtomicBool,
+}
+
+impl OnlineRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn is_online(&self, acc_id: AccountId) -> bool {
+        self.accounts.contains_key(&acc_id)
+    }
+
+    pub fn get_state(&self, acc_id: AccountId) -> Option<OnlineState> {
+        self.accounts.get(&acc_id).map(|entry| entry.state)
+    }
+
+    /// Snapshot of all online accounts
+    pub fn accounts(&self) -> Vec<(AccountId, OnlineState)> {
+        self.accounts
+            .iter()
+            .map(|entry| (*entry.key(), entry.state))
+            .collect()
+    }
+
+    /// Registers the account at the login server,
+    /// stale entries of crashed sessions or timed out migrations are replaced
+    pub fn login(&self, acc_id: AccountId, now: Instant) -> Result<OnlineLogin, OnlineError> {
+        if self.is_closed() {
+            return Err(OnlineError::Closed);
+        }
+
+        match self.accounts.entry(acc_id) {
+            Entry::Occupied(mut entry) if entry.get().is_stale(now) => {
+                let old = entry.insert(OnlineEntry::new(OnlineState::Login, now));
+                Ok(OnlineLogin::ReplacedStale(old.state))
+            }
+            Entry::Occupied(_) => Err(OnlineError::AlreadyLoggedIn),
+            Entry::Vacant(entry) => {
+                entry.insert(OnlineEntry::new(OnlineState::Login, now));
+                Ok(OnlineLogin::Fresh)
+            }
+        }
+    }
+
+    /// Updates the state of the account, the account is registered if required
+    pub fn set_state(&self, acc_id: AccountId, state: OnlineState, now: Instant) {
+        self.accounts
+            .entry(acc_id)
+            .and_modify(|entry| {
+                entry.state = state;
+                entry.last_seen = now;
+            })
+            .or_insert_with(|| OnlineEntry::new(state, now));
+    }
+
+    /// Refreshes the entry, fails if a disconnect was requested
+    pub fn keep_alive(&self, acc_id: AccountId, now: Instant) -> Result<(), OnlineError> {
+        if let Some(mut entry) = self.accounts.get_mut(&acc_id) {
+            entry.last_seen = now;
+            if entry.disconnect || self.is_closed() {
+                return Err(OnlineError::DisconnectRequested);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Requests the session of the account to disconnect on the next keep-alive,
+    /// returns false if the account is not online
+    pub fn request_disconnect(&self, acc_id: AccountId) -> bool {
+        self.accounts
+            .get_mut(&acc_id)
+            .map(|mut entry| entry.disconnect = true)
+            .is_some()
+    }
+
+    /// Rejects further logins and requests every session to disconnect
+    /// on the next keep-alive
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    pub fn logout(&self, acc_id: AccountId) {
+        self.accounts.remove(&acc_id);
+    }
+
+    /// Removes all stale entries and returns them
+    pub fn remove_stale(&self, now: Instant) -> Vec<(AccountId, OnlineState)> {
+        let mut stale = vec![];
+        self.accounts.retain(|acc_id, entry| {
+            if entry.is_stale(now) {
+                stale.push((*acc_id, entry.state));
+                false
+            } else {
+                true
+            }
+        });
+        stale
+    }
+
+    /// Number of accounts in the given channel
+    pub fn channel_count(&self, world: WorldId, channel: ChannelId) -> usize {
+        self.accounts
+            .iter()
+            .filter(|entry| entry.state == OnlineState::Channel(world, channel))
+            .count()
+    }
+
+    /// Number of accounts per world and channel, channels without accounts are omitted
+    pub fn channel_population(&self) -> BTreeMap<(WorldId, ChannelId), usize> {
+        let mut population = BTreeMap::new();
+        for entry in self.accounts.iter() {
+            if let OnlineState::Channel(world, ch) = entry.state {
+                *population.entry((world, ch)).or_default() += 1;
+            }
+        }
+        population
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, Instant},
+    };
+
+    use crate::services::session::ShroomMigrationKey;
+
+    use super::{OnlineError, OnlineLogin, OnlineRegistry, OnlineState, ONLINE_TIMEOUT};
+
+    #[test]
+    fn login_twice() {
+        let now = Instant::now();
+        let reg = OnlineRegistry::new();
+
+        assert_eq!(reg.login(1, now), Ok(OnlineLogin::Fresh));
+        assert_eq!(reg.login(1, now), Err(OnlineError::AlreadyLoggedIn));
+        assert_eq!(reg.login(2, now), Ok(OnlineLogin::Fresh));
+
+        reg.set_state(1, OnlineState::Channel(0, 0), now);
+        assert_eq!(reg.login(1, now), Err(OnlineError::AlreadyLoggedIn));
+        assert_eq!(reg.channel_count(0, 0), 1);
+        assert_eq!(reg.channel_count(1, 0), 0);
+        reg.set_state(2, OnlineState::Channel(0, 0), now);
+        assert_eq!(reg.channel_population().get(&(0, 0)), Some(&2));
+        reg.set_state(2, OnlineState::CashShop, now);
+
+        // The next keep-alive disconnects the session
+        assert!(reg.request_disconnect(1));
+        assert_eq!(
+            reg.keep_alive(1, now),
+            Err(OnlineError::DisconnectRequested)
+        );
+        reg.logout(1);
+        assert_eq!(reg.login(1, now), Ok(OnlineLogin::Fresh));
+        assert!(!reg.request_disconnect(3));
+    }
+
+    #[test]
+    fn stale_entries() {
+        let now = Instant::now();
+        let reg = OnlineRegistry::new();
+        let key = ShroomMigrationKey::new([0; 8], IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let migrating = OnlineState::Migrating {
+            key,
+            deadline: now + Duration::from_secs(30),
+        };
+
+        reg.login(1, now).unwrap();
+        reg.set_state(1, migrating, now);
+        assert_eq!(
+            reg.login(1, now + Duration::from_secs(10)),
+            Err(OnlineError::AlreadyLoggedIn)
+        );
+        assert_eq!(
+            reg.login(1, now + Duration::from_secs(30)),
+            Ok(OnlineLogin::ReplacedStale(migrating))
+        );
+        reg.logout(1);
+
+        // Accounts without keep-alive are released after the timeout
+        reg.set_state(2, OnlineState::Channel(0, 1), now);
+        reg.keep_alive(2, now + ONLINE_TIMEOUT / 2).unwrap();
+        assert!(reg.remove_stale(now + ONLINE_TIMEOUT).is_empty());
+        assert_eq!(
+            reg.remove_stale(now + ONLINE_TIMEOUT * 2),
+            vec![(2, OnlineState::Channel(0, 1))]
+        );
+        assert!(!reg.is_online(2));
+    }
+
+    #[test]
+    fn close() {
+        let now = Instant::now();
+        let reg = OnlineRegistry::new();
+
+        reg.login(1, now).unwrap();
+        reg.keep_alive(1, now).unwrap();
+
+        // Every session disconnects and no further logins are accepted
+        reg.close();
+        assert_eq!(
+            reg.keep_alive(1, now),
+            Err(OnlineError::DisconnectRequested)
+        );
+        assert_eq!(reg.login(2, now), Err(OnlineError::Closed));
+    }
+}
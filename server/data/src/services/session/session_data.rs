@@ -1,22 +1,96 @@
 use std::{collections::BTreeMap, sync::Arc};
 
-use proto95::id::SkillId;
+use proto95::{id::SkillId, login::world::ChannelId, shared::char::CashID};
+use sea_orm::{DatabaseConnection, TransactionTrait};
 
 use crate::{
     entities::{self, skill},
     services::{
         character::Character,
-        data::{character::CharacterID, DataServices},
+        data::{
+            account::AccountId,
+            character::{save_skills, update_char, CharacterID},
+            item::save_inventory,
+            pet::save_pets,
+            DataServices,
+        },
+        helper::intentory::inv::InventoryLayout,
+        model::pet::Pet,
     },
 };
 
-use super::session_manager::{OwnedSession, SessionBackend};
+use super::{
+    center::MigrationTicket,
+    session_manager::{OwnedSession, SessionBackend, SessionError, SessionResult},
+    AccountSessionData,
+};
+
+/// Activities, which bind the character to the current channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelBoundActivity {
+    Trade,
+    Shop,
+    MiniGame,
+    /// Private field instance of a party quest or an event
+    Event,
+}
 
 #[derive(Debug, Clone)]
 pub struct ShroomSessionData {
     pub acc: entities::account::Model,
     pub char: Character,
     pub skills: BTreeMap<SkillId, skill::Model>,
+    /// Pets of the pet items in the cash inventory
+    pub pets: BTreeMap<CashID, Pet>,
+    /// Last game channel, used to return from the cash shop
+    pub channel_id: ChannelId,
+    /// Running trade or event, the channel can't be changed meanwhile
+    pub activity: Option<ChannelBoundActivity>,
+    /// Skills of the last save, to skip saving unchanged skills
+    saved_skills: BTreeMap<SkillId, skill::Model>,
+    /// Inventory layout of the last save, to skip saving an unchanged inventory
+    saved_inventory: InventoryLayout,
+}
+
+impl ShroomSessionData {
+    /// Binds the character to the channel, only one activity can run at a time
+    pub fn start_activity(&mut self, activity: ChannelBoundActivity) -> anyhow::Result<()> {
+        if let Some(running) = self.activity {
+            anyhow::bail!("Unable to start {activity:?}, already in {running:?}");
+        }
+        self.activity = Some(activity);
+        Ok(())
+    }
+
+    /// Ends the activity, If It's the running one
+    pub fn end_activity(&mut self, activity: ChannelBoundActivity) {
+        if self.activity == Some(activity) {
+            self.activity = None;
+        }
+    }
+
+    /// Checks that no activity binds the character to the channel,
+    /// before It changes the channel or enters the cash shop
+    pub fn check_migration(&self) -> anyhow::Result<()> {
+        if let Some(activity) = self.activity {
+            anyhow::bail!("Character is bound to the channel by: {activity:?}");
+        }
+        Ok(())
+    }
+}
+
+impl AccountSessionData for ShroomSessionData {
+    fn account_id(&self) -> AccountId {
+        self.acc.id
+    }
+
+    fn migration_ticket(&self) -> MigrationTicket {
+        MigrationTicket {
+            acc_id: self.acc.id,
+            char_id: self.char.model.id,
+            channel_id: self.channel_id,
+        }
+    }
 }
 
 pub type OwnedShroomSession = OwnedSession<uuid::Uuid, ShroomSessionData>;
@@ -24,19 +98,40 @@ pub type OwnedShroomSession = OwnedSession<uuid::Uuid, ShroomSessionData>;
 #[derive(Debug)]
 pub struct ShroomSessionBackend {
     pub(crate) data: Arc<DataServices>,
+    pub(crate) db: DatabaseConnection,
 }
 
 #[async_trait::async_trait]
 impl SessionBackend for ShroomSessionBackend {
     type SessionData = ShroomSessionData;
-    type SessionLoadParam = (entities::account::Model, CharacterID);
+    type SessionLoadParam = MigrationTicket;
+    type SessionOwner = CharacterID;
+
+    fn get_owner(param: &Self::SessionLoadParam) -> Self::SessionOwner {
+        param.char_id
+    }
 
-    async fn load(&self, param: Self::SessionLoadParam) -> anyhow::Result<Self::SessionData> {
-        let (acc, char_id) = param;
-        //TODO: important verify that char belongs to the account
-        let char = Character::new(self.data.char.must_get(char_id).await?, self.data.item.load_inventory_for_character(char_id).await?);
+    /// Loads the character of the account, deleted characters can't be loaded
+    async fn load(&self, param: Self::SessionLoadParam) -> SessionResult<Self::SessionData> {
+        let MigrationTicket {
+            acc_id,
+            char_id,
+            channel_id,
+        } = param;
+        let acc = self.data.account.must_get(acc_id).await?;
+        let model = self.data.char.must_get(char_id).await?;
+        if model.acc_id != acc.id || model.deleted_at.is_some() {
+            return Err(SessionError::CharacterNotOwned {
+                acc_id: acc.id,
+                char_id,
+            });
+        }
+        let char = Character::new(
+            model,
+            self.data.item.load_inventory_for_character(char_id).await?,
+        );
 
-        let skills = self
+        let skills: BTreeMap<_, _> = self
             .data
             .char
             .load_skills(char_id)
@@ -44,14 +139,64 @@ impl SessionBackend for ShroomSessionBackend {
             .into_iter()
             .map(|skill| (SkillId(skill.id as u32), skill))
             .collect();
-        Ok(ShroomSessionData { acc, char, skills })
+        let pets = self.data.pet.load_pets(&char.inventory.cash).await?;
+
+        Ok(ShroomSessionData {
+            acc,
+            saved_inventory: char.inventory.layout(),
+            char,
+            saved_skills: skills.clone(),
+            skills,
+            pets,
+            channel_id,
+            activity: None,
+        })
     }
-    async fn save(&self, session: Self::SessionData) -> anyhow::Result<()> {
+
+    /// Saves the character, the skills, the inventory and the pets in one transaction,
+    /// unchanged stats, skills and inventories are skipped
+    async fn save(&self, session: &mut Self::SessionData) -> anyhow::Result<()> {
         let char_id = session.char.model.id;
-        self.data
-            .item
-            .save_inventory(session.char.inventory, char_id)
-            .await?;
+        let skills_dirty = session.skills != session.saved_skills;
+        let inventory_dirty = session.char.inventory.layout() != session.saved_inventory;
+
+        // Saving sets the ids of new items and pets and resets their changes,
+        // which is undone, when the transaction fails
+        let inventory = inventory_dirty.then(|| session.char.inventory.clone());
+        let pets = session.pets.clone();
+        let res = async {
+            let txn = self.db.begin().await?;
+            if let Some(char) = session.char.get_dirty_model() {
+                update_char(&txn, char).await?;
+            }
+            if skills_dirty {
+                save_skills(&txn, char_id, session.skills.values()).await?;
+            }
+            //TODO save quest records once they are stored
+            if inventory_dirty {
+                save_inventory(&txn, &mut session.char.inventory, char_id).await?;
+            }
+            save_pets(&txn, session.pets.values_mut()).await?;
+            txn.commit().await?;
+            anyhow::Ok(())
+        }
+        .await;
+
+        if let Err(err) = res {
+            if let Some(inventory) = inventory {
+                session.char.inventory = inventory;
+            }
+            session.pets = pets;
+            return Err(err);
+        }
+
+        session.char.clear_dirty();
+        if skills_dirty {
+            session.saved_skills = session.skills.clone();
+        }
+        if inventory_dirty {
+            session.saved_inventory = session.char.inventory.layout();
+        }
 
         Ok(())
     }
@@ -1,25 +1,66 @@
-use dashmap::DashMap;
+use dashmap::{mapref::entry::Entry, DashMap};
 use std::{
     hash::Hash,
+    ops::{Deref, DerefMut},
     sync::Arc,
-    time::{Duration, Instant}, ops::{Deref, DerefMut},
+    time::{Duration, Instant},
 };
+use thiserror::Error;
 use tokio::sync::Mutex;
 
+use crate::services::data::{account::AccountId, character::CharacterID};
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("Session for key already exists")]
+    KeyInUse,
+    #[error("Session owner already has a live session")]
+    OwnerInUse,
+    #[error("No session for key")]
+    NotFound,
+    #[error("Session is already claimed")]
+    Claimed,
+    #[error("Timeout while claiming the session")]
+    Timeout,
+    #[error("Session is still referenced")]
+    StillReferenced,
+    #[error("Character {char_id} does not belong to account {acc_id}")]
+    CharacterNotOwned {
+        acc_id: AccountId,
+        char_id: CharacterID,
+    },
+    #[error("Session backend: {0}")]
+    Backend(#[from] anyhow::Error),
+}
+
+impl SessionError {
+    /// The client tried to load data It must not access,
+    /// the client should be disconnected
+    pub fn is_integrity_violation(&self) -> bool {
+        matches!(self, Self::OwnerInUse | Self::CharacterNotOwned { .. })
+    }
+}
+
+pub type SessionResult<T> = Result<T, SessionError>;
+
 #[async_trait::async_trait]
 pub trait SessionBackend {
     type SessionData: std::fmt::Debug;
     type SessionLoadParam;
+    /// Only a single live session can exist per owner
+    type SessionOwner: Eq + Hash + Clone + std::fmt::Debug;
 
-    async fn load(&self, param: Self::SessionLoadParam) -> anyhow::Result<Self::SessionData>;
-    async fn save(&self, session: Self::SessionData) -> anyhow::Result<()>;
+    fn get_owner(param: &Self::SessionLoadParam) -> Self::SessionOwner;
+    async fn load(&self, param: Self::SessionLoadParam) -> SessionResult<Self::SessionData>;
+    async fn save(&self, session: &mut Self::SessionData) -> anyhow::Result<()>;
 }
 
-
 #[derive(Debug)]
 pub struct OwnedSession<Key, SessionData> {
     pub session: tokio::sync::OwnedMutexGuard<SessionData>,
-    pub key: Key
+    pub key: Key,
+    /// Time of the last save, used for the autosave
+    pub saved_at: Instant,
 }
 
 impl<Key, SessionData> Deref for OwnedSession<Key, SessionData> {
@@ -38,102 +79,246 @@ impl<Key, SessionData> DerefMut for OwnedSession<Key, SessionData> {
 
 pub type SessionMutex<SessionData> = Arc<Mutex<SessionData>>;
 
+/// Keeps the owner from loading a session until It's dropped
 #[derive(Debug)]
-pub struct SessionManager<Key: Eq + Hash, Backend: SessionBackend> {
-    sessions: DashMap<Key, SessionMutex<Backend::SessionData>>,
-    backend: Backend
+pub struct OwnerReservation<'a, Owner: Eq + Hash, Key: Eq + Hash> {
+    owners: &'a DashMap<Owner, Key>,
+    owner: Owner,
+    key: Key,
+}
+
+impl<'a, Owner: Eq + Hash, Key: Eq + Hash> Drop for OwnerReservation<'a, Owner, Key> {
+    fn drop(&mut self) {
+        self.owners.remove_if(&self.owner, |_, k| *k == self.key);
+    }
+}
+
+#[derive(Debug)]
+struct SessionEntry<Owner, SessionData> {
+    owner: Owner,
+    data: SessionMutex<SessionData>,
 }
 
+/// Holds the loaded sessions, a session is claimed by the connection or the
+/// migration, which currently uses It. Unclaimed sessions are left over by
+/// connections, which ended without closing their session
+#[derive(Debug)]
+pub struct SessionManager<Key: Eq + Hash, Backend: SessionBackend> {
+    sessions: DashMap<Key, SessionEntry<Backend::SessionOwner, Backend::SessionData>>,
+    owners: DashMap<Backend::SessionOwner, Key>,
+    backend: Backend,
+    autosave_interval: Duration,
+    /// Claimed sessions, which were saved before this time, are saved on their next autosave
+    save_requested_at: std::sync::Mutex<Option<Instant>>,
+}
 
 impl<Key, Backend> SessionManager<Key, Backend>
 where
     Key: Eq + Hash + Clone + std::fmt::Debug,
     Backend: SessionBackend + Send + 'static,
 {
-    pub fn new(backend: Backend) -> Self {
+    pub fn new(backend: Backend, autosave_interval: Duration) -> Self {
         Self {
             sessions: DashMap::new(),
-            backend
+            owners: DashMap::new(),
+            backend,
+            autosave_interval,
+            save_requested_at: std::sync::Mutex::new(None),
         }
     }
 
-
-    // TODO: create proper house-cleaning process here and document it
-    fn clear_closed_session(&self) {
-        let mut held_locks = vec![];
-
-        self.sessions.retain(|_, v| {
-            if let Ok(guard) = v.clone().try_lock_owned() {
-                held_locks.push(guard);
-                false
-            } else {
-                true
-            }
-        });
+    pub fn keys(&self) -> Vec<Key> {
+        self.sessions.iter().map(|s| s.key().clone()).collect()
     }
 
-    fn create_session_from_data(&self, key: Key, data: Backend::SessionData) -> anyhow::Result<()> {
-        let mut inserted = false;
-        self.sessions.entry(key).or_insert_with(|| {
-            inserted = true;
-            Arc::new(Mutex::new(data))
-        });
+    /// Whether the owner has a loaded session, claimed or not, or a reservation
+    pub fn is_loaded(&self, owner: &Backend::SessionOwner) -> bool {
+        self.owners.contains_key(owner)
+    }
 
-        if !inserted {
-            anyhow::bail!("Session for key already exists");
+    /// Reserves the owner with a key, which no session uses, so the owner can't load
+    /// a session while the reservation is held. Fails If the owner has a loaded session
+    pub fn reserve_owner(
+        &self,
+        owner: Backend::SessionOwner,
+        key: Key,
+    ) -> SessionResult<OwnerReservation<'_, Backend::SessionOwner, Key>> {
+        match self.owners.entry(owner.clone()) {
+            Entry::Occupied(_) => Err(SessionError::OwnerInUse),
+            Entry::Vacant(entry) => {
+                entry.insert(key.clone());
+                Ok(OwnerReservation {
+                    owners: &self.owners,
+                    owner,
+                    key,
+                })
+            }
         }
+    }
 
-        Ok(())
+    /// Number of sessions, which are claimed by a connection or a migration
+    pub fn claimed(&self) -> usize {
+        self.sessions
+            .iter()
+            .filter(|s| s.value().data.try_lock().is_err())
+            .count()
     }
 
-    pub async fn close_session(&self, session: OwnedSession<Key, Backend::SessionData>) -> anyhow::Result<()> {
+    /// Saves and removes the session, the claim must be the only reference
+    pub async fn close_session(
+        &self,
+        session: OwnedSession<Key, Backend::SessionData>,
+    ) -> SessionResult<()> {
         let key = session.key.clone();
-        //self.backend.save(session).await?;
-        
+
         // Release lock
         drop(session);
 
-        // Remove session
-        let session = self.sessions.remove(&key).unwrap();
+        let Some((_, entry)) = self
+            .sessions
+            .remove_if(&key, |_, entry| Arc::strong_count(&entry.data) == 1)
+        else {
+            return Err(if self.sessions.contains_key(&key) {
+                SessionError::StillReferenced
+            } else {
+                SessionError::NotFound
+            });
+        };
+        self.owners.remove_if(&entry.owner, |_, k| *k == key);
 
-        let session = Arc::<tokio::sync::Mutex<<Backend as SessionBackend>::SessionData>>::try_unwrap(session.1).unwrap();
-        let session_data = session.into_inner();
-        self.backend.save(session_data).await?;
+        let mut session_data = Arc::try_unwrap(entry.data)
+            .map_err(|_| SessionError::StillReferenced)?
+            .into_inner();
+        self.backend.save(&mut session_data).await?;
 
+        Ok(())
+    }
 
+    /// Saves the claimed session without releasing it
+    pub async fn save_session(
+        &self,
+        session: &mut OwnedSession<Key, Backend::SessionData>,
+    ) -> anyhow::Result<()> {
+        self.backend.save(&mut session.session).await?;
+        session.saved_at = Instant::now();
         Ok(())
     }
 
-    pub async fn create_session(&self, key: Key, param: Backend::SessionLoadParam) -> anyhow::Result<()> {
-        self.clear_closed_session();
+    /// Requests a save of the claimed sessions, which happens on their next autosave
+    pub fn request_save(&self) {
+        *self.save_requested_at.lock().expect("Save request lock") = Some(Instant::now());
+    }
 
-        let data = self.backend.load(param).await?;
-        self.create_session_from_data(key, data)
+    fn is_save_requested(&self, saved_at: Instant) -> bool {
+        self.save_requested_at
+            .lock()
+            .expect("Save request lock")
+            .is_some_and(|requested_at| requested_at > saved_at)
     }
 
+    /// Saves the claimed session once the autosave interval passed since the last save
+    /// or a save was requested, returns whether the session was saved. Active sessions
+    /// are claimed by their connection, so the connection has to call this periodically
+    pub async fn autosave(
+        &self,
+        session: &mut OwnedSession<Key, Backend::SessionData>,
+    ) -> anyhow::Result<bool> {
+        if session.saved_at.elapsed() < self.autosave_interval
+            && !self.is_save_requested(session.saved_at)
+        {
+            return Ok(false);
+        }
+
+        self.save_session(session).await?;
+        Ok(true)
+    }
+
+    pub async fn create_session(
+        &self,
+        key: Key,
+        param: Backend::SessionLoadParam,
+    ) -> SessionResult<()> {
+        self.create_claim_session(key, param).await?;
+        Ok(())
+    }
+
+    /// Loads and claims the session, fails If the owner already has a live session.
+    /// An unclaimed session of the owner is saved and replaced
     pub async fn create_claim_session(
         &self,
         key: Key,
-        param: Backend::SessionLoadParam
-    ) -> anyhow::Result<OwnedSession<Key, Backend::SessionData>>
-    where
-        Key: Clone,
-    {
-        self.create_session(key.clone(), param).await?;
-        self.try_claim_session(&key)
+        param: Backend::SessionLoadParam,
+    ) -> SessionResult<OwnedSession<Key, Backend::SessionData>> {
+        let owner = Backend::get_owner(&param);
+        let prev_key = self.owners.get(&owner).map(|k| k.value().clone());
+        if let Some(prev_key) = prev_key {
+            let prev = self
+                .try_claim_session(&prev_key)
+                .map_err(|_| SessionError::OwnerInUse)?;
+            log::warn!("Replacing unclaimed session of {owner:?}");
+            self.close_session(prev).await?;
+        }
+
+        match self.owners.entry(owner.clone()) {
+            Entry::Occupied(_) => return Err(SessionError::OwnerInUse),
+            Entry::Vacant(entry) => {
+                entry.insert(key.clone());
+            }
+        }
+
+        let res = match self.backend.load(param).await {
+            Ok(data) => self.insert_claimed(key.clone(), owner.clone(), data),
+            Err(err) => Err(err),
+        };
+        if res.is_err() {
+            self.owners.remove_if(&owner, |_, k| *k == key);
+        }
+        res
+    }
+
+    /// Inserts the data as claimed session, so no one else can claim It first
+    fn insert_claimed(
+        &self,
+        key: Key,
+        owner: Backend::SessionOwner,
+        data: Backend::SessionData,
+    ) -> SessionResult<OwnedSession<Key, Backend::SessionData>> {
+        let data = Arc::new(Mutex::new(data));
+        let session = data
+            .clone()
+            .try_lock_owned()
+            .map_err(|_| SessionError::Claimed)?;
+
+        match self.sessions.entry(key.clone()) {
+            Entry::Occupied(_) => Err(SessionError::KeyInUse),
+            Entry::Vacant(entry) => {
+                entry.insert(SessionEntry { owner, data });
+                Ok(OwnedSession {
+                    session,
+                    key,
+                    saved_at: Instant::now(),
+                })
+            }
+        }
     }
 
-    pub fn try_claim_session(&self, key: &Key) -> anyhow::Result<OwnedSession<Key, Backend::SessionData>> {
-        let data = self
-            .sessions
+    fn get_session_data(&self, key: &Key) -> SessionResult<SessionMutex<Backend::SessionData>> {
+        self.sessions
             .get(key)
-            .ok_or_else(|| anyhow::anyhow!("No session for key"))?
-            .value()
-            .clone();
+            .map(|entry| entry.data.clone())
+            .ok_or(SessionError::NotFound)
+    }
+
+    pub fn try_claim_session(
+        &self,
+        key: &Key,
+    ) -> SessionResult<OwnedSession<Key, Backend::SessionData>> {
+        let data = self.get_session_data(key)?;
 
         Ok(OwnedSession {
-            session: data.try_lock_owned()?,
-            key: key.clone()
+            session: data.try_lock_owned().map_err(|_| SessionError::Claimed)?,
+            key: key.clone(),
+            saved_at: Instant::now(),
         })
     }
 
@@ -141,25 +326,168 @@ where
         &self,
         key: &Key,
         timeout: Duration,
-    ) -> anyhow::Result<OwnedSession<Key, Backend::SessionData>> {
-        let data = self
-            .sessions
-            .get(key)
-            .ok_or_else(|| anyhow::anyhow!("No session for key"))?
-            .value()
-            .clone();
+    ) -> SessionResult<OwnedSession<Key, Backend::SessionData>> {
+        let data = self.get_session_data(key)?;
 
         let now = Instant::now();
         while now.elapsed() < timeout {
             if let Ok(session) = data.clone().try_lock_owned() {
                 return Ok(OwnedSession {
                     session,
-                    key: key.clone()
+                    key: key.clone(),
+                    saved_at: Instant::now(),
                 });
             }
             tokio::time::sleep(Duration::from_millis(10)).await;
         }
 
-        anyhow::bail!("Timeout")
+        Err(SessionError::Timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::{SessionBackend, SessionError, SessionManager, SessionResult};
+
+    #[derive(Debug, Default)]
+    struct MockBackend {
+        saved: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl SessionBackend for MockBackend {
+        type SessionData = u32;
+        type SessionLoadParam = (u32, u32);
+        type SessionOwner = u32;
+
+        fn get_owner(param: &Self::SessionLoadParam) -> Self::SessionOwner {
+            param.1
+        }
+
+        async fn load(&self, param: Self::SessionLoadParam) -> SessionResult<u32> {
+            let (acc_id, char_id) = param;
+            if acc_id != char_id {
+                return Err(SessionError::CharacterNotOwned {
+                    acc_id: acc_id as i32,
+                    char_id: char_id as i32,
+                });
+            }
+            Ok(char_id)
+        }
+
+        async fn save(&self, _session: &mut u32) -> anyhow::Result<()> {
+            self.saved.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn get_svc() -> SessionManager<u32, MockBackend> {
+        SessionManager::new(MockBackend::default(), Duration::from_secs(60))
+    }
+
+    #[tokio::test]
+    async fn single_session_per_owner() {
+        let svc = get_svc();
+        let session = svc.create_claim_session(1, (1, 1)).await.unwrap();
+        assert_eq!(*session, 1);
+
+        let err = svc.create_claim_session(2, (1, 1)).await.unwrap_err();
+        assert!(matches!(err, SessionError::OwnerInUse));
+        assert!(err.is_integrity_violation());
+        let err = svc.create_claim_session(1, (2, 2)).await.unwrap_err();
+        assert!(matches!(err, SessionError::KeyInUse));
+        assert!(matches!(
+            svc.try_claim_session(&1),
+            Err(SessionError::Claimed)
+        ));
+
+        // Closing releases the owner
+        svc.close_session(session).await.unwrap();
+        assert_eq!(svc.backend.saved.load(Ordering::SeqCst), 1);
+        assert!(svc.keys().is_empty());
+        let session = svc.create_claim_session(2, (1, 1)).await.unwrap();
+        svc.close_session(session).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn load_failure_releases_owner() {
+        let svc = get_svc();
+        let err = svc.create_claim_session(1, (2, 1)).await.unwrap_err();
+        assert!(matches!(err, SessionError::CharacterNotOwned { .. }));
+        assert!(err.is_integrity_violation());
+
+        assert!(svc.create_claim_session(1, (1, 1)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn requested_save() {
+        let svc = get_svc();
+        let mut session = svc.create_claim_session(1, (1, 1)).await.unwrap();
+        assert!(svc.is_loaded(&1));
+        assert!(!svc.autosave(&mut session).await.unwrap());
+
+        // The requested save happens once, even though the interval didn't pass
+        svc.request_save();
+        assert!(svc.autosave(&mut session).await.unwrap());
+        assert!(!svc.autosave(&mut session).await.unwrap());
+        assert_eq!(svc.backend.saved.load(Ordering::SeqCst), 1);
+
+        svc.close_session(session).await.unwrap();
+        assert!(!svc.is_loaded(&1));
+    }
+
+    #[tokio::test]
+    async fn reserved_owner() {
+        let svc = get_svc();
+        let reservation = svc.reserve_owner(1, 10).unwrap();
+        assert!(svc.is_loaded(&1));
+        assert!(matches!(
+            svc.create_claim_session(1, (1, 1)).await,
+            Err(SessionError::OwnerInUse)
+        ));
+        assert!(matches!(
+            svc.reserve_owner(1, 11),
+            Err(SessionError::OwnerInUse)
+        ));
+
+        // Dropping the reservation releases the owner
+        drop(reservation);
+        let session = svc.create_claim_session(1, (1, 1)).await.unwrap();
+        assert!(matches!(
+            svc.reserve_owner(1, 10),
+            Err(SessionError::OwnerInUse)
+        ));
+        svc.close_session(session).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn replace_unclaimed_session() {
+        let svc = get_svc();
+        // The session is left over by a connection, which ended without closing It
+        drop(svc.create_claim_session(1, (1, 1)).await.unwrap());
+        assert_eq!(svc.claimed(), 0);
+
+        let session = svc.create_claim_session(2, (1, 1)).await.unwrap();
+        assert_eq!(svc.backend.saved.load(Ordering::SeqCst), 1);
+        assert_eq!(svc.keys(), vec![2]);
+
+        // The claim must be the only reference
+        let handle = svc.get_session_data(&2).unwrap();
+        assert!(matches!(
+            svc.close_session(session).await,
+            Err(SessionError::StillReferenced)
+        ));
+        drop(handle);
+        let session = svc.try_claim_session(&2).unwrap();
+        svc.close_session(session).await.unwrap();
+        assert!(matches!(
+            svc.try_claim_session(&2),
+            Err(SessionError::NotFound)
+        ));
     }
 }
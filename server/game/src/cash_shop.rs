@@ -0,0 +1,610 @@
+use std::{net::IpAddr, time::Instant};
+
+use async_trait::async_trait;
+use data::{
+    entities::cash_item,
+    proto_mapper::db_to_shroom_time,
+    services::{
+        data::cash_shop::{CashShopError, LockerItem},
+        helper::intentory::inv::InventoryExt,
+        metrics,
+        model::item::{EquipItem, StackItem},
+        session::{
+            online::OnlineState, session_data::OwnedShroomSession, ClientKey, OnlineChar,
+            ShroomMigrationKey,
+        },
+        SharedServices,
+    },
+};
+use proto95::{
+    game::{
+        cash_shop::{
+            BestItem, CashGiftDone, CashItemBuyReq, CashItemFailReason, CashItemGiftReq,
+            CashItemInfo, CashItemMoveLToSReq, CashItemMoveSToLReq, CashLockerData,
+            CashMoveLToSDone, CashShopCashItemReq, CashShopCashItemResp, CashShopQueryCashReq,
+            CashShopQueryCashResp, CashShopTransferFieldReq, SetCashShopResp,
+        },
+        MigrateCommandResp,
+    },
+    id::ItemId,
+    login::world::WorldId,
+    recv_opcodes::RecvOpcodes,
+    shared::{
+        char::{CharDataFlagsAll, CharDataHeader},
+        inventory::InventoryType,
+        item::Item,
+        PongReq,
+    },
+};
+use shroom_net::{
+    net::{
+        service::{
+            handler::{MakeServerSessionHandler, SessionHandleResult, ShroomSessionHandler},
+            resp::{MigrateResponse, PongResponse},
+            server_sess::SharedSessionHandle,
+        },
+        ShroomSession,
+    },
+    packet::{PacketReader, ShroomPacket},
+    shroom_router_fn, PacketBuffer,
+};
+use tokio::net::TcpStream;
+
+use crate::{claim_migrated_session, get_char_data, pet, GameResult};
+
+#[derive(Debug, Clone)]
+pub struct MakeCashShopHandler {
+    services: SharedServices,
+    world_id: WorldId,
+}
+
+impl MakeCashShopHandler {
+    pub fn new(services: SharedServices, world_id: WorldId) -> Self {
+        Self { services, world_id }
+    }
+}
+
+#[async_trait::async_trait]
+impl MakeServerSessionHandler for MakeCashShopHandler {
+    type Transport = TcpStream;
+
+    type Error = anyhow::Error;
+
+    type Handler = CashShopHandler;
+
+    async fn make_handler(
+        &mut self,
+        sess: &mut ShroomSession<Self::Transport>,
+        sess_handle: SharedSessionHandle,
+    ) -> Result<Self::Handler, Self::Error> {
+        let addr = sess.peer_addr()?.ip();
+        let (session, client_key) =
+            claim_migrated_session(sess, &self.services, OnlineState::CashShop).await?;
+        log::info!(
+            "Cash shop session for acc: {} - char: {}",
+            session.acc.username,
+            session.char.model.name
+        );
+
+        let handler = CashShopHandler {
+            session,
+            services: self.services.clone(),
+            world_id: self.world_id,
+            addr,
+            client_key,
+            sess_handle,
+        };
+
+        sess.send_packet(metrics::count_resp(handler.set_cash_shop()))
+            .await?;
+        sess.send_packet(metrics::count_resp(CashShopCashItemResp::LoadLockerDone(
+            handler.load_locker().await?,
+        )))
+        .await?;
+        sess.send_packet(metrics::count_resp(handler.query_cash()))
+            .await?;
+        self.services.session_manager.add_client(
+            OnlineChar {
+                acc_id: handler.session.acc.id,
+                char_id: handler.session.char.model.id,
+                name: handler.session.char.model.name.clone(),
+            },
+            handler.sess_handle.clone(),
+        );
+
+        Ok(handler)
+    }
+}
+
+pub struct CashShopHandler {
+    session: OwnedShroomSession,
+    services: SharedServices,
+    world_id: WorldId,
+    addr: IpAddr,
+    client_key: ClientKey,
+    sess_handle: SharedSessionHandle,
+}
+
+#[async_trait]
+impl ShroomSessionHandler for CashShopHandler {
+    type Transport = TcpStream;
+    type Error = anyhow::Error;
+    type Msg = ();
+
+    async fn handle_msg(
+        &mut self,
+        _session: &mut ShroomSession<Self::Transport>,
+        _msg: Self::Msg,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn handle_packet(
+        &mut self,
+        packet: ShroomPacket,
+        session: &mut ShroomSession<Self::Transport>,
+    ) -> Result<SessionHandleResult, Self::Error> {
+        metrics::count_packet_in(packet.as_ref());
+        shroom_router_fn!(
+            handler,
+            CashShopHandler,
+            ShroomSession<TcpStream>,
+            anyhow::Error,
+            CashShopHandler::handle_default,
+            PongReq => CashShopHandler::handle_pong,
+            CashShopQueryCashReq => CashShopHandler::handle_query_cash,
+            CashShopCashItemReq => CashShopHandler::handle_cash_item,
+            CashShopTransferFieldReq => CashShopHandler::handle_exit,
+        );
+
+        Ok(handler(self, session, packet.into_reader()).await?)
+    }
+
+    async fn finish(mut self, is_migrating: bool) -> Result<(), Self::Error> {
+        log::info!("Finishing cash shop session...");
+        self.services
+            .session_manager
+            .remove_client(self.session.char.model.id);
+        // Grants are only applied live in the field
+        self.services.apply_pending_grants(&mut self.session.char);
+        if is_migrating {
+            self.services
+                .session_manager
+                .migrate_session(
+                    ShroomMigrationKey::new(self.client_key, self.addr),
+                    self.session,
+                )
+                .await?;
+        } else {
+            self.services
+                .session_manager
+                .close_session(self.session)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+fn map_cash_item(item: &cash_item::Model) -> anyhow::Result<CashItemInfo> {
+    Ok(CashItemInfo {
+        cash_id: item.cash_id as u64,
+        acc_id: item.acc_id as u32,
+        char_id: item.char_id.unwrap_or(0) as u32,
+        item_id: ItemId(item.item_id as u32),
+        commodity_sn: item.commodity_sn as u32,
+        quantity: item.quantity as u16,
+        buyer_char_name: item.gift_from.as_deref().unwrap_or_default().try_into()?,
+        expiration: item.expires_at.map(db_to_shroom_time).into(),
+        payback_rate: 0,
+        discount_rate: 0,
+    })
+}
+
+/// Business errors are reported to the client, database errors end the session
+fn fail_reason(err: CashShopError) -> anyhow::Result<CashItemFailReason> {
+    Ok(match err {
+        CashShopError::NotOnSale | CashShopError::SlotLimitReached => {
+            CashItemFailReason::NotAvailable
+        }
+        CashShopError::NotEnoughCash => CashItemFailReason::NotEnoughCash,
+        CashShopError::ReceiverNotFound => CashItemFailReason::WrongReceiverName,
+        CashShopError::GiftSameAccount => CashItemFailReason::GiftSameAccount,
+        CashShopError::InventoryFull => CashItemFailReason::InventoryFull,
+        CashShopError::ItemNotFound(_)
+        | CashShopError::AccountNotFound(_)
+        | CashShopError::InvalidItem(_) => CashItemFailReason::Unknown,
+        CashShopError::Db(err) => return Err(err.into()),
+    })
+}
+
+impl CashShopHandler {
+    fn set_cash_shop(&self) -> SetCashShopResp {
+        SetCashShopResp {
+            char_data_flags: CharDataFlagsAll,
+            char_data_hdr: CharDataHeader {
+                combat_orders: 0,
+                extra_data: None.into(),
+            },
+            char_data: get_char_data(&self.session),
+            authorized: true,
+            account_name: self.session.acc.username.clone(),
+            not_sale: self
+                .services
+                .meta
+                .get_commodities()
+                .filter(|commodity| !commodity.is_on_sale())
+                .map(|commodity| commodity.sn)
+                .collect(),
+            modified_commodity_count: 0,
+            discount_rate_count: 0,
+            best_items: std::array::from_fn(|_| BestItem {
+                category: 0,
+                gender: 0,
+                commodity_sn: 0,
+            }),
+            stock: Default::default(),
+            limit_goods_count: 0,
+            zero_goods_count: 0,
+            event_on: false,
+            highest_char_level: self.session.char.model.level as u32,
+        }
+    }
+
+    fn query_cash(&self) -> CashShopQueryCashResp {
+        let acc = &self.session.acc;
+        CashShopQueryCashResp {
+            nx_credit: acc.nx_credit as u32,
+            shroom_points: acc.shroom_points as u32,
+            nx_prepaid: acc.nx_prepaid as u32,
+        }
+    }
+
+    async fn load_locker(&self) -> anyhow::Result<CashLockerData> {
+        let acc = &self.session.acc;
+        let items = self.services.data.cash_shop.get_locker(acc.id).await?;
+        let chars = self
+            .services
+            .data
+            .char
+            .get_characters_for_account(acc.id, self.world_id)
+            .await?;
+        let slots = self
+            .services
+            .data
+            .char
+            .get_char_slots(acc, self.world_id)
+            .await?;
+
+        Ok(CashLockerData {
+            items: items
+                .iter()
+                .map(map_cash_item)
+                .collect::<anyhow::Result<Vec<_>>>()?
+                .into(),
+            trunk_slots: 4,
+            char_slots: slots.slots as u16,
+            buy_char_count: slots.bought as u16,
+            char_count: chars.len() as u16,
+        })
+    }
+
+    fn send_query_cash(&mut self) -> anyhow::Result<()> {
+        let mut buf = PacketBuffer::new();
+        metrics::write_pkt(&mut buf, self.query_cash())?;
+        self.sess_handle.try_send_pkt_buf(&buf)?;
+        Ok(())
+    }
+
+    pub async fn handle_default(
+        &mut self,
+        op: RecvOpcodes,
+        pr: PacketReader<'_>,
+    ) -> anyhow::Result<SessionHandleResult> {
+        log::info!("Unhandled cash shop packet: {:?} {:?}", op, pr.into_inner());
+        metrics::count_unhandled_packet(op);
+        Ok(SessionHandleResult::Ok)
+    }
+
+    async fn handle_pong(&mut self, _req: PongReq) -> anyhow::Result<PongResponse> {
+        let sm = &self.services.session_manager;
+        if let Err(err) = sm.online().keep_alive(self.session.acc.id, Instant::now()) {
+            // Kicked clients save right away, so the character is stored
+            // even If the session can't be released afterwards
+            if let Err(err) = sm.save_session(&mut self.session).await {
+                log::error!("Unable to save kicked session: {err:?}");
+            }
+            return Err(err.into());
+        }
+        // The ping interval drives the autosave
+        if let Err(err) = self
+            .services
+            .session_manager
+            .autosave(&mut self.session)
+            .await
+        {
+            log::error!("Autosave failed: {err:?}");
+        }
+        Ok(PongResponse)
+    }
+
+    async fn handle_query_cash(
+        &mut self,
+        _req: CashShopQueryCashReq,
+    ) -> GameResult<CashShopQueryCashResp> {
+        Ok(metrics::count_resp(self.query_cash()))
+    }
+
+    /// Returns to the channel the character entered the cash shop from
+    async fn handle_exit(
+        &mut self,
+        _req: CashShopTransferFieldReq,
+    ) -> anyhow::Result<MigrateResponse<MigrateCommandResp>> {
+        let addr = self
+            .services
+            .server_info
+            .get_channel_addr(self.world_id, self.session.channel_id)?;
+
+        Ok(MigrateResponse(metrics::count_resp(MigrateCommandResp {
+            unknown: true,
+            addr: addr.try_into()?,
+        })))
+    }
+
+    async fn handle_cash_item(
+        &mut self,
+        req: CashShopCashItemReq,
+    ) -> GameResult<CashShopCashItemResp> {
+        match req {
+            CashShopCashItemReq::Buy(req) => self.handle_buy(req).await,
+            CashShopCashItemReq::Gift(req) => self.handle_gift(req).await,
+            CashShopCashItemReq::IncCharSlotCount(req) => self.handle_inc_char_slot(req).await,
+            CashShopCashItemReq::MoveLToS(req) => self.handle_move_l_to_s(req).await,
+            CashShopCashItemReq::MoveSToL(req) => self.handle_move_s_to_l(req).await,
+        }
+    }
+
+    async fn handle_buy(&mut self, req: CashItemBuyReq) -> GameResult<CashShopCashItemResp> {
+        let meta = self.services.meta;
+        let Some(commodity) = meta.get_commodity(req.commodity_sn) else {
+            return Ok(metrics::count_resp(CashShopCashItemResp::BuyFailed(
+                CashItemFailReason::NotAvailable,
+            )));
+        };
+
+        let res = self
+            .services
+            .data
+            .cash_shop
+            .buy(
+                self.session.acc.id,
+                self.session.char.model.id,
+                req.cash_type,
+                commodity,
+            )
+            .await;
+
+        Ok(metrics::count_resp(match res {
+            Ok((acc, item)) => {
+                self.session.acc = acc;
+                self.send_query_cash()?;
+                CashShopCashItemResp::BuyDone(map_cash_item(&item)?)
+            }
+            Err(err) => CashShopCashItemResp::BuyFailed(fail_reason(err)?),
+        }))
+    }
+
+    async fn handle_gift(&mut self, req: CashItemGiftReq) -> GameResult<CashShopCashItemResp> {
+        let meta = self.services.meta;
+        let Some(commodity) = meta.get_commodity(req.commodity_sn) else {
+            return Ok(metrics::count_resp(CashShopCashItemResp::GiftFailed(
+                CashItemFailReason::NotAvailable,
+            )));
+        };
+
+        let res = self
+            .services
+            .data
+            .cash_shop
+            .gift(
+                self.session.acc.id,
+                &self.session.char.model.name,
+                commodity,
+                &req.receiver,
+                req.msg,
+            )
+            .await;
+
+        Ok(metrics::count_resp(match res {
+            Ok((acc, item)) => {
+                self.session.acc = acc;
+                self.send_query_cash()?;
+                CashShopCashItemResp::GiftDone(CashGiftDone {
+                    receiver: req.receiver,
+                    item_id: ItemId(item.item_id as u32),
+                    quantity: item.quantity as u16,
+                    price: commodity.price,
+                })
+            }
+            Err(err) => CashShopCashItemResp::GiftFailed(fail_reason(err)?),
+        }))
+    }
+
+    /// Buys an additional character slot for the current world
+    async fn handle_inc_char_slot(
+        &mut self,
+        req: CashItemBuyReq,
+    ) -> GameResult<CashShopCashItemResp> {
+        let meta = self.services.meta;
+        let Some(commodity) = meta.get_commodity(req.commodity_sn) else {
+            return Ok(metrics::count_resp(
+                CashShopCashItemResp::IncCharSlotCountFailed(CashItemFailReason::NotAvailable),
+            ));
+        };
+
+        let res = self
+            .services
+            .data
+            .cash_shop
+            .buy_char_slot(self.session.acc.id, self.world_id, req.cash_type, commodity)
+            .await;
+
+        Ok(metrics::count_resp(match res {
+            Ok((acc, slots)) => {
+                self.session.acc = acc;
+                self.send_query_cash()?;
+                CashShopCashItemResp::IncCharSlotCountDone(slots.slots as u16)
+            }
+            Err(err) => CashShopCashItemResp::IncCharSlotCountFailed(fail_reason(err)?),
+        }))
+    }
+
+    /// Moves an item from the locker into the inventory of the character
+    async fn handle_move_l_to_s(
+        &mut self,
+        req: CashItemMoveLToSReq,
+    ) -> GameResult<CashShopCashItemResp> {
+        if !matches!(req.inv_type, InventoryType::Equip | InventoryType::Cash) {
+            anyhow::bail!("Invalid cash item inventory: {:?}", req.inv_type);
+        }
+
+        let meta = &self.services.meta;
+        let char_id = self.session.char.model.id;
+        let res = self
+            .services
+            .data
+            .cash_shop
+            .move_to_inventory(
+                self.session.acc.id,
+                char_id,
+                req.cash_id,
+                &mut self.session.char.inventory,
+                |inv, item| {
+                    let item_id = ItemId(item.item_id as u32);
+                    if item_id.is_equip() {
+                        let meta = meta
+                            .get_eq_data(item_id)
+                            .ok_or(CashShopError::InvalidItem(item_id))?;
+                        let mut equip = EquipItem::from_item_id(item_id, meta);
+                        equip.cash_id = Some(req.cash_id);
+                        equip.expiration = item.expires_at;
+                        inv.equip.get_inner_mut().try_add_slot(equip.into())
+                    } else {
+                        let mut stack = StackItem::from_item_id(item_id, item.quantity as u16);
+                        stack.cash_id = Some(req.cash_id);
+                        stack.expiration = item.expires_at;
+                        inv.cash.get_inner_mut().try_add_slot(stack.into())
+                    }
+                    .map_err(|_| CashShopError::InventoryFull)
+                },
+            )
+            .await;
+
+        let (item, slot) = match res {
+            Ok(res) => res,
+            Err(err) => {
+                return Ok(metrics::count_resp(CashShopCashItemResp::MoveLToSFailed(
+                    fail_reason(err)?,
+                )))
+            }
+        };
+
+        let item_id = ItemId(item.item_id as u32);
+        if item_id.is_pet() {
+            let pet = self
+                .services
+                .data
+                .pet
+                .get_or_create_pet(item_id, req.cash_id, item.expires_at)
+                .await?;
+            self.session.pets.insert(req.cash_id, pet);
+        }
+
+        let inv = &self.session.char.inventory;
+        let item = if item_id.is_equip() {
+            Item::Equip(inv.equip.get(slot).unwrap().item.as_ref().into())
+        } else {
+            pet::map_cash_item(&self.session, &inv.cash.get(slot).unwrap().item)
+        };
+
+        Ok(metrics::count_resp(CashShopCashItemResp::MoveLToSDone(
+            CashMoveLToSDone {
+                pos: slot as u16 + 1,
+                item,
+            },
+        )))
+    }
+
+    /// Moves a cash item from the inventory of the character back into the locker
+    async fn handle_move_s_to_l(
+        &mut self,
+        req: CashItemMoveSToLReq,
+    ) -> GameResult<CashShopCashItemResp> {
+        if !matches!(req.inv_type, InventoryType::Equip | InventoryType::Cash) {
+            anyhow::bail!("Invalid cash item inventory: {:?}", req.inv_type);
+        }
+
+        let cash_id = Some(req.cash_id);
+        let char_id = self.session.char.model.id;
+        let res = self
+            .services
+            .data
+            .cash_shop
+            .move_to_locker(
+                self.session.acc.id,
+                char_id,
+                req.cash_id,
+                &mut self.session.char.inventory,
+                |inv| {
+                    if matches!(req.inv_type, InventoryType::Equip) {
+                        let slot = inv
+                            .equip
+                            .iter()
+                            .find(|(_, item)| item.item.cash_id == cash_id)
+                            .map(|(slot, _)| slot)?;
+                        inv.equip.remove(slot).map(|item| LockerItem {
+                            cash_id: req.cash_id,
+                            item_id: item.item_id.0 as i32,
+                            quantity: 1,
+                            expires_at: item.item.expiration,
+                        })
+                    } else {
+                        let slot = inv
+                            .cash
+                            .iter()
+                            .find(|(_, item)| item.item.cash_id == cash_id)
+                            .map(|(slot, _)| slot)?;
+                        inv.cash.remove(slot).map(|item| LockerItem {
+                            cash_id: req.cash_id,
+                            item_id: item.item_id.0 as i32,
+                            quantity: item.quantity as i32,
+                            expires_at: item.item.expiration,
+                        })
+                    }
+                },
+            )
+            .await;
+
+        let item = match res {
+            Ok(item) => item,
+            Err(err) => {
+                return Ok(metrics::count_resp(CashShopCashItemResp::MoveSToLFailed(
+                    fail_reason(err)?,
+                )))
+            }
+        };
+
+        if let Some(mut pet) = self.session.pets.remove(&req.cash_id) {
+            pet.set_summoned(false);
+            self.services
+                .data
+                .pet
+                .save_pets(std::iter::once(&mut pet))
+                .await?;
+        }
+
+        Ok(metrics::count_resp(CashShopCashItemResp::MoveSToLDone(
+            map_cash_item(&item)?,
+        )))
+    }
+}
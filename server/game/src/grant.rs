@@ -0,0 +1,48 @@
+use data::services::metrics;
+use proto95::shared::char::CharStatChangedResp;
+use shroom_net::{packet::proto::partial::PartialFlag, PacketBuffer};
+
+use crate::GameHandler;
+
+impl GameHandler {
+    /// Applies the grants of the admin api and updates the client
+    pub(crate) async fn handle_grants(&mut self) -> anyhow::Result<()> {
+        let char_id = self.session.char.model.id;
+        let grants = self.services.grants.take(char_id);
+        if grants.is_empty() {
+            return Ok(());
+        }
+
+        let mut added = Vec::new();
+        let mut stats_changed = false;
+        for grant in grants {
+            match self.services.apply_grant(&mut self.session.char, grant) {
+                Ok(Some(slot)) => added.push(slot),
+                Ok(None) => stats_changed = true,
+                Err(err) => log::warn!("Unable to apply {grant:?} to {char_id}: {err:?}"),
+            }
+        }
+
+        if stats_changed {
+            let mut buf = PacketBuffer::new();
+            metrics::write_pkt(
+                &mut buf,
+                CharStatChangedResp {
+                    excl: false,
+                    stats: PartialFlag {
+                        hdr: (),
+                        data: self.session.char.get_char_partial(),
+                    },
+                    secondary_stat: false,
+                    battle_recovery: false,
+                },
+            )?;
+            self.sess_handle.try_send_pkt_buf(&buf)?;
+        }
+
+        if added.is_empty() {
+            return Ok(());
+        }
+        self.send_added_items(added).await
+    }
+}
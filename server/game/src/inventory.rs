@@ -0,0 +1,437 @@
+use data::services::{
+    helper::intentory::inv::{InventoryExt, StackInventory},
+    metrics,
+    model::item::{EquipItem, ScrollInfo},
+};
+use proto95::{
+    game::user::remote::UserItemUpgradeEffectResp,
+    id::ItemId,
+    shared::{
+        inventory::{
+            CharEquipSlot, GatherItemReq, GatherItemResultResp, GoldHammerReq, GoldHammerResult,
+            GoldHammerResultResp, InvOpAdd, InvOpMove, InvOpRemove, InvOpUpdateQuantity,
+            InvSortRequest, InventoryOperation, InventoryOperationsResp, InventoryType,
+            ItemHyperUpgradeReq, ItemUpgradeReq, SortItemResultResp,
+        },
+        item::Item,
+    },
+};
+use rand::Rng;
+use shroom_net::PacketBuffer;
+
+use crate::{GameHandler, GameResult};
+
+/// The client replaces an updated equip by removing and re-adding it
+fn update_equip_ops(slot: i16, item: Item) -> [InventoryOperation; 2] {
+    [
+        InventoryOperation::Remove(InvOpRemove {
+            inv_type: InventoryType::Equip,
+            pos: slot as u16,
+        }),
+        InventoryOperation::Add(InvOpAdd {
+            inv_type: InventoryType::Equip,
+            pos: slot as u16,
+            item,
+        }),
+    ]
+}
+
+/// Stack size used for items without a slot max
+const DEFAULT_SLOT_MAX: usize = 100;
+
+/// Empty stacks are removed from the client inventory
+fn stack_quantity_op(inv_type: InventoryType, ix: usize, quantity: usize) -> InventoryOperation {
+    let pos = ix as u16 + 1;
+    if quantity == 0 {
+        InventoryOperation::Remove(InvOpRemove { inv_type, pos })
+    } else {
+        InventoryOperation::UpdateQuantity(InvOpUpdateQuantity {
+            inv_type,
+            pos,
+            quantity: quantity as u16,
+        })
+    }
+}
+
+/// The client swaps the items, if the new slot is not empty
+fn move_op(inv_type: InventoryType, from: usize, to: usize) -> InventoryOperation {
+    InventoryOperation::Move(InvOpMove {
+        inv_type,
+        pos: from as u16 + 1,
+        new_pos: to as u16 + 1,
+    })
+}
+
+/// Client inventory slots start at 1
+pub(crate) fn client_slot_to_ix(slot: u16) -> anyhow::Result<usize> {
+    (slot as usize)
+        .checked_sub(1)
+        .ok_or_else(|| anyhow::format_err!("Invalid slot: {slot}"))
+}
+
+impl GameHandler {
+    pub fn send_inv_ops(&mut self, operations: Vec<InventoryOperation>) -> anyhow::Result<()> {
+        let mut buf = PacketBuffer::new();
+        metrics::write_pkt(
+            &mut buf,
+            InventoryOperationsResp {
+                reset_excl: true,
+                operations: operations.into(),
+                secondary_stat_changed: false,
+            },
+        )?;
+        self.sess_handle.try_send_pkt_buf(&buf)?;
+        Ok(())
+    }
+
+    /// Equipped items are addressed by negative slots
+    fn get_equip_mut(&mut self, slot: i16) -> anyhow::Result<&mut EquipItem> {
+        let inv = &mut self.session.char.inventory;
+        let item = if slot < 0 {
+            let slot = CharEquipSlot::try_from(slot.unsigned_abs() as u8)?;
+            inv.equipped.get_mut(slot)
+        } else {
+            inv.equip.get_mut(client_slot_to_ix(slot as u16)?)
+        };
+
+        item.map(|item| item.item.as_mut())
+            .ok_or_else(|| anyhow::format_err!("No equip in slot: {slot}"))
+    }
+
+    fn remove_equip(&mut self, slot: i16) -> anyhow::Result<InventoryOperation> {
+        let inv = &mut self.session.char.inventory;
+        if slot < 0 {
+            let slot = CharEquipSlot::try_from(slot.unsigned_abs() as u8)?;
+            inv.equipped.remove(slot);
+        } else {
+            inv.equip.remove(client_slot_to_ix(slot as u16)?);
+        }
+
+        Ok(InventoryOperation::Remove(InvOpRemove {
+            inv_type: InventoryType::Equip,
+            pos: slot as u16,
+        }))
+    }
+
+    pub(crate) fn stack_inv_mut(
+        &mut self,
+        inv_type: InventoryType,
+    ) -> anyhow::Result<&mut StackInventory> {
+        let inv = &mut self.session.char.inventory;
+        Ok(match inv_type {
+            InventoryType::Consume => &mut inv.use_,
+            InventoryType::Install => &mut inv.misc,
+            InventoryType::Etc => &mut inv.etc,
+            InventoryType::Cash => &mut inv.cash,
+            _ => anyhow::bail!("Invalid stack inventory: {inv_type:?}"),
+        })
+    }
+
+    /// Takes a single item from a stack inventory
+    pub fn take_stack_item(
+        &mut self,
+        inv_type: InventoryType,
+        ix: usize,
+    ) -> anyhow::Result<InventoryOperation> {
+        let remaining = self.stack_inv_mut(inv_type)?.take_items(ix, 1)?;
+        Ok(stack_quantity_op(inv_type, ix, remaining))
+    }
+
+    pub async fn save_inventory(&mut self) -> anyhow::Result<()> {
+        let char_id = self.session.char.model.id;
+        self.services
+            .data
+            .item
+            .save_inventory(&mut self.session.char.inventory, char_id)
+            .await
+    }
+
+    /// Sends the items, which were added to the slots
+    pub(crate) async fn send_added_items(
+        &mut self,
+        added: Vec<(InventoryType, usize)>,
+    ) -> anyhow::Result<()> {
+        // Equips require a db id to be encoded
+        self.save_inventory().await?;
+
+        let mut ops = Vec::with_capacity(added.len());
+        for (inv_type, ix) in added {
+            let item = if matches!(inv_type, InventoryType::Equip) {
+                self.session
+                    .char
+                    .inventory
+                    .equip
+                    .get(ix)
+                    .map(|item| Item::Equip(item.item.as_ref().into()))
+            } else {
+                self.stack_inv_mut(inv_type)?
+                    .get(ix)
+                    .map(|item| Item::Stack(item.item.as_ref().into()))
+            }
+            .ok_or_else(|| anyhow::format_err!("No item in slot: {ix}"))?;
+
+            ops.push(InventoryOperation::Add(InvOpAdd {
+                inv_type,
+                pos: ix as u16 + 1,
+                item,
+            }));
+        }
+        self.send_inv_ops(ops)
+    }
+
+    /// Removes an added item again, before the client was notified
+    pub(crate) fn remove_added_item(
+        &mut self,
+        inv_type: InventoryType,
+        ix: usize,
+    ) -> anyhow::Result<()> {
+        if matches!(inv_type, InventoryType::Equip) {
+            self.session.char.inventory.equip.remove(ix);
+        } else {
+            self.stack_inv_mut(inv_type)?.remove(ix);
+        }
+        Ok(())
+    }
+
+    /// Compacts the inventory into the lowest slots, partial stacks are merged
+    pub async fn handle_gather_items(
+        &mut self,
+        req: GatherItemReq,
+    ) -> GameResult<GatherItemResultResp> {
+        let inv_type = req.inv_ty;
+        let mut ops = Vec::new();
+        let swaps = if matches!(inv_type, InventoryType::Equip) {
+            self.session
+                .char
+                .inventory
+                .equip
+                .get_inner_mut()
+                .shift_slots()
+        } else {
+            let meta = self.services.meta;
+            let inv = self.stack_inv_mut(inv_type)?;
+            let merged = inv.merge_stacks(|id| {
+                meta.get_item_data(id)
+                    .map(|item| item.slot_max as usize)
+                    .filter(|&slot_max| slot_max > 0)
+                    .unwrap_or(DEFAULT_SLOT_MAX)
+            });
+            ops.extend(
+                merged
+                    .into_iter()
+                    .map(|(ix, quantity)| stack_quantity_op(inv_type, ix, quantity)),
+            );
+            inv.get_inner_mut().shift_slots()
+        };
+        ops.extend(
+            swaps
+                .into_iter()
+                .map(|(from, to)| move_op(inv_type, from, to)),
+        );
+
+        self.send_inv_ops(ops)?;
+        self.save_inventory().await?;
+
+        Ok(metrics::count_resp(GatherItemResultResp {
+            unknown: false,
+            inv_type,
+        })
+        .into())
+    }
+
+    /// Sorts the inventory by item id
+    pub async fn handle_sort_items(
+        &mut self,
+        req: InvSortRequest,
+    ) -> GameResult<SortItemResultResp> {
+        let inv_type = req.inv_type;
+        let swaps = if matches!(inv_type, InventoryType::Equip) {
+            self.session.char.inventory.equip.get_inner_mut().sort()
+        } else {
+            self.stack_inv_mut(inv_type)?.get_inner_mut().sort()
+        };
+
+        self.send_inv_ops(
+            swaps
+                .into_iter()
+                .map(|(from, to)| move_op(inv_type, from, to))
+                .collect(),
+        )?;
+        self.save_inventory().await?;
+
+        Ok(metrics::count_resp(SortItemResultResp {
+            unknown: false,
+            inv_type,
+        })
+        .into())
+    }
+
+    /// Hyper upgrades use enhancement scrolls, the regular upgrade all the other scrolls
+    fn upgrade_equip(
+        &mut self,
+        use_slot: u16,
+        equip_slot: i16,
+        white_scroll: bool,
+        enchant_skill: bool,
+        hyper_upgrade: bool,
+    ) -> anyhow::Result<()> {
+        let meta = self.services.meta;
+        let use_ix = client_slot_to_ix(use_slot)?;
+        let scroll_id = self
+            .session
+            .char
+            .inventory
+            .use_
+            .get(use_ix)
+            .ok_or_else(|| anyhow::format_err!("No scroll in slot: {use_slot}"))?
+            .item_id;
+        if !scroll_id.is_scroll() {
+            anyhow::bail!("Not a scroll: {scroll_id:?}");
+        }
+        if scroll_id.is_enhancement_scroll() != hyper_upgrade {
+            anyhow::bail!("Invalid upgrade with scroll: {scroll_id:?}");
+        }
+        let scroll_meta = meta
+            .get_item_data(scroll_id)
+            .ok_or_else(|| anyhow::format_err!("Invalid scroll: {scroll_id:?}"))?;
+        let scroll = ScrollInfo::from_meta(scroll_id, scroll_meta);
+
+        let white_scroll_ix = if white_scroll {
+            let ix = self
+                .session
+                .char
+                .inventory
+                .use_
+                .iter()
+                .find(|(_, item)| item.item_id == ItemId::WHITE_SCROLL)
+                .map(|(ix, _)| ix)
+                .ok_or_else(|| anyhow::format_err!("No white scroll"))?;
+            Some(ix)
+        } else {
+            None
+        };
+
+        let equip = self.get_equip_mut(equip_slot)?;
+        let equip_meta = meta
+            .get_eq_data(equip.item_id)
+            .ok_or_else(|| anyhow::format_err!("Invalid equip: {:?}", equip.item_id))?;
+        if !equip.can_use_scroll(&scroll, equip_meta) {
+            anyhow::bail!("Scroll {scroll_id:?} can't be used on {:?}", equip.item_id);
+        }
+
+        // The scrolls are used up, before the equip is changed
+        let mut ops = vec![self.take_stack_item(InventoryType::Consume, use_ix)?];
+        if let Some(ix) = white_scroll_ix {
+            ops.push(self.take_stack_item(InventoryType::Consume, ix)?);
+        }
+
+        let equip = self.get_equip_mut(equip_slot)?;
+        let res = equip.apply_scroll(rand::thread_rng(), &scroll, white_scroll);
+        let equip_item = Item::Equip((&*equip).into());
+        if res.is_destroyed() {
+            ops.push(self.remove_equip(equip_slot)?);
+        } else {
+            ops.extend(update_equip_ops(equip_slot, equip_item));
+        }
+        self.send_inv_ops(ops)?;
+
+        self.field
+            .add_item_upgrade_effect(UserItemUpgradeEffectResp {
+                char_id: self.session.char.model.id as u32,
+                success: res.is_success(),
+                cursed: res.is_destroyed(),
+                enchant_skill,
+                enchant_category: 0,
+                white_scroll,
+                recoverable: false,
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn handle_item_upgrade(&mut self, req: ItemUpgradeReq) -> anyhow::Result<()> {
+        self.upgrade_equip(
+            req.use_slot,
+            req.equip_slot as i16,
+            req.flag.use_white_scroll(),
+            req.enchant_skill,
+            false,
+        )
+    }
+
+    pub async fn handle_item_hyper_upgrade(
+        &mut self,
+        req: ItemHyperUpgradeReq,
+    ) -> anyhow::Result<()> {
+        self.upgrade_equip(
+            req.use_slot,
+            req.equip_slot as i16,
+            false,
+            req.enchant_skill,
+            true,
+        )
+    }
+
+    pub async fn handle_gold_hammer(
+        &mut self,
+        req: GoldHammerReq,
+    ) -> GameResult<GoldHammerResultResp> {
+        let hammer_id = req.hammer_item_id;
+        let hammer_ix = client_slot_to_ix(req.hammer_slot as u16)?;
+        let inv = &self.session.char.inventory;
+        let hammer_inv = if hammer_id.is_golden_hammer() {
+            &inv.use_
+        } else if hammer_id == ItemId::VICIOUS_HAMMER {
+            &inv.cash
+        } else {
+            anyhow::bail!("Not a hammer: {hammer_id:?}");
+        };
+        if hammer_inv.get(hammer_ix).map(|item| item.item_id) != Some(hammer_id) {
+            anyhow::bail!("No hammer in slot: {}", req.hammer_slot);
+        }
+
+        let equip_slot = req.equip_slot as i16;
+        if !self.get_equip_mut(equip_slot)?.can_use_hammer() {
+            return Ok(metrics::count_resp(GoldHammerResultResp {
+                result: GoldHammerResult::Error,
+                code: 1,
+            })
+            .into());
+        }
+
+        // The hammer is used up, before the slot is added
+        let hammer_inv_type = if hammer_id.is_golden_hammer() {
+            InventoryType::Consume
+        } else {
+            InventoryType::Cash
+        };
+        // Golden hammers have a success rate, the vicious hammer always succeeds
+        let success = if hammer_id.is_golden_hammer() {
+            let hammer_meta = self
+                .services
+                .meta
+                .get_item_data(hammer_id)
+                .ok_or_else(|| anyhow::format_err!("Invalid hammer: {hammer_id:?}"))?;
+            rand::thread_rng().gen_bool(hammer_meta.success.min(100) as f64 / 100.)
+        } else {
+            true
+        };
+        let mut ops = vec![self.take_stack_item(hammer_inv_type, hammer_ix)?];
+
+        if success {
+            let equip = self.get_equip_mut(equip_slot)?;
+            if !equip.apply_hammer() {
+                anyhow::bail!("Unable to hammer: {:?}", equip.item_id);
+            }
+            let equip_item = Item::Equip((&*equip).into());
+            ops.extend(update_equip_ops(equip_slot, equip_item));
+        }
+        self.send_inv_ops(ops)?;
+
+        Ok(metrics::count_resp(GoldHammerResultResp {
+            result: GoldHammerResult::Done,
+            code: if success { 0 } else { 1 },
+        })
+        .into())
+    }
+}
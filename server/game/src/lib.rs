@@ -1,18 +1,30 @@
+pub mod cash_shop;
+pub mod grant;
+pub mod inventory;
+pub mod pet;
 pub mod repl;
 pub mod state;
+pub mod stats;
 
 use std::ops::Neg;
 
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Instant;
 
 use async_trait::async_trait;
 
 use data::entities::character;
 use data::proto_mapper::db_to_shroom_time;
-use data::services::field::FieldJoinHandle;
+use data::services::field::{DropPicker, FieldJoinHandle};
+use data::services::helper::intentory::inv::StackInventory;
 use data::services::helper::pool::drop::{DropLeaveParam, DropTypeValue};
-use data::services::session::session_data::OwnedShroomSession;
-use data::services::session::{ClientKey, ShroomMigrationKey};
+use data::services::metrics;
+use data::services::model::pet::MAX_ACTIVE_PETS;
+use data::services::session::online::OnlineState;
+use data::services::session::session_data::{
+    ChannelBoundActivity, OwnedShroomSession, ShroomSessionData,
+};
+use data::services::session::{ClientKey, OnlineChar, ShroomMigrationKey};
 use data::services::SharedServices;
 use shroom_net::net::service::handler::{
     MakeServerSessionHandler, SessionHandleResult, ShroomSessionHandler,
@@ -20,11 +32,11 @@ use shroom_net::net::service::handler::{
 use shroom_net::net::service::resp::{MigrateResponse, PongResponse};
 use shroom_net::net::service::server_sess::SharedSessionHandle;
 use shroom_net::net::ShroomSession;
-use shroom_net::{shroom_router_fn, HasOpcode};
+use shroom_net::{shroom_router_fn, HasOpcode, PacketBuffer};
 
 use shroom_net::packet::EncodePacket;
 
-use shroom_net::packet::proto::list::{ShroomIndexList8, ShroomIndexListZ};
+use shroom_net::packet::proto::list::ShroomIndexList8;
 use shroom_net::packet::proto::partial::PartialFlag;
 use shroom_net::packet::proto::time::ShroomExpirationTime;
 use shroom_net::packet::{
@@ -38,19 +50,29 @@ use shroom_net::packet::{
 
 use data::services::helper::pool::Drop;
 
+use proto95::game::mini_room::{MiniRoomAction, MiniRoomReq};
 use proto95::game::mob::{MobMoveCtrlAckResp, MobMoveReq};
+use proto95::game::pet::{
+    PetActionCommandReq, PetDropPickUpReq, PetMoveReq, UserActivatePetReq, UserPetFoodItemUseReq,
+};
 use proto95::game::user::{
-    ChangeSkillRecordResp, UpdatedSkillRecord, UserDropMoneyReq, UserDropPickUpReq, UserHitReq,
+    ChangeSkillRecordResp, UpdatedSkillRecord, UserAbilityMassUpReq, UserAbilityUpReq,
+    UserConsumeCashItemUseReq, UserDropMoneyReq, UserDropPickUpReq, UserHitReq,
     UserMeleeAttackReq, UserSkillUpReq, UserStatChangeReq,
 };
 
 use proto95::id::{FaceId, HairId, ItemId, Skin};
 use proto95::shared::char::{AvatarData, AvatarEquips, PetIds, SkillInfo, TeleportRockInfo};
-use proto95::shared::inventory::InvChangeSlotPosReq;
+use proto95::shared::inventory::{
+    GatherItemReq, GoldHammerReq, InvChangeSlotPosReq, InvSortRequest, ItemHyperUpgradeReq,
+    ItemUpgradeReq,
+};
 use proto95::shared::{ClientDumpLogReq, FootholdId, PongReq, Vec2};
 use proto95::{
     game::{
+        cash_shop::MigrateToCashShopReq,
         chat::{ChatMsgReq, UserChatMsgResp},
+        drop::DropId,
         field::{
             CrcSeed, LogoutGiftConfig, NotificationList, SetFieldCharData, SetFieldResp,
             SetFieldResult,
@@ -59,7 +81,8 @@ use proto95::{
         keymaps::FuncKeyMapInitResp,
         user::{UserMoveReq, UserPortalScriptReq, UserTransferFieldReq},
         BroadcastMessageResp, ClaimSvrStatusChangedResp, CtxSetGenderResp, MigrateCommandResp,
-        MigrateInGameReq, TransferChannelReq,
+        MigrateInGameReq, TransferChannelIgnoredReason, TransferChannelReq,
+        TransferChannelReqIgnoredResp,
     },
     id::MapId,
     login::world::{ChannelId, WorldId},
@@ -78,6 +101,43 @@ use tokio::net::TcpStream;
 
 pub type GameResult<T> = Result<T, anyhow::Error>;
 
+/// Largest distance to a drop, which the character picks up,
+/// the last known position lags behind the client
+const USER_PICK_UP_RANGE: i32 = 150;
+
+/// Reads the client hello after a migration and claims the migrated session
+pub async fn claim_migrated_session(
+    net_session: &mut ShroomSession<TcpStream>,
+    services: &SharedServices,
+    state: OnlineState,
+) -> anyhow::Result<(OwnedShroomSession, ClientKey)> {
+    let addr = net_session.peer_addr()?;
+    log::info!(
+        "Migrated sess: {} - waiting abit for session to be free",
+        addr
+    );
+
+    let pkt = net_session.read_packet().await?;
+    log::info!("Migration: {:?}", pkt);
+    let mut pr = pkt.into_reader();
+
+    let op = pr.read_opcode::<RecvOpcodes>()?;
+    log::info!("New client with opcode: {:?}", op);
+    if op != MigrateInGameReq::OPCODE {
+        anyhow::bail!("Wrong client hello packet: {op:?}")
+    }
+
+    let req = MigrateInGameReq::decode_packet(&mut pr)?;
+    let addr = addr.ip();
+
+    let session = services
+        .session_manager
+        .claim_migration_session(ShroomMigrationKey::new(req.client_key, addr), state)
+        .await?;
+
+    Ok((session, req.client_key))
+}
+
 #[derive(Debug, Clone)]
 pub struct MakeGameHandler {
     services: SharedServices,
@@ -116,7 +176,8 @@ impl MakeServerSessionHandler for MakeGameHandler {
             sess_handle,
         )
         .await?;
-        sess.send_packet(handler.set_field()).await?;
+        sess.send_packet(metrics::count_resp(handler.set_field()))
+            .await?;
         handler.init_char(sess).await?;
 
         Ok(handler)
@@ -136,6 +197,7 @@ pub struct GameHandler {
     field: FieldJoinHandle,
     repl: GameRepl,
     avatar_data: AvatarData,
+    pets: [Option<pet::ActivePet>; MAX_ACTIVE_PETS],
 }
 
 impl GameHandler {
@@ -146,28 +208,11 @@ impl GameHandler {
         world_id: WorldId,
         sess_handle: SharedSessionHandle,
     ) -> anyhow::Result<Self> {
-        let addr = net_session.peer_addr()?;
-        log::info!("Game sess: {} - waiting abit for session to be free", addr);
-
-        let pkt = net_session.read_packet().await?;
-        log::info!("Migration: {:?}", pkt);
-        let mut pr = pkt.into_reader();
-
-        let op = pr.read_opcode::<RecvOpcodes>()?;
-        log::info!("New client with opcode: {:?}", op);
-        if op != MigrateInGameReq::OPCODE {
-            anyhow::bail!("Wrong client hello packet: {op:?}")
-        }
-
-        let req = MigrateInGameReq::decode_packet(&mut pr)?;
         let addr = net_session.peer_addr()?.ip();
-
-        dbg!(ShroomMigrationKey::new(req.client_key, addr));
-
-        let session = services
-            .session_manager
-            .claim_migration_session(ShroomMigrationKey::new(req.client_key, addr))
-            .await?;
+        let state = OnlineState::Channel(world_id, channel_id);
+        let (mut session, client_key) =
+            claim_migrated_session(net_session, &services, state).await?;
+        session.channel_id = channel_id;
 
         log::info!(
             "Session for acc: {} - char: {}",
@@ -183,9 +228,19 @@ impl GameHandler {
                 session.char.model.id,
                 avatar_data.clone(),
                 sess_handle.clone(),
+                world_id,
+                channel_id,
                 MapId(session.char.model.map_id as u32),
             )
             .await?;
+        services.session_manager.add_client(
+            OnlineChar {
+                acc_id: session.acc.id,
+                char_id: session.char.model.id,
+                name: session.char.model.name.clone(),
+            },
+            sess_handle.clone(),
+        );
 
         Ok(Self {
             session,
@@ -193,13 +248,14 @@ impl GameHandler {
             channel_id,
             world_id,
             addr,
-            client_key: req.client_key,
+            client_key,
             pos: Vec2::default(),
             fh: 0,
             sess_handle,
             field: join_field,
             repl: GameRepl::new(),
             avatar_data,
+            pets: Default::default(),
         })
     }
 }
@@ -223,6 +279,7 @@ impl ShroomSessionHandler for GameHandler {
         packet: ShroomPacket,
         session: &mut ShroomSession<Self::Transport>,
     ) -> Result<SessionHandleResult, Self::Error> {
+        metrics::count_packet_in(packet.as_ref());
         shroom_router_fn!(
             handler,
             GameHandler,
@@ -236,6 +293,7 @@ impl ShroomSessionHandler for GameHandler {
             UserPortalScriptReq => GameHandler::handle_portal_script,
             UserTransferFieldReq => GameHandler::handle_field_transfer,
             TransferChannelReq => GameHandler::handle_channel_transfer,
+            MigrateToCashShopReq => GameHandler::handle_migrate_to_cash_shop,
             UserDropPickUpReq => GameHandler::handle_drop_pick_up,
             UserDropMoneyReq => GameHandler::handle_drop_money,
             MobMoveReq => GameHandler::handle_mob_move,
@@ -244,19 +302,49 @@ impl ShroomSessionHandler for GameHandler {
             UserHitReq => GameHandler::handle_user_hit,
             UserStatChangeReq => GameHandler::handle_stat_change,
             InvChangeSlotPosReq => GameHandler::handle_inv_change_slot,
+            ItemUpgradeReq => GameHandler::handle_item_upgrade,
+            ItemHyperUpgradeReq => GameHandler::handle_item_hyper_upgrade,
+            GoldHammerReq => GameHandler::handle_gold_hammer,
+            GatherItemReq => GameHandler::handle_gather_items,
+            InvSortRequest => GameHandler::handle_sort_items,
+            UserAbilityUpReq => GameHandler::handle_ability_up,
+            UserAbilityMassUpReq => GameHandler::handle_ability_mass_up,
+            UserConsumeCashItemUseReq => GameHandler::handle_consume_cash_item_use,
+            UserActivatePetReq => GameHandler::handle_activate_pet,
+            PetMoveReq => GameHandler::handle_pet_move,
+            PetActionCommandReq => GameHandler::handle_pet_action_command,
+            UserPetFoodItemUseReq => GameHandler::handle_pet_food,
+            PetDropPickUpReq => GameHandler::handle_pet_drop_pick_up,
             ClientDumpLogReq => GameHandler::handle_client_dump_log,
+            MiniRoomReq => GameHandler::handle_mini_room,
         );
 
-        Ok(handler(self, session, packet.into_reader()).await?)
+        let res = handler(self, session, packet.into_reader()).await?;
+        if let SessionHandleResult::Ok = res {
+            self.handle_pending_warp(session).await?;
+            self.handle_grants().await?;
+        }
+        Ok(res)
     }
 
-    async fn finish(self, is_migrating: bool) -> Result<(), Self::Error> {
+    async fn finish(mut self, is_migrating: bool) -> Result<(), Self::Error> {
         log::info!("Finishing game session...");
+        self.services
+            .session_manager
+            .remove_client(self.session.char.model.id);
+        self.services.field.take_warp(self.session.char.model.id);
+        self.services.apply_pending_grants(&mut self.session.char);
+        // Leave the field before the session is pushed,
+        // so the character is gone before It's able to join the next field
+        drop(self.field);
         if is_migrating {
-            self.services.session_manager.migrate_session(
-                ShroomMigrationKey::new(self.client_key, self.addr),
-                self.session,
-            )?;
+            self.services
+                .session_manager
+                .migrate_session(
+                    ShroomMigrationKey::new(self.client_key, self.addr),
+                    self.session,
+                )
+                .await?;
         } else {
             self.services
                 .session_manager
@@ -270,14 +358,14 @@ impl ShroomSessionHandler for GameHandler {
 
 impl GameHandler {
     async fn handle_client_dump_log(&mut self, req: ClientDumpLogReq) -> anyhow::Result<()> {
-        dbg!(req);
+        log::warn!("Client dump: {req:?}");
         Ok(())
     }
 
     async fn handle_user_hit(&mut self, req: UserHitReq) -> GameResult<CharStatChangedResp> {
         self.session.char.update_hp((req.dmg_internal as i32).neg());
 
-        Ok(CharStatChangedResp {
+        Ok(metrics::count_resp(CharStatChangedResp {
             excl: false,
             stats: PartialFlag {
                 hdr: (),
@@ -285,7 +373,7 @@ impl GameHandler {
             },
             secondary_stat: false,
             battle_recovery: false,
-        }
+        })
         .into())
     }
 
@@ -296,7 +384,7 @@ impl GameHandler {
         self.session.char.update_hp(req.hp as i32);
         self.session.char.update_mp(req.mp as i32);
 
-        Ok(CharStatChangedResp {
+        Ok(metrics::count_resp(CharStatChangedResp {
             excl: false,
             stats: PartialFlag {
                 hdr: (),
@@ -304,7 +392,7 @@ impl GameHandler {
             },
             secondary_stat: false,
             battle_recovery: false,
-        }
+        })
         .into())
     }
 
@@ -313,11 +401,31 @@ impl GameHandler {
     }
 
     async fn handle_pong(&mut self, _req: PongReq) -> anyhow::Result<PongResponse> {
+        let sm = &self.services.session_manager;
+        if let Err(err) = sm.online().keep_alive(self.session.acc.id, Instant::now()) {
+            // Kicked clients save right away, so the character is stored
+            // even If the session can't be released afterwards
+            if let Err(err) = sm.save_session(&mut self.session).await {
+                log::error!("Unable to save kicked session: {err:?}");
+            }
+            return Err(err.into());
+        }
+        // The ping interval drives the hunger of the active pets
+        self.update_pet_hunger()?;
+        // The ping interval also drives the autosave
+        if let Err(err) = self
+            .services
+            .session_manager
+            .autosave(&mut self.session)
+            .await
+        {
+            log::error!("Autosave failed: {err:?}");
+        }
         Ok(PongResponse)
     }
 
     async fn handle_skill_up(&mut self, req: UserSkillUpReq) -> GameResult<ChangeSkillRecordResp> {
-        Ok(ChangeSkillRecordResp {
+        Ok(metrics::count_resp(ChangeSkillRecordResp {
             reset_excl: true,
             skill_records: vec![UpdatedSkillRecord {
                 id: req.skill_id,
@@ -327,7 +435,7 @@ impl GameHandler {
             }]
             .into(),
             updated_secondary_stat: false,
-        }
+        })
         .into())
     }
 
@@ -351,102 +459,40 @@ impl GameHandler {
         pr: PacketReader<'_>,
     ) -> anyhow::Result<SessionHandleResult> {
         log::info!("Unhandled packet: {:?} {:?}", op, pr.into_inner());
+        metrics::count_unhandled_packet(op);
         Ok(SessionHandleResult::Ok)
     }
 
     async fn init_char(&mut self, sess: &mut ShroomSession<TcpStream>) -> anyhow::Result<()> {
-        sess.send_packet(FriendResultResp::Reset3(FriendList::empty()))
-            .await?;
-        sess.send_packet(FuncKeyMapInitResp::default_map()).await?;
-        sess.send_packet(ClaimSvrStatusChangedResp { connected: true })
+        sess.send_packet(metrics::count_resp(FriendResultResp::Reset3(
+            FriendList::empty(),
+        )))
+        .await?;
+        sess.send_packet(metrics::count_resp(FuncKeyMapInitResp::default_map()))
             .await?;
-        sess.send_packet(CtxSetGenderResp {
+        sess.send_packet(metrics::count_resp(ClaimSvrStatusChangedResp {
+            connected: true,
+        }))
+        .await?;
+        sess.send_packet(metrics::count_resp(CtxSetGenderResp {
             gender: (&self.session.char.model.gender).into(),
-        })
+        }))
         .await?;
 
-        sess.send_packet(BroadcastMessageResp::PinkMessage("Hello".to_string()))
-            .await?;
+        sess.send_packet(metrics::count_resp(BroadcastMessageResp::PinkMessage(
+            "Hello".to_string(),
+        )))
+        .await?;
 
-        sess.send_packet(self.enable_char()).await?;
+        sess.send_packet(metrics::count_resp(self.enable_char()))
+            .await?;
+        self.restore_pets()?;
 
         Ok(())
     }
 
     fn set_field(&mut self) -> SetFieldResp {
-        let char = &self.session.char;
-
-        let equipped: ShroomIndexListZ16<Item> = self
-            .session
-            .char
-            .inventory
-            .equipped
-            .iter()
-            .map(|(slot, item)| (slot as u16, Item::Equip(item.item.as_ref().into())))
-            .collect();
-
-        let etc: ShroomIndexListZ8<Item> = self
-            .session
-            .char
-            .inventory
-            .etc
-            .iter()
-            .map(|(slot, item)| (slot as u8 + 1, Item::Stack(item.item.as_ref().into())))
-            .collect();
-
-        let invsize = [
-            char.model.equip_slots as u8,
-            char.model.use_slots as u8,
-            char.model.setup_slots as u8,
-            char.model.etc_slots as u8,
-            char.model.cash_slots as u8,
-        ];
-
-        let char_equipped = CharDataEquipped {
-            equipped,
-            ..Default::default()
-        };
-
-        let skill_records: ShroomList16<SkillInfo> = self
-            .session
-            .skills
-            .iter()
-            .map(|(id, skill)| SkillInfo {
-                id: *id,
-                level: skill.skill_level as u32,
-                expiration: skill.expires_at.map(db_to_shroom_time).into(),
-                master_level: skill.master_level as u32,
-            })
-            .collect();
-
-        let char_stat: &character::Model = &char.model.clone();
-
-        let char_data = CharDataAll {
-            stat: CharDataStat {
-                stat: char_stat.into(),
-                friend_max: 30,
-                linked_character: None.into(),
-            },
-            money: char.model.mesos as u32,
-            invsize,
-            equipextslotexpiration: ShroomExpirationTime::never(),
-            equipped: char_equipped,
-            useinv: ShroomIndexListZ::default(),
-            setupinv: ShroomIndexListZ::default(),
-            etcinv: etc,
-            cashinv: ShroomIndexListZ::default(),
-            skillrecords: skill_records,
-            skllcooltime: ShroomList16::default(),
-            quests: ShroomList16::default(),
-            questscompleted: ShroomList16::default(),
-            minigamerecords: ShroomList16::default(),
-            socialrecords: ShroomList16::default(),
-            teleportrockinfo: TeleportRockInfo::default(),
-            newyearcards: ShroomList16::default(),
-            questrecordsexpired: ShroomList16::default(),
-            questcompleteold: ShroomList16::default(),
-            visitorquestloginfo: ShroomList16::default(),
-        };
+        let char_data = get_char_data(&self.session);
 
         let char_data = SetFieldCharData {
             notifications: NotificationList::default(),
@@ -488,14 +534,12 @@ impl GameHandler {
     async fn handle_melee_attack(&mut self, req: UserMeleeAttackReq) -> anyhow::Result<()> {
         for target in req.targets {
             let dmg = target.hits.iter().sum::<u32>();
-            self.field
-                .attack_mob(
-                    target.mob_id,
-                    dmg,
-                    self.session.char.model.id,
-                    &mut self.sess_handle,
-                )
-                .await?;
+            self.field.attack_mob(
+                target.mob_id,
+                dmg,
+                self.session.char.model.id,
+                &self.sess_handle,
+            )?;
         }
 
         Ok(())
@@ -505,15 +549,10 @@ impl GameHandler {
         &mut self,
         req: UserDropPickUpReq,
     ) -> GameResult<CharStatChangedResp> {
-        dbg!(&req);
-
-        self.field
-            .handle_pickup(req.drop_id, &mut self.session.char)?;
-        self.field.remove_drop(
-            req.drop_id,
-            DropLeaveParam::UserPickup(self.session.char.model.id as u32),
-        )?;
-        Ok(CharStatChangedResp {
+        let param = DropLeaveParam::UserPickup(self.session.char.model.id as u32);
+        self.pick_up_drop(req.drop_id, USER_PICK_UP_RANGE, param)
+            .await?;
+        Ok(metrics::count_resp(CharStatChangedResp {
             excl: true,
             stats: PartialFlag {
                 hdr: (),
@@ -521,10 +560,71 @@ impl GameHandler {
             },
             secondary_stat: false,
             battle_recovery: false,
-        }
+        })
         .into())
     }
 
+    /// Picks the drop up within the range of the character, items are only taken
+    /// with free space in the inventory. Rejected pick ups leave the drop on the field
+    pub(crate) async fn pick_up_drop(
+        &mut self,
+        drop_id: DropId,
+        range: i32,
+        param: DropLeaveParam,
+    ) -> anyhow::Result<()> {
+        let picker = DropPicker {
+            char_id: self.session.char.model.id,
+            pos: self.pos,
+            range,
+        };
+        let (value, quantity) = match self.field.peek_drop(drop_id, picker).await {
+            Ok(drop) => drop,
+            Err(err) => {
+                log::info!("Rejected pick up: {err}");
+                return Ok(());
+            }
+        };
+
+        let added = match value {
+            DropTypeValue::Item(item_id) => {
+                let res = self.services.data.item.add_item(
+                    &mut self.session.char.inventory,
+                    item_id,
+                    quantity.try_into()?,
+                );
+                match res {
+                    Ok(added) => Some(added),
+                    Err(err) => {
+                        log::info!("Unable to pick up {item_id:?}: {err}");
+                        return Ok(());
+                    }
+                }
+            }
+            DropTypeValue::Mesos(_) => None,
+        };
+
+        let drop = match self.field.pick_up(drop_id, picker, param).await {
+            Ok(drop) => drop,
+            Err(err) => {
+                // Another character picked the drop up meanwhile
+                if let Some((inv_type, ix)) = added {
+                    self.remove_added_item(inv_type, ix)?;
+                }
+                log::info!("Rejected pick up: {err}");
+                return Ok(());
+            }
+        };
+
+        match (drop.value, added) {
+            (DropTypeValue::Mesos(mesos), _) => {
+                self.session.char.update_mesos(mesos.try_into()?);
+            }
+            (DropTypeValue::Item(_), Some(added)) => self.send_added_items(vec![added]).await?,
+            (DropTypeValue::Item(_), None) => {}
+        }
+        Ok(())
+    }
+
     async fn handle_drop_money(
         &mut self,
         req: UserDropMoneyReq,
@@ -539,7 +639,7 @@ impl GameHandler {
                 quantity: 1,
             })?;
         }
-        Ok(CharStatChangedResp {
+        Ok(metrics::count_resp(CharStatChangedResp {
             excl: true,
             stats: PartialFlag {
                 hdr: (),
@@ -547,7 +647,7 @@ impl GameHandler {
             },
             secondary_stat: false,
             battle_recovery: false,
-        }
+        })
         .into())
     }
 
@@ -568,7 +668,9 @@ impl GameHandler {
             pw.write_opcode(UserChatMsgResp::OPCODE)?;
             resp.encode_packet(&mut pw)?;
 
-            self.sess_handle.tx.try_send(pw.into_packet().as_ref())?;
+            let pkt = pw.into_packet();
+            metrics::count_packet_out(pkt.as_ref());
+            self.sess_handle.tx.try_send(pkt.as_ref())?;
         } else {
             self.field.add_chat(UserChatMsgResp {
                 char: self.session.char.model.id as u32,
@@ -586,14 +688,14 @@ impl GameHandler {
 
         self.field.update_mob_pos(req, self.session.char.model.id)?;
 
-        Ok(MobMoveCtrlAckResp {
+        Ok(metrics::count_resp(MobMoveCtrlAckResp {
             id,
             ctrl_sn,
             next_atk_possible: false,
             mp: 0,
             skill_id: 0,
             slv: 0,
-        }
+        })
         .into())
     }
 
@@ -601,7 +703,7 @@ impl GameHandler {
         &mut self,
         _req: UserPortalScriptReq,
     ) -> GameResult<CharStatChangedResp> {
-        Ok(self.enable_char().into())
+        Ok(metrics::count_resp(self.enable_char()).into())
     }
 
     async fn handle_field_transfer(
@@ -612,10 +714,7 @@ impl GameHandler {
             let return_map =
                 MapId(self.field.get_meta().info.return_map.unwrap_or_default() as u32);
 
-            self.session.char.model.hp = 1;
-            self.session.char.model.mp = 1;
-            self.session.char.model.map_id = return_map.0 as i32;
-            self.session.char.model.spawn_point = self
+            let spawn_point = self
                 .services
                 .meta
                 .get_field_data(return_map)
@@ -623,7 +722,8 @@ impl GameHandler {
                 .portal
                 .first_key_value()
                 .map(|(k, _)| *k)
-                .unwrap_or_default() as i32;
+                .unwrap_or_default();
+            self.session.char.revive(return_map, spawn_point as u8);
 
             self.field = self
                 .services
@@ -632,6 +732,8 @@ impl GameHandler {
                     self.session.char.model.id,
                     self.avatar_data.clone(),
                     self.sess_handle.clone(),
+                    self.world_id,
+                    self.channel_id,
                     MapId(self.session.char.model.map_id as u32),
                 )
                 .await?;
@@ -643,11 +745,15 @@ impl GameHandler {
                     self.session.char.model.id,
                     self.avatar_data.clone(),
                     self.sess_handle.clone(),
+                    self.world_id,
+                    self.channel_id,
                     MapId(self.session.char.model.map_id as u32),
                 )
                 .await?;
+            self.update_field_activity();
+            self.spawn_pets()?;
 
-            Ok(self.set_field().into())
+            Ok(metrics::count_resp(self.set_field()).into())
         } else {
             let portal = self
                 .field
@@ -659,8 +765,7 @@ impl GameHandler {
 
             // TODO(!) tm should be an option as mapid 999999 is invalid
             let map_id = MapId(portal.tm as u32);
-            self.session.char.model.map_id = map_id.0 as i32;
-            self.session.char.model.spawn_point = self
+            let spawn_point = self
                 .services
                 .meta
                 .get_field_data(map_id)
@@ -669,7 +774,8 @@ impl GameHandler {
                 .iter()
                 .find(|(_, p)| p.pn == portal.tn)
                 .map(|(id, _)| *id as u8)
-                .unwrap_or(0) as i32;
+                .unwrap_or(0);
+            self.session.char.set_pos(map_id, spawn_point);
 
             self.field = self
                 .services
@@ -678,13 +784,110 @@ impl GameHandler {
                     self.session.char.model.id,
                     self.avatar_data.clone(),
                     self.sess_handle.clone(),
+                    self.world_id,
+                    self.channel_id,
                     MapId(self.session.char.model.map_id as u32),
                 )
                 .await?;
+            self.update_field_activity();
+            self.spawn_pets()?;
 
             let transfer_field = self.set_field();
-            Ok(transfer_field.into())
+            Ok(metrics::count_resp(transfer_field).into())
+        }
+    }
+
+    /// Moves the character into the field instance, which It was warped to
+    async fn handle_pending_warp(
+        &mut self,
+        net_session: &mut ShroomSession<TcpStream>,
+    ) -> anyhow::Result<()> {
+        let char_id = self.session.char.model.id;
+        let Some(warp) = self.services.field.take_warp(char_id) else {
+            return Ok(());
+        };
+        if warp.key.world_id != self.world_id || warp.key.channel_id != self.channel_id {
+            log::warn!("Ignored warp of {char_id} into another channel: {warp:?}");
+            return Ok(());
         }
+
+        let field = match self
+            .services
+            .field
+            .join_instance(
+                char_id,
+                self.avatar_data.clone(),
+                self.sess_handle.clone(),
+                warp.key,
+            )
+            .await
+        {
+            Ok(field) => field,
+            Err(err) => {
+                log::warn!("Unable to warp {char_id}: {err:?}");
+                return Ok(());
+            }
+        };
+        self.field = field;
+        self.update_field_activity();
+        self.session
+            .char
+            .set_pos(warp.key.field_id, warp.spawn_point);
+        self.spawn_pets()?;
+
+        net_session
+            .send_packet(metrics::count_resp(self.set_field()))
+            .await?;
+        Ok(())
+    }
+
+    /// Mini rooms are closed by leaving the field,
+    /// a private instance binds the character to the channel until It leaves the instance
+    fn update_field_activity(&mut self) {
+        self.session.activity = self
+            .field
+            .key()
+            .instance
+            .map(|_| ChannelBoundActivity::Event);
+    }
+
+    /// Tracks the trade, shop or mini game the character takes part in
+    async fn handle_mini_room(&mut self, req: MiniRoomReq) -> anyhow::Result<()> {
+        match req.action() {
+            Some(MiniRoomAction::Create) => {
+                let room_type = req
+                    .room_type
+                    .0
+                    .ok_or_else(|| anyhow::format_err!("Mini room without type"))?;
+                let activity = if room_type.is_game() {
+                    ChannelBoundActivity::MiniGame
+                } else if room_type.is_shop() {
+                    ChannelBoundActivity::Shop
+                } else {
+                    ChannelBoundActivity::Trade
+                };
+                if let Err(err) = self.session.start_activity(activity) {
+                    log::info!("Rejected mini room: {err}");
+                }
+            }
+            // Entered rooms are only known by their serial number, so they count as trade
+            Some(MiniRoomAction::Enter) => {
+                if let Err(err) = self.session.start_activity(ChannelBoundActivity::Trade) {
+                    log::info!("Rejected mini room: {err}");
+                }
+            }
+            Some(MiniRoomAction::Leave) => {
+                for activity in [
+                    ChannelBoundActivity::Trade,
+                    ChannelBoundActivity::Shop,
+                    ChannelBoundActivity::MiniGame,
+                ] {
+                    self.session.end_activity(activity);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
     }
 
     async fn handle_movement(&mut self, req: UserMoveReq) -> anyhow::Result<()> {
@@ -704,17 +907,152 @@ impl GameHandler {
     async fn handle_channel_transfer(
         &mut self,
         req: TransferChannelReq,
-    ) -> anyhow::Result<MigrateResponse<MigrateCommandResp>> {
+    ) -> anyhow::Result<Option<MigrateResponse<MigrateCommandResp>>> {
         log::info!("Transfer channel: {:?}", req);
+        let addr = match self.check_channel_transfer(req.channel_id as ChannelId) {
+            Ok(addr) => addr,
+            Err(err) => {
+                log::info!("Rejected channel transfer: {err}");
+                self.send_transfer_ignored(TransferChannelIgnoredReason::CannotChange)?;
+                return Ok(None);
+            }
+        };
+
+        // The character lands on the same map at the spawn point closest to the current position
+        let map_id = MapId(self.session.char.model.map_id as u32);
+        let spawn_point = self.field.nearest_spawn_point(self.pos);
+        self.session.char.set_pos(map_id, spawn_point);
+
+        Ok(Some(MigrateResponse(metrics::count_resp(
+            MigrateCommandResp {
+                unknown: true,
+                addr: addr.try_into()?,
+            },
+        ))))
+    }
+
+    /// Checks the target channel and that no activity binds the character to this channel
+    fn check_channel_transfer(&self, channel_id: ChannelId) -> anyhow::Result<SocketAddr> {
+        if channel_id == self.channel_id {
+            anyhow::bail!("Already in channel {channel_id}");
+        }
+        self.session.check_migration()?;
+        if self.session.char.model.hp <= 0 {
+            anyhow::bail!("Character is dead");
+        }
+
+        self.services
+            .server_info
+            .get_channel_addr(self.world_id, channel_id)
+    }
+
+    fn send_transfer_ignored(
+        &mut self,
+        reason: TransferChannelIgnoredReason,
+    ) -> anyhow::Result<()> {
+        let mut buf = PacketBuffer::new();
+        metrics::write_pkt(&mut buf, TransferChannelReqIgnoredResp { reason })?;
+        self.sess_handle.try_send_pkt_buf(&buf)?;
+        Ok(())
+    }
+
+    async fn handle_migrate_to_cash_shop(
+        &mut self,
+        _req: MigrateToCashShopReq,
+    ) -> anyhow::Result<Option<MigrateResponse<MigrateCommandResp>>> {
+        if let Err(err) = self.session.check_migration() {
+            log::info!("Rejected cash shop migration: {err}");
+            self.send_transfer_ignored(TransferChannelIgnoredReason::CashShopNotAvailable)?;
+            return Ok(None);
+        }
+
         let addr = self
             .services
             .server_info
-            .get_channel_addr(self.world_id, req.channel_id as ChannelId)?;
+            .get_cash_shop_addr(self.world_id)?;
 
-        Ok(MigrateResponse(MigrateCommandResp {
-            unknown: true,
-            addr: addr.try_into()?,
-        }))
+        Ok(Some(MigrateResponse(metrics::count_resp(
+            MigrateCommandResp {
+                unknown: true,
+                addr: addr.try_into()?,
+            },
+        ))))
+    }
+}
+
+fn map_stack_inv(inv: &StackInventory) -> ShroomIndexListZ8<Item> {
+    inv.iter()
+        .map(|(slot, item)| (slot as u8 + 1, Item::Stack(item.item.as_ref().into())))
+        .collect()
+}
+
+/// Character data shared by the field and the cash shop
+pub fn get_char_data(session: &ShroomSessionData) -> CharDataAll {
+    let char = &session.char;
+
+    let equipped: ShroomIndexListZ16<Item> = char
+        .inventory
+        .equipped
+        .iter()
+        .map(|(slot, item)| (slot as u16, Item::Equip(item.item.as_ref().into())))
+        .collect();
+
+    let invsize = [
+        char.model.equip_slots as u8,
+        char.model.use_slots as u8,
+        char.model.setup_slots as u8,
+        char.model.etc_slots as u8,
+        char.model.cash_slots as u8,
+    ];
+
+    let char_equipped = CharDataEquipped {
+        equipped,
+        ..Default::default()
+    };
+
+    let skill_records: ShroomList16<SkillInfo> = session
+        .skills
+        .iter()
+        .map(|(id, skill)| SkillInfo {
+            id: *id,
+            level: skill.skill_level as u32,
+            expiration: skill.expires_at.map(db_to_shroom_time).into(),
+            master_level: skill.master_level as u32,
+        })
+        .collect();
+
+    let char_stat: &character::Model = &char.model.clone();
+
+    CharDataAll {
+        stat: CharDataStat {
+            stat: char_stat.into(),
+            friend_max: 30,
+            linked_character: None.into(),
+        },
+        money: char.model.mesos as u32,
+        invsize,
+        equipextslotexpiration: ShroomExpirationTime::never(),
+        equipped: char_equipped,
+        useinv: map_stack_inv(&char.inventory.use_),
+        setupinv: map_stack_inv(&char.inventory.misc),
+        etcinv: map_stack_inv(&char.inventory.etc),
+        cashinv: char
+            .inventory
+            .cash
+            .iter()
+            .map(|(slot, item)| (slot as u8 + 1, pet::map_cash_item(session, &item.item)))
+            .collect(),
+        skillrecords: skill_records,
+        skllcooltime: ShroomList16::default(),
+        quests: ShroomList16::default(),
+        questscompleted: ShroomList16::default(),
+        minigamerecords: ShroomList16::default(),
+        socialrecords: ShroomList16::default(),
+        teleportrockinfo: TeleportRockInfo::default(),
+        newyearcards: ShroomList16::default(),
+        questrecordsexpired: ShroomList16::default(),
+        questcompleteold: ShroomList16::default(),
+        visitorquestloginfo: ShroomList16::default(),
     }
 }
 
@@ -0,0 +1,409 @@
+use std::time::Instant;
+
+use data::services::{
+    helper::{
+        intentory::inv::InventoryExt,
+        pool::{drop::DropLeaveParam, user::FieldPet},
+    },
+    metrics,
+    model::{
+        item::StackItem,
+        pet::{HungerTimer, MAX_ACTIVE_PETS, MAX_FULLNESS},
+    },
+    session::session_data::ShroomSessionData,
+};
+use proto95::{
+    game::pet::{
+        PetActionCommandReq, PetActionCommandResp, PetActionType, PetDropPickUpReq, PetIx,
+        PetLeaveReason, PetMoveReq, UserActivatePetReq, UserPetFoodItemUseReq,
+    },
+    shared::{
+        char::{CashID, CharStatChangedResp, CharStatPartial, PetCashIds},
+        inventory::{InvOpAdd, InventoryOperation, InventoryType},
+        item::Item,
+    },
+};
+use shroom_net::{
+    packet::{proto::partial::PartialFlag, CondOption},
+    PacketBuffer,
+};
+
+use crate::{inventory::client_slot_to_ix, GameHandler, GameResult};
+
+/// Pets follow their owner, so they pick up drops in a wider range around the owner
+const PET_PICK_UP_RANGE: i32 = 400;
+
+/// Runtime state of a summoned pet, the stats are kept in the session
+#[derive(Debug)]
+pub struct ActivePet {
+    pub cash_id: CashID,
+    pub hunger: HungerTimer,
+}
+
+/// Pet items are encoded with the stats of the pet
+pub fn map_cash_item(session: &ShroomSessionData, item: &StackItem) -> Item {
+    match item.cash_id.and_then(|cash_id| session.pets.get(&cash_id)) {
+        Some(pet) => Item::Pet(pet.into()),
+        None => Item::Stack(item.into()),
+    }
+}
+
+impl GameHandler {
+    fn find_pet_ix(&self, cash_id: CashID) -> Option<usize> {
+        self.pets
+            .iter()
+            .position(|pet| pet.as_ref().map(|pet| pet.cash_id) == Some(cash_id))
+    }
+
+    fn active_pet_ids(&self) -> PetCashIds {
+        std::array::from_fn(|ix| self.pets[ix].as_ref().map_or(0, |pet| pet.cash_id))
+    }
+
+    fn pet_stat_changed(&self) -> CharStatChangedResp {
+        let [pet1, pet2, pet3] = self.active_pet_ids();
+        CharStatChangedResp {
+            excl: true,
+            stats: PartialFlag {
+                hdr: (),
+                data: CharStatPartial {
+                    pet1: CondOption(Some(pet1)),
+                    pet2: CondOption(Some(pet2)),
+                    pet3: CondOption(Some(pet3)),
+                    ..CharStatPartial::default()
+                },
+            },
+            secondary_stat: false,
+            battle_recovery: false,
+        }
+    }
+
+    /// Re-adds the pet item, so the client shows the updated stats
+    fn pet_update_op(&self, cash_id: CashID) -> anyhow::Result<InventoryOperation> {
+        let (slot, item) = self
+            .session
+            .char
+            .inventory
+            .cash
+            .iter()
+            .find(|(_, item)| item.item.cash_id == Some(cash_id))
+            .ok_or_else(|| anyhow::format_err!("No pet item for: {cash_id}"))?;
+
+        Ok(InventoryOperation::Add(InvOpAdd {
+            inv_type: InventoryType::Cash,
+            pos: slot as u16 + 1,
+            item: map_cash_item(&self.session, &item.item),
+        }))
+    }
+
+    fn get_field_pet(&self, cash_id: CashID) -> anyhow::Result<FieldPet> {
+        let pet = self
+            .session
+            .pets
+            .get(&cash_id)
+            .ok_or_else(|| anyhow::format_err!("Invalid pet: {cash_id}"))?;
+
+        Ok(FieldPet {
+            tmpl_id: pet.item_id,
+            name: pet.name.clone(),
+            cash_id,
+            pos: self.pos,
+            move_action: 0,
+            fh: self.fh,
+        })
+    }
+
+    fn activate_pet(
+        &mut self,
+        pet_ix: usize,
+        cash_id: CashID,
+        show_effect: bool,
+    ) -> anyhow::Result<()> {
+        let field_pet = self.get_field_pet(cash_id)?;
+        if let Some(pet) = self.session.pets.get_mut(&cash_id) {
+            pet.set_summoned(true);
+        }
+
+        self.pets[pet_ix] = Some(ActivePet {
+            cash_id,
+            hunger: HungerTimer::new(Instant::now()),
+        });
+        self.field.add_pet(
+            self.session.char.model.id,
+            pet_ix as PetIx,
+            field_pet,
+            show_effect,
+        )
+    }
+
+    fn deactivate_pet(&mut self, pet_ix: usize, reason: PetLeaveReason) -> anyhow::Result<()> {
+        let Some(active) = self.pets[pet_ix].take() else {
+            return Ok(());
+        };
+        if let Some(pet) = self.session.pets.get_mut(&active.cash_id) {
+            pet.set_summoned(false);
+        }
+
+        self.field
+            .remove_pet(self.session.char.model.id, pet_ix as PetIx, reason)
+    }
+
+    /// Summons the pets, which were active when the character logged out or migrated
+    pub fn restore_pets(&mut self) -> anyhow::Result<()> {
+        let summoned = self
+            .session
+            .pets
+            .values()
+            .filter(|pet| pet.summoned)
+            .map(|pet| pet.cash_id)
+            .collect::<Vec<_>>();
+
+        for (pet_ix, cash_id) in summoned.into_iter().enumerate() {
+            if pet_ix < MAX_ACTIVE_PETS {
+                self.activate_pet(pet_ix, cash_id, false)?;
+            } else if let Some(pet) = self.session.pets.get_mut(&cash_id) {
+                pet.set_summoned(false);
+            }
+        }
+
+        self.send_pet_stats()
+    }
+
+    /// Spawns the active pets after the character entered a new field
+    pub fn spawn_pets(&mut self) -> anyhow::Result<()> {
+        let active = self
+            .pets
+            .iter()
+            .enumerate()
+            .filter_map(|(pet_ix, pet)| Some((pet_ix, pet.as_ref()?.cash_id)))
+            .collect::<Vec<_>>();
+
+        for (pet_ix, cash_id) in active {
+            let field_pet = self.get_field_pet(cash_id)?;
+            self.field.add_pet(
+                self.session.char.model.id,
+                pet_ix as PetIx,
+                field_pet,
+                false,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn send_pet_stats(&mut self) -> anyhow::Result<()> {
+        let mut buf = PacketBuffer::new();
+        metrics::write_pkt(&mut buf, self.pet_stat_changed())?;
+        self.sess_handle.try_send_pkt_buf(&buf)?;
+        Ok(())
+    }
+
+    /// Applies the hunger of the active pets, starving or expired pets are sent home
+    pub fn update_pet_hunger(&mut self) -> anyhow::Result<()> {
+        let now = Instant::now();
+        let now_utc = chrono::Utc::now().naive_utc();
+        let mut ops = Vec::new();
+        let mut deactivated = false;
+
+        for pet_ix in 0..MAX_ACTIVE_PETS {
+            let Some(active) = self.pets[pet_ix].as_mut() else {
+                continue;
+            };
+            let intervals = active.hunger.tick(now);
+            let cash_id = active.cash_id;
+
+            let pet = self
+                .session
+                .pets
+                .get_mut(&cash_id)
+                .ok_or_else(|| anyhow::format_err!("Invalid pet: {cash_id}"))?;
+            let reason = if pet.is_expired(now_utc) {
+                Some(PetLeaveReason::Expired)
+            } else if intervals > 0 {
+                pet.hunger(intervals);
+                let starving = pet.is_starving();
+                ops.push(self.pet_update_op(cash_id)?);
+                starving.then_some(PetLeaveReason::Hungry)
+            } else {
+                None
+            };
+
+            if let Some(reason) = reason {
+                self.deactivate_pet(pet_ix, reason)?;
+                deactivated = true;
+            }
+        }
+
+        if !ops.is_empty() {
+            self.send_inv_ops(ops)?;
+        }
+        if deactivated {
+            self.send_pet_stats()?;
+        }
+        Ok(())
+    }
+
+    pub async fn handle_activate_pet(
+        &mut self,
+        req: UserActivatePetReq,
+    ) -> GameResult<CharStatChangedResp> {
+        let item = self
+            .session
+            .char
+            .inventory
+            .cash
+            .get(client_slot_to_ix(req.slot)?)
+            .ok_or_else(|| anyhow::format_err!("No pet in slot: {}", req.slot))?;
+        if !item.item_id.is_pet() {
+            anyhow::bail!("Not a pet: {:?}", item.item_id);
+        }
+        let cash_id = item
+            .item
+            .cash_id
+            .ok_or_else(|| anyhow::format_err!("Pet without cash id: {:?}", item.item_id))?;
+
+        if let Some(pet_ix) = self.find_pet_ix(cash_id) {
+            self.deactivate_pet(pet_ix, PetLeaveReason::Normal)?;
+            return Ok(metrics::count_resp(self.pet_stat_changed()).into());
+        }
+
+        let pet = self
+            .session
+            .pets
+            .get(&cash_id)
+            .ok_or_else(|| anyhow::format_err!("Invalid pet: {cash_id}"))?;
+        if pet.is_expired(chrono::Utc::now().naive_utc()) {
+            return Ok(metrics::count_resp(self.enable_char()).into());
+        }
+        let Some(pet_ix) = self.pets.iter().position(Option::is_none) else {
+            return Ok(metrics::count_resp(self.enable_char()).into());
+        };
+
+        self.activate_pet(pet_ix, cash_id, true)?;
+        Ok(metrics::count_resp(self.pet_stat_changed()).into())
+    }
+
+    pub async fn handle_pet_move(&mut self, req: PetMoveReq) -> anyhow::Result<()> {
+        let Some(pet_ix) = self.find_pet_ix(req.locker_id) else {
+            return Ok(());
+        };
+
+        self.field
+            .update_pet_pos(self.session.char.model.id, pet_ix as PetIx, req.move_path)?;
+        self.update_pet_hunger()
+    }
+
+    pub async fn handle_pet_action_command(
+        &mut self,
+        req: PetActionCommandReq,
+    ) -> anyhow::Result<()> {
+        let Some(pet_ix) = self.find_pet_ix(req.locker_id) else {
+            return Ok(());
+        };
+        let pet = self
+            .session
+            .pets
+            .get_mut(&req.locker_id)
+            .ok_or_else(|| anyhow::format_err!("Invalid pet: {}", req.locker_id))?;
+
+        let success = pet.command(rand::thread_rng());
+        if success {
+            let op = self.pet_update_op(req.locker_id)?;
+            self.send_inv_ops(vec![op])?;
+        }
+
+        self.field.add_pet_action(PetActionCommandResp {
+            char_id: self.session.char.model.id as u32,
+            pet_ix: pet_ix as PetIx,
+            action_type: PetActionType::Command,
+            action: req.command,
+            success,
+            chat_balloon: false,
+        })
+    }
+
+    /// Pet food is given to the hungriest active pet
+    pub async fn handle_pet_food(
+        &mut self,
+        req: UserPetFoodItemUseReq,
+    ) -> GameResult<CharStatChangedResp> {
+        if !req.item_id.is_pet_food() {
+            anyhow::bail!("Not pet food: {:?}", req.item_id);
+        }
+        let food_ix = client_slot_to_ix(req.slot)?;
+        let food_id = self
+            .session
+            .char
+            .inventory
+            .use_
+            .get(food_ix)
+            .map(|item| item.item_id);
+        if food_id != Some(req.item_id) {
+            anyhow::bail!("No pet food in slot: {}", req.slot);
+        }
+
+        let hungriest = self
+            .pets
+            .iter()
+            .enumerate()
+            .filter_map(|(pet_ix, pet)| {
+                let cash_id = pet.as_ref()?.cash_id;
+                Some((pet_ix, cash_id, self.session.pets.get(&cash_id)?.fullness))
+            })
+            .min_by_key(|(_, _, fullness)| *fullness);
+        let Some((pet_ix, cash_id, _)) = hungriest else {
+            return Ok(metrics::count_resp(self.enable_char()).into());
+        };
+
+        let inc = self
+            .services
+            .meta
+            .get_item_data(req.item_id)
+            .map_or(0, |item| item.inc.min(MAX_FULLNESS as u32) as u8);
+        let success = self
+            .session
+            .pets
+            .get_mut(&cash_id)
+            .ok_or_else(|| anyhow::format_err!("Invalid pet: {cash_id}"))?
+            .feed(inc);
+
+        let ops = vec![
+            self.take_stack_item(InventoryType::Consume, food_ix)?,
+            self.pet_update_op(cash_id)?,
+        ];
+        self.send_inv_ops(ops)?;
+
+        self.field.add_pet_action(PetActionCommandResp {
+            char_id: self.session.char.model.id as u32,
+            pet_ix: pet_ix as PetIx,
+            action_type: PetActionType::Food,
+            action: 1,
+            success,
+            chat_balloon: false,
+        })?;
+
+        Ok(metrics::count_resp(self.enable_char()).into())
+    }
+
+    pub async fn handle_pet_drop_pick_up(
+        &mut self,
+        req: PetDropPickUpReq,
+    ) -> GameResult<CharStatChangedResp> {
+        let Some(pet_ix) = self.find_pet_ix(req.locker_id) else {
+            return Ok(metrics::count_resp(self.enable_char()).into());
+        };
+
+        let param = DropLeaveParam::PetPickup(self.session.char.model.id as u32, pet_ix as PetIx);
+        self.pick_up_drop(req.drop_id, PET_PICK_UP_RANGE, param)
+            .await?;
+        Ok(metrics::count_resp(CharStatChangedResp {
+            excl: true,
+            stats: PartialFlag {
+                hdr: (),
+                data: self.session.char.get_char_partial(),
+            },
+            secondary_stat: false,
+            battle_recovery: false,
+        })
+        .into())
+    }
+}
@@ -1,10 +1,14 @@
-use clap::{Command, FromArgMatches, Parser, Subcommand};
+use clap::{Args, Command, FromArgMatches, Parser, Subcommand};
+use data::services::data::ban::BanParams;
 use data::services::helper::pool::{
     drop::{Drop, DropTypeValue},
     user::User,
     Mob,
 };
-use proto95::id::ItemId;
+use proto95::{
+    id::{ItemId, MapId},
+    login::{world::WorldId, BanReason},
+};
 
 use crate::GameHandler;
 
@@ -17,6 +21,34 @@ pub enum ReplCmd {
     FakeUser { id: u32 },
     Aggro,
     Dispose,
+    Ban(BanArgs),
+    Unban { name: String },
+    CharSlots(CharSlotArgs),
+    Instance(InstanceArgs),
+    CloseInstance,
+}
+
+/// Bans the account of the character, permanently if no days are given
+#[derive(Args, Debug)]
+pub struct BanArgs {
+    name: String,
+    days: Option<u32>,
+    reason: Option<u8>,
+}
+
+/// Adds character slots to the account of the character in its world
+#[derive(Args, Debug)]
+pub struct CharSlotArgs {
+    name: String,
+    count: u32,
+}
+
+/// Creates a private instance of the map and warps you and the characters into It
+#[derive(Args, Debug)]
+pub struct InstanceArgs {
+    #[arg(long)]
+    map: Option<u32>,
+    names: Vec<String>,
 }
 
 pub struct GameRepl {
@@ -63,17 +95,15 @@ impl GameHandler {
             ReplCmd::Mob { id } => {
                 let mob = id.unwrap_or(1110100);
                 let meta = self.services.meta.get_mob_data(mob).unwrap();
-                self.field
-                    .add_mob(Mob {
-                        meta,
-                        tmpl_id: mob,
-                        pos: self.pos,
-                        fh: self.fh,
-                        origin_fh: None,
-                        hp: meta.max_hp,
-                        perc: 100,
-                    })
-                    .await?;
+                self.field.add_mob(Mob {
+                    meta,
+                    tmpl_id: mob,
+                    pos: self.pos,
+                    fh: self.fh,
+                    origin_fh: None,
+                    hp: meta.max_hp,
+                    perc: 100,
+                })?;
                 None
             }
             ReplCmd::Mesos { amount } => {
@@ -103,6 +133,7 @@ impl GameHandler {
                     char_id: id,
                     pos: self.pos,
                     fh: self.fh,
+                    pets: Default::default(),
                 })?;
                 None
             }
@@ -116,9 +147,83 @@ impl GameHandler {
                 None
             }
             ReplCmd::Chat { msg } => Some(msg),
+            ReplCmd::Ban(_)
+            | ReplCmd::Unban { .. }
+            | ReplCmd::CharSlots(_)
+            | ReplCmd::Instance(_)
+            | ReplCmd::CloseInstance
+                if !self.is_gm() =>
+            {
+                Some("Insufficient permissions".to_string())
+            }
+            ReplCmd::Ban(BanArgs { name, days, reason }) => {
+                let reason = reason
+                    .map(BanReason::try_from)
+                    .transpose()
+                    .map_err(|_| anyhow::format_err!("Invalid ban reason"))?
+                    .unwrap_or_default();
+                let params = BanParams {
+                    duration: days.map(|days| chrono::Duration::days(days as i64)),
+                    issued_by: Some(self.session.char.model.name.clone()),
+                    ..BanParams::permanent(reason)
+                };
+                let ban = self.services.data.ban.ban_character(&name, params).await?;
+                Some(match ban.expires_at {
+                    Some(expires_at) => format!("Banned {name} until {expires_at}"),
+                    None => format!("Banned {name} permanently"),
+                })
+            }
+            ReplCmd::Unban { name } => {
+                let n = self.services.data.ban.unban_character(&name).await?;
+                Some(format!("Revoked {n} ban(s) of {name}"))
+            }
+            ReplCmd::CharSlots(CharSlotArgs { name, count }) => {
+                let data = &self.services.data;
+                let char = data
+                    .char
+                    .get_by_name(&name)
+                    .await?
+                    .ok_or_else(|| anyhow::format_err!("No character with name: {name}"))?;
+                let acc = data.account.must_get(char.acc_id).await?;
+                let slots = data
+                    .char
+                    .add_char_slots(&acc, char.world_id as WorldId, count)
+                    .await?;
+                Some(format!("{name} has {} character slots now", slots.slots))
+            }
+            ReplCmd::Instance(InstanceArgs { map, names }) => {
+                let map_id = map.map_or(self.field.key().field_id, MapId);
+                let key = self
+                    .services
+                    .field
+                    .create_instance(self.world_id, self.channel_id, map_id)
+                    .await?;
+                let mut chars = vec![self.session.char.model.id];
+                for name in names {
+                    let char = self
+                        .services
+                        .data
+                        .char
+                        .get_by_name(&name)
+                        .await?
+                        .ok_or_else(|| anyhow::format_err!("No character with name: {name}"))?;
+                    chars.push(char.id);
+                }
+                self.services.field.warp_group(chars, key, 0);
+                Some(format!("Created instance {key:?}"))
+            }
+            ReplCmd::CloseInstance => {
+                let key = self.field.key();
+                self.services.field.destroy_instance(key).await?;
+                Some(format!("Closed instance {key:?}"))
+            }
         })
     }
 
+    fn is_gm(&self) -> bool {
+        self.session.acc.gm_level > 0
+    }
+
     pub async fn handle_repl(&mut self, s: &str) -> anyhow::Result<Option<String>> {
         Ok(match self.repl.match_cmd(s) {
             Err(_) => Some(self.repl.help()),
@@ -0,0 +1,77 @@
+use data::services::{character::stats::ApStat, helper::intentory::inv::InventoryExt, metrics};
+use proto95::{
+    game::user::{UserAbilityMassUpReq, UserAbilityUpReq, UserConsumeCashItemUseReq},
+    shared::{char::CharStatChangedResp, inventory::InventoryType},
+};
+use shroom_net::packet::proto::partial::PartialFlag;
+
+use crate::{GameHandler, GameResult};
+
+impl GameHandler {
+    /// Only sends the stats which were changed
+    pub fn stat_changed_resp(&mut self) -> CharStatChangedResp {
+        CharStatChangedResp {
+            excl: true,
+            stats: PartialFlag {
+                hdr: (),
+                data: self.session.char.get_char_partial(),
+            },
+            secondary_stat: false,
+            battle_recovery: false,
+        }
+    }
+
+    pub async fn handle_ability_up(
+        &mut self,
+        req: UserAbilityUpReq,
+    ) -> GameResult<CharStatChangedResp> {
+        let stat = ApStat::try_from(req.stat)?;
+        self.session.char.add_ap(rand::thread_rng(), &[(stat, 1)])?;
+        Ok(metrics::count_resp(self.stat_changed_resp()).into())
+    }
+
+    pub async fn handle_ability_mass_up(
+        &mut self,
+        req: UserAbilityMassUpReq,
+    ) -> GameResult<CharStatChangedResp> {
+        let stats = req
+            .stats
+            .items
+            .iter()
+            .map(|stat| Ok((ApStat::try_from(stat.stat)?, stat.amount)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        self.session.char.add_ap(rand::thread_rng(), &stats)?;
+        Ok(metrics::count_resp(self.stat_changed_resp()).into())
+    }
+
+    pub async fn handle_consume_cash_item_use(
+        &mut self,
+        req: UserConsumeCashItemUseReq,
+    ) -> GameResult<CharStatChangedResp> {
+        let ix = (req.slot as usize)
+            .checked_sub(1)
+            .ok_or_else(|| anyhow::format_err!("Invalid slot: {}", req.slot))?;
+        let item = self
+            .session
+            .char
+            .inventory
+            .cash
+            .get(ix)
+            .ok_or_else(|| anyhow::format_err!("No item in slot: {}", req.slot))?;
+        if item.item_id != req.item_id {
+            anyhow::bail!("Item mismatch: {:?} != {:?}", item.item_id, req.item_id);
+        }
+
+        let Some(ap_reset) = req.ap_reset.0 else {
+            anyhow::bail!("Unhandled cash item: {:?}", req.item_id);
+        };
+        let from = ApStat::try_from(ap_reset.from)?;
+        let to = ApStat::try_from(ap_reset.to)?;
+        self.session.char.reset_ap(rand::thread_rng(), from, to)?;
+
+        let op = self.take_stack_item(InventoryType::Cash, ix)?;
+        self.send_inv_ops(vec![op])?;
+
+        Ok(metrics::count_resp(self.stat_changed_resp()).into())
+    }
+}
@@ -1,5 +1,21 @@
+use std::time::Duration;
+
+use crate::throttle::ThrottleConfig;
+
+/// Creates accounts for unknown usernames on the first login
+#[derive(Debug, Clone)]
+pub struct AutoRegisterConfig {
+    pub max_accounts_per_ip: usize,
+}
+
 #[derive(Debug, Default)]
 pub struct LoginConfig {
     pub enable_pin: bool,
-    pub enable_pic: bool
-}
\ No newline at end of file
+    pub enable_pic: bool,
+    pub throttle: ThrottleConfig,
+    /// Auto registration is disabled if not set
+    pub auto_register: Option<AutoRegisterConfig>,
+    /// Deleted characters are kept for this period before they are purged,
+    /// if not set characters are deleted immediately
+    pub char_delete_grace: Option<Duration>,
+}
@@ -1,14 +1,23 @@
 pub mod config;
 pub mod login_state;
+pub mod throttle;
 
 use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Instant;
 
 use async_trait::async_trait;
 use config::LoginConfig;
-use data::services::data::account::AccountServiceError;
+use data::proto_mapper::db_to_shroom_time;
+use data::services::data::account::{AccountId, AccountServiceError};
 use data::services::data::character::{CharacterCreateDTO, CharacterID, ItemStarterSet};
-use data::services::session::ShroomMigrationKey;
-use data::{entities::character, services};
+use data::services::meta::char_creation::CharCreateError;
+use data::services::metrics;
+use data::services::session::{center::MigrationTicket, ShroomMigrationKey};
+use data::{
+    entities::{account, character},
+    services,
+};
 use login_state::LoginState;
 
 use proto95::shared::char::AvatarEquips;
@@ -22,16 +31,17 @@ use proto95::{
         },
         char::{
             CharRankInfo, CheckDuplicateIDReq, CheckDuplicateIDResp, CheckDuplicateIDResult,
-            CreateCharReq, CreateCharResp, DeleteCharReq, DeleteCharResp, DeleteCharResult,
-            MigrateStageInfo, SelectCharReq, SelectCharResp, SelectCharResult, SelectWorldCharList,
-            SelectWorldResp, ViewChar, ViewCharWithRank,
+            CheckSecondPasswordResp, CreateCharReq, CreateCharResp, DeleteCharReq, DeleteCharResp,
+            DeleteCharResult, MigrateStageInfo, SelectCharCheckPicReq, SelectCharEnablePicReq,
+            SelectCharReq, SelectCharResp, SelectCharResult, SelectCharResultCode,
+            SelectWorldCharList, SelectWorldResp, ViewChar, ViewCharWithRank,
         },
         pin::{CheckPinReq, CheckPinResp, UpdatePinReq, UpdatePinResp},
         world::{
             ChannelId, LogoutWorldReq, SelectWorldReq, WorldCheckUserLimitReq,
-            WorldCheckUserLimitResp, WorldId, WorldInfoReq, WorldInfoResp, WorldReq,
+            WorldCheckUserLimitResp, WorldId, WorldInfoReq, WorldReq,
         },
-        CreateSecurityHandleReq, LoginOpt, LoginResultHeader,
+        CreateSecurityHandleReq, LoginOpt, LoginResultHeader, MachineId,
     },
     recv_opcodes::RecvOpcodes,
     shared::{
@@ -43,11 +53,12 @@ use shroom_net::net::service::handler::{SessionHandleResult, ShroomSessionHandle
 use shroom_net::net::service::resp::{
     MigrateResponse, PacketOpcodeExt, PongResponse, ResponsePacket,
 };
+use shroom_net::net::service::server_sess::SharedSessionHandle;
 use shroom_net::net::ShroomSession;
 use shroom_net::packet::list::ShroomIndexList8;
-use shroom_net::packet::time::ShroomTime;
 use shroom_net::packet::ShroomList8;
-use shroom_net::{shroom_router_fn, HasOpcode, PacketReader, ShroomPacket};
+use shroom_net::{shroom_router_fn, HasOpcode, PacketBuffer, PacketReader, ShroomPacket};
+use throttle::LoginThrottle;
 use tokio::net::TcpStream;
 
 pub type LoginResult<T> = Result<T, anyhow::Error>;
@@ -57,6 +68,10 @@ pub struct LoginHandler {
     state: LoginState,
     addr: IpAddr,
     cfg: &'static LoginConfig,
+    sess_handle: SharedSessionHandle,
+    throttle: Arc<LoginThrottle>,
+    /// Account, which this handler registered as online
+    online_acc: Option<AccountId>,
 }
 
 impl LoginHandler {
@@ -64,12 +79,50 @@ impl LoginHandler {
         services: services::SharedServices,
         cfg: &'static LoginConfig,
         addr: IpAddr,
+        sess_handle: SharedSessionHandle,
+        throttle: Arc<LoginThrottle>,
     ) -> Self {
         Self {
             services,
             state: LoginState::default(),
             cfg,
             addr,
+            sess_handle,
+            throttle,
+            online_acc: None,
+        }
+    }
+
+    /// Second password option for the character selection
+    fn get_login_opt(&self, acc: &account::Model) -> LoginOpt {
+        match (self.cfg.enable_pic, acc.pic.is_some()) {
+            (false, _) => LoginOpt::NoSecondPassword1,
+            (true, false) => LoginOpt::EnableSecondPassword,
+            (true, true) => LoginOpt::CheckSecondPassword,
+        }
+    }
+
+    /// Registers the account as online, if the account is already online
+    /// the other session is asked to disconnect
+    async fn register_online(&mut self, acc_id: AccountId) -> bool {
+        if self.online_acc == Some(acc_id) {
+            return true;
+        }
+
+        let session_man = &self.services.session_manager;
+        if let Some(prev_acc_id) = self.online_acc.take() {
+            session_man.logout(prev_acc_id).await;
+        }
+
+        match session_man.login(acc_id).await {
+            Ok(()) => {
+                self.online_acc = Some(acc_id);
+                true
+            }
+            Err(_) => {
+                session_man.request_disconnect(acc_id).await;
+                false
+            }
         }
     }
 }
@@ -93,6 +146,7 @@ impl ShroomSessionHandler for LoginHandler {
         packet: ShroomPacket,
         session: &mut ShroomSession<Self::Transport>,
     ) -> Result<SessionHandleResult, Self::Error> {
+        metrics::count_packet_in(packet.as_ref());
         shroom_router_fn!(
             handler,
             LoginHandler,
@@ -116,24 +170,42 @@ impl ShroomSessionHandler for LoginHandler {
             CreateCharReq => LoginHandler::handle_create_char,
             DeleteCharReq => LoginHandler::handle_delete_character,
             SelectCharReq => LoginHandler::handle_select_char,
+            SelectCharEnablePicReq => LoginHandler::handle_select_char_enable_pic,
+            SelectCharCheckPicReq => LoginHandler::handle_select_char_check_pic,
             ExceptionLogReq => LoginHandler::handle_exception_log
         );
 
         handler(self, session, packet.into_reader()).await
     }
+
+    async fn finish(self, _is_migrating: bool) -> Result<(), Self::Error> {
+        // After the char selection the account is tracked by the migration
+        if let Some(acc_id) = self.online_acc {
+            self.services.session_manager.logout(acc_id).await;
+        }
+
+        Ok(())
+    }
 }
 
 impl LoginHandler {
     pub async fn handle_default(
         &mut self,
-        _op: RecvOpcodes,
+        op: RecvOpcodes,
         pr: PacketReader<'_>,
     ) -> anyhow::Result<SessionHandleResult> {
         log::info!("Unhandled packet: {:?}", pr.into_inner());
+        metrics::count_unhandled_packet(op);
         Ok(SessionHandleResult::Ok)
     }
 
     async fn handle_pong(&mut self, _req: PongReq) -> anyhow::Result<PongResponse> {
+        if let Some(acc_id) = self.online_acc {
+            self.services
+                .session_manager
+                .online()
+                .keep_alive(acc_id, Instant::now())?;
+        }
         Ok(PongResponse)
     }
 
@@ -170,13 +242,13 @@ impl LoginHandler {
             .await?;
         self.state.reset();
 
-        Ok(ConfirmEULAResp { success: true }.into())
+        Ok(metrics::count_resp(ConfirmEULAResp { success: true }).into())
     }
 
     async fn handle_check_pin(&mut self, req: CheckPinReq) -> LoginResult<CheckPinResp> {
         let acc = self.state.get_pin()?;
 
-        Ok(if self.cfg.enable_pin {
+        Ok(metrics::count_resp(if self.cfg.enable_pin {
             match req.pin.opt {
                 Some(pin) => {
                     if self.services.data.account.check_pin(acc, &pin.pin)? {
@@ -189,7 +261,7 @@ impl LoginHandler {
             }
         } else {
             CheckPinResp::Accepted
-        }
+        })
         .into())
     }
 
@@ -205,7 +277,7 @@ impl LoginHandler {
             .update_account(|acc| self.services.data.account.set_pin(acc, pin))
             .await?;
 
-        Ok(UpdatePinResp { success: true }.into())
+        Ok(metrics::count_resp(UpdatePinResp { success: true }).into())
     }
 
     async fn handle_set_gender(&mut self, req: SetGenderReq) -> LoginResult<SetGenderResp> {
@@ -223,10 +295,10 @@ impl LoginHandler {
         self.state.transition_login().unwrap();
 
         //TODO this doesn't set the client key, maybe make it dc?
-        Ok(SetGenderResp {
+        Ok(metrics::count_resp(SetGenderResp {
             gender,
             success: true,
-        }
+        })
         .into())
     }
 
@@ -239,57 +311,108 @@ impl LoginHandler {
 
     async fn handle_world_check_user_limit(
         &mut self,
-        _req: WorldCheckUserLimitReq,
+        req: WorldCheckUserLimitReq,
     ) -> LoginResult<WorldCheckUserLimitResp> {
         let _acc = self.state.get_server_selection()?;
+        let population = self.services.session_manager.channel_population()?;
 
-        Ok(WorldCheckUserLimitResp {
-            over_user_limit: false,
-            populate_level: 0,
-        }
-        .into())
+        self.services
+            .server_info
+            .check_user_limit(req.world as WorldId, &population)
+            .map(metrics::count_resp)
     }
 
-    fn get_world_info(&self) -> Vec<WorldInfoResp> {
-        self.services
+    /// Sends the world list, the encoded list is cached until the population changes
+    fn send_world_info(&self) -> anyhow::Result<()> {
+        let population = self.services.session_manager.channel_population()?;
+        let buf = self
+            .services
             .server_info
-            .get_world_info_packets()
-            .into_iter()
-            .map(|p| p.into())
-            .collect()
+            .get_encoded_world_info(population)?;
+        self.sess_handle.try_send_pkt_buf(&buf)?;
+        Ok(())
     }
 
-    async fn handle_world_information(
-        &mut self,
-        _req: WorldInfoReq,
-    ) -> anyhow::Result<Vec<WorldInfoResp>> {
-        Ok(self.get_world_info())
+    async fn handle_world_information(&mut self, _req: WorldInfoReq) -> anyhow::Result<()> {
+        self.send_world_info()
     }
 
-    async fn handle_world_request(
-        &mut self,
-        _req: WorldReq,
-    ) -> anyhow::Result<Vec<WorldInfoResp>> {
-        Ok(self.get_world_info())
+    async fn handle_world_request(&mut self, _req: WorldReq) -> anyhow::Result<()> {
+        self.send_world_info()
     }
 
     pub async fn handle_check_password(
         &mut self,
         req: CheckPasswordReq,
     ) -> LoginResult<CheckPasswordResp> {
-        let login_result = self.services.data.account.try_login(&req.id, &req.pw).await;
+        let now = Instant::now();
+        // Throttled attempts are rejected before the costly password verification
+        if let Err(throttled) = self.throttle.check(self.addr, &req.id, now) {
+            log::info!(
+                "Login throttled - ip: {}, user: {}: {throttled:?}",
+                self.addr,
+                req.id
+            );
+            return Ok(metrics::count_resp(CheckPasswordResp::TooManyConnections(
+                LoginResultHeader::default(),
+            ))
+            .into());
+        }
+
+        let hwid = machine_id_to_hwid(&req.machine_id);
+        let account = &self.services.data.account;
+        let login_result = match account.try_login(&req.id, &req.pw).await {
+            Err(AccountServiceError::UsernameNotFound) => self.try_register(&req.id, &req.pw).await,
+            res => res,
+        };
+        let login_result = match login_result {
+            Ok(acc) => account
+                .check_hardware_info(&acc, &hwid, self.addr)
+                .await
+                .map(|_| acc),
+            Err(err) => Err(err),
+        };
+        match &login_result {
+            Err(AccountServiceError::UsernameNotFound | AccountServiceError::PasswordMismatch) => {
+                self.throttle.record_failure(self.addr, &req.id, now)
+            }
+            Ok(_) => self.throttle.record_success(&req.id),
+            _ => (),
+        }
         let hdr = LoginResultHeader::default();
 
+        if let Ok(acc) = &login_result {
+            if !self.register_online(acc.id).await {
+                return Ok(metrics::count_resp(CheckPasswordResp::AlreadyLoggedIn(hdr)).into());
+            }
+        }
+
         let res = match login_result {
             Err(AccountServiceError::UsernameNotFound) => CheckPasswordResp::InvalidUserName(hdr),
             Err(AccountServiceError::PasswordMismatch) => CheckPasswordResp::InvalidPassword(hdr),
-            Err(AccountServiceError::AccountIsBanned) => CheckPasswordResp::BlockedIp(BlockedIp {
-                hdr,
-                reason: 0,
-                ban_time: ShroomTime::now(), // TODO
-            }),
+            Err(AccountServiceError::AccountIsBanned(ban)) => match ban.expires_at {
+                // The client only shows the block reason for temporary bans
+                Some(expires_at) => CheckPasswordResp::BlockedIp(BlockedIp {
+                    hdr,
+                    reason: ban.reason,
+                    ban_time: db_to_shroom_time(expires_at),
+                }),
+                None => CheckPasswordResp::IdDeleted(hdr),
+            },
+            Err(
+                AccountServiceError::UsernameWrongSize
+                | AccountServiceError::UsernameWrongChar
+                | AccountServiceError::IpAccountLimit,
+            ) => CheckPasswordResp::InvalidUserName(hdr),
+            Err(
+                AccountServiceError::PasswordWrongSize | AccountServiceError::PasswordWrongChar,
+            ) => CheckPasswordResp::InvalidPassword(hdr),
+            Err(AccountServiceError::HostIsBanned(_)) => {
+                CheckPasswordResp::UnableToLoginWithIp(hdr)
+            }
             Ok(acc) => {
                 let account_info = (&acc).into();
+                let login_opt = self.get_login_opt(&acc);
                 self.state.transition_login_with_acc(acc)?;
                 let client_key = self
                     .state
@@ -299,7 +422,7 @@ impl LoginHandler {
                 let login_info = (!self.state.is_set_gender_stage())
                     .then_some(LoginInfo {
                         skip_pin: false,
-                        login_opt: proto95::login::LoginOpt::EnableSecondPassword,
+                        login_opt,
                         client_key,
                     })
                     .into();
@@ -316,34 +439,61 @@ impl LoginHandler {
                     })
                 }
             }
-            _ => todo!("Unhandled Account Service Login Result: {:?}", login_result),
+            Err(err) => {
+                log::error!("Login failed: {err:?}");
+                CheckPasswordResp::SystemError(hdr)
+            }
         };
 
-        Ok(res.into())
+        Ok(metrics::count_resp(res).into())
+    }
+
+    /// Registers an unknown username if auto registration is enabled,
+    /// the new account continues with the TOS and gender selection
+    async fn try_register(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<account::Model, AccountServiceError> {
+        let Some(cfg) = self.cfg.auto_register.as_ref() else {
+            return Err(AccountServiceError::UsernameNotFound);
+        };
+
+        let acc = self
+            .services
+            .data
+            .account
+            .register(username, password, self.addr, cfg.max_accounts_per_ip)
+            .await?;
+        log::info!("Registered account {username} from {}", self.addr);
+        Ok(acc)
     }
 
     async fn handle_select_world(&mut self, req: SelectWorldReq) -> LoginResult<SelectWorldResp> {
         let acc = self.state.get_server_selection()?;
+        let world = req.world_id as WorldId;
+        let channel = req.channel_id as ChannelId;
+        // Ensure the world and the channel exist
+        self.services.server_info.get_channel_addr(world, channel)?;
+
         let char_list = self
             .services
             .data
             .char
-            .get_characters_for_account(acc.id)
+            .get_characters_for_account(acc.id, world)
             .await?;
         let characters: ShroomList8<_> = char_list.iter().map(map_char_with_rank).collect();
+        let slots = self.services.data.char.get_char_slots(acc, world).await?;
 
         let char_list = SelectWorldCharList {
             characters,
-            //TODO pic handling
-            login_opt: LoginOpt::NoSecondPassword1,
-            slot_count: acc.character_slots as u32,
-            //TODO get buy count
-            buy_char_count: 3,
+            login_opt: self.get_login_opt(acc),
+            slot_count: slots.slots,
+            buy_char_count: slots.bought,
         };
-        self.state
-            .transition_char_select(req.world_id as WorldId, req.channel_id as ChannelId)?;
+        self.state.transition_char_select(world, channel)?;
 
-        Ok(SelectWorldResp::Success(char_list).into())
+        Ok(metrics::count_resp(SelectWorldResp::Success(char_list)).into())
     }
 
     async fn handle_check_duplicate_id(
@@ -351,26 +501,35 @@ impl LoginHandler {
         req: CheckDuplicateIDReq,
     ) -> anyhow::Result<CheckDuplicateIDResp> {
         let _ = self.state.get_char_select()?;
-        let name_used = !self.services.data.char.check_name(&req.name).await?;
-
-        let resp = if name_used {
-            CheckDuplicateIDResp {
-                name: "".to_string(),
-                result: CheckDuplicateIDResult::Error1,
-            }
-        } else {
-            CheckDuplicateIDResp {
-                name: req.name,
-                result: CheckDuplicateIDResult::Success,
+        let rules = self.services.meta.get_char_creation_rules();
+        let res = self.services.data.char.check_name(&req.name, rules).await;
+
+        let result = match res {
+            Ok(()) => CheckDuplicateIDResult::Success,
+            Err(CharCreateError::NameInUse) => CheckDuplicateIDResult::NameInUse,
+            Err(CharCreateError::InvalidName | CharCreateError::ForbiddenName) => {
+                CheckDuplicateIDResult::InvalidName
             }
+            Err(err) => return Err(err.into()),
         };
 
-        Ok(resp.into())
+        Ok(metrics::count_resp(CheckDuplicateIDResp {
+            name: req.name,
+            result,
+        })
+        .into())
     }
 
     async fn handle_create_char(&mut self, req: CreateCharReq) -> LoginResult<CreateCharResp> {
-        let (acc, _, _) = self.state.get_char_select()?;
+        let (acc, world, _) = self.state.get_char_select()?;
+        let server = self.services.server_info.get_server(world)?;
+        if server.block_char_creation {
+            anyhow::bail!("Character creation is blocked in world: {world}");
+        }
 
+        let Ok(skin) = (req.starter_set.skin_color as u8).try_into() else {
+            return Ok(metrics::count_resp(CreateCharResp::UnknownErr(())).into());
+        };
         let starter_set = ItemStarterSet {
             shoes: req.starter_set.shoes,
             bottom: req.starter_set.bottom,
@@ -379,7 +538,7 @@ impl LoginHandler {
             guide: req.job.get_guide_item(),
         };
 
-        let char_id = self
+        let res = self
             .services
             .data
             .char
@@ -389,77 +548,197 @@ impl LoginHandler {
                     name: req.name,
                     job_group: req.job,
                     face: req.starter_set.face,
-                    skin: (req.starter_set.skin_color as u8).try_into()?,
+                    skin,
                     hair: req.starter_set.hair,
-                    //TODO hair color
+                    hair_color: req.starter_set.hair_color,
                     starter_set,
                     gender: req.gender,
+                    world_id: world,
                 },
+                self.services.meta.get_char_creation_rules(),
                 &self.services.data.item,
             )
-            .await?;
+            .await;
 
-        let char = self.services.data.char.get(char_id).await?.unwrap();
-        Ok(CreateCharResp::Success(map_char(&char)).into())
+        let char_id = match res {
+            Ok(char_id) => char_id,
+            Err(err) => {
+                log::info!("Character creation of account {} failed: {err}", acc.id);
+                return Ok(metrics::count_resp(create_char_failed(err)?).into());
+            }
+        };
+        let char = self.services.data.char.must_get(char_id).await?;
+        Ok(metrics::count_resp(CreateCharResp::Success(map_char(&char))).into())
     }
 
     async fn handle_delete_character(&mut self, req: DeleteCharReq) -> LoginResult<DeleteCharResp> {
-        let (acc, _, _) = self.state.get_char_select()?;
-        let status = self
+        let (acc, world, _) = self.state.get_char_select()?;
+        let pic = (self.cfg.enable_pic && acc.pic.is_some()).then_some(req.pic.as_str());
+        let res = self
             .services
             .data
             .char
-            .delete_character(acc, req.char_id as i32, &req.pic)
-            .await?;
+            .delete_character(
+                acc,
+                world,
+                req.char_id as i32,
+                pic,
+                self.cfg.char_delete_grace,
+            )
+            .await;
 
-        let result = match status {
-            DeleteCharResult::Success => DeleteCharResult::Success,
-            //TODO add more
-            _ => DeleteCharResult::UnknownErr,
+        let result = match res {
+            Ok(result) => result,
+            Err(err) => {
+                log::error!("Failed to delete character {}: {err:?}", req.char_id);
+                DeleteCharResult::DBFail
+            }
         };
 
-        Ok(DeleteCharResp {
+        Ok(metrics::count_resp(DeleteCharResp {
             char_id: req.char_id,
             result,
-        }
+        })
         .into())
     }
 
     async fn handle_select_char(
         &mut self,
         req: SelectCharReq,
+    ) -> anyhow::Result<MigrateResponse<ResponsePacket<SelectCharResp>>> {
+        if self.cfg.enable_pic {
+            anyhow::bail!("Selecting a character requires the PIC");
+        }
+        self.check_select_char(req.char_id).await?;
+        self.migrate_char(req.char_id).await
+    }
+
+    /// Registers the PIC on the first character selection
+    async fn handle_select_char_enable_pic(
+        &mut self,
+        req: SelectCharEnablePicReq,
+    ) -> anyhow::Result<MigrateResponse<ResponsePacket<SelectCharResp>>> {
+        if !self.cfg.enable_pic {
+            anyhow::bail!("PIC is disabled");
+        }
+        self.check_select_char(req.char_id).await?;
+        self.state
+            .update_account(|acc| self.services.data.account.register_pic(acc, req.pic))
+            .await?;
+
+        self.migrate_char(req.char_id).await
+    }
+
+    async fn handle_select_char_check_pic(
+        &mut self,
+        req: SelectCharCheckPicReq,
+    ) -> anyhow::Result<Option<MigrateResponse<ResponsePacket<SelectCharResp>>>> {
+        if !self.cfg.enable_pic {
+            anyhow::bail!("PIC is disabled");
+        }
+        let (acc, world, _) = self.state.get_char_select()?;
+        // Fails after too many invalid attempts, which disconnects the client
+        let code = self
+            .services
+            .data
+            .char
+            .select_char_with_pic(acc, world, req.char_id as CharacterID, &req.pic)
+            .await?;
+
+        match code {
+            SelectCharResultCode::Success => Ok(Some(self.migrate_char(req.char_id).await?)),
+            SelectCharResultCode::InvalidPic => {
+                let mut buf = PacketBuffer::new();
+                metrics::write_pkt(&mut buf, CheckSecondPasswordResp { u1: 0 })?;
+                self.sess_handle.try_send_pkt_buf(&buf)?;
+                Ok(None)
+            }
+            code => anyhow::bail!("Unable to select character: {code:?}"),
+        }
+    }
+
+    async fn check_select_char(&self, char_id: u32) -> anyhow::Result<()> {
+        let (acc, world, _) = self.state.get_char_select()?;
+        let code = self
+            .services
+            .data
+            .char
+            .select_char(acc, world, char_id as CharacterID)
+            .await?;
+        if !code.is_success() {
+            anyhow::bail!("Unable to select character: {code:?}");
+        }
+
+        Ok(())
+    }
+
+    async fn migrate_char(
+        &mut self,
+        char_id: u32,
     ) -> anyhow::Result<MigrateResponse<ResponsePacket<SelectCharResp>>> {
         let (_, world, channel) = self.state.get_char_select()?;
 
-        let acc = self.state.claim_account()?;
+        let acc_id = self.state.claim_account()?.id;
         let client_key = self.state.get_client_key()?;
 
-        self.services
+        let res = self
+            .services
             .session_manager
             .create_migration_session(
                 ShroomMigrationKey::new(client_key, self.addr),
-                (acc, req.char_id as CharacterID),
+                MigrationTicket {
+                    acc_id,
+                    char_id: char_id as CharacterID,
+                    channel_id: channel,
+                },
             )
-            .await?;
+            .await;
+        if let Err(err) = res {
+            // Returning the error disconnects the client
+            if err.is_integrity_violation() {
+                log::warn!("Session integrity violation by account {acc_id}: {err}");
+            }
+            return Err(err.into());
+        }
+        self.online_acc = None;
 
         let addr = self.services.server_info.get_channel_addr(world, channel)?;
         let migrate = MigrateStageInfo {
             socket_addr: addr.try_into()?,
-            char_id: req.char_id,
+            char_id,
             premium: false,
             premium_arg: 0,
         };
 
-        let pkt = SelectCharResp {
-            error_code: 0,
-            result: SelectCharResult::Success(migrate),
-        }
+        let pkt = metrics::count_resp(SelectCharResp {
+            error_code: SelectCharResultCode::Success,
+            result: Some(SelectCharResult::Success(migrate)).into(),
+        })
         .with_opcode(SelectCharResp::OPCODE);
 
         Ok(MigrateResponse(pkt))
     }
 }
 
+fn create_char_failed(err: CharCreateError) -> anyhow::Result<CreateCharResp> {
+    Ok(match err {
+        CharCreateError::InvalidName
+        | CharCreateError::ForbiddenName
+        | CharCreateError::NameInUse => CreateCharResp::InvalidCharName(()),
+        // Only a modified client can send choices which are not on the creation screen
+        CharCreateError::JobNotAvailable(_) | CharCreateError::InvalidChoice(..) => {
+            CreateCharResp::UnknownErr(())
+        }
+        CharCreateError::NoFreeSlot => CreateCharResp::SystemError(()),
+        CharCreateError::Disconnect(_) => CreateCharResp::DBFail(()),
+        CharCreateError::Other(err) => return Err(err),
+    })
+}
+
+fn machine_id_to_hwid(machine_id: &MachineId) -> String {
+    machine_id.0.iter().map(|b| format!("{b:02x}")).collect()
+}
+
 pub fn map_char_to_avatar(char: &character::Model) -> AvatarData {
     AvatarData {
         gender: (&char.gender).into(),
@@ -0,0 +1,315 @@
+use std::{
+    collections::VecDeque,
+    hash::Hash,
+    net::IpAddr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use data::services::metrics::LoginThrottleStats;
+
+/// Limits for failed logins, the IP limits are higher than the account limits
+/// because multiple players may share a single IP
+#[derive(Debug, Clone)]
+pub struct ThrottleConfig {
+    /// Failed attempts older than this window are forgotten
+    pub window: Duration,
+    /// Failed attempts per IP within the window before the IP is locked out
+    pub max_ip_failures: usize,
+    /// Failed attempts per username within the window before the username is locked out
+    pub max_user_failures: usize,
+    /// Failed attempts within the window before the backoff applies
+    pub backoff_after: usize,
+    /// Delay after the first failure exceeding `backoff_after`, doubles with every further failure
+    pub backoff_base: Duration,
+    pub backoff_max: Duration,
+    pub lockout: Duration,
+}
+
+impl ThrottleConfig {
+    pub const DEFAULT: Self = Self {
+        window: Duration::from_secs(15 * 60),
+        max_ip_failures: 30,
+        max_user_failures: 10,
+        backoff_after: 3,
+        backoff_base: Duration::from_secs(1),
+        backoff_max: Duration::from_secs(30),
+        lockout: Duration::from_secs(15 * 60),
+    };
+
+    fn backoff(&self, failures: usize) -> Option<Duration> {
+        let exp = failures.checked_sub(self.backoff_after)?;
+        let factor = 1u32.checked_shl(exp.min(31) as u32).unwrap_or(u32::MAX);
+        Some(
+            self.backoff_base
+                .saturating_mul(factor)
+                .min(self.backoff_max),
+        )
+    }
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Reason why a login attempt was rejected before the credentials were checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Throttled {
+    /// Attempted again before the backoff delay passed
+    Backoff { retry_at: Instant },
+    /// Too many failures within the window
+    LockedOut { until: Instant },
+}
+
+#[derive(Debug, Default)]
+struct FailureWindow {
+    failures: VecDeque<Instant>,
+    locked_until: Option<Instant>,
+}
+
+impl FailureWindow {
+    fn prune(&mut self, window: Duration, now: Instant) {
+        while self
+            .failures
+            .front()
+            .is_some_and(|t| now.saturating_duration_since(*t) >= window)
+        {
+            self.failures.pop_front();
+        }
+        if self.locked_until.is_some_and(|until| until <= now) {
+            self.locked_until = None;
+        }
+    }
+
+    fn check(&mut self, cfg: &ThrottleConfig, now: Instant) -> Result<(), Throttled> {
+        self.prune(cfg.window, now);
+        if let Some(until) = self.locked_until {
+            return Err(Throttled::LockedOut { until });
+        }
+
+        let last = self.failures.back().copied();
+        match (last, cfg.backoff(self.failures.len())) {
+            (Some(last), Some(delay)) if last + delay > now => Err(Throttled::Backoff {
+                retry_at: last + delay,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records a failure, returns true if this failure caused a lockout
+    fn add_failure(&mut self, cfg: &ThrottleConfig, max: usize, now: Instant) -> bool {
+        self.prune(cfg.window, now);
+        self.failures.push_back(now);
+        if self.failures.len() >= max {
+            self.failures.clear();
+            self.locked_until = Some(now + cfg.lockout);
+            return true;
+        }
+        false
+    }
+
+    fn is_expired(&self, window: Duration, now: Instant) -> bool {
+        self.locked_until.map_or(true, |until| until <= now)
+            && self
+                .failures
+                .back()
+                .map_or(true, |t| now.saturating_duration_since(*t) >= window)
+    }
+}
+
+fn check_key<K: Hash + Eq>(
+    map: &DashMap<K, FailureWindow>,
+    key: &K,
+    cfg: &ThrottleConfig,
+    now: Instant,
+) -> Result<(), Throttled> {
+    map.get_mut(key)
+        .map_or(Ok(()), |mut entry| entry.check(cfg, now))
+}
+
+/// Tracks failed logins per IP and per username with sliding windows,
+/// this is checked before the password is verified
+#[derive(Debug)]
+pub struct LoginThrottle {
+    cfg: ThrottleConfig,
+    ips: DashMap<IpAddr, FailureWindow>,
+    users: DashMap<String, FailureWindow>,
+    attempts: AtomicU64,
+    failures: AtomicU64,
+    throttled: AtomicU64,
+    lockouts: AtomicU64,
+}
+
+fn user_key(username: &str) -> String {
+    username.to_lowercase()
+}
+
+impl LoginThrottle {
+    pub fn new(cfg: ThrottleConfig) -> Self {
+        Self {
+            cfg,
+            ips: DashMap::new(),
+            users: DashMap::new(),
+            attempts: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+            lockouts: AtomicU64::new(0),
+        }
+    }
+
+    /// Checks whether a login attempt is allowed, the longer restriction wins
+    pub fn check(&self, ip: IpAddr, username: &str, now: Instant) -> Result<(), Throttled> {
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+        let ip_res = check_key(&self.ips, &ip, &self.cfg, now);
+        let user_res = check_key(&self.users, &user_key(username), &self.cfg, now);
+
+        let res = match (ip_res, user_res) {
+            (Err(a @ Throttled::LockedOut { .. }), _)
+            | (_, Err(a @ Throttled::LockedOut { .. })) => Err(a),
+            (Err(a), _) | (_, Err(a)) => Err(a),
+            _ => Ok(()),
+        };
+        if res.is_err() {
+            self.throttled.fetch_add(1, Ordering::Relaxed);
+        }
+        res
+    }
+
+    pub fn record_failure(&self, ip: IpAddr, username: &str, now: Instant) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        let ip_locked =
+            self.ips
+                .entry(ip)
+                .or_default()
+                .add_failure(&self.cfg, self.cfg.max_ip_failures, now);
+        let user_locked = self
+            .users
+            .entry(user_key(username))
+            .or_default()
+            .add_failure(&self.cfg, self.cfg.max_user_failures, now);
+
+        if ip_locked || user_locked {
+            self.lockouts.fetch_add(1, Ordering::Relaxed);
+            log::warn!("Login locked out - ip: {ip}, user: {username}");
+        }
+    }
+
+    /// Resets the failures of the username, failures of the IP are kept
+    /// so a single host can't spray passwords across accounts
+    pub fn record_success(&self, username: &str) {
+        self.users.remove(&user_key(username));
+    }
+
+    /// Removes entries without recent failures or active lockouts
+    pub fn remove_expired(&self, now: Instant) {
+        let window = self.cfg.window;
+        self.ips.retain(|_, entry| !entry.is_expired(window, now));
+        self.users.retain(|_, entry| !entry.is_expired(window, now));
+    }
+
+    pub fn stats(&self) -> LoginThrottleStats {
+        LoginThrottleStats {
+            attempts: self.attempts.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            throttled: self.throttled.load(Ordering::Relaxed),
+            lockouts: self.lockouts.load(Ordering::Relaxed),
+            tracked_ips: self.ips.len(),
+            tracked_users: self.users.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, Instant},
+    };
+
+    use super::{LoginThrottle, ThrottleConfig, Throttled};
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    const OTHER_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+    fn cfg() -> ThrottleConfig {
+        ThrottleConfig {
+            window: Duration::from_secs(60),
+            max_ip_failures: 8,
+            max_user_failures: 5,
+            backoff_after: 2,
+            backoff_base: Duration::from_secs(1),
+            backoff_max: Duration::from_secs(4),
+            lockout: Duration::from_secs(300),
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        let now = Instant::now();
+        let secs = |s| now + Duration::from_secs(s);
+        let throttle = LoginThrottle::new(cfg());
+
+        throttle.record_failure(IP, "admin", now);
+        assert_eq!(throttle.check(IP, "admin", now), Ok(()));
+        throttle.record_failure(IP, "admin", now);
+        assert_eq!(
+            throttle.check(IP, "admin", now),
+            Err(Throttled::Backoff { retry_at: secs(1) })
+        );
+        // Backoff applies to the username from every IP
+        assert!(throttle.check(OTHER_IP, "ADMIN", now).is_err());
+        assert_eq!(throttle.check(IP, "admin", secs(1)), Ok(()));
+
+        throttle.record_failure(IP, "admin", secs(1));
+        assert_eq!(
+            throttle.check(IP, "admin", secs(2)),
+            Err(Throttled::Backoff { retry_at: secs(3) })
+        );
+
+        // Failures outside of the window are forgotten
+        assert_eq!(throttle.check(IP, "admin", secs(61)), Ok(()));
+        throttle.record_failure(IP, "admin", secs(61));
+        assert_eq!(throttle.check(IP, "admin", secs(61)), Ok(()));
+
+        throttle.record_success("admin");
+        assert_eq!(throttle.stats().tracked_users, 0);
+        assert_eq!(throttle.stats().failures, 4);
+    }
+
+    #[test]
+    fn lockout() {
+        let now = Instant::now();
+        let secs = |s| now + Duration::from_secs(s);
+        let throttle = LoginThrottle::new(cfg());
+
+        for i in 0..5 {
+            throttle.record_failure(IP, "admin", secs(i * 10));
+        }
+        let locked = Err(Throttled::LockedOut { until: secs(340) });
+        assert_eq!(throttle.check(OTHER_IP, "admin", secs(40)), locked);
+        assert_eq!(throttle.check(IP, "admin", secs(100)), locked);
+        assert_eq!(throttle.check(IP, "other", secs(100)), Ok(()));
+        assert_eq!(throttle.check(IP, "admin", secs(340)), Ok(()));
+
+        // Spraying passwords across accounts locks the IP
+        for i in 0..8 {
+            throttle.record_failure(IP, &format!("user{i}"), secs(400));
+        }
+        assert_eq!(
+            throttle.check(IP, "someone", secs(400)),
+            Err(Throttled::LockedOut { until: secs(700) })
+        );
+        assert_eq!(throttle.check(OTHER_IP, "someone", secs(400)), Ok(()));
+
+        let stats = throttle.stats();
+        assert_eq!(stats.lockouts, 2);
+        assert_eq!(stats.throttled, 3);
+
+        throttle.remove_expired(secs(700));
+        assert_eq!(throttle.stats().tracked_ips, 0);
+        assert_eq!(throttle.stats().tracked_users, 0);
+    }
+}
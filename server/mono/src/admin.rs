@@ -0,0 +1,324 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{Path, State},
+    http::{header, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use data::services::{
+    data::{account::AccountId, ban::BanParams, character::CharacterID},
+    grant::{Grant, GrantTarget},
+    meta::meta_service::MetaService,
+    session::online::OnlineState,
+    SharedServices,
+};
+use proto95::{
+    id::ItemId,
+    login::{
+        world::{ChannelId, WorldId},
+        BanReason,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::META_DIR;
+
+#[derive(Debug)]
+enum AdminError {
+    NotFound(String),
+    /// The request is valid, but can't be applied, like an invalid item or a busy session
+    Rejected(anyhow::Error),
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for AdminError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::Internal(err)
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorResp {
+    error: String,
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        let (status, error) = match self {
+            Self::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            Self::Rejected(err) => (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()),
+            Self::Internal(err) => {
+                log::error!("Admin request failed: {err:?}");
+                (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+            }
+        };
+        (status, Json(ErrorResp { error })).into_response()
+    }
+}
+
+type AdminResult<T> = Result<Json<T>, AdminError>;
+
+#[derive(Clone)]
+struct AdminState {
+    services: SharedServices,
+    token: &'static str,
+}
+
+/// Every request requires the configured token as bearer token
+async fn authorize<B>(State(state): State<AdminState>, req: Request<B>, next: Next<B>) -> Response {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match token {
+        Some(token)
+            if constant_time_eq::constant_time_eq(token.as_bytes(), state.token.as_bytes()) =>
+        {
+            next.run(req).await
+        }
+        _ => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+#[derive(Serialize)]
+struct OnlineCharResp {
+    acc_id: AccountId,
+    char_id: CharacterID,
+    name: String,
+    /// `channel`, `cash_shop` or `migrating`
+    state: &'static str,
+    world: Option<WorldId>,
+    channel: Option<ChannelId>,
+    /// Characters in the cash shop or between fields have no map
+    map: Option<u32>,
+    instance: Option<u32>,
+}
+
+async fn list_characters(State(state): State<AdminState>) -> AdminResult<Vec<OnlineCharResp>> {
+    let sm = &state.services.session_manager;
+    let fields = state.services.field.user_fields().await;
+    let chars = sm
+        .online_chars()
+        .into_iter()
+        .map(|char| {
+            let (online, world, channel) = match sm.online().get_state(char.acc_id) {
+                Some(OnlineState::Channel(world, channel)) => {
+                    ("channel", Some(world), Some(channel))
+                }
+                Some(OnlineState::CashShop) => ("cash_shop", None, None),
+                _ => ("migrating", None, None),
+            };
+            let field = fields.get(&char.char_id);
+            OnlineCharResp {
+                acc_id: char.acc_id,
+                char_id: char.char_id,
+                name: char.name,
+                state: online,
+                world,
+                channel,
+                map: field.map(|key| key.field_id.0),
+                instance: field.and_then(|key| key.instance),
+            }
+        })
+        .collect();
+    Ok(Json(chars))
+}
+
+#[derive(Serialize)]
+struct DoneResp {
+    done: bool,
+}
+
+async fn kick_account(
+    State(state): State<AdminState>,
+    Path(acc_id): Path<AccountId>,
+) -> AdminResult<DoneResp> {
+    let sm = &state.services.session_manager;
+    if !sm.online().is_online(acc_id) {
+        return Err(AdminError::NotFound(format!(
+            "Account {acc_id} is not online"
+        )));
+    }
+    sm.request_disconnect(acc_id).await;
+    Ok(Json(DoneResp { done: true }))
+}
+
+#[derive(Deserialize)]
+struct BanReq {
+    /// Ban reason code of the client, defaults to hacking
+    reason: Option<u8>,
+    /// Bans without days are permanent
+    days: Option<u32>,
+    message: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BanResp {
+    ban_id: i32,
+    expires_at: Option<String>,
+}
+
+async fn ban_account(
+    State(state): State<AdminState>,
+    Path(acc_id): Path<AccountId>,
+    Json(req): Json<BanReq>,
+) -> AdminResult<BanResp> {
+    let data = &state.services.data;
+    if data.account.get(acc_id).await?.is_none() {
+        return Err(AdminError::NotFound(format!("No account {acc_id}")));
+    }
+
+    let reason = req
+        .reason
+        .map(BanReason::try_from)
+        .transpose()
+        .map_err(|_| AdminError::Rejected(anyhow::format_err!("Invalid ban reason")))?
+        .unwrap_or_default();
+    let params = BanParams {
+        message: req.message,
+        duration: req.days.map(|days| chrono::Duration::days(days as i64)),
+        issued_by: Some("admin api".to_string()),
+        ..BanParams::permanent(reason)
+    };
+    let ban = data.ban.ban(acc_id, params).await?;
+    state
+        .services
+        .session_manager
+        .request_disconnect(acc_id)
+        .await;
+
+    Ok(Json(BanResp {
+        ban_id: ban.id,
+        expires_at: ban.expires_at.map(|t| t.to_string()),
+    }))
+}
+
+#[derive(Deserialize)]
+struct NoticeReq {
+    msg: String,
+}
+
+async fn send_notice(
+    State(state): State<AdminState>,
+    Json(req): Json<NoticeReq>,
+) -> AdminResult<DoneResp> {
+    state.services.session_manager.broadcast_notice(req.msg)?;
+    Ok(Json(DoneResp { done: true }))
+}
+
+#[derive(Serialize)]
+struct GrantResp {
+    /// `live` for online characters, `stored` otherwise
+    applied: &'static str,
+}
+
+async fn give(state: &AdminState, char_id: CharacterID, grant: Grant) -> AdminResult<GrantResp> {
+    if state.services.data.char.get(char_id).await?.is_none() {
+        return Err(AdminError::NotFound(format!("No character {char_id}")));
+    }
+
+    let applied = match state
+        .services
+        .give(char_id, grant)
+        .await
+        .map_err(AdminError::Rejected)?
+    {
+        GrantTarget::Live => "live",
+        GrantTarget::Stored => "stored",
+    };
+    Ok(Json(GrantResp { applied }))
+}
+
+#[derive(Deserialize)]
+struct MesosReq {
+    /// Negative amounts take mesos
+    amount: i32,
+}
+
+async fn give_mesos(
+    State(state): State<AdminState>,
+    Path(char_id): Path<CharacterID>,
+    Json(req): Json<MesosReq>,
+) -> AdminResult<GrantResp> {
+    give(&state, char_id, Grant::Mesos(req.amount)).await
+}
+
+#[derive(Deserialize)]
+struct ItemReq {
+    item_id: u32,
+    #[serde(default = "default_quantity")]
+    quantity: u16,
+}
+
+fn default_quantity() -> u16 {
+    1
+}
+
+async fn give_item(
+    State(state): State<AdminState>,
+    Path(char_id): Path<CharacterID>,
+    Json(req): Json<ItemReq>,
+) -> AdminResult<GrantResp> {
+    let grant = Grant::Item {
+        id: ItemId(req.item_id),
+        quantity: req.quantity,
+    };
+    give(&state, char_id, grant).await
+}
+
+/// Loaded fields keep their meta data until they are unloaded
+async fn reload_meta(State(state): State<AdminState>) -> AdminResult<DoneResp> {
+    let meta = tokio::task::spawn_blocking(|| MetaService::load_from_dir(META_DIR))
+        .await
+        .map_err(anyhow::Error::from)??;
+    state.services.meta.replace(meta);
+    log::info!("Reloaded meta data");
+    Ok(Json(DoneResp { done: true }))
+}
+
+#[derive(Serialize)]
+struct SaveAllResp {
+    saved: usize,
+    failed: usize,
+    /// Sessions of connected clients, which are saved on their next keep-alive
+    requested: usize,
+}
+
+async fn save_all(State(state): State<AdminState>) -> AdminResult<SaveAllResp> {
+    let summary = state.services.session_manager.save_all().await;
+    Ok(Json(SaveAllResp {
+        saved: summary.saved,
+        failed: summary.failed,
+        requested: summary.requested,
+    }))
+}
+
+/// Serves the admin api of the mono server
+pub async fn srv_admin(
+    addr: SocketAddr,
+    services: SharedServices,
+    token: &'static str,
+) -> anyhow::Result<()> {
+    let state = AdminState { services, token };
+    let app = Router::new()
+        .route("/characters", get(list_characters))
+        .route("/characters/:char_id/mesos", post(give_mesos))
+        .route("/characters/:char_id/items", post(give_item))
+        .route("/accounts/:acc_id/kick", post(kick_account))
+        .route("/accounts/:acc_id/ban", post(ban_account))
+        .route("/notice", post(send_notice))
+        .route("/meta/reload", post(reload_meta))
+        .route("/save-all", post(save_all))
+        .route_layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state);
+
+    log::info!("Admin api listening on {addr}");
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use center::CenterService;
+use dotenv::dotenv;
+use mono::config;
+
+/// Center of a split deployment, which must be reachable by all login and channel processes
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    pretty_env_logger::init();
+    dotenv().ok();
+
+    let settings = config::get_configuration().expect("Failed to load configuration");
+    log::info!("{0} - Center - {1}", settings.server_name, settings.version);
+
+    let addr: SocketAddr = settings.center.addr.parse()?;
+    let center = CenterService::new(Duration::from_secs(settings.center.migration_timeout_secs));
+    log::info!("Listening on {addr} ...");
+    Arc::new(center).serve(addr).await
+}
@@ -0,0 +1,92 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use clap::Parser;
+use dotenv::dotenv;
+use mono::{
+    build_worlds, config, crypto_ctx, handshake_gen, join_servers, release_stale_sessions,
+    shutdown, shutdown_signal, spawn_world, split_services, srv_metrics,
+};
+use proto95::login::world::{ChannelId, WorldId};
+use tokio::task::JoinSet;
+
+/// Channels of a single world in a split deployment
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(long, default_value_t = 0)]
+    world: WorldId,
+    /// Comma separated channel ids
+    #[arg(long, value_delimiter = ',')]
+    channels: Vec<ChannelId>,
+    /// Also runs the cash shop of the world
+    #[arg(long)]
+    cash_shop: bool,
+    /// Unique name of the process, which is reported to the center
+    #[arg(long)]
+    name: Option<String>,
+    /// Port of the prometheus metrics of this process, the endpoint is disabled without a port
+    #[arg(long)]
+    metrics_port: Option<u16>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    pretty_env_logger::init();
+    dotenv().ok();
+    let args = Args::parse();
+    if args.channels.is_empty() && !args.cash_shop {
+        anyhow::bail!("Neither channels nor the cash shop to run");
+    }
+
+    let settings = config::get_configuration().expect("Failed to load configuration");
+    log::info!(
+        "{0} - Channel {1:?} - {2}",
+        settings.server_name,
+        args.channels,
+        settings.version
+    );
+
+    let server_addr: IpAddr = settings.external_ip.parse()?;
+    let bind_addr: IpAddr = settings.bind_ip.parse()?;
+
+    let servers = build_worlds(&settings, server_addr)?;
+    let name = args
+        .name
+        .unwrap_or_else(|| format!("channel-{}-{:?}", args.world, args.channels));
+    let channels = args.channels.iter().map(|&ch| (args.world, ch)).collect();
+    let services = split_services(&settings, servers, name, channels).await?;
+
+    // Every server runs in the set, so a failed server stops the process
+    let mut set = JoinSet::new();
+    tokio::spawn(release_stale_sessions(services.clone()));
+    if let Some(port) = args.metrics_port {
+        set.spawn(srv_metrics(
+            SocketAddr::new(bind_addr, port),
+            services.clone(),
+        ));
+    }
+
+    spawn_world(
+        &mut set,
+        &crypto_ctx(),
+        bind_addr,
+        &handshake_gen(&settings)?,
+        &services,
+        args.world,
+        args.channels,
+        args.cash_shop,
+    )?;
+
+    log::info!("Listening ...");
+    tokio::select! {
+        res = join_servers(&mut set) => res?,
+        res = shutdown_signal() => res?,
+    }
+
+    shutdown(
+        &services,
+        set,
+        Duration::from_secs(settings.shutdown_countdown_secs),
+    )
+    .await
+}
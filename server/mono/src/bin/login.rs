@@ -0,0 +1,76 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use dotenv::dotenv;
+use mono::{
+    build_worlds,
+    config::{self, Environment},
+    crypto_ctx, handshake_gen, join_servers, login_config, purge_deleted_chars,
+    release_stale_sessions, server_config, shutdown, shutdown_signal, split_services,
+    srv_login_server, srv_metrics, srv_shrooming,
+};
+use tokio::task::JoinSet;
+
+/// Login server of a split deployment, the channels run in `shroom-channel`
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    pretty_env_logger::init();
+    dotenv().ok();
+
+    let settings = config::get_configuration().expect("Failed to load configuration");
+    log::info!("{0} - Login - {1}", settings.server_name, settings.version);
+
+    let server_addr: IpAddr = settings.external_ip.parse()?;
+    let bind_addr: IpAddr = settings.bind_ip.parse()?;
+
+    // Every server runs in the set, so a failed server stops the process
+    let mut set = JoinSet::new();
+    set.spawn(srv_shrooming(SocketAddr::new(
+        bind_addr,
+        settings.shrooming_port,
+    )));
+
+    let servers = build_worlds(&settings, server_addr)?;
+    let services = split_services(&settings, servers, "login".to_string(), vec![]).await?;
+    if let Environment::Local = config::get_environment() {
+        // The database file outlives the processes
+        match services.seed_acc_char().await {
+            Ok((acc_id, char_id)) => log::info!("Created test account {acc_id} - char: {char_id}"),
+            Err(err) => log::info!("Skipped the test account: {err}"),
+        }
+    }
+
+    tokio::spawn(release_stale_sessions(services.clone()));
+    if let Some(port) = settings.metrics_port {
+        set.spawn(srv_metrics(
+            SocketAddr::new(bind_addr, port),
+            services.clone(),
+        ));
+    }
+
+    let login_cfg = login_config(&settings);
+    if let Some(grace) = login_cfg.char_delete_grace {
+        tokio::spawn(purge_deleted_chars(services.clone(), grace));
+    }
+
+    set.spawn(srv_login_server(
+        server_config(&crypto_ctx()),
+        SocketAddr::new(bind_addr, settings.base_port),
+        handshake_gen(&settings)?,
+        services.clone(),
+        login_cfg,
+    ));
+
+    log::info!("Listening ...");
+    tokio::select! {
+        res = join_servers(&mut set) => res?,
+        res = shutdown_signal() => res?,
+    }
+
+    shutdown(
+        &services,
+        set,
+        Duration::from_secs(settings.shutdown_countdown_secs),
+    )
+    .await
+}
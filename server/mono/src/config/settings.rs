@@ -2,13 +2,161 @@
 pub struct Config {
     pub version: String,
     pub server_name: String,
-    pub num_worlds: usize,
-    pub num_channels: usize,
+    pub worlds: Vec<WorldSettings>,
+    /// Port of the login server
     pub base_port: u16,
     pub external_ip: String,
     pub client_version: usize,
     pub bind_ip: String,
     pub shrooming_port: u16,
+    /// Port of the prometheus metrics, the endpoint is disabled without a port
+    pub metrics_port: Option<u16>,
+    #[serde(default)]
+    pub auto_register: AutoRegisterSettings,
+    #[serde(default)]
+    pub throttle: ThrottleSettings,
+    /// Characters with a second password(PIC) require It to be selected or deleted
+    #[serde(default = "default_enable_pic")]
+    pub enable_pic: bool,
+    /// Days a deleted character is kept before it's purged, 0 deletes immediately
+    #[serde(default)]
+    pub char_delete_grace_days: u32,
+    /// Seconds the players are warned before the server shuts down
+    #[serde(default = "default_shutdown_countdown")]
+    pub shutdown_countdown_secs: u64,
+    /// Seconds an empty field stays loaded, 0 unloads It right away
+    #[serde(default = "default_field_idle")]
+    pub field_idle_secs: u64,
+    #[serde(default)]
+    pub center: CenterSettings,
+    /// Admin api of the mono server, disabled without this section
+    pub admin: Option<AdminSettings>,
+}
+
+fn default_enable_pic() -> bool {
+    true
+}
+
+fn default_shutdown_countdown() -> u64 {
+    30
+}
+
+fn default_field_idle() -> u64 {
+    60
+}
+
+fn default_rate() -> u16 {
+    100
+}
+
+/// A single world, the channels and the cash shop listen on consecutive ports
+#[derive(serde::Deserialize)]
+pub struct WorldSettings {
+    pub name: String,
+    pub num_channels: usize,
+    /// Port of the first channel, defaults to the port after the previous world
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub event_desc: String,
+    /// Rates in percent
+    #[serde(default = "default_rate")]
+    pub exp_rate: u16,
+    #[serde(default = "default_rate")]
+    pub drop_rate: u16,
+    #[serde(default)]
+    pub block_char_creation: bool,
+    #[serde(default)]
+    pub user_limit: UserLimitSettings,
+}
+
+/// Population thresholds per world
+#[derive(serde::Deserialize)]
+pub struct UserLimitSettings {
+    pub busy_users: usize,
+    pub max_users: usize,
+}
+
+impl Default for UserLimitSettings {
+    fn default() -> Self {
+        Self {
+            busy_users: 800,
+            max_users: 1000,
+        }
+    }
+}
+
+/// Creates accounts for unknown usernames on the first login,
+/// only meant for test servers
+#[derive(serde::Deserialize)]
+pub struct AutoRegisterSettings {
+    pub enabled: bool,
+    pub max_accounts_per_ip: usize,
+}
+
+impl Default for AutoRegisterSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_accounts_per_ip: 3,
+        }
+    }
+}
+
+/// Limits for failed logins, the IP limits are higher than the username limits
+/// because multiple players may share a single IP
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct ThrottleSettings {
+    pub window_secs: u64,
+    pub max_ip_failures: usize,
+    pub max_user_failures: usize,
+    /// Failed attempts before the delay between attempts applies
+    pub backoff_after: usize,
+    /// Delay after the first failure past `backoff_after`, doubles with every further failure
+    pub backoff_base_secs: u64,
+    pub backoff_max_secs: u64,
+    pub lockout_secs: u64,
+}
+
+impl Default for ThrottleSettings {
+    fn default() -> Self {
+        Self {
+            window_secs: 15 * 60,
+            max_ip_failures: 30,
+            max_user_failures: 10,
+            backoff_after: 3,
+            backoff_base_secs: 1,
+            backoff_max_secs: 30,
+            lockout_secs: 15 * 60,
+        }
+    }
+}
+
+/// Http api for server operations, requests need the token as bearer token
+#[derive(serde::Deserialize)]
+pub struct AdminSettings {
+    pub port: u16,
+    pub token: String,
+}
+
+/// Center of a split deployment, the mono server doesn't use It
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct CenterSettings {
+    pub addr: String,
+    /// Database shared by the local processes
+    pub sqlite: String,
+    pub migration_timeout_secs: u64,
+}
+
+impl Default for CenterSettings {
+    fn default() -> Self {
+        Self {
+            addr: "127.0.0.1:8491".to_string(),
+            sqlite: "sqlite://shroom.db?mode=rwc".to_string(),
+            migration_timeout_secs: 30,
+        }
+    }
 }
 
 pub fn get_configuration() -> Result<Config, config::ConfigError> {
@@ -0,0 +1,688 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    ops::Range,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use data::services::{
+    meta::meta_service::{MetaHandle, MetaService},
+    metrics,
+    server_info::{ServerInfo, UserLimit},
+    Services, SharedServices,
+};
+use login::{
+    config::{AutoRegisterConfig, LoginConfig},
+    throttle::{LoginThrottle, ThrottleConfig},
+    LoginHandler,
+};
+use proto95::{
+    login::world::{ChannelId, WorldId},
+    send_opcodes::SendOpcodes,
+};
+use shroom_net::{
+    crypto::{ig_cipher::IgContext, CryptoContext},
+    net::{
+        service::{
+            handler::MakeServerSessionHandler,
+            server_sess::{SharedSessionHandle, ShroomServer, ShroomServerConfig},
+            BasicHandshakeGenerator, HandshakeGenerator,
+        },
+        ShroomSession,
+    },
+    PacketWriter, ShroomPacket,
+};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::{net::TcpStream, task::JoinSet};
+
+use center::RemoteCenter;
+use shrooming::{FileIndex, FileSvr, MetricsSource, MetricsSvr};
+
+use crate::config::Environment;
+
+pub mod admin;
+pub mod config;
+
+#[derive(Clone, Debug)]
+pub struct Shared;
+
+#[derive(Debug, Clone)]
+pub struct MakeLoginHandler {
+    services: SharedServices,
+    cfg: &'static LoginConfig,
+    throttle: Arc<LoginThrottle>,
+}
+
+#[async_trait::async_trait]
+impl MakeServerSessionHandler for MakeLoginHandler {
+    type Transport = TcpStream;
+
+    type Error = anyhow::Error;
+
+    type Handler = LoginHandler;
+
+    async fn make_handler(
+        &mut self,
+        sess: &mut ShroomSession<Self::Transport>,
+        sess_handle: SharedSessionHandle,
+    ) -> Result<Self::Handler, Self::Error> {
+        Ok(LoginHandler::new(
+            self.services.clone(),
+            self.cfg,
+            sess.peer_addr()?.ip(),
+            sess_handle,
+            self.throttle.clone(),
+        ))
+    }
+}
+
+pub async fn srv_login_server(
+    cfg: ShroomServerConfig,
+    addr: impl tokio::net::ToSocketAddrs,
+    handshake_gen: impl HandshakeGenerator,
+    services: SharedServices,
+    login_cfg: &'static LoginConfig,
+) -> anyhow::Result<()> {
+    let throttle = Arc::new(LoginThrottle::new(login_cfg.throttle.clone()));
+    tokio::spawn(remove_expired_throttles(throttle.clone()));
+
+    let mut login_server = ShroomServer::new(
+        cfg,
+        handshake_gen,
+        MakeLoginHandler {
+            services,
+            cfg: login_cfg,
+            throttle,
+        },
+    );
+    login_server.serve_tcp(addr).await?;
+    Ok(())
+}
+
+pub async fn srv_game_server(
+    cfg: ShroomServerConfig,
+    addr: impl tokio::net::ToSocketAddrs,
+    handshake_gen: impl HandshakeGenerator,
+    services: SharedServices,
+    world_id: u32,
+    channel_id: u16,
+) -> anyhow::Result<()> {
+    let mut game_server = ShroomServer::new(
+        cfg,
+        handshake_gen,
+        game::MakeGameHandler::new(services, channel_id, world_id),
+    );
+    game_server.serve_tcp(addr).await?;
+    Ok(())
+}
+
+pub async fn srv_cash_shop_server(
+    cfg: ShroomServerConfig,
+    addr: impl tokio::net::ToSocketAddrs,
+    handshake_gen: impl HandshakeGenerator,
+    services: SharedServices,
+    world_id: u32,
+) -> anyhow::Result<()> {
+    let mut cash_shop_server = ShroomServer::new(
+        cfg,
+        handshake_gen,
+        game::cash_shop::MakeCashShopHandler::new(services, world_id),
+    );
+    cash_shop_server.serve_tcp(addr).await?;
+    Ok(())
+}
+
+/// Periodically releases accounts of timed out migrations and crashed sessions,
+/// this also reaps and saves the sessions of timed out migrations
+pub async fn release_stale_sessions(services: SharedServices) {
+    let mut interval = tokio::time::interval(Duration::from_secs(10));
+    loop {
+        interval.tick().await;
+        services.session_manager.release_stale().await;
+    }
+}
+
+/// Periodically purges soft deleted characters once their grace period passed
+pub async fn purge_deleted_chars(services: SharedServices, grace: Duration) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        interval.tick().await;
+        match services.data.char.purge_deleted_characters(grace).await {
+            Ok(0) => {}
+            Ok(n) => log::info!("Purged {n} deleted characters"),
+            Err(err) => log::error!("Failed to purge deleted characters: {err:?}"),
+        }
+    }
+}
+
+/// Periodically removes login throttle entries without recent failures
+/// and exports the stats of the throttle
+async fn remove_expired_throttles(throttle: Arc<LoginThrottle>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(15));
+    loop {
+        interval.tick().await;
+        throttle.remove_expired(Instant::now());
+        metrics::set_login_throttle(throttle.stats());
+    }
+}
+
+pub async fn srv_shrooming(addr: SocketAddr) -> anyhow::Result<()> {
+    let file_ix = FileIndex::build_index(
+        [
+            "notes.txt",
+            "../../client/shroom_hook/target/i686-pc-windows-gnu/release/dinput8.dll",
+            "../../target/i686-pc-windows-gnu/release/shroom_launchar.exe",
+        ]
+        .iter(),
+    )?;
+
+    FileSvr::new(file_ix).serve(addr).await?;
+
+    Ok(())
+}
+
+struct ServicesMetrics(SharedServices);
+
+#[async_trait::async_trait]
+impl MetricsSource for ServicesMetrics {
+    async fn render(&self) -> anyhow::Result<String> {
+        metrics::render(&self.0).await
+    }
+}
+
+/// Serves the prometheus metrics of this process
+pub async fn srv_metrics(addr: SocketAddr, services: SharedServices) -> anyhow::Result<()> {
+    MetricsSvr::new(ServicesMetrics(services)).serve(addr).await
+}
+
+fn world_ports(world: &ServerInfo) -> Range<u16> {
+    world.channels[0].port..world.next_free_port()
+}
+
+/// Builds the worlds from the configuration, worlds without an explicit port
+/// listen right after the previous world
+pub fn build_worlds(
+    settings: &config::Config,
+    server_addr: IpAddr,
+) -> anyhow::Result<Vec<ServerInfo>> {
+    if settings.worlds.is_empty() {
+        anyhow::bail!("At least one world must be configured");
+    }
+
+    let mut next_port = settings.base_port + 1;
+    let mut worlds: Vec<ServerInfo> = Vec::with_capacity(settings.worlds.len());
+    for world in settings.worlds.iter() {
+        if world.num_channels == 0 {
+            anyhow::bail!("World {} has no channels", world.name);
+        }
+        let mut server = ServerInfo::new(
+            server_addr,
+            settings.base_port,
+            world.port.unwrap_or(next_port),
+            world.name.clone(),
+            world.num_channels,
+        );
+        server.user_limit = UserLimit {
+            busy_users: world.user_limit.busy_users,
+            max_users: world.user_limit.max_users,
+        };
+        server.event_desc = world.event_desc.clone();
+        server.event_exp = world.exp_rate;
+        server.event_drop_rate = world.drop_rate;
+        server.block_char_creation = world.block_char_creation;
+
+        let ports = world_ports(&server);
+        let mut reserved = [settings.base_port, settings.shrooming_port]
+            .into_iter()
+            .chain(settings.metrics_port)
+            .chain(settings.admin.as_ref().map(|admin| admin.port));
+        let overlaps = worlds
+            .iter()
+            .map(world_ports)
+            .any(|other| ports.start < other.end && other.start < ports.end);
+        if overlaps || reserved.any(|port| ports.contains(&port)) {
+            anyhow::bail!("Ports of world {} overlap: {ports:?}", world.name);
+        }
+
+        next_port = server.next_free_port();
+        worlds.push(server);
+    }
+
+    Ok(worlds)
+}
+
+pub async fn join_servers(set: &mut JoinSet<anyhow::Result<()>>) -> anyhow::Result<()> {
+    while let Some(res) = set.join_next().await {
+        let _ = res?;
+    }
+    Ok(())
+}
+
+/// Resolves on Ctrl-C or SIGTERM
+pub async fn shutdown_signal() -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut term = signal(SignalKind::terminate())?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res?,
+            _ = term.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
+
+/// Remaining seconds of the countdown, at which the players are notified again
+const SHUTDOWN_NOTICES: [u64; 3] = [30, 10, 5];
+
+/// Clients are kicked right away, so this only has to cover their saves
+const SHUTDOWN_SAVE_TIMEOUT: Duration = Duration::from_secs(15);
+
+async fn shutdown_countdown(services: &SharedServices, countdown: Duration) {
+    let mut left = countdown.as_secs();
+    while left > 0 {
+        let msg = format!("The server shuts down in {left} seconds, please log out.");
+        if let Err(err) = services.session_manager.broadcast_notice(msg) {
+            log::error!("Failed to broadcast shutdown notice: {err:?}");
+        }
+
+        let next = SHUTDOWN_NOTICES
+            .iter()
+            .copied()
+            .find(|&n| n < left)
+            .unwrap_or(0);
+        tokio::time::sleep(Duration::from_secs(left - next)).await;
+        left = next;
+    }
+}
+
+/// Stops accepting connections, warns the players,
+/// then kicks all clients and saves every session
+pub async fn shutdown(
+    services: &SharedServices,
+    mut servers: JoinSet<anyhow::Result<()>>,
+    countdown: Duration,
+) -> anyhow::Result<()> {
+    log::info!("Shutting down in {}s ...", countdown.as_secs());
+    // Aborting the servers drops their listeners
+    servers.abort_all();
+    shutdown_countdown(services, countdown).await;
+
+    let summary = services
+        .session_manager
+        .shutdown(SHUTDOWN_SAVE_TIMEOUT)
+        .await;
+    log::info!(
+        "Shutdown complete: {} sessions saved, {} failed",
+        summary.saved,
+        summary.failed
+    );
+    if summary.failed > 0 {
+        anyhow::bail!("Failed to save {} sessions", summary.failed);
+    }
+
+    Ok(())
+}
+
+pub fn get_ping_packet() -> ShroomPacket {
+    let mut pw = PacketWriter::default();
+    pw.write_opcode(SendOpcodes::AliveReq).expect("Ping opcode");
+    pw.into_packet()
+}
+
+//TODO add crypto keys to config
+pub fn crypto_ctx() -> Arc<CryptoContext> {
+    Arc::new(CryptoContext {
+        aes_key: *include_bytes!("../../../keys/net/aes_key.bin"),
+        ig_ctx: IgContext::new(
+            *include_bytes!("../../../keys/net/round_shifting_key.bin"),
+            *include_bytes!("../../../keys/net/initial_round_key.bin"),
+        ),
+    })
+}
+
+pub fn server_config(crypto_ctx: &Arc<CryptoContext>) -> ShroomServerConfig {
+    ShroomServerConfig {
+        crypto_ctx: crypto_ctx.clone(),
+        migrate_delay: Duration::from_millis(7500),
+        ping_packet: get_ping_packet(),
+        ping_interval: Duration::from_secs(45),
+    }
+}
+
+pub fn handshake_gen(settings: &config::Config) -> anyhow::Result<BasicHandshakeGenerator> {
+    Ok(match settings.client_version {
+        83 => BasicHandshakeGenerator::v83(),
+        95 => BasicHandshakeGenerator::v95(),
+        _ => anyhow::bail!("unexpected client version"),
+    })
+}
+
+pub fn login_config(settings: &config::Config) -> &'static LoginConfig {
+    let login_cfg = Box::new(LoginConfig {
+        enable_pic: settings.enable_pic,
+        enable_pin: false,
+        throttle: ThrottleConfig {
+            window: Duration::from_secs(settings.throttle.window_secs),
+            max_ip_failures: settings.throttle.max_ip_failures,
+            max_user_failures: settings.throttle.max_user_failures,
+            backoff_after: settings.throttle.backoff_after,
+            backoff_base: Duration::from_secs(settings.throttle.backoff_base_secs),
+            backoff_max: Duration::from_secs(settings.throttle.backoff_max_secs),
+            lockout: Duration::from_secs(settings.throttle.lockout_secs),
+        },
+        auto_register: settings
+            .auto_register
+            .enabled
+            .then_some(AutoRegisterConfig {
+                max_accounts_per_ip: settings.auto_register.max_accounts_per_ip,
+            }),
+        char_delete_grace: (settings.char_delete_grace_days > 0)
+            .then(|| Duration::from_secs(settings.char_delete_grace_days as u64 * 24 * 60 * 60)),
+    });
+    if login_cfg.auto_register.is_some() {
+        log::warn!("Auto registration of accounts is enabled");
+    }
+    Box::leak(login_cfg)
+}
+
+pub const META_DIR: &str = "../../game_data/rbin";
+
+/// Meta will be available all the time, the admin api of the mono server can reload It
+pub fn load_meta() -> anyhow::Result<&'static MetaHandle> {
+    let meta = Box::new(MetaHandle::new(MetaService::load_from_dir(META_DIR)?));
+    Ok(Box::leak(meta))
+}
+
+/// Spawns the given channels of the world and optionally the cash shop of the world
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_world(
+    set: &mut JoinSet<anyhow::Result<()>>,
+    crypto_ctx: &Arc<CryptoContext>,
+    bind_addr: IpAddr,
+    handshake_gen: &BasicHandshakeGenerator,
+    services: &SharedServices,
+    world_id: WorldId,
+    channels: impl IntoIterator<Item = ChannelId>,
+    cash_shop: bool,
+) -> anyhow::Result<()> {
+    let world = services.server_info.get_server(world_id)?;
+    for ch in channels {
+        let channel = world
+            .channels
+            .get(ch as usize)
+            .ok_or_else(|| anyhow::format_err!("World {world_id} has no channel {ch}"))?;
+        set.spawn(srv_game_server(
+            server_config(crypto_ctx),
+            SocketAddr::new(bind_addr, channel.port),
+            handshake_gen.clone(),
+            services.clone(),
+            world_id,
+            ch,
+        ));
+    }
+    if cash_shop {
+        set.spawn(srv_cash_shop_server(
+            server_config(crypto_ctx),
+            SocketAddr::new(bind_addr, world.cash_shop_port),
+            handshake_gen.clone(),
+            services.clone(),
+            world_id,
+        ));
+    }
+    Ok(())
+}
+
+/// Services of a split process, locally the processes share a sqlite file
+pub async fn split_services(
+    settings: &config::Config,
+    servers: Vec<ServerInfo>,
+    server: String,
+    channels: Vec<(WorldId, ChannelId)>,
+) -> anyhow::Result<SharedServices> {
+    let meta = load_meta()?;
+    let services = match config::get_environment() {
+        Environment::Local => {
+            Services::seeded_in_sqlite(&settings.center.sqlite, servers, meta).await?
+        }
+        _ => Services::seeded_in_db(servers, meta).await?,
+    };
+    let center = RemoteCenter::connect_lazy(settings.center.addr.parse()?)?;
+    let services = services
+        .with_center(Arc::new(center), server, channels)
+        .with_field_idle_timeout(Duration::from_secs(settings.field_idle_secs))
+        .as_shared();
+    tokio::spawn(report_to_center(services.clone()));
+    Ok(services)
+}
+
+/// Periodically reports the accounts of this process to the center
+pub async fn report_to_center(services: SharedServices) {
+    let mut interval = tokio::time::interval(Duration::from_secs(5));
+    loop {
+        interval.tick().await;
+        if let Err(err) = services.session_manager.report_to_center().await {
+            log::error!("Failed to report to the center: {err:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        path::Path,
+        time::Duration,
+    };
+
+    use data::services::{
+        server_info::ServerInfo,
+        session::{center::MigrationTicket, online::OnlineState, ShroomMigrationKey},
+        Services, SharedServices,
+    };
+    use proto95::{
+        game::{
+            mini_room::{MiniRoomAction, MiniRoomReq, MiniRoomType},
+            MigrateCommandResp, MigrateInGameReq, TransferChannelIgnoredReason, TransferChannelReq,
+            TransferChannelReqIgnoredResp,
+        },
+        id::MapId,
+        login::{ClientKey, MachineId},
+        send_opcodes::SendOpcodes,
+    };
+    use shroom_net::{
+        net::{service::BasicHandshakeGenerator, ShroomSession},
+        packet::{
+            proto::{time::Ticks, CondOption},
+            DecodePacket,
+        },
+        ShroomPacket,
+    };
+    use tokio::{net::TcpStream, task::JoinSet};
+
+    use super::{crypto_ctx, get_ping_packet, load_meta, srv_game_server, ShroomServerConfig};
+
+    const IP: Ipv4Addr = Ipv4Addr::LOCALHOST;
+    const CHANNEL_PORT: u16 = 18585;
+    const CLIENT_KEY: ClientKey = [1, 2, 3, 4, 5, 6, 7, 8];
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Skips the packets of the field until the packet with the opcode arrives
+    async fn read_until(
+        sess: &mut ShroomSession<TcpStream>,
+        op: SendOpcodes,
+    ) -> anyhow::Result<ShroomPacket> {
+        let op = u16::from(op).to_le_bytes();
+        tokio::time::timeout(TIMEOUT, async {
+            loop {
+                let pkt = sess.read_packet().await?;
+                if pkt.as_ref().starts_with(&op) {
+                    return anyhow::Ok(pkt);
+                }
+            }
+        })
+        .await?
+    }
+
+    async fn read_transfer_ignored(
+        sess: &mut ShroomSession<TcpStream>,
+    ) -> anyhow::Result<TransferChannelIgnoredReason> {
+        let pkt = read_until(sess, SendOpcodes::TransferChannelReqIgnored).await?;
+        let mut pr = pkt.into_reader();
+        pr.read_opcode::<SendOpcodes>()?;
+        Ok(TransferChannelReqIgnoredResp::decode_packet(&mut pr)?.reason)
+    }
+
+    /// Connects to the channel and migrates into It with the client key
+    async fn migrate_in(channel_id: u16, char_id: u32) -> anyhow::Result<ShroomSession<TcpStream>> {
+        let io = TcpStream::connect(SocketAddr::new(IP.into(), CHANNEL_PORT + channel_id)).await?;
+        let (mut sess, _) = ShroomSession::initialize_client_session(io, &crypto_ctx()).await?;
+        sess.send_packet(MigrateInGameReq {
+            char_id,
+            machine_id: MachineId([0; 0x10]),
+            is_gm: false,
+            unknown: false,
+            client_key: CLIENT_KEY,
+        })
+        .await?;
+        read_until(&mut sess, SendOpcodes::SetField).await?;
+        Ok(sess)
+    }
+
+    /// Any handled packet applies pending warps, a chat outside of a room is ignored
+    async fn send_noop(sess: &mut ShroomSession<TcpStream>) -> anyhow::Result<()> {
+        sess.send_packet(MiniRoomReq {
+            action: MiniRoomAction::Chat as u8,
+            room_type: CondOption(None),
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn transfer_channel(
+        sess: &mut ShroomSession<TcpStream>,
+        channel_id: u8,
+    ) -> anyhow::Result<()> {
+        sess.send_packet(TransferChannelReq {
+            channel_id,
+            ticks: Ticks(0),
+        })
+        .await?;
+        Ok(())
+    }
+
+    fn spawn_channels(set: &mut JoinSet<anyhow::Result<()>>, services: &SharedServices) {
+        for channel_id in 0..2 {
+            // The client is disconnected right after the migrate command
+            let cfg = ShroomServerConfig {
+                crypto_ctx: crypto_ctx(),
+                migrate_delay: Duration::from_millis(100),
+                ping_packet: get_ping_packet(),
+                ping_interval: Duration::from_secs(45),
+            };
+            set.spawn(srv_game_server(
+                cfg,
+                SocketAddr::new(IP.into(), CHANNEL_PORT + channel_id),
+                BasicHandshakeGenerator::v95(),
+                services.clone(),
+                0,
+                channel_id,
+            ));
+        }
+    }
+
+    /// Two channels of the mono server with the real game handlers,
+    /// a client changes the channel after a trade and an event rejected the change
+    #[tokio::test]
+    async fn channel_change() -> anyhow::Result<()> {
+        // The meta data isn't part of the repository
+        if !Path::new(super::META_DIR).is_dir() {
+            eprintln!("Skipped, no meta data in {}", super::META_DIR);
+            return Ok(());
+        }
+
+        let world = ServerInfo::new(IP.into(), CHANNEL_PORT - 1, CHANNEL_PORT, "Test".into(), 2);
+        let services = Services::seeded_in_memory([world], load_meta()?)
+            .await?
+            .as_shared();
+        let (acc_id, char_id) = services.seed_acc_char().await?;
+        let map_id = MapId(services.data.char.must_get(char_id).await?.map_id as u32);
+
+        // The login hands the session over to the first channel
+        services.session_manager.login(acc_id).await?;
+        services
+            .session_manager
+            .create_migration_session(
+                ShroomMigrationKey::new(CLIENT_KEY, IP.into()),
+                MigrationTicket {
+                    acc_id,
+                    char_id,
+                    channel_id: 0,
+                },
+            )
+            .await?;
+        let mut set = JoinSet::new();
+        spawn_channels(&mut set, &services);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut sess = migrate_in(0, char_id as u32).await?;
+
+        // A private instance binds the character to the channel
+        let instance = services.field.create_instance(0, 0, map_id).await?;
+        services.field.warp_group([char_id], instance, 0);
+        send_noop(&mut sess).await?;
+        read_until(&mut sess, SendOpcodes::SetField).await?;
+        transfer_channel(&mut sess, 1).await?;
+        assert!(matches!(
+            read_transfer_ignored(&mut sess).await?,
+            TransferChannelIgnoredReason::CannotChange
+        ));
+        services.field.destroy_instance(instance).await?;
+        send_noop(&mut sess).await?;
+        read_until(&mut sess, SendOpcodes::SetField).await?;
+
+        // So does a trade, until the character leaves the room
+        sess.send_packet(MiniRoomReq {
+            action: MiniRoomAction::Create as u8,
+            room_type: CondOption(Some(MiniRoomType::TradingRoom)),
+        })
+        .await?;
+        transfer_channel(&mut sess, 1).await?;
+        assert!(matches!(
+            read_transfer_ignored(&mut sess).await?,
+            TransferChannelIgnoredReason::CannotChange
+        ));
+        sess.send_packet(MiniRoomReq {
+            action: MiniRoomAction::Leave as u8,
+            room_type: CondOption(None),
+        })
+        .await?;
+
+        // The current channel is rejected as well
+        transfer_channel(&mut sess, 0).await?;
+        assert!(matches!(
+            read_transfer_ignored(&mut sess).await?,
+            TransferChannelIgnoredReason::CannotChange
+        ));
+
+        transfer_channel(&mut sess, 1).await?;
+        let pkt = read_until(&mut sess, SendOpcodes::MigrateCommand).await?;
+        let mut pr = pkt.into_reader();
+        pr.read_opcode::<SendOpcodes>()?;
+        let migrate = MigrateCommandResp::decode_packet(&mut pr)?;
+        assert_eq!(migrate.addr.port, CHANNEL_PORT + 1);
+        drop(sess);
+
+        // The second channel claims the session, which the first channel pushed
+        let _sess = migrate_in(1, char_id as u32).await?;
+        let online = services.session_manager.online();
+        assert_eq!(online.get_state(acc_id), Some(OnlineState::Channel(0, 1)));
+        assert_eq!(online.channel_count(0, 0), 0);
+        assert_eq!(online.channel_count(0, 1), 1);
+
+        set.abort_all();
+        Ok(())
+    }
+}
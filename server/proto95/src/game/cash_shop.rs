@@ -0,0 +1,203 @@
+use shroom_net::{
+    packet::proto::{
+        time::{ShroomExpirationTime, Ticks},
+        ShroomList16, ShroomList32,
+    },
+    packet_opcode, shroom_enum_code, shroom_packet_enum,
+};
+use shroom_net_derive::ShroomPacket;
+
+use crate::{
+    id::ItemId,
+    recv_opcodes::RecvOpcodes,
+    send_opcodes::SendOpcodes,
+    shared::{
+        char::{CharDataAll, CharDataFlagsAll, CharDataHeader},
+        inventory::InventoryType,
+        item::Item,
+        NameStr,
+    },
+};
+
+pub type CashId = u64;
+pub type CommoditySn = u32;
+
+#[derive(ShroomPacket, Debug)]
+pub struct MigrateToCashShopReq {
+    pub ticks: Ticks,
+}
+packet_opcode!(
+    MigrateToCashShopReq,
+    RecvOpcodes::UserMigrateToCashShopRequest
+);
+
+/// Sent by the client when leaving the cash shop, has no payload
+#[derive(ShroomPacket, Debug)]
+pub struct CashShopTransferFieldReq;
+packet_opcode!(
+    CashShopTransferFieldReq,
+    RecvOpcodes::UserTransferFieldRequest
+);
+
+#[derive(ShroomPacket, Debug)]
+pub struct BestItem {
+    pub category: u32,
+    pub gender: u32,
+    pub commodity_sn: CommoditySn,
+}
+
+pub const BEST_ITEMS: usize = 90;
+
+#[derive(ShroomPacket, Debug)]
+pub struct CashItemStock {
+    pub commodity_sn: CommoditySn,
+    pub state: u32,
+}
+
+#[derive(ShroomPacket, Debug)]
+pub struct SetCashShopResp {
+    pub char_data_flags: CharDataFlagsAll,
+    pub char_data_hdr: CharDataHeader,
+    pub char_data: CharDataAll,
+    // Always true, the account name is only encoded for authorized sessions
+    pub authorized: bool,
+    pub account_name: String,
+    pub not_sale: ShroomList32<CommoditySn>,
+    // Modified commodities and discount rates are not supported yet
+    pub modified_commodity_count: u16,
+    pub discount_rate_count: u8,
+    pub best_items: [BestItem; BEST_ITEMS],
+    pub stock: ShroomList16<CashItemStock>,
+    pub limit_goods_count: u16,
+    pub zero_goods_count: u16,
+    pub event_on: bool,
+    pub highest_char_level: u32,
+}
+packet_opcode!(SetCashShopResp, SendOpcodes::SetCashShop);
+
+#[derive(ShroomPacket, Debug)]
+pub struct CashShopQueryCashReq;
+packet_opcode!(CashShopQueryCashReq, RecvOpcodes::CashShopQueryCashRequest);
+
+#[derive(ShroomPacket, Debug)]
+pub struct CashShopQueryCashResp {
+    pub nx_credit: u32,
+    pub shroom_points: u32,
+    pub nx_prepaid: u32,
+}
+packet_opcode!(CashShopQueryCashResp, SendOpcodes::CashShopQueryCashResult);
+
+shroom_enum_code!(CashType, u32, NxCredit = 1, ShroomPoints = 2, NxPrepaid = 4);
+
+#[derive(ShroomPacket, Debug)]
+pub struct CashItemBuyReq {
+    pub unknown: u8,
+    pub cash_type: CashType,
+    pub commodity_sn: CommoditySn,
+}
+
+#[derive(ShroomPacket, Debug)]
+pub struct CashItemGiftReq {
+    pub birthday: u32,
+    pub commodity_sn: CommoditySn,
+    pub receiver: String,
+    pub msg: String,
+}
+
+#[derive(ShroomPacket, Debug)]
+pub struct CashItemMoveLToSReq {
+    pub cash_id: CashId,
+    pub inv_type: InventoryType,
+    pub pos: u16,
+}
+
+#[derive(ShroomPacket, Debug)]
+pub struct CashItemMoveSToLReq {
+    pub cash_id: CashId,
+    pub inv_type: InventoryType,
+}
+
+shroom_packet_enum!(
+    #[derive(Debug)]
+    pub enum CashShopCashItemReq: u8 {
+        Buy(CashItemBuyReq) = 3,
+        Gift(CashItemGiftReq) = 4,
+        IncCharSlotCount(CashItemBuyReq) = 8,
+        MoveLToS(CashItemMoveLToSReq) = 0x0D,
+        MoveSToL(CashItemMoveSToLReq) = 0x0E
+    }
+);
+packet_opcode!(CashShopCashItemReq, RecvOpcodes::CashShopCashItemRequest);
+
+/// Locker entry, GW_CashItemInfo
+#[derive(ShroomPacket, Debug)]
+pub struct CashItemInfo {
+    pub cash_id: CashId,
+    pub acc_id: u32,
+    pub char_id: u32,
+    pub item_id: ItemId,
+    pub commodity_sn: CommoditySn,
+    pub quantity: u16,
+    pub buyer_char_name: NameStr,
+    pub expiration: ShroomExpirationTime,
+    pub payback_rate: u32,
+    pub discount_rate: u32,
+}
+
+#[derive(ShroomPacket, Debug)]
+pub struct CashLockerData {
+    pub items: ShroomList16<CashItemInfo>,
+    pub trunk_slots: u16,
+    pub char_slots: u16,
+    pub buy_char_count: u16,
+    pub char_count: u16,
+}
+
+#[derive(ShroomPacket, Debug)]
+pub struct CashGiftDone {
+    pub receiver: String,
+    pub item_id: ItemId,
+    pub quantity: u16,
+    pub price: u32,
+}
+
+#[derive(ShroomPacket, Debug)]
+pub struct CashMoveLToSDone {
+    pub pos: u16,
+    pub item: Item,
+}
+
+shroom_enum_code!(
+    CashItemFailReason,
+    u8,
+    Unknown = 0,
+    RequestTimedOut = 0xA3,
+    NotEnoughCash = 0xA5,
+    GiftUnderAge = 0xA6,
+    GiftLimitExceeded = 0xA7,
+    WrongReceiverName = 0xA9,
+    GiftSameAccount = 0xAA,
+    NotAvailable = 0xAC,
+    InventoryFull = 0xB1,
+    WrongGender = 0xB5
+);
+
+shroom_packet_enum!(
+    #[derive(Debug)]
+    pub enum CashShopCashItemResp: u8 {
+        LoadLockerDone(CashLockerData) = 0x4B,
+        LoadLockerFailed(CashItemFailReason) = 0x4C,
+        BuyDone(CashItemInfo) = 0x57,
+        BuyFailed(CashItemFailReason) = 0x58,
+        GiftDone(CashGiftDone) = 0x5E,
+        GiftFailed(CashItemFailReason) = 0x5F,
+        /// New character slot count of the world
+        IncCharSlotCountDone(u16) = 0x64,
+        IncCharSlotCountFailed(CashItemFailReason) = 0x65,
+        MoveLToSDone(CashMoveLToSDone) = 0x68,
+        MoveLToSFailed(CashItemFailReason) = 0x69,
+        MoveSToLDone(CashItemInfo) = 0x6A,
+        MoveSToLFailed(CashItemFailReason) = 0x6B
+    }
+);
+packet_opcode!(CashShopCashItemResp, SendOpcodes::CashShopCashItemResult);
@@ -115,7 +115,14 @@ shroom_enum_code!(
 
 impl DropLeaveType {
     fn has_pickup_id(&self) -> bool {
-        matches!(self, Self::UserPickup | Self::MobPickup | Self::PetSkill)
+        matches!(
+            self,
+            Self::UserPickup | Self::MobPickup | Self::PetPickup | Self::PetSkill
+        )
+    }
+
+    fn is_pet_pickup(&self) -> bool {
+        matches!(self, Self::PetPickup)
     }
 }
 
@@ -125,5 +132,7 @@ pub struct DropLeaveFieldResp {
     pub id: DropId,
     #[pkt(if(field = "leave_type", cond = "DropLeaveType::has_pickup_id"))]
     pub pickup_id: CondOption<u32>,
+    #[pkt(if(field = "leave_type", cond = "DropLeaveType::is_pet_pickup"))]
+    pub pet_ix: CondOption<u32>,
 }
 packet_opcode!(DropLeaveFieldResp, SendOpcodes::DropLeaveField);
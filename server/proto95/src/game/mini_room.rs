@@ -0,0 +1,65 @@
+use shroom_net::{packet::proto::CondOption, packet_opcode, shroom_enum_code};
+use shroom_net_derive::ShroomPacket;
+
+use crate::recv_opcodes::RecvOpcodes;
+
+shroom_enum_code!(
+    MiniRoomType,
+    u8,
+    Omok = 1,
+    MemoryGame = 2,
+    TradingRoom = 3,
+    PersonalShop = 4,
+    EntrustedShop = 5,
+    CashTradingRoom = 6
+);
+
+impl MiniRoomType {
+    pub fn is_game(&self) -> bool {
+        matches!(self, Self::Omok | Self::MemoryGame)
+    }
+
+    pub fn is_shop(&self) -> bool {
+        matches!(self, Self::PersonalShop | Self::EntrustedShop)
+    }
+}
+
+shroom_enum_code!(
+    MiniRoomAction,
+    u8,
+    Create = 0,
+    CreateResult = 1,
+    Invite = 2,
+    InviteResult = 3,
+    Enter = 4,
+    EnterResult = 5,
+    Chat = 6,
+    GameMessage = 7,
+    UserChat = 8,
+    Avatar = 9,
+    Leave = 0xA,
+    Balloon = 0xB,
+    NotAvailableField = 0xC,
+    FreeMarketClip = 0xD,
+    CheckSsn2 = 0xE
+);
+
+fn is_create(action: &u8) -> bool {
+    *action == MiniRoomAction::Create as u8
+}
+
+/// Only the header of the request is decoded, the rooms have their own actions
+/// for trades, shops and games, which are not listed in `MiniRoomAction`
+#[derive(ShroomPacket, Debug)]
+pub struct MiniRoomReq {
+    pub action: u8,
+    #[pkt(if(field = "action", cond = "is_create"))]
+    pub room_type: CondOption<MiniRoomType>,
+}
+packet_opcode!(MiniRoomReq, RecvOpcodes::MiniRoom);
+
+impl MiniRoomReq {
+    pub fn action(&self) -> Option<MiniRoomAction> {
+        MiniRoomAction::try_from(self.action).ok()
+    }
+}
@@ -1,16 +1,18 @@
 pub mod reactor;
 pub mod pet;
 pub mod npc;
+pub mod cash_shop;
 pub mod chat;
 pub mod drop;
 pub mod field;
 pub mod friend;
 pub mod keymaps;
 pub mod macros;
+pub mod mini_room;
 pub mod mob;
 pub mod user;
 use shroom_net_derive::ShroomPacket;
-use shroom_net::{packet::{proto::time::Ticks}, packet_opcode, shroom_packet_enum};
+use shroom_net::{packet::{proto::time::Ticks}, packet_opcode, shroom_enum_code, shroom_packet_enum};
 
 use crate::{
     id::job_id::JobId,
@@ -57,6 +59,22 @@ pub struct TransferChannelReq {
 }
 packet_opcode!(TransferChannelReq, RecvOpcodes::UserTransferChannelRequest);
 
+shroom_enum_code!(
+    TransferChannelIgnoredReason,
+    u8,
+    CannotChange = 1,
+    CashShopNotAvailable = 2
+);
+
+#[derive(ShroomPacket, Debug)]
+pub struct TransferChannelReqIgnoredResp {
+    pub reason: TransferChannelIgnoredReason,
+}
+packet_opcode!(
+    TransferChannelReqIgnoredResp,
+    SendOpcodes::TransferChannelReqIgnored
+);
+
 #[derive(ShroomPacket, Debug)]
 pub struct MigrateCommandResp {
     pub unknown: bool, //always true?
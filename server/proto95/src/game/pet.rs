@@ -1,9 +1,14 @@
 use shroom_net_derive::ShroomPacket;
-use shroom_net::{packet::{proto::time::Ticks}, packet_opcode};
+use shroom_net::{packet::{proto::time::Ticks}, packet_opcode, shroom_enum_code, shroom_packet_enum};
 
-use crate::{recv_opcodes::RecvOpcodes, shared::Vec2};
+use crate::{
+    id::ItemId,
+    recv_opcodes::RecvOpcodes,
+    send_opcodes::SendOpcodes,
+    shared::{char::CharacterId, movement::MovePath, Vec2},
+};
 
-use super::ObjectId;
+use super::{user::remote::PetInitInfo, ObjectId};
 
 pub type PetLockerId = u64;
 pub type PetId = u32;
@@ -26,6 +31,89 @@ pub struct PetDropPickUpReq {
     pub rect_crc: u32
 
 
-    
+
+}
+packet_opcode!(PetDropPickUpReq, RecvOpcodes::PetDropPickUpRequest);
+
+#[derive(ShroomPacket, Debug)]
+pub struct UserActivatePetReq {
+    pub ticks: Ticks,
+    pub slot: u16,
+    /// Activates the pet as the boss pet
+    pub lead: bool,
+}
+packet_opcode!(UserActivatePetReq, RecvOpcodes::UserActivatePetRequest);
+
+#[derive(ShroomPacket, Debug)]
+pub struct PetMoveReq {
+    pub locker_id: PetLockerId,
+    pub field_key: u8,
+    pub field_crc: u32,
+    pub move_path: MovePath,
+}
+packet_opcode!(PetMoveReq, RecvOpcodes::PetMove);
+
+#[derive(ShroomPacket, Debug)]
+pub struct PetActionCommandReq {
+    pub locker_id: PetLockerId,
+    pub u1: u8,
+    pub command: u8,
+}
+packet_opcode!(PetActionCommandReq, RecvOpcodes::PetActionCommand);
+
+#[derive(ShroomPacket, Debug)]
+pub struct UserPetFoodItemUseReq {
+    pub ticks: Ticks,
+    pub slot: u16,
+    pub item_id: ItemId,
+}
+packet_opcode!(
+    UserPetFoodItemUseReq,
+    RecvOpcodes::UserPetFoodItemUseRequest
+);
+
+shroom_enum_code!(PetLeaveReason, u8, Normal = 0, Hungry = 1, Expired = 2);
+
+#[derive(ShroomPacket, Debug)]
+pub struct PetEnterData {
+    /// Plays the summon effect
+    pub show_effect: bool,
+    pub pet: PetInitInfo,
+}
+
+shroom_packet_enum!(
+    #[derive(Debug)]
+    pub enum PetActivateResult: u8 {
+        Deactivated(PetLeaveReason) = 0,
+        Activated(PetEnterData) = 1
+    }
+);
+
+#[derive(ShroomPacket, Debug)]
+pub struct PetActivatedResp {
+    pub char_id: CharacterId,
+    pub pet_ix: PetIx,
+    pub result: PetActivateResult,
+}
+packet_opcode!(PetActivatedResp, SendOpcodes::PetActivated);
+
+#[derive(ShroomPacket, Debug)]
+pub struct PetMoveResp {
+    pub char_id: CharacterId,
+    pub pet_ix: PetIx,
+    pub move_path: MovePath,
+}
+packet_opcode!(PetMoveResp, SendOpcodes::PetMove);
+
+shroom_enum_code!(PetActionType, u8, Command = 0, Food = 1);
+
+#[derive(ShroomPacket, Debug)]
+pub struct PetActionCommandResp {
+    pub char_id: CharacterId,
+    pub pet_ix: PetIx,
+    pub action_type: PetActionType,
+    pub action: u8,
+    pub success: bool,
+    pub chat_balloon: bool,
 }
-packet_opcode!(PetDropPickUpReq, RecvOpcodes::PetDropPickUpRequest);
\ No newline at end of file
+packet_opcode!(PetActionCommandResp, SendOpcodes::PetActionCommand);
@@ -4,7 +4,10 @@ use bitflags::bitflags;
 use shroom_net::{
     mark_shroom_bitflags,
     packet::{
-        proto::{option::ShroomOption8, CondOption, PacketWrapped, ShroomList16, ShroomList8},
+        proto::{
+            option::ShroomOption8, CondOption, PacketWrapped, ShroomList16, ShroomList32,
+            ShroomList8,
+        },
         DecodePacket, PacketReader, time::Ticks, ShroomDurationMs16, ShroomExpirationTime,
     },
     packet_opcode, shroom_packet_enum, NetError, NetResult,
@@ -15,7 +18,7 @@ use crate::{
     id::{ItemId, MapId, SkillId},
     recv_opcodes::RecvOpcodes,
     send_opcodes::SendOpcodes,
-    shared::{movement::MovePath, TagPoint, Vec2},
+    shared::{char::CharStatFlags, movement::MovePath, TagPoint, Vec2},
 };
 
 use super::{mob::MobId, ObjectId};
@@ -508,6 +511,45 @@ pub struct UserSkillUpReq {
 }
 packet_opcode!(UserSkillUpReq, RecvOpcodes::UserSkillUpRequest);
 
+#[derive(ShroomPacket, Debug)]
+pub struct UserAbilityUpReq {
+    pub ticks: Ticks,
+    pub stat: CharStatFlags,
+}
+packet_opcode!(UserAbilityUpReq, RecvOpcodes::UserAbilityUpRequest);
+
+#[derive(ShroomPacket, Debug)]
+pub struct AbilityUpStat {
+    pub stat: CharStatFlags,
+    pub amount: u32,
+}
+
+#[derive(ShroomPacket, Debug)]
+pub struct UserAbilityMassUpReq {
+    pub ticks: Ticks,
+    pub stats: ShroomList32<AbilityUpStat>,
+}
+packet_opcode!(UserAbilityMassUpReq, RecvOpcodes::UserAbilityMassUpRequest);
+
+#[derive(ShroomPacket, Debug)]
+pub struct ApResetInfo {
+    pub to: CharStatFlags,
+    pub from: CharStatFlags,
+}
+
+#[derive(ShroomPacket, Debug)]
+pub struct UserConsumeCashItemUseReq {
+    pub ticks: Ticks,
+    pub slot: u16,
+    pub item_id: ItemId,
+    #[pkt(if(field = "item_id", cond = "ItemId::is_ap_reset"))]
+    pub ap_reset: CondOption<ApResetInfo>,
+}
+packet_opcode!(
+    UserConsumeCashItemUseReq,
+    RecvOpcodes::UserConsumeCashItemUseRequest
+);
+
 #[derive(ShroomPacket, Debug)]
 pub struct UserSkillUseReq {
     pub ticks: Ticks,
@@ -30,12 +30,12 @@ pub struct GuildMarkData {
 
 #[derive(ShroomPacket, Debug)]
 pub struct PetInitInfo {
-    tmpl_id: u32,
-    name: String,
-    pet_locker_sn: u64,
-    pos_prev: Vec2,
-    move_action: u8,
-    fh: FootholdId,
+    pub tmpl_id: u32,
+    pub name: String,
+    pub pet_locker_sn: u64,
+    pub pos_prev: Vec2,
+    pub move_action: u8,
+    pub fh: FootholdId,
 }
 
 #[derive(ShroomPacket, Debug, Default)]
@@ -191,6 +191,18 @@ packet_opcode!(
     SendOpcodes::UserShowUpgradeTombEffect
 );
 
+#[derive(ShroomPacket, Debug)]
+pub struct UserItemUpgradeEffectResp {
+    pub char_id: CharacterId,
+    pub success: bool,
+    pub cursed: bool,
+    pub enchant_skill: bool,
+    pub enchant_category: u32,
+    pub white_scroll: bool,
+    pub recoverable: bool,
+}
+packet_opcode!(UserItemUpgradeEffectResp, SendOpcodes::UserItemUpgradeEffect);
+
 #[derive(ShroomPacket, Debug)]
 pub struct UserThrowGrenadeResp {
     pub char_id: CharacterId,
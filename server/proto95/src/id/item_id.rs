@@ -68,6 +68,10 @@ impl ItemId {
         self.0 / 1000 == 5000
     }
 
+    pub fn is_pet_food(&self) -> bool {
+        self.0 / 10000 == 212
+    }
+
     pub fn is_nx_card(&self) -> bool {
         matches!(*self, Self::NX_CARD_100 | Self::NX_CARD_250)
     }
@@ -83,11 +87,70 @@ impl ItemId {
     pub fn is_explorer_mount(&self) -> bool {
         (Self::HOG..=Self::RED_DRACO).contains(self) || *self == Self::EXPLORER_SADDLE
     }
+
+    pub fn is_equip(&self) -> bool {
+        self.0 / 1_000_000 == 1
+    }
+
+    pub fn is_scroll(&self) -> bool {
+        self.0 / 10000 == 204
+    }
+
+    pub fn is_chaos_scroll(&self) -> bool {
+        (2049100..=2049199).contains(&self.0)
+    }
+
+    pub fn is_enhancement_scroll(&self) -> bool {
+        (2049300..=2049399).contains(&self.0)
+    }
+
+    pub fn is_clean_slate_scroll(&self) -> bool {
+        (Self::CLEAN_SLATE_1..=Self::CLEAN_SLATE_20).contains(self)
+    }
+
+    pub fn is_white_scroll(&self) -> bool {
+        *self == Self::WHITE_SCROLL
+    }
+
+    pub fn is_ap_reset(&self) -> bool {
+        *self == Self::AP_RESET
+    }
+
+    pub fn is_golden_hammer(&self) -> bool {
+        self.0 / 10000 == 247
+    }
+
+    /// Some equips are excluded from hammers, although they have upgrade slots
+    pub fn can_use_hammer(&self) -> bool {
+        self.is_equip()
+            && !matches!(
+                *self,
+                Self::HORNTAIL_NECKLACE | Self::CHAOS_HORNTAIL_NECKLACE
+            )
+    }
+
+    /// Checks if the scroll can be applied to the given equip
+    /// normal scrolls encode the equip category in the 3rd and 4th digit
+    /// 2043001 => 130xxxx(One-Handed Sword)
+    pub fn can_scroll_equip(&self, equip: ItemId) -> bool {
+        if !self.is_scroll() || !equip.is_equip() {
+            return false;
+        }
+
+        if self.is_chaos_scroll() || self.is_clean_slate_scroll() || self.is_enhancement_scroll() {
+            return true;
+        }
+
+        (self.0 / 100) % 100 == (equip.0 / 10000) % 100
+    }
 }
 
 impl ItemId {
     // Misc
     pub const PENDANT_OF_THE_SPIRIT: ItemId = ItemId(1122017);
+    pub const HORNTAIL_NECKLACE: ItemId = ItemId(1122000);
+    pub const CHAOS_HORNTAIL_NECKLACE: ItemId = ItemId(1122076);
+    pub const CHAR_SLOT_EXPANSION: ItemId = ItemId(5430000);
     pub const HEART_SHAPED_CHOCOLATE: ItemId = ItemId(5110000);
     pub const HAPPY_BIRTHDAY: ItemId = ItemId(2022153);
     pub const FISHING_CHAIR: ItemId = ItemId(3011000);
@@ -230,9 +293,11 @@ impl ItemId {
     pub const CLEAN_SLATE_3: ItemId = ItemId(2049001);
     pub const CLEAN_SLATE_5: ItemId = ItemId(2049002);
     pub const CLEAN_SLATE_20: ItemId = ItemId(2049003);
+    pub const EQUIP_ENHANCEMENT_SCROLL: ItemId = ItemId(2049300);
     pub const RING_STR_100_SCROLL: ItemId = ItemId(2041100);
     pub const DRAGON_STONE_SCROLL: ItemId = ItemId(2041200);
     pub const BELT_STR_100_SCROLL: ItemId = ItemId(2041300);
+    pub const GOLDEN_HAMMER: ItemId = ItemId(2470000);
 
     // Cure debuff
     pub const ALL_CURE_POTION: ItemId = ItemId(2050004);
@@ -254,6 +319,9 @@ impl ItemId {
     pub const DRAGON_PET: ItemId = ItemId(5000028);
     pub const ROBO_PET: ItemId = ItemId(5000047);
 
+    // Pet food
+    pub const PET_FOOD: ItemId = ItemId(2120000);
+
     // Pet equip
     pub const MESO_MAGNET: ItemId = ItemId(1812000);
     pub const ITEM_POUCH: ItemId = ItemId(1812001);
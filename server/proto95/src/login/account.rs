@@ -106,6 +106,7 @@ shroom_packet_enum!(
         InvalidUserName(LoginResultHeader) = 5,
         SystemError(LoginResultHeader) = 6,
         AlreadyLoggedIn(LoginResultHeader) = 7,
+        TooManyConnections(LoginResultHeader) = 10,
         UnableToLoginWithIp(LoginResultHeader) = 13,
         TOS(LoginResultHeader) = 23,
         Unknown(LoginResultHeader) = 255
@@ -1,5 +1,5 @@
 use shroom_net::{
-    packet::proto::{option::ShroomOption8, ShroomList8},
+    packet::proto::{option::ShroomOption8, CondOption, ShroomList8},
     packet_opcode, shroom_enum_code, shroom_packet_enum,
 };
 use shroom_net_derive::ShroomPacket;
@@ -47,10 +47,10 @@ shroom_packet_enum!(
 
 #[derive(ShroomPacket, Debug)]
 pub struct SelectCharResp {
-    //TODO: use enums
-    pub error_code: u8,
-    //TODO add all options
-    pub result: SelectCharResult,
+    pub error_code: SelectCharResultCode,
+    /// Only sent on success
+    #[pkt(if(field = "error_code", cond = "SelectCharResultCode::is_success"))]
+    pub result: CondOption<SelectCharResult>,
 }
 packet_opcode!(SelectCharResp, SendOpcodes::SelectCharacterResult);
 
@@ -88,10 +88,11 @@ packet_opcode!(SelectWorldResp, SendOpcodes::SelectWorldResult);
 shroom_packet_enum!(
     pub enum CreateCharResp: u8 {
         Success(ViewChar) = 0,
+        DBFail(()) = 6,
+        UnknownErr(()) = 9,
         Timeout(()) = 0xa,
         SystemError(()) = 0x1a,
         InvalidCharName(()) = 0x1e
-        //TODO more errors?
     }
 );
 packet_opcode!(CreateCharResp, SendOpcodes::CreateNewCharacterResult);
@@ -111,6 +112,12 @@ shroom_enum_code!(
     ErrHasFamily = 0x1D
 );
 
+impl SelectCharResultCode {
+    pub fn is_success(&self) -> bool {
+        matches!(self, Self::Success)
+    }
+}
+
 shroom_enum_code!(
     DeleteCharResult,
     u8,
@@ -324,12 +331,12 @@ shroom_enum_code!(
     CheckDuplicateIDResult,
     u8,
     Success = 0,
-    // TODO: mapped to 5
-    Error1 = 1,
-    // map to 10
-    Error2 = 2,
-    // map to 18 or well every code aside from 0,1,2
-    Error3 = 3
+    // Mapped to 5
+    NameInUse = 1,
+    // Mapped to 10
+    Timeout = 2,
+    // Mapped to 18, every code aside from 0, 1, 2
+    InvalidName = 3
 );
 
 #[derive(ShroomPacket, Debug)]
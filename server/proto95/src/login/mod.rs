@@ -1,5 +1,6 @@
 pub mod pin;
-use shroom_net::{shroom_enum_code, packet_opcode, packet::CondOption};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use shroom_net::{shroom_enum_code, packet_opcode, packet::CondOption, mark_shroom_enum};
 use shroom_net_derive::ShroomPacket;
 
 use crate::recv_opcodes::RecvOpcodes;
@@ -71,7 +72,26 @@ shroom_enum_code!(
 63, c7 => blocked for typing
 
 */
-pub type BanReason = u8;
+/// Block reasons, which the client can display in the login dialog
+#[derive(Debug, Eq, PartialEq, Copy, Clone, TryFromPrimitive, IntoPrimitive, Default)]
+#[repr(u8)]
+pub enum BanReason {
+    #[default]
+    Hacking = 1,
+    Botting = 2,
+    Advertising = 3,
+    Harassment = 4,
+    Cursing = 5,
+    Scamming = 6,
+    Misconduct = 7,
+    IllegalTransaction = 8,
+    IllegalCharging = 9,
+    TemporaryRequest = 10,
+    ImpersonatingGm = 11,
+    IllegalProgram = 12,
+    MegaphoneAbuse = 13,
+}
+mark_shroom_enum!(BanReason);
 
 #[derive(Debug, ShroomPacket)]
 pub struct HardwareInfo {
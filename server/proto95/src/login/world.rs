@@ -1,7 +1,8 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
 use shroom_net_derive::ShroomPacket;
 use shroom_net::{packet::{
     proto::{conditional::CondOption, ShroomList16, ShroomList8},
-}, packet_opcode};
+}, packet_opcode, mark_shroom_enum};
 
 use crate::{recv_opcodes::RecvOpcodes, send_opcodes::SendOpcodes, shared::Vec2};
 
@@ -29,11 +30,21 @@ pub struct WorldCheckUserLimitReq {
 }
 packet_opcode!(WorldCheckUserLimitReq, RecvOpcodes::CheckUserLimit);
 
+/// Population of a world, the client warns about busy worlds
+#[derive(Debug, Eq, PartialEq, Copy, Clone, TryFromPrimitive, IntoPrimitive, Default)]
+#[repr(u8)]
+pub enum PopulateLevel {
+    #[default]
+    Normal = 0,
+    Busy = 1,
+    Full = 2,
+}
+mark_shroom_enum!(PopulateLevel);
+
 #[derive(Debug, ShroomPacket)]
 pub struct WorldCheckUserLimitResp {
     pub over_user_limit: bool,
-    //TODO seems like a bool
-    pub populate_level: u8,
+    pub populate_level: PopulateLevel,
 }
 packet_opcode!(WorldCheckUserLimitResp, SendOpcodes::CheckUserLimitResult);
 
@@ -18,7 +18,7 @@ shroom_enum_code!(
     u8,
     Equip = 1,
     Consume = 2,
-    Install = 30,
+    Install = 3,
     Etc = 4,
     Cash = 5,
     Equipped = 6,
@@ -290,6 +290,20 @@ pub struct GatherItemReq {
 }
 packet_opcode!(GatherItemReq, RecvOpcodes::UserGatherItemRequest);
 
+#[derive(Debug, ShroomPacket)]
+pub struct GatherItemResultResp {
+    pub unknown: bool,
+    pub inv_type: InventoryType,
+}
+packet_opcode!(GatherItemResultResp, SendOpcodes::GatherItemResult);
+
+#[derive(Debug, ShroomPacket)]
+pub struct SortItemResultResp {
+    pub unknown: bool,
+    pub inv_type: InventoryType,
+}
+packet_opcode!(SortItemResultResp, SendOpcodes::SortItemResult);
+
 #[derive(Debug, ShroomPacket)]
 pub struct ItemOptionUpgradeReq {
     pub timestamp: Ticks,
@@ -319,11 +333,36 @@ pub struct ItemUpgradeReq {
     pub timestamp: Ticks,
     pub use_slot: u16,
     pub equip_slot: u16,
-    pub white_scroll_slot: u16,
+    pub flag: ScrollFlag,
     pub enchant_skill: bool,
 }
 packet_opcode!(ItemUpgradeReq, RecvOpcodes::UserUpgradeItemUseRequest);
 
+#[derive(Debug, ShroomPacket)]
+pub struct GoldHammerReq {
+    pub timestamp: Ticks,
+    pub hammer_slot: u32,
+    pub hammer_item_id: ItemId,
+    pub equip_inv_type: u32,
+    pub equip_slot: u32,
+}
+packet_opcode!(GoldHammerReq, RecvOpcodes::GoldHammerRequest);
+
+shroom_enum_code!(
+    GoldHammerResult,
+    u8,
+    Done = 0x38,
+    Error = 0x39
+);
+
+#[derive(Debug, ShroomPacket)]
+pub struct GoldHammerResultResp {
+    pub result: GoldHammerResult,
+    // 0 means success, 1 means failure
+    pub code: u32,
+}
+packet_opcode!(GoldHammerResultResp, SendOpcodes::GoldHammerResult);
+
 #[derive(Debug, ShroomPacket)]
 pub struct TamingMobUseFoodReq {
     pub timestamp: Ticks,
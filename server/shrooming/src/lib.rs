@@ -1,6 +1,8 @@
 pub mod files;
 pub mod file_svr;
+pub mod metrics_svr;
 
 
 pub use file_svr::FileSvr;
+pub use metrics_svr::{MetricsSource, MetricsSvr};
 pub use files::{FileIndex, FileClient};
\ No newline at end of file
@@ -0,0 +1,42 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{extract::State, response::IntoResponse, routing::get, Router};
+use hyper::{header, StatusCode};
+
+/// Source of the metrics, which are rendered in the prometheus text format
+#[async_trait::async_trait]
+pub trait MetricsSource: Send + Sync + 'static {
+    async fn render(&self) -> anyhow::Result<String>;
+}
+
+pub struct MetricsSvr<S> {
+    source: S,
+}
+
+impl<S: MetricsSource> MetricsSvr<S> {
+    pub fn new(source: S) -> Self {
+        Self { source }
+    }
+
+    async fn serve_metrics(State(svr): State<Arc<Self>>) -> impl IntoResponse {
+        match svr.source.render().await {
+            Ok(metrics) => Ok((
+                [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+                metrics,
+            )),
+            Err(err) => Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string())),
+        }
+    }
+
+    pub async fn serve(self, addr: SocketAddr) -> anyhow::Result<()> {
+        let app = Router::new()
+            .route("/metrics", get(Self::serve_metrics))
+            .with_state(Arc::new(self));
+
+        axum::Server::bind(&addr)
+            .serve(app.into_make_service())
+            .await?;
+
+        Ok(())
+    }
+}